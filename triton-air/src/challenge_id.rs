@@ -49,6 +49,12 @@ pub enum ChallengeId {
 
     U32Indeterminate,
 
+    /// The indeterminate for the run-time permutation check, _i.e._, the running product
+    /// manipulated by instructions `push_perm`, `pop_perm`, and `assert_perm`. The
+    /// elements are compressed using weights
+    /// [`StackWeight0`][Self::StackWeight0] through [`StackWeight4`][Self::StackWeight4].
+    PermutationCheckIndeterminate,
+
     /// The indeterminate for the Lookup Argument between the Processor Table and all memory-like
     /// tables, _i.e._, the OpStack Table, the Ram Table, and the JumpStack Table, guaranteeing
     /// that all clock jump differences are directed forward.
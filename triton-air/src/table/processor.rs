@@ -207,6 +207,11 @@ impl AIR for ProcessorTable {
             aux_row(AuxColumn::U32LookupClientLogDerivative)
                 - x_constant(LookupArg::default_initial());
 
+        // run-time permutation check
+        let running_product_for_permutation_check_is_initialized_correctly =
+            aux_row(AuxColumn::PermutationCheckRunningProduct)
+                - x_constant(PermArg::default_initial());
+
         vec![
             clk_is_0,
             ip_is_0,
@@ -237,6 +242,7 @@ impl AIR for ProcessorTable {
             running_evaluation_hash_digest_is_initialized_correctly,
             running_evaluation_sponge_absorb_is_initialized_correctly,
             running_sum_log_derivative_for_u32_table_is_initialized_correctly,
+            running_product_for_permutation_check_is_initialized_correctly,
         ]
     }
 
@@ -332,6 +338,7 @@ impl AIR for ProcessorTable {
             running_evaluation_hash_digest_updates_correctly(circuit_builder),
             running_evaluation_sponge_updates_correctly(circuit_builder),
             log_derivative_with_u32_table_updates_correctly(circuit_builder),
+            running_product_for_permutation_check_updates_correctly(circuit_builder),
         ];
 
         [
@@ -346,12 +353,16 @@ impl AIR for ProcessorTable {
         circuit_builder: &ConstraintCircuitBuilder<SingleRowIndicator>,
     ) -> Vec<ConstraintCircuitMonad<SingleRowIndicator>> {
         let main_row = |col: Self::MainColumn| circuit_builder.input(Main(col.master_main_index()));
+        let aux_row = |col: Self::AuxColumn| circuit_builder.input(Aux(col.master_aux_index()));
         let constant = |c| circuit_builder.b_constant(c);
+        let x_constant = |x| circuit_builder.x_constant(x);
 
         let last_ci_is_halt =
             main_row(Self::MainColumn::CI) - constant(Instruction::Halt.opcode_b());
+        let permutation_check_holds = aux_row(Self::AuxColumn::PermutationCheckRunningProduct)
+            - x_constant(PermArg::default_initial());
 
-        vec![last_ci_is_halt]
+        vec![last_ci_is_halt, permutation_check_holds]
     }
 }
 
@@ -2154,59 +2165,73 @@ fn instruction_xb_dot_step(
     .concat()
 }
 
+/// Dispatches an instruction to the function building its transition constraints. See
+/// [`for_each_instruction`](isa::for_each_instruction).
+macro_rules! instruction_constraints {
+    ([$instruction:expr, $circuit_builder:expr] $(
+        $variant:ident $(($arg:ident: $ty:ident))? {
+            name: $name:literal,
+            stack: $stack:expr,
+            u32: $u32:literal,
+            execute: $execute:ident,
+            constraints: $constraints:ident $(,)?
+        }
+    )*) => {
+        match $instruction {
+            $(Instruction::$variant { .. } => $constraints($circuit_builder),)*
+        }
+    };
+}
+
+fn instruction_push_perm(
+    circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
+) -> Vec<ConstraintCircuitMonad<DualRowIndicator>> {
+    [
+        instruction_group_step_1(circuit_builder),
+        constraints_for_shrinking_stack_by(circuit_builder, 5),
+        instruction_group_no_ram(circuit_builder),
+        instruction_group_no_io(circuit_builder),
+    ]
+    .concat()
+}
+
+fn instruction_pop_perm(
+    circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
+) -> Vec<ConstraintCircuitMonad<DualRowIndicator>> {
+    [
+        instruction_group_step_1(circuit_builder),
+        constraints_for_shrinking_stack_by(circuit_builder, 5),
+        instruction_group_no_ram(circuit_builder),
+        instruction_group_no_io(circuit_builder),
+    ]
+    .concat()
+}
+
+fn instruction_assert_perm(
+    circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
+) -> Vec<ConstraintCircuitMonad<DualRowIndicator>> {
+    let curr_aux_row = |col: AuxColumn| circuit_builder.input(CurrentAux(col.master_aux_index()));
+    let x_constant = |x| circuit_builder.x_constant(x);
+
+    let running_product_is_1 = curr_aux_row(AuxColumn::PermutationCheckRunningProduct)
+        - x_constant(PermArg::default_initial());
+
+    [
+        vec![running_product_is_1],
+        instruction_group_step_1(circuit_builder),
+        instruction_group_keep_op_stack(circuit_builder),
+        instruction_group_no_ram(circuit_builder),
+        instruction_group_no_io(circuit_builder),
+    ]
+    .concat()
+}
+
 #[doc(hidden)] // allows testing in different crate
 pub fn transition_constraints_for_instruction(
     circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
     instruction: Instruction,
 ) -> Vec<ConstraintCircuitMonad<DualRowIndicator>> {
-    match instruction {
-        Instruction::Pop(_) => instruction_pop(circuit_builder),
-        Instruction::Push(_) => instruction_push(circuit_builder),
-        Instruction::Divine(_) => instruction_divine(circuit_builder),
-        Instruction::Pick(_) => instruction_pick(circuit_builder),
-        Instruction::Place(_) => instruction_place(circuit_builder),
-        Instruction::Dup(_) => instruction_dup(circuit_builder),
-        Instruction::Swap(_) => instruction_swap(circuit_builder),
-        Instruction::Halt => instruction_halt(circuit_builder),
-        Instruction::Nop => instruction_nop(circuit_builder),
-        Instruction::Skiz => instruction_skiz(circuit_builder),
-        Instruction::Call(_) => instruction_call(circuit_builder),
-        Instruction::Return => instruction_return(circuit_builder),
-        Instruction::Recurse => instruction_recurse(circuit_builder),
-        Instruction::RecurseOrReturn => instruction_recurse_or_return(circuit_builder),
-        Instruction::Assert => instruction_assert(circuit_builder),
-        Instruction::ReadMem(_) => instruction_read_mem(circuit_builder),
-        Instruction::WriteMem(_) => instruction_write_mem(circuit_builder),
-        Instruction::Hash => instruction_hash(circuit_builder),
-        Instruction::AssertVector => instruction_assert_vector(circuit_builder),
-        Instruction::SpongeInit => instruction_sponge_init(circuit_builder),
-        Instruction::SpongeAbsorb => instruction_sponge_absorb(circuit_builder),
-        Instruction::SpongeAbsorbMem => instruction_sponge_absorb_mem(circuit_builder),
-        Instruction::SpongeSqueeze => instruction_sponge_squeeze(circuit_builder),
-        Instruction::Add => instruction_add(circuit_builder),
-        Instruction::AddI(_) => instruction_addi(circuit_builder),
-        Instruction::Mul => instruction_mul(circuit_builder),
-        Instruction::Invert => instruction_invert(circuit_builder),
-        Instruction::Eq => instruction_eq(circuit_builder),
-        Instruction::Split => instruction_split(circuit_builder),
-        Instruction::Lt => instruction_lt(circuit_builder),
-        Instruction::And => instruction_and(circuit_builder),
-        Instruction::Xor => instruction_xor(circuit_builder),
-        Instruction::Log2Floor => instruction_log_2_floor(circuit_builder),
-        Instruction::Pow => instruction_pow(circuit_builder),
-        Instruction::DivMod => instruction_div_mod(circuit_builder),
-        Instruction::PopCount => instruction_pop_count(circuit_builder),
-        Instruction::XxAdd => instruction_xx_add(circuit_builder),
-        Instruction::XxMul => instruction_xx_mul(circuit_builder),
-        Instruction::XInvert => instruction_xinv(circuit_builder),
-        Instruction::XbMul => instruction_xb_mul(circuit_builder),
-        Instruction::ReadIo(_) => instruction_read_io(circuit_builder),
-        Instruction::WriteIo(_) => instruction_write_io(circuit_builder),
-        Instruction::MerkleStep => instruction_merkle_step(circuit_builder),
-        Instruction::MerkleStepMem => instruction_merkle_step_mem(circuit_builder),
-        Instruction::XxDotStep => instruction_xx_dot_step(circuit_builder),
-        Instruction::XbDotStep => instruction_xb_dot_step(circuit_builder),
-    }
+    isa::for_each_instruction!(instruction_constraints(instruction, circuit_builder))
 }
 
 /// Constrains instruction argument `nia` such that 0 < nia <= 5.
@@ -3076,6 +3101,64 @@ fn running_evaluation_sponge_updates_correctly(
         + sponge_squeeze_deselector * running_evaluation_updates_for_squeeze
 }
 
+/// Instruction `push_perm` multiplies the factor `(α - p)` into the running product,
+/// instruction `pop_perm` divides it out. Here, `p` is the weighted sum of the top 5 op
+/// stack elements. All other instructions leave the running product unchanged.
+fn running_product_for_permutation_check_updates_correctly(
+    circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
+) -> ConstraintCircuitMonad<DualRowIndicator> {
+    let constant = |c: u32| circuit_builder.b_constant(c);
+    let challenge = |c: ChallengeId| circuit_builder.challenge(c);
+    let curr_main_row =
+        |col: MainColumn| circuit_builder.input(CurrentMain(col.master_main_index()));
+    let curr_aux_row = |col: AuxColumn| circuit_builder.input(CurrentAux(col.master_aux_index()));
+    let next_aux_row = |col: AuxColumn| circuit_builder.input(NextAux(col.master_aux_index()));
+
+    let push_perm_deselector =
+        instruction_deselector_current_row(circuit_builder, Instruction::PushPerm);
+    let pop_perm_deselector =
+        instruction_deselector_current_row(circuit_builder, Instruction::PopPerm);
+    let permutation_check_instruction_selector = (curr_main_row(MainColumn::CI)
+        - constant(Instruction::PushPerm.opcode()))
+        * (curr_main_row(MainColumn::CI) - constant(Instruction::PopPerm.opcode()));
+
+    let weights = [
+        ChallengeId::StackWeight0,
+        ChallengeId::StackWeight1,
+        ChallengeId::StackWeight2,
+        ChallengeId::StackWeight3,
+        ChallengeId::StackWeight4,
+    ]
+    .map(challenge);
+    let elements = [
+        MainColumn::ST0,
+        MainColumn::ST1,
+        MainColumn::ST2,
+        MainColumn::ST3,
+        MainColumn::ST4,
+    ]
+    .map(curr_main_row);
+    let compressed_row = weights
+        .into_iter()
+        .zip_eq(elements)
+        .map(|(weight, element)| weight * element)
+        .sum::<ConstraintCircuitMonad<_>>();
+    let factor = challenge(ChallengeId::PermutationCheckIndeterminate) - compressed_row;
+
+    let running_product = curr_aux_row(AuxColumn::PermutationCheckRunningProduct);
+    let running_product_next = next_aux_row(AuxColumn::PermutationCheckRunningProduct);
+
+    let running_product_absorbs_factor =
+        running_product_next.clone() - running_product.clone() * factor.clone();
+    let running_product_releases_factor =
+        running_product_next.clone() * factor - running_product.clone();
+    let running_product_remains = running_product_next - running_product;
+
+    permutation_check_instruction_selector * running_product_remains
+        + push_perm_deselector * running_product_absorbs_factor
+        + pop_perm_deselector * running_product_releases_factor
+}
+
 fn log_derivative_with_u32_table_updates_correctly(
     circuit_builder: &ConstraintCircuitBuilder<DualRowIndicator>,
 ) -> ConstraintCircuitMonad<DualRowIndicator> {
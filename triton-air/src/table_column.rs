@@ -160,6 +160,10 @@ pub enum ProcessorAuxColumn {
     /// The (running sum of the) logarithmic derivative for the clock jump difference Lookup
     /// Argument with the memory-like tables.
     ClockJumpDifferenceLookupServerLogDerivative,
+
+    /// The running product of the run-time permutation check, manipulated by instructions
+    /// `push_perm` and `pop_perm`.
+    PermutationCheckRunningProduct,
 }
 
 #[repr(usize)]
@@ -0,0 +1,341 @@
+//! The commands understood by the debugger, and how to parse them.
+
+use std::str::FromStr;
+
+use thiserror::Error;
+use triton_vm::isa::op_stack::OpStackElement;
+use triton_vm::prelude::*;
+
+/// A command given to a [`Session`](crate::session::Session).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    /// Execute the given number of instructions, entering called subroutines.
+    Step(usize),
+
+    /// Execute one instruction, executing called subroutines entirely.
+    Next,
+
+    /// Run until the current subroutine returns.
+    Finish,
+
+    /// Run until a breakpoint or watchpoint is hit, or until the program terminates.
+    Continue,
+
+    /// Run until the given location is reached.
+    Until(Location),
+
+    /// Set a breakpoint at the given location, which is only hit if the condition, if
+    /// any, holds.
+    Break(Location, Option<Condition>),
+
+    /// Remove the breakpoint at the given location.
+    Delete(Location),
+
+    /// Stop whenever the value at the given RAM address changes.
+    Watch(BFieldElement),
+
+    /// Show some part of the state of Triton VM.
+    Print(Item),
+
+    Help,
+    Quit,
+}
+
+/// A location in the program, given either as an address or as a label.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Location {
+    Address(u64),
+    Label(String),
+}
+
+/// A part of the state of Triton VM that can be [printed](Command::Print).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Item {
+    /// The entire [`VMState`].
+    State,
+
+    /// The op stack, annotated with the [type hints](isa::instruction::TypeHint) at the
+    /// current instruction.
+    Stack,
+
+    JumpStack,
+
+    /// The given number of consecutive RAM cells, starting at the given address.
+    Ram {
+        address: BFieldElement,
+        length: usize,
+    },
+
+    /// The source code around the current instruction.
+    Source,
+}
+
+/// A comparison of an op stack element or a RAM cell with a constant, used for
+/// conditional breakpoints.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub is_negated: bool,
+    pub value: BFieldElement,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operand {
+    Stack(OpStackElement),
+    Ram(BFieldElement),
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum CommandError {
+    #[error("unknown command `{0}`; type `help` for a list of commands")]
+    UnknownCommand(String),
+
+    #[error("missing argument for `{0}`")]
+    MissingArgument(String),
+
+    #[error("invalid argument `{0}`")]
+    InvalidArgument(String),
+
+    #[error("unexpected `{0}`")]
+    TrailingInput(String),
+}
+
+/// The commands and their descriptions, as shown by [`Command::Help`].
+pub const HELP: &str = "\
+step [n]                 execute n instructions (default: 1), entering subroutines
+next                     execute one instruction, stepping over subroutine calls
+finish                   run until the current subroutine returns
+continue                 run until a breakpoint or watchpoint is hit
+until <location>         run until the given address or label is reached
+break <location> [if <operand> ==|!= <value>]
+                         set a breakpoint, optionally with a condition on an op
+                         stack element `st<i>` or a RAM cell `ram[<address>]`
+delete <location>        remove the breakpoint at the given address or label
+watch <address>          stop whenever the value at the given RAM address changes
+print state|stack|jump_stack|source
+print ram <address> [n]  show some part of the state
+help                     show this help
+quit                     exit the debugger";
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err(CommandError::UnknownCommand(String::new()));
+        };
+        let mut argument = || {
+            words
+                .next()
+                .ok_or_else(|| CommandError::MissingArgument(name.to_string()))
+        };
+
+        let command = match name {
+            "step" | "s" => {
+                let num_steps = match words.next() {
+                    Some(n) => n.parse().map_err(|_| invalid(n))?,
+                    None => 1,
+                };
+                Self::Step(num_steps)
+            }
+            "next" | "n" => Self::Next,
+            "finish" | "f" => Self::Finish,
+            "continue" | "c" => Self::Continue,
+            "until" | "u" => Self::Until(argument()?.parse()?),
+            "break" | "b" => {
+                let location = argument()?.parse()?;
+                let condition = match words.next() {
+                    Some("if") => Some(words.by_ref().collect::<Vec<_>>().join(" ").parse()?),
+                    Some(other) => return Err(CommandError::TrailingInput(other.to_string())),
+                    None => None,
+                };
+                Self::Break(location, condition)
+            }
+            "delete" | "d" => Self::Delete(argument()?.parse()?),
+            "watch" | "w" => Self::Watch(field_element(argument()?)?),
+            "print" | "p" => match argument()? {
+                "state" => Self::Print(Item::State),
+                "stack" => Self::Print(Item::Stack),
+                "jump_stack" => Self::Print(Item::JumpStack),
+                "source" => Self::Print(Item::Source),
+                "ram" => {
+                    let address = field_element(argument()?)?;
+                    let length = match words.next() {
+                        Some(n) => n.parse().map_err(|_| invalid(n))?,
+                        None => 1,
+                    };
+                    Self::Print(Item::Ram { address, length })
+                }
+                item => return Err(invalid(item)),
+            },
+            "help" | "h" => Self::Help,
+            "quit" | "q" => Self::Quit,
+            _ => return Err(CommandError::UnknownCommand(name.to_string())),
+        };
+
+        if let Some(trailing) = words.next() {
+            return Err(CommandError::TrailingInput(trailing.to_string()));
+        }
+        Ok(command)
+    }
+}
+
+impl FromStr for Location {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            let address = s.parse().map_err(|_| invalid(s))?;
+            return Ok(Self::Address(address));
+        }
+        Ok(Self::Label(s.to_string()))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = CommandError;
+
+    /// Parse a condition like `st0 == 5` or `ram[42] != -1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (operand, is_negated, value) = if let Some((operand, value)) = s.split_once("!=") {
+            (operand, true, value)
+        } else if let Some((operand, value)) = s.split_once("==") {
+            (operand, false, value)
+        } else {
+            return Err(invalid(s));
+        };
+
+        Ok(Self {
+            operand: operand.trim().parse()?,
+            is_negated,
+            value: field_element(value.trim())?,
+        })
+    }
+}
+
+impl FromStr for Operand {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(index) = s.strip_prefix("st") {
+            let index = index.parse::<u32>().map_err(|_| invalid(s))?;
+            let element = OpStackElement::try_from(index).map_err(|_| invalid(s))?;
+            return Ok(Self::Stack(element));
+        }
+
+        let address = s
+            .strip_prefix("ram[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| invalid(s))?;
+        Ok(Self::Ram(field_element(address.trim())?))
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds in the given state.
+    pub fn holds(&self, state: &VMState) -> bool {
+        let actual = match self.operand {
+            Operand::Stack(element) => state.op_stack[element],
+            Operand::Ram(address) => state.ram.get(&address).copied().unwrap_or_default(),
+        };
+
+        (actual == self.value) != self.is_negated
+    }
+}
+
+/// Parse a field element, which may be negative.
+fn field_element(s: &str) -> Result<BFieldElement, CommandError> {
+    let element = match s.strip_prefix('-') {
+        Some(absolute_value) => absolute_value.parse().map(|e: BFieldElement| -e),
+        None => s.parse(),
+    };
+    element.map_err(|_| invalid(s))
+}
+
+fn invalid(argument: &str) -> CommandError {
+    CommandError::InvalidArgument(argument.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use super::*;
+
+    #[test]
+    fn commands_can_be_abbreviated() {
+        assert!(Ok(Command::Step(1)) == "s".parse());
+        assert!(Ok(Command::Next) == "n".parse());
+        assert!(Ok(Command::Finish) == "f".parse());
+        assert!(Ok(Command::Continue) == "c".parse());
+        assert!(Ok(Command::Print(Item::Stack)) == "p stack".parse());
+        assert!(Ok(Command::Quit) == "q".parse());
+    }
+
+    #[test]
+    fn step_can_take_number_of_steps() {
+        assert!(Ok(Command::Step(17)) == "step 17".parse());
+        let_assert!(Err(CommandError::InvalidArgument(_)) = "step many".parse::<Command>());
+    }
+
+    #[test]
+    fn locations_are_addresses_or_labels() {
+        let address = Location::Address(42);
+        assert!(Ok(Command::Until(address)) == "until 42".parse());
+
+        let label = Location::Label("lib::foo".to_string());
+        assert!(Ok(Command::Delete(label)) == "delete lib::foo".parse());
+    }
+
+    #[test]
+    fn breakpoint_can_have_condition() {
+        let_assert!(Ok(Command::Break(location, Some(condition))) = "b 7 if st3 != -1".parse());
+        assert!(Location::Address(7) == location);
+        assert!(Operand::Stack(OpStackElement::ST3) == condition.operand);
+        assert!(condition.is_negated);
+        assert!(-bfe!(1) == condition.value);
+
+        let_assert!(Ok(Command::Break(_, Some(condition))) = "b foo if ram[ 42 ]==5".parse());
+        assert!(Operand::Ram(bfe!(42)) == condition.operand);
+        assert!(!condition.is_negated);
+        assert!(bfe!(5) == condition.value);
+    }
+
+    #[test]
+    fn malformed_conditions_are_rejected() {
+        let_assert!(Err(_) = "break 7 if st16 == 1".parse::<Command>());
+        let_assert!(Err(_) = "break 7 if st0 < 1".parse::<Command>());
+        let_assert!(Err(_) = "break 7 if ram[x] == 1".parse::<Command>());
+        let_assert!(Err(_) = "break 7 unless st0 == 1".parse::<Command>());
+    }
+
+    #[test]
+    fn print_ram_takes_address_and_optional_length() {
+        let_assert!(Ok(Command::Print(Item::Ram { address, length })) = "p ram 7 3".parse());
+        assert!((bfe!(7), 3) == (address, length));
+        let_assert!(Ok(Command::Print(Item::Ram { length: 1, .. })) = "p ram 7".parse());
+    }
+
+    #[test]
+    fn unknown_commands_and_trailing_input_are_rejected() {
+        let_assert!(Err(CommandError::UnknownCommand(_)) = "jump 7".parse::<Command>());
+        let_assert!(Err(CommandError::MissingArgument(_)) = "break".parse::<Command>());
+        let_assert!(Err(CommandError::TrailingInput(_)) = "next 3".parse::<Command>());
+    }
+
+    #[test]
+    fn condition_holds_if_comparison_holds() {
+        let program = triton_program!(push 5 push 0 write_mem 1 halt);
+        let mut state = VMState::new(program, [].into(), [].into());
+        let_assert!(Ok(()) = state.run());
+
+        let condition = |s: &str| s.parse::<Condition>().unwrap();
+        assert!(condition("ram[0] == 5").holds(&state));
+        assert!(condition("ram[1] == 0").holds(&state));
+        assert!(condition("st0 != 0").holds(&state));
+        assert!(!condition("st0 == 0").holds(&state));
+    }
+}
@@ -0,0 +1,7 @@
+//! An interactive debugger for Triton assembly.
+//!
+//! The [commands](command) of the debugger are executed in a [session], which is built
+//! on top of Triton VM's [`Debugger`](triton_vm::debugger::Debugger).
+
+pub mod command;
+pub mod session;
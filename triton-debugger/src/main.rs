@@ -0,0 +1,98 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::ExitCode;
+
+use triton_debugger::session;
+use triton_debugger::session::Session;
+use triton_vm::isa::program_file::ProgramReader;
+use triton_vm::isa::program_file::FILE_EXTENSION;
+use triton_vm::prelude::*;
+
+const USAGE: &str =
+    "usage: triton-debugger <program> [--input <a,b,c>] [--non-determinism <file.json>]";
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn try_main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let mut program_path = None;
+    let mut public_input = vec![];
+    let mut non_determinism = NonDeterminism::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                let input = args.next().ok_or(USAGE)?;
+                public_input = parse_public_input(&input)?;
+            }
+            "--non-determinism" => {
+                let path = args.next().ok_or(USAGE)?;
+                let file = File::open(&path).map_err(|err| format!("{path}: {err}"))?;
+                non_determinism = serde_json::from_reader(BufReader::new(file))
+                    .map_err(|err| format!("{path}: {err}"))?;
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if program_path.is_none() && !arg.starts_with('-') => program_path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let program_path = program_path.ok_or(USAGE)?;
+
+    let session = load_session(&program_path, public_input.into(), non_determinism)?;
+    session::run(session, io::stdin().lock(), io::stdout().lock()).map_err(|err| err.to_string())
+}
+
+/// Load a `.tvm` program file, or parse any other file as Triton assembly.
+fn load_session(
+    path: &str,
+    public_input: PublicInput,
+    non_determinism: NonDeterminism,
+) -> Result<Session, String> {
+    let with_path = |err: &dyn std::fmt::Display| format!("{path}: {err}");
+
+    let is_program_file = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == FILE_EXTENSION);
+    if is_program_file {
+        let file = File::open(path).map_err(|err| with_path(&err))?;
+        let program = ProgramReader::new(BufReader::new(file))
+            .read_program()
+            .map_err(|err| with_path(&err))?;
+        let state = VMState::new(program, public_input, non_determinism);
+        return Ok(Session::new(state));
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| with_path(&err))?;
+    let program = Program::from_code(&source).map_err(|err| with_path(&err))?;
+    let state = VMState::new(program, public_input, non_determinism);
+    Ok(Session::new(state).with_source(source))
+}
+
+/// Parse a comma-separated list of field elements, like `1,2,-3`.
+fn parse_public_input(input: &str) -> Result<Vec<BFieldElement>, String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+        .map(|element| {
+            let parsed = match element.strip_prefix('-') {
+                Some(absolute_value) => absolute_value.parse().map(|e: BFieldElement| -e),
+                None => element.parse(),
+            };
+            parsed.map_err(|_| format!("invalid public input `{element}`"))
+        })
+        .collect()
+}
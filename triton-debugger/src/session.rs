@@ -0,0 +1,555 @@
+//! A debugging session: [executes](Session::execute) [`Command`]s on a [`Debugger`] and
+//! renders the results as text.
+
+use std::fmt::Write as _;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::iter;
+
+use itertools::Itertools;
+use thiserror::Error;
+use triton_vm::debugger::Debugger;
+use triton_vm::debugger::StopReason;
+use triton_vm::isa::instruction::TypeHint;
+use triton_vm::prelude::*;
+
+use crate::command::Command;
+use crate::command::Item;
+use crate::command::Location;
+use crate::command::HELP;
+
+/// The prompt shown by [`run`] when waiting for the next command.
+pub const PROMPT: &str = "(tdb) ";
+
+/// The number of lines or instructions shown before and after the current instruction.
+const CONTEXT_SIZE: usize = 3;
+
+#[derive(Debug)]
+pub struct Session {
+    debugger: Debugger,
+
+    /// The source code of the program, if it was parsed from source code.
+    source: Option<String>,
+    has_quit: bool,
+}
+
+impl Session {
+    pub fn new(state: VMState) -> Self {
+        Self {
+            debugger: Debugger::new(state),
+            source: None,
+            has_quit: false,
+        }
+    }
+
+    /// Show the given source code around the current instruction instead of the
+    /// disassembled program. The program must have been parsed from this source code.
+    #[must_use]
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Whether the [`Quit`](Command::Quit) command was executed.
+    pub fn has_quit(&self) -> bool {
+        self.has_quit
+    }
+
+    /// Parse and [execute](Self::execute) the given line of input.
+    pub fn handle(&mut self, line: &str) -> String {
+        match line.parse() {
+            Ok(command) => self.execute(command),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Execute the given command and describe its outcome.
+    pub fn execute(&mut self, command: Command) -> String {
+        let stop_reason = match command {
+            Command::Step(num_steps) => self.step(num_steps),
+            Command::Next => self.debugger.step_over(),
+            Command::Finish => self.debugger.step_out(),
+            Command::Continue => self.debugger.continue_to_breakpoint(),
+            Command::Until(location) => match self.resolve(&location) {
+                Ok(address) => self.debugger.run_to_address(address),
+                Err(err) => return err.to_string(),
+            },
+            Command::Break(location, condition) => {
+                let address = match self.resolve(&location) {
+                    Ok(address) => address,
+                    Err(err) => return err.to_string(),
+                };
+                match condition {
+                    Some(c) => self
+                        .debugger
+                        .set_conditional_breakpoint(address, move |state| c.holds(state)),
+                    None => self.debugger.set_breakpoint(address),
+                }
+                return format!("breakpoint at {}", self.describe_address(address));
+            }
+            Command::Delete(location) => {
+                let address = match self.resolve(&location) {
+                    Ok(address) => address,
+                    Err(err) => return err.to_string(),
+                };
+                let description = self.describe_address(address);
+                return match self.debugger.remove_breakpoint(address) {
+                    true => format!("deleted breakpoint at {description}"),
+                    false => format!("no breakpoint at {description}"),
+                };
+            }
+            Command::Watch(address) => {
+                self.debugger.watch(address);
+                return format!("watching ram[{address}]");
+            }
+            Command::Print(item) => return self.print(item),
+            Command::Help => return HELP.to_string(),
+            Command::Quit => {
+                self.has_quit = true;
+                return String::new();
+            }
+        };
+
+        let description = self.describe_stop(&stop_reason);
+        if stop_reason == StopReason::Halted {
+            return description;
+        }
+        format!("{description}\n{}", self.source_context())
+    }
+
+    /// Step into the given number of instructions, stopping early for the same reasons
+    /// as [`Debugger::step_into`], except for completing a step.
+    fn step(&mut self, num_steps: usize) -> StopReason {
+        let mut stop_reason = StopReason::StepCompleted;
+        for _ in 0..num_steps {
+            stop_reason = self.debugger.step_into();
+            if stop_reason != StopReason::StepCompleted {
+                break;
+            }
+        }
+        stop_reason
+    }
+
+    fn describe_stop(&self, stop_reason: &StopReason) -> String {
+        let state = self.debugger.state();
+        let ip = state.instruction_pointer as u64;
+        match stop_reason {
+            StopReason::StepCompleted | StopReason::AddressReached(_) => {
+                format!(
+                    "cycle {} at {}",
+                    state.cycle_count,
+                    self.describe_address(ip)
+                )
+            }
+            StopReason::Breakpoint(address) => {
+                format!("breakpoint at {}", self.describe_address(*address))
+            }
+            StopReason::Watchpoint {
+                address,
+                old_value,
+                new_value,
+            } => format!("ram[{address}] changed from {old_value} to {new_value}"),
+            StopReason::CycleCountReached(cycle_count) => format!("reached cycle {cycle_count}"),
+            StopReason::Halted => format!("program halted after {} cycles", state.cycle_count),
+            StopReason::Crashed(err) => format!("Triton VM crashed: {err}"),
+        }
+    }
+
+    fn print(&self, item: Item) -> String {
+        match item {
+            Item::State => self.debugger.state().to_string(),
+            Item::Stack => self.op_stack(),
+            Item::JumpStack => self.jump_stack(),
+            Item::Ram { address, length } => self.ram(address, length),
+            Item::Source => self.source_context(),
+        }
+    }
+
+    /// The op stack, top first, with every element annotated by the [`TypeHint`]s that
+    /// apply to it at the current instruction.
+    fn op_stack(&self) -> String {
+        let state = self.debugger.state();
+        let type_hints = state
+            .program
+            .type_hints_at(state.instruction_pointer as u64);
+
+        (0..state.op_stack.len())
+            .map(|index| {
+                let element = state.op_stack[index];
+                let annotation = type_hints
+                    .iter()
+                    .filter(|hint| index >= hint.starting_index)
+                    .filter(|hint| index < hint.starting_index + hint.length)
+                    .map(|hint| describe_type_hint(hint, index))
+                    .join(", ");
+                let element = element.to_string();
+                let line = format!("st{index:<3} {element:>20}  {annotation}");
+                line.trim_end().to_string()
+            })
+            .join("\n")
+    }
+
+    /// The jump stack, innermost subroutine first.
+    fn jump_stack(&self) -> String {
+        let jump_stack = &self.debugger.state().jump_stack;
+        if jump_stack.is_empty() {
+            return "jump stack is empty".to_string();
+        }
+
+        jump_stack
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(depth, &(origin, destination))| {
+                let destination = self.describe_address(destination.value());
+                format!("#{depth} {destination}, returns to {origin}")
+            })
+            .join("\n")
+    }
+
+    fn ram(&self, address: BFieldElement, length: usize) -> String {
+        let ram = &self.debugger.state().ram;
+        (0..length)
+            .map(|offset| address + bfe!(offset as u64))
+            .map(|address| {
+                let value = ram.get(&address).copied().unwrap_or_else(|| bfe!(0));
+                format!("ram[{address}] = {value}")
+            })
+            .join("\n")
+    }
+
+    /// The source code around the current instruction, if the source is known.
+    /// Otherwise, the disassembled program around the current instruction.
+    fn source_context(&self) -> String {
+        let state = self.debugger.state();
+        let ip = state.instruction_pointer as u64;
+        let location = state.program.source_location_at(ip);
+        match (&self.source, location) {
+            (Some(source), Some(location)) => source_listing(source, location.line),
+            _ => self.disassembly(ip),
+        }
+    }
+
+    fn disassembly(&self, ip: u64) -> String {
+        let program = &self.debugger.state().program;
+        let addresses = instruction_addresses(program).collect_vec();
+        let Some(current) = addresses.iter().position(|&address| address == ip) else {
+            return format!("no instruction at address {ip}");
+        };
+
+        let start = current.saturating_sub(CONTEXT_SIZE);
+        let end = addresses.len().min(current + CONTEXT_SIZE + 1);
+        addresses[start..end]
+            .iter()
+            .map(|&address| {
+                let marker = if address == ip { '>' } else { ' ' };
+                let instruction = program.instructions[address as usize];
+                format!("{marker} {address:>5}  {instruction}")
+            })
+            .join("\n")
+    }
+
+    fn resolve(&self, location: &Location) -> Result<u64, SessionError> {
+        let program = &self.debugger.state().program;
+        match location {
+            Location::Address(address) => {
+                let is_instruction = instruction_addresses(program).contains(address);
+                is_instruction
+                    .then_some(*address)
+                    .ok_or(SessionError::NoInstructionAt(*address))
+            }
+            Location::Label(label) => instruction_addresses(program)
+                .find(|&address| program.label_for_address(address) == *label)
+                .ok_or_else(|| SessionError::UnknownLabel(label.clone())),
+        }
+    }
+
+    /// The address, followed by its source location or label, whichever is known.
+    fn describe_address(&self, address: u64) -> String {
+        let program = &self.debugger.state().program;
+        if let Some(location) = program.source_location_at(address) {
+            let file = location.file.as_deref().unwrap_or("<source>");
+            return format!("address {address} ({file}:{})", location.line);
+        }
+        format!("address {address} ({})", program.label_for_address(address))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+enum SessionError {
+    #[error("no instruction starts at address {0}")]
+    NoInstructionAt(u64),
+
+    #[error("unknown label `{0}`")]
+    UnknownLabel(String),
+}
+
+/// Read commands from `input` and write their results to `output` until the
+/// [`Quit`](Command::Quit) command is given or the input ends. An empty line repeats
+/// the previous command.
+pub fn run(mut session: Session, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    writeln!(output, "{}", session.source_context())?;
+    write!(output, "{PROMPT}")?;
+    output.flush()?;
+
+    let mut previous_command: Option<Command> = None;
+    for line in input.lines() {
+        let line = line?;
+        let command = match line.trim() {
+            "" => previous_command.clone().ok_or(None),
+            line => line.parse().map_err(Some),
+        };
+
+        let response = match command {
+            Ok(command) => {
+                previous_command = Some(command.clone());
+                session.execute(command)
+            }
+            Err(Some(err)) => err.to_string(),
+            Err(None) => String::new(),
+        };
+        if session.has_quit() {
+            return Ok(());
+        }
+
+        if !response.is_empty() {
+            writeln!(output, "{response}")?;
+        }
+        write!(output, "{PROMPT}")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+/// The addresses at which instructions start. Arguments of instructions are skipped.
+fn instruction_addresses(program: &Program) -> impl Iterator<Item = u64> + '_ {
+    let mut address = 0;
+    iter::from_fn(move || {
+        let instruction = program.instructions.get(address)?;
+        let current = address;
+        address += instruction.size();
+        Some(current as u64)
+    })
+}
+
+/// The lines of the source code around the given line, which is marked.
+fn source_listing(source: &str, line_number: usize) -> String {
+    let start = line_number.saturating_sub(CONTEXT_SIZE + 1);
+    source
+        .lines()
+        .enumerate()
+        .skip(start)
+        .take(2 * CONTEXT_SIZE + 1)
+        .map(|(index, line)| {
+            let number = index + 1;
+            let marker = if number == line_number { '>' } else { ' ' };
+            format!("{marker} {number:>5}  {line}")
+                .trim_end()
+                .to_string()
+        })
+        .join("\n")
+}
+
+/// For example, `digest: Digest[1]` for the second element of a type hint
+/// `hint digest: Digest = stack[0..5]`.
+fn describe_type_hint(hint: &TypeHint, index: usize) -> String {
+    let mut description = hint.variable_name.clone();
+    if let Some(type_name) = &hint.type_name {
+        let _ = write!(description, ": {type_name}");
+    }
+    if hint.length > 1 {
+        let _ = write!(description, "[{}]", index - hint.starting_index);
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use super::*;
+
+    /// Calls a subroutine that doubles the top of the stack and writes the result to
+    /// RAM address 42.
+    const SOURCE: &str = "\
+push 21
+call double
+hint result: u32 = stack[0]
+push 42
+write_mem 1
+pop 1
+halt
+double:
+    dup 0
+    add
+    return
+";
+
+    fn session() -> Session {
+        let program = Program::from_code(SOURCE).unwrap();
+        let state = VMState::new(program, [].into(), [].into());
+        Session::new(state).with_source(SOURCE.to_string())
+    }
+
+    #[test]
+    fn stepping_shows_source_around_current_instruction() {
+        let mut session = session();
+        let output = session.handle("step 2");
+        assert!(output.starts_with("cycle 2 at address 11 (<source>:9)"));
+        assert!(output.contains(">     9      dup 0"));
+        assert!(output.contains("      8  double:"));
+    }
+
+    #[test]
+    fn next_steps_over_subroutine_calls() {
+        let mut session = session();
+        session.handle("step");
+        let output = session.handle("next");
+        assert!(output.starts_with("cycle 5 at address 4"));
+        assert!(session.debugger().state().op_stack[0] == bfe!(42));
+    }
+
+    #[test]
+    fn finish_returns_from_subroutine() {
+        let mut session = session();
+        session.handle("step 2");
+        session.handle("finish");
+        assert!(4 == session.debugger().state().instruction_pointer);
+    }
+
+    #[test]
+    fn breakpoints_can_be_set_on_labels() {
+        let mut session = session();
+        assert!("breakpoint at address 11 (<source>:9)" == session.handle("break double"));
+
+        let output = session.handle("continue");
+        assert!(output.starts_with("breakpoint at address 11"));
+        assert!(11 == session.debugger().state().instruction_pointer);
+
+        assert!(session
+            .handle("delete double")
+            .starts_with("deleted breakpoint"));
+        assert!(session.handle("delete double").starts_with("no breakpoint"));
+        assert!(session
+            .handle("continue")
+            .starts_with("program halted after"));
+    }
+
+    #[test]
+    fn conditional_breakpoints_only_stop_if_condition_holds() {
+        let mut session = session();
+        session.handle("break 11 if st0 != 21");
+        assert!(session.handle("continue").starts_with("program halted"));
+
+        let mut session = self::session();
+        session.handle("break 11 if st0 == 21");
+        assert!(session.handle("continue").starts_with("breakpoint"));
+    }
+
+    #[test]
+    fn breakpoints_must_be_at_instructions() {
+        let mut session = session();
+        assert!("no instruction starts at address 1" == session.handle("break 1"));
+        assert!("unknown label `triple`" == session.handle("break triple"));
+    }
+
+    #[test]
+    fn watchpoints_stop_when_ram_changes() {
+        let mut session = session();
+        session.handle("watch 42");
+        let output = session.handle("continue");
+        assert!(output.starts_with("ram[42] changed from 0 to 42"));
+    }
+
+    #[test]
+    fn op_stack_is_annotated_with_type_hints() {
+        let mut session = session();
+        session.handle("until 4");
+        let stack = session.handle("print stack");
+        let_assert!(Some(top) = stack.lines().next());
+        assert!(top.starts_with("st0"));
+        assert!(top.ends_with("42  result: u32"));
+        assert!(17 == stack.lines().count());
+    }
+
+    #[test]
+    fn type_hints_need_not_have_type() {
+        let program = triton_program!(push 7 hint x = stack[0] halt);
+        let mut session = Session::new(VMState::new(program, [].into(), [].into()));
+        session.handle("step");
+        let stack = session.op_stack();
+        assert!("st0                      7  x" == stack.lines().next().unwrap());
+        assert!("st1                      0" == stack.lines().nth(1).unwrap());
+    }
+
+    #[test]
+    fn multi_element_type_hints_annotate_every_element() {
+        let program = triton_program!(hint digest: Digest = stack[0..5] halt);
+        let session = Session::new(VMState::new(program, [].into(), [].into()));
+        let stack = session.op_stack();
+        assert!(stack.lines().next().unwrap().ends_with("digest: Digest[0]"));
+        assert!(stack.lines().nth(4).unwrap().ends_with("digest: Digest[4]"));
+        assert!(!stack.lines().nth(5).unwrap().contains("digest"));
+    }
+
+    #[test]
+    fn jump_stack_shows_called_subroutines() {
+        let mut session = session();
+        assert!("jump stack is empty" == session.handle("print jump_stack"));
+        session.handle("step 2");
+        let jump_stack = session.handle("p jump_stack");
+        assert!("#0 address 11 (<source>:9), returns to 4" == jump_stack);
+    }
+
+    #[test]
+    fn ram_view_shows_consecutive_cells() {
+        let mut session = session();
+        session.handle("continue");
+        let ram = session.handle("print ram 41 3");
+        assert!("ram[41] = 0\nram[42] = 42\nram[43] = 0" == ram);
+    }
+
+    #[test]
+    fn programs_without_source_are_disassembled() {
+        let program = triton_program!(push 1 push 2 add pop 1 halt);
+        let mut session = Session::new(VMState::new(program, [].into(), [].into()));
+        let output = session.handle("step");
+        assert!(output.contains(">     2  push 2"));
+        assert!(output.contains("      4  add"));
+        assert!(output.contains("      0  push 1"));
+    }
+
+    #[test]
+    fn print_state_uses_display_of_vm_state() {
+        let session = session();
+        let state = session.debugger().state().to_string();
+        assert!(state == session.print(Item::State));
+    }
+
+    #[test]
+    fn empty_line_repeats_previous_command_and_quit_stops() {
+        let input = "step\n\n\nquit\nstep\n";
+        let mut output = vec![];
+        let_assert!(Ok(()) = run(session(), input.as_bytes(), &mut output));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("cycle 3 at"));
+        assert!(!output.contains("cycle 4 at"));
+        assert!(output.ends_with(PROMPT));
+    }
+
+    #[test]
+    fn invalid_commands_are_reported() {
+        let mut output = vec![];
+        let_assert!(Ok(()) = run(session(), "jump\n".as_bytes(), &mut output));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("unknown command `jump`"));
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use strum::Display;
+
+use crate::instruction::Instruction;
+use crate::program::Program;
+
+/// The control-flow graph of a [`Program`], made up of [basic blocks](BasicBlock).
+///
+/// A basic block ends with any instruction that can transfer control elsewhere, _i.e._,
+/// [`skiz`](Instruction::Skiz), [`call`](Instruction::Call),
+/// [`return`](Instruction::Return), [`recurse`](Instruction::Recurse),
+/// [`recurse_or_return`](Instruction::RecurseOrReturn), and [`halt`](Instruction::Halt),
+/// or right before the start of another basic block.
+///
+/// The code starting at address 0, as well as the code starting at any `call` target,
+/// forms a [subroutine](Subroutine). Its basic blocks are those reachable from its entry
+/// without following `call`s. Since `recurse` jumps back to the start of the subroutine that
+/// was called most recently, a `recurse` leads to the entry of every subroutine containing
+/// it. A `return` has no successor within the graph; control continues at the
+/// [return site](EdgeKind::ReturnSite) of the corresponding `call`.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use triton_isa::control_flow::ControlFlowGraph;
+/// let program = triton_program!(
+///     push 3 call countdown halt
+///     countdown:
+///         dup 0 push 0 eq skiz return
+///         push -1 add recurse
+/// );
+/// let cfg = ControlFlowGraph::new(&program);
+/// assert_eq!(5, cfg.blocks().count());
+///
+/// let call_graph = cfg.call_graph();
+/// assert_eq!(vec![5], call_graph.callees(0).collect::<Vec<_>>());
+///
+/// // render with Graphviz, for example, using `dot -Tsvg`
+/// let dot = cfg.to_dot();
+/// assert!(dot.starts_with("digraph"));
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<u64, BasicBlock>,
+    subroutines: BTreeMap<u64, Subroutine>,
+}
+
+/// A maximal sequence of instructions that is always executed from start to end.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    /// The address of the block's first instruction.
+    pub start: u64,
+
+    /// The label of the block's first instruction, or a substitute if there is no label.
+    /// See also [`Program::label_for_address`].
+    pub label: String,
+
+    /// The block's instructions, in order.
+    pub instructions: Vec<Instruction>,
+
+    /// The control-flow edges leaving this block.
+    pub successors: Vec<Edge>,
+}
+
+/// A control-flow edge leading to the start of a [`BasicBlock`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Edge {
+    /// The address of the basic block control flows to.
+    pub target: u64,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Display, Copy, Clone, Eq, PartialEq, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum EdgeKind {
+    /// Control flows to the next instruction.
+    Next,
+
+    /// [`skiz`](Instruction::Skiz) skips the next instruction.
+    Skip,
+
+    /// [`call`](Instruction::Call) enters a subroutine.
+    Call,
+
+    /// Once the subroutine entered by a [`call`](Instruction::Call) returns, control
+    /// flows to the instruction following the `call`.
+    ReturnSite,
+
+    /// [`recurse`](Instruction::Recurse) or
+    /// [`recurse_or_return`](Instruction::RecurseOrReturn) jumps back to the start of the
+    /// current subroutine.
+    Recurse,
+}
+
+/// A subroutine: the code starting at address 0 or at any [`call`](Instruction::Call)
+/// target.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Subroutine {
+    /// The address of the subroutine's first instruction.
+    pub entry: u64,
+
+    /// The label of the subroutine's first instruction, or a substitute if there is no
+    /// label. See also [`Program::label_for_address`].
+    pub label: String,
+
+    /// The start addresses of all [basic blocks](BasicBlock) belonging to the subroutine.
+    /// Basic blocks can belong to multiple subroutines.
+    pub blocks: BTreeSet<u64>,
+}
+
+/// Which [subroutines](Subroutine) of a [`Program`] [`call`](Instruction::Call) which
+/// other subroutines.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct CallGraph {
+    labels: BTreeMap<u64, String>,
+    callees: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &Program) -> Self {
+        let instructions = &program.instructions;
+        let program_len = instructions.len() as u64;
+        let instruction_at = |address: u64| instructions.get(address as usize).copied();
+
+        let mut leaders = BTreeSet::from([0]);
+        let mut subroutine_entries = BTreeSet::from([0]);
+        let mut address = 0;
+        while let Some(instruction) = instruction_at(address) {
+            let successor = address + instruction.size() as u64;
+            match instruction {
+                Instruction::Call(target) => {
+                    leaders.extend([target.value(), successor]);
+                    subroutine_entries.insert(target.value());
+                }
+                Instruction::Skiz => {
+                    leaders.insert(successor);
+                    if let Some(skipped) = instruction_at(successor) {
+                        leaders.insert(successor + skipped.size() as u64);
+                    }
+                }
+                _ if ends_basic_block(instruction) => {
+                    leaders.insert(successor);
+                }
+                _ => (),
+            }
+            address = successor;
+        }
+        leaders.retain(|&leader| leader < program_len);
+        subroutine_entries.retain(|&entry| entry < program_len);
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut block = BasicBlock {
+                start,
+                label: program.label_for_address(start),
+                ..Default::default()
+            };
+            let mut address = start;
+            while let Some(instruction) = instruction_at(address) {
+                block.instructions.push(instruction);
+                let successor = address + instruction.size() as u64;
+                if !ends_basic_block(instruction) && !leaders.contains(&successor) {
+                    address = successor;
+                    continue;
+                }
+
+                let edge = |target, kind| Edge { target, kind };
+                block.successors = match instruction {
+                    Instruction::Call(target) => vec![
+                        edge(target.value(), EdgeKind::Call),
+                        edge(successor, EdgeKind::ReturnSite),
+                    ],
+                    Instruction::Skiz => {
+                        let skipped = instruction_at(successor);
+                        let skip_target = skipped.map(|skip| successor + skip.size() as u64);
+                        let skip_edge = skip_target.map(|target| edge(target, EdgeKind::Skip));
+                        [Some(edge(successor, EdgeKind::Next)), skip_edge]
+                            .into_iter()
+                            .flatten()
+                            .collect()
+                    }
+                    Instruction::Return
+                    | Instruction::Recurse
+                    | Instruction::RecurseOrReturn
+                    | Instruction::Halt => vec![],
+                    _ => vec![edge(successor, EdgeKind::Next)],
+                };
+                break;
+            }
+            block.successors.retain(|edge| edge.target < program_len);
+            blocks.insert(start, block);
+        }
+
+        let mut cfg = Self {
+            blocks,
+            subroutines: BTreeMap::new(),
+        };
+        for entry in subroutine_entries {
+            let subroutine = Subroutine {
+                entry,
+                label: program.label_for_address(entry),
+                blocks: cfg.intraprocedurally_reachable_blocks(entry),
+            };
+            cfg.subroutines.insert(entry, subroutine);
+        }
+        cfg.add_recursion_edges();
+
+        cfg
+    }
+
+    /// The start addresses of all basic blocks reachable from the given address without
+    /// entering any other subroutine.
+    fn intraprocedurally_reachable_blocks(&self, entry: u64) -> BTreeSet<u64> {
+        let mut reachable = BTreeSet::new();
+        let mut work_list = vec![entry];
+        while let Some(start) = work_list.pop() {
+            if !reachable.insert(start) {
+                continue;
+            }
+            let successors = self.blocks[&start].successors.iter();
+            let intraprocedural_successors = successors
+                .filter(|edge| edge.kind != EdgeKind::Call)
+                .map(|edge| edge.target);
+            work_list.extend(intraprocedural_successors);
+        }
+        reachable
+    }
+
+    fn add_recursion_edges(&mut self) {
+        for subroutine in self.subroutines.values() {
+            for start in &subroutine.blocks {
+                let block = self.blocks.get_mut(start).unwrap();
+                let Some(&last_instruction) = block.instructions.last() else {
+                    continue;
+                };
+                if matches!(
+                    last_instruction,
+                    Instruction::Recurse | Instruction::RecurseOrReturn
+                ) {
+                    let target = subroutine.entry;
+                    let kind = EdgeKind::Recurse;
+                    block.successors.push(Edge { target, kind });
+                }
+            }
+        }
+    }
+
+    /// The basic block starting at the given address, if any.
+    pub fn block(&self, start: u64) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// All basic blocks, ordered by their start address.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// The subroutine starting at the given address, if any.
+    pub fn subroutine(&self, entry: u64) -> Option<&Subroutine> {
+        self.subroutines.get(&entry)
+    }
+
+    /// All subroutines, ordered by their entry address.
+    pub fn subroutines(&self) -> impl Iterator<Item = &Subroutine> {
+        self.subroutines.values()
+    }
+
+    pub fn call_graph(&self) -> CallGraph {
+        let mut call_graph = CallGraph::default();
+        for subroutine in self.subroutines() {
+            let callees = subroutine
+                .blocks
+                .iter()
+                .flat_map(|start| &self.blocks[start].successors)
+                .filter(|edge| edge.kind == EdgeKind::Call)
+                .map(|edge| edge.target)
+                .collect();
+            let entry = subroutine.entry;
+            call_graph.labels.insert(entry, subroutine.label.clone());
+            call_graph.callees.insert(entry, callees);
+        }
+        call_graph
+    }
+
+    /// The control-flow graph in the [DOT language][dot], which can be rendered by
+    /// Graphviz and many other tools.
+    ///
+    /// [dot]: https://graphviz.org/doc/info/lang.html
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph control_flow {{").unwrap();
+        writeln!(dot, "  node [shape=box, fontname=monospace];").unwrap();
+        for block in self.blocks() {
+            let mut label = format!("{}:\\l", block.label);
+            for instruction in &block.instructions {
+                write!(label, "  {instruction}\\l").unwrap();
+            }
+            writeln!(dot, "  {} [label=\"{label}\"];", block.start).unwrap();
+        }
+        for block in self.blocks() {
+            for edge in &block.successors {
+                let (source, target, kind) = (block.start, edge.target, edge.kind);
+                let style = match kind {
+                    EdgeKind::Call => "dashed",
+                    EdgeKind::Recurse => "dotted",
+                    _ => "solid",
+                };
+                writeln!(
+                    dot,
+                    "  {source} -> {target} [label=\"{kind}\", style={style}];"
+                )
+                .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+fn ends_basic_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Call(_)
+            | Instruction::Skiz
+            | Instruction::Return
+            | Instruction::Recurse
+            | Instruction::RecurseOrReturn
+            | Instruction::Halt
+    )
+}
+
+impl CallGraph {
+    pub fn new(program: &Program) -> Self {
+        ControlFlowGraph::new(program).call_graph()
+    }
+
+    /// The entry addresses of all subroutines, in ascending order.
+    pub fn subroutines(&self) -> impl Iterator<Item = u64> + '_ {
+        self.callees.keys().copied()
+    }
+
+    /// The entry addresses of all subroutines called by the given subroutine, in
+    /// ascending order.
+    pub fn callees(&self, subroutine: u64) -> impl Iterator<Item = u64> + '_ {
+        self.callees.get(&subroutine).into_iter().flatten().copied()
+    }
+
+    /// The entry addresses of all subroutines calling the given subroutine, in
+    /// ascending order.
+    pub fn callers(&self, subroutine: u64) -> impl Iterator<Item = u64> + '_ {
+        self.callees
+            .iter()
+            .filter(move |(_, callees)| callees.contains(&subroutine))
+            .map(|(&caller, _)| caller)
+    }
+
+    /// The call graph in the [DOT language][dot], which can be rendered by Graphviz and
+    /// many other tools.
+    ///
+    /// [dot]: https://graphviz.org/doc/info/lang.html
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph call_graph {{").unwrap();
+        writeln!(dot, "  node [shape=box, fontname=monospace];").unwrap();
+        for (entry, label) in &self.labels {
+            writeln!(dot, "  {entry} [label=\"{label}\"];").unwrap();
+        }
+        for (caller, callees) in &self.callees {
+            for callee in callees {
+                writeln!(dot, "  {caller} -> {callee};").unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use itertools::Itertools;
+
+    use crate::triton_program;
+
+    use super::*;
+
+    fn edges(cfg: &ControlFlowGraph, start: u64) -> Vec<(u64, EdgeKind)> {
+        let block = cfg.block(start).unwrap();
+        block
+            .successors
+            .iter()
+            .map(|e| (e.target, e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn straight_line_program_is_one_block() {
+        let program = triton_program!(push 1 push 2 add pop 1 halt);
+        let cfg = ControlFlowGraph::new(&program);
+        assert!(1 == cfg.blocks().count());
+        assert!(1 == cfg.subroutines().count());
+
+        let block = cfg.block(0).unwrap();
+        assert!(5 == block.instructions.len());
+        assert!(block.successors.is_empty());
+    }
+
+    #[test]
+    fn skiz_splits_blocks() {
+        let program = triton_program!(push 1 skiz push 2 push 3 halt);
+        let cfg = ControlFlowGraph::new(&program);
+        assert!(vec![0, 3, 5] == cfg.blocks().map(|b| b.start).collect_vec());
+        assert!(vec![(3, EdgeKind::Next), (5, EdgeKind::Skip)] == edges(&cfg, 0));
+        assert!(vec![(5, EdgeKind::Next)] == edges(&cfg, 3));
+    }
+
+    #[test]
+    fn call_leads_to_subroutine_and_return_site() {
+        let program = triton_program!(
+            call foo halt
+            foo: return
+        );
+        let cfg = ControlFlowGraph::new(&program);
+        assert!(vec![(3, EdgeKind::Call), (2, EdgeKind::ReturnSite)] == edges(&cfg, 0));
+        assert!(edges(&cfg, 3).is_empty());
+        assert!("foo" == cfg.block(3).unwrap().label);
+
+        let main = cfg.subroutine(0).unwrap();
+        assert!(BTreeSet::from([0, 2]) == main.blocks);
+        let foo = cfg.subroutine(3).unwrap();
+        assert!(BTreeSet::from([3]) == foo.blocks);
+    }
+
+    #[test]
+    fn recurse_leads_back_to_subroutine_entry() {
+        let program = triton_program!(
+            push 3 call loop halt
+            loop:
+                push -1 add dup 0 skiz recurse
+                return
+        );
+        let cfg = ControlFlowGraph::new(&program);
+        let loop_entry = 5;
+        assert!(vec![(11, EdgeKind::Next), (12, EdgeKind::Skip)] == edges(&cfg, loop_entry));
+        assert!(vec![(loop_entry, EdgeKind::Recurse)] == edges(&cfg, 11));
+        assert!(BTreeSet::from([5, 11, 12]) == cfg.subroutine(loop_entry).unwrap().blocks);
+    }
+
+    #[test]
+    fn recurse_or_return_leads_to_every_subroutine_sharing_the_block() {
+        let program = triton_program!(
+            call a call b halt
+            a: push 1 pop 1
+            b: recurse_or_return
+        );
+        let cfg = ControlFlowGraph::new(&program);
+        let recursion_targets = edges(&cfg, 9);
+        assert!(vec![(5, EdgeKind::Recurse), (9, EdgeKind::Recurse)] == recursion_targets);
+    }
+
+    #[test]
+    fn call_graph_knows_callers_and_callees() {
+        let program = triton_program!(
+            call a call b halt
+            a: call b return
+            b: return
+        );
+        let call_graph = CallGraph::new(&program);
+        assert!(vec![0, 5, 8] == call_graph.subroutines().collect_vec());
+        assert!(vec![5, 8] == call_graph.callees(0).collect_vec());
+        assert!(vec![8] == call_graph.callees(5).collect_vec());
+        assert!(call_graph.callees(8).next().is_none());
+        assert!(vec![0, 5] == call_graph.callers(8).collect_vec());
+    }
+
+    #[test]
+    fn graphs_can_be_rendered_as_dot() {
+        let program = triton_program!(
+            call foo halt
+            foo: push 1 skiz recurse return
+        );
+        let cfg = ControlFlowGraph::new(&program);
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph control_flow {"));
+        assert!(dot.contains(r#"3 [label="foo:\l  push 1\l  skiz\l"];"#));
+        assert!(dot.contains(r#"0 -> 3 [label="call", style=dashed];"#));
+        assert!(dot.contains(r#"6 -> 3 [label="recurse", style=dotted];"#));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let dot = cfg.call_graph().to_dot();
+        assert!(dot.contains(r#"3 [label="foo"];"#));
+        assert!(dot.contains("0 -> 3;"));
+    }
+}
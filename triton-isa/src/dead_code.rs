@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::control_flow::ControlFlowGraph;
+use crate::program::Program;
+
+/// The result of [`eliminate_dead_code`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeadCodeElimination {
+    /// The program without dead code.
+    pub program: Program,
+
+    /// Maps the address of every instruction that was kept to its address in the new
+    /// [program](Self::program). Addresses of removed instructions are not in the map.
+    pub address_map: BTreeMap<u64, u64>,
+}
+
+impl DeadCodeElimination {
+    /// The address in the new program of the instruction at the given address in the
+    /// original program, or `None` if that instruction was removed.
+    pub fn new_address(&self, old_address: u64) -> Option<u64> {
+        self.address_map.get(&old_address).copied()
+    }
+
+    /// The number of words by which the program shrunk.
+    pub fn num_removed_words(&self, original: &Program) -> usize {
+        original.len_bwords() - self.program.len_bwords()
+    }
+}
+
+/// Remove all instructions from the given program that can never be executed, like
+/// subroutines that are never called, or code following a `halt` or `return` that is
+/// not jumped to.
+///
+/// An instruction can be executed if its [basic block](crate::control_flow::BasicBlock)
+/// is reachable from address 0 in the program's [control-flow graph](ControlFlowGraph).
+/// The remaining instructions are moved to close the gaps, and the arguments of `call`
+/// instructions are updated accordingly. Labels, breakpoints, type hints, assertion
+/// contexts, and source locations move with their instructions; the
+/// [address map](DeadCodeElimination::address_map) can be used to relocate any other
+/// address-based information.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use triton_isa::dead_code::eliminate_dead_code;
+/// let program = triton_program!(
+///     call used halt
+///     unused: push 1 return
+///     used: push 2 return
+/// );
+/// let elimination = eliminate_dead_code(&program);
+/// assert_eq!(triton_program!(call used halt used: push 2 return), elimination.program);
+/// assert_eq!(Some(3), elimination.new_address(6));
+/// assert_eq!(None, elimination.new_address(3));
+/// ```
+pub fn eliminate_dead_code(program: &Program) -> DeadCodeElimination {
+    let cfg = ControlFlowGraph::new(program);
+
+    let mut live_blocks = BTreeSet::new();
+    let mut work_list = cfg.block(0).into_iter().collect::<Vec<_>>();
+    while let Some(block) = work_list.pop() {
+        if !live_blocks.insert(block.start) {
+            continue;
+        }
+        let successors = block.successors.iter();
+        work_list.extend(successors.filter_map(|edge| cfg.block(edge.target)));
+    }
+
+    let mut address_map = BTreeMap::new();
+    let mut new_address = 0;
+    for start in live_blocks {
+        let mut old_address = start;
+        for instruction in &cfg.block(start).unwrap().instructions {
+            address_map.insert(old_address, new_address);
+            old_address += instruction.size() as u64;
+            new_address += instruction.size() as u64;
+        }
+    }
+
+    let program = program.relocated(&address_map);
+    DeadCodeElimination {
+        program,
+        address_map,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use twenty_first::prelude::*;
+
+    use crate::instruction::Instruction;
+    use crate::triton_program;
+
+    use super::*;
+
+    #[test]
+    fn program_without_dead_code_is_unchanged() {
+        let program = triton_program!(
+            push 3 call countdown halt
+            countdown:
+                dup 0 push 0 eq skiz return
+                push -1 add recurse
+        );
+        let elimination = eliminate_dead_code(&program);
+        assert!(program == elimination.program);
+        assert!(0 == elimination.num_removed_words(&program));
+        for (old_address, new_address) in elimination.address_map {
+            assert!(old_address == new_address);
+        }
+    }
+
+    #[test]
+    fn code_after_halt_is_removed() {
+        let program = triton_program!(push 1 halt push 2 pop 1 halt);
+        let elimination = eliminate_dead_code(&program);
+        assert!(triton_program!(push 1 halt) == elimination.program);
+        assert!(5 == elimination.num_removed_words(&program));
+    }
+
+    #[test]
+    fn uncalled_subroutines_are_removed() {
+        let program = triton_program!(
+            call foo halt
+            bar: call baz return
+            foo: push 1 return
+            baz: push 2 return
+        );
+        let elimination = eliminate_dead_code(&program);
+        let expected = triton_program!(call foo halt foo: push 1 return);
+        assert!(expected == elimination.program);
+        assert!("foo" == elimination.program.label_for_address(3));
+
+        let code = elimination.program.to_string();
+        assert!(!code.contains("bar"));
+        assert!(!code.contains("baz"));
+    }
+
+    #[test]
+    fn skipped_instruction_is_kept() {
+        let program = triton_program!(push 0 skiz halt push 1 halt);
+        let elimination = eliminate_dead_code(&program);
+        assert!(program == elimination.program);
+    }
+
+    #[test]
+    fn debug_information_is_relocated() {
+        let program = triton_program!(
+            call foo halt
+            bar: push 1 return
+            foo:
+                hint x = stack[0]
+                break
+                push 0 assert error_id 42
+                return
+        );
+        let elimination = eliminate_dead_code(&program);
+        let program = elimination.program;
+
+        assert!(program.is_breakpoint(3));
+        assert!(program.is_breakpoint(4));
+        assert!(!program.is_breakpoint(5));
+        assert!(1 == program.type_hints_at(3).len());
+        assert!(let Some(_) = program.assertion_context_at(5));
+    }
+
+    #[test]
+    fn call_arguments_are_relocated() {
+        let program = triton_program!(
+            call foo halt
+            bar: push 1 push 2 return
+            foo: call baz return
+            baz: return
+        );
+        let elimination = eliminate_dead_code(&program);
+        let instructions = elimination.program.instructions;
+        assert!(Instruction::Call(bfe!(3)) == instructions[0]);
+        assert!(Instruction::Call(bfe!(6)) == instructions[3]);
+    }
+}
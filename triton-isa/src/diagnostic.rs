@@ -0,0 +1,667 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::ops::Range;
+
+use itertools::Itertools;
+use nom::error::VerboseError;
+use nom::error::VerboseErrorKind;
+use nom::Offset;
+use strum::Display;
+
+use crate::instruction::AnInstruction;
+use crate::instruction::ALL_INSTRUCTIONS;
+use crate::instruction::ALL_INSTRUCTION_NAMES;
+use crate::parser;
+use crate::parser::InstructionToken;
+use crate::parser::ParseError;
+use crate::parser::ParseFailure;
+use crate::program::SourceLocation;
+
+/// How severe a [`Diagnostic`] is.
+#[derive(Debug, Display, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum Severity {
+    /// The code cannot be turned into a [`Program`](crate::program::Program).
+    Error,
+
+    /// The code is valid but likely not what was intended.
+    Warning,
+}
+
+/// What kind of problem a [`Diagnostic`] describes.
+#[non_exhaustive]
+#[derive(Debug, Display, Copy, Clone, Eq, PartialEq, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A statement that cannot be parsed, for example, an unknown instruction.
+    Syntax,
+
+    /// A `call` to a label that is not defined.
+    MissingLabel,
+
+    /// A label that is defined more than once.
+    DuplicateLabel,
+
+    /// An [assertion context](crate::instruction::AssertionContext) that does not
+    /// follow an assertion.
+    MisplacedAssertionContext,
+
+    /// A [data section](crate::instruction::DataSection) that overlaps another one.
+    OverlappingDataSections,
+
+    /// A relative `call` whose target is outside the code.
+    RelativeCallOutOfRange,
+
+    /// A label that is never called.
+    UnusedLabel,
+}
+
+/// A problem in Triton assembly source code. See [`diagnose`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    pub kind: DiagnosticKind,
+
+    /// The byte range of the problematic code in the source code.
+    pub span: Range<usize>,
+
+    pub message: String,
+
+    /// A change to the source code that likely resolves the problem, if one is known.
+    pub fix: Option<Fix>,
+}
+
+/// A suggested change to source code, resolving some [`Diagnostic`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Fix {
+    /// A human-readable description of the fix, for example, “did you mean `halt`?”
+    pub description: String,
+
+    /// The text that should replace the [span](Diagnostic::span) of the diagnostic.
+    pub replacement: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(fix) = &self.fix {
+            write!(f, " ({})", fix.description)?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    fn error(kind: DiagnosticKind, span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind,
+            span,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn syntax_error(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self::error(DiagnosticKind::Syntax, span, message)
+    }
+
+    fn with_fix(mut self, fix: Option<Fix>) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    /// The location of the start of the diagnostic's span in the given source code.
+    pub fn source_location(&self, source: &str) -> SourceLocation {
+        let preceding_source = &source[..self.span.start];
+        let line = preceding_source.matches('\n').count() + 1;
+        let line_start = preceding_source.rfind('\n').map_or(0, |index| index + 1);
+        let column = preceding_source[line_start..].chars().count() + 1;
+
+        SourceLocation {
+            file: None,
+            line,
+            column,
+        }
+    }
+}
+
+impl Fix {
+    fn did_you_mean(replacement: &str) -> Self {
+        Self {
+            description: format!("did you mean `{replacement}`?"),
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+/// Find all problems in the given Triton assembly source code in one pass.
+///
+/// Unlike [parsing](crate::parser::parse), diagnosing does not stop at the first
+/// error. Statements that cannot be parsed are skipped, and the remaining code is
+/// checked as usual. Among other things, this finds unknown instructions, illegal
+/// instruction arguments, missing and duplicate labels, misplaced
+/// [assertion contexts](crate::instruction::AssertionContext), overlapping
+/// [data sections](crate::instruction::DataSection), and relative calls that leave the
+/// code. If the code starts with
+/// [module directives](crate::linker::Linker), calls to labels that are not defined in
+/// the code are assumed to refer to other modules.
+///
+/// The diagnostics are sorted by their position in the source code.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::diagnostic::diagnose;
+/// let code = "push 1 recurse_or_retrun pop 6 halt";
+/// let diagnostics = diagnose(code);
+/// assert_eq!(2, diagnostics.len());
+///
+/// let fix = diagnostics[0].fix.as_ref().unwrap();
+/// assert_eq!(7..24, diagnostics[0].span);
+/// assert_eq!("recurse_or_return", fix.replacement);
+///
+/// let message = &diagnostics[1].message;
+/// assert_eq!(29..30, diagnostics[1].span);
+/// assert_eq!("illegal argument for `pop`: expected 1, 2, 3, 4, or 5", message);
+/// ```
+pub fn diagnose(code: &str) -> Vec<Diagnostic> {
+    let (module, failures) = parser::parse_module_with_recovery(code);
+    let tokens = module.instructions;
+
+    let mut diagnostics = failures
+        .into_iter()
+        .map(|(statement, failure)| diagnose_failure(code, statement, failure))
+        .collect_vec();
+
+    let labels = tokens
+        .iter()
+        .filter_map(|token| match token {
+            InstructionToken::Label(label, _) => Some(label.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let is_module = !module.directives.is_empty();
+    if let Err(err) = parser::ensure_no_missing_or_duplicate_labels(code, &tokens) {
+        diagnostics.extend(diagnose_label_errors(code, err, &labels, is_module));
+    }
+    if let Err(err) = parser::ensure_assertion_context_is_matched_with_assertion(code, &tokens) {
+        diagnostics.extend(diagnose_misplaced_assertion_contexts(code, err));
+    }
+    if let Err(err) = parser::ensure_data_sections_are_disjoint(code, &tokens) {
+        diagnostics.extend(diagnose_overlapping_data_sections(code, err));
+    }
+    if let Err(err) = parser::ensure_relative_calls_are_in_range(code, &tokens) {
+        diagnostics.extend(diagnose_relative_calls_out_of_range(code, err));
+    }
+    if !is_module {
+        diagnostics.extend(diagnose_unused_labels(code, &tokens));
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.span.start, diagnostic.span.end));
+    diagnostics
+}
+
+fn diagnose_failure(code: &str, statement: &str, failure: ParseFailure) -> Diagnostic {
+    // Failures from which the parser cannot recover carry a description of the problem.
+    // Errors of the various alternatives the parser tried are not helpful, since they
+    // only explain why the last of these alternatives did not match.
+    let is_unrecoverable = matches!(failure, nom::Err::Failure(_));
+    let (nom::Err::Error(error) | nom::Err::Failure(error)) = failure else {
+        return Diagnostic::syntax_error(span_of_word(code, statement), "incomplete input");
+    };
+    let innermost_context = error.errors.iter().find_map(|(location, kind)| match kind {
+        VerboseErrorKind::Context(context) => Some((*location, *context)),
+        _ => None,
+    });
+    if let (true, Some((location, context))) = (is_unrecoverable, innermost_context) {
+        // The body of a control-flow construct ends at the first statement that cannot be
+        // parsed. Diagnosing that statement is more helpful than expecting the body's end.
+        let is_in_body = context.starts_with("expecting `");
+        if is_in_body && !word(location).is_empty() {
+            let error = VerboseError { errors: vec![] };
+            return diagnose_failure(code, location, nom::Err::Error(error));
+        }
+
+        let is_at_end_of_statement = word(location).is_empty();
+        let location = if is_at_end_of_statement {
+            statement
+        } else {
+            location
+        };
+        return Diagnostic::syntax_error(span_of_word(code, location), context);
+    }
+
+    let statement_word = word(statement);
+    let (next_word, _) = words_after(statement);
+    let is_label = statement_word.ends_with(':') || next_word.starts_with(':');
+    if is_label {
+        let message = "label must start with a letter or underscore";
+        return Diagnostic::syntax_error(span_of_word(code, statement), message);
+    }
+    if let Some(expectation) = argument_expectation(statement_word) {
+        let (argument, argument_location) = words_after(statement);
+        let message = format!("illegal argument for `{statement_word}`: expected {expectation}");
+        if argument.is_empty() {
+            let message = format!("missing argument for `{statement_word}`");
+            return Diagnostic::syntax_error(span_of_word(code, statement), message);
+        }
+        return Diagnostic::syntax_error(span_of_word(code, argument_location), message);
+    }
+
+    let message = match statement_word {
+        "hint" => "malformed type hint".to_string(),
+        "const" => "malformed constant definition".to_string(),
+        "data" => "malformed data section".to_string(),
+        "macro" => "malformed macro definition".to_string(),
+        "endmacro" => "`endmacro` without matching `macro`".to_string(),
+        "else" | "endif" => format!("`{statement_word}` without matching `if`"),
+        "endwhile" => "`endwhile` without matching `while`".to_string(),
+        "endfor" => "`endfor` without matching `for`".to_string(),
+        "import" | "include" => "module directives must precede all instructions".to_string(),
+        "error_id" | "error_message" => "malformed assertion context".to_string(),
+        _ if statement_word.contains('!') => "malformed macro invocation".to_string(),
+        _ => format!("unknown instruction `{statement_word}`"),
+    };
+    let known_words = ALL_INSTRUCTION_NAMES
+        .into_iter()
+        .chain(parser::KEYWORDS)
+        .chain(parser::CONTROL_FLOW_KEYWORDS);
+    let fix = closest_match(statement_word, known_words).map(Fix::did_you_mean);
+    let fix = fix.filter(|_| message.starts_with("unknown"));
+
+    Diagnostic::syntax_error(span_of_word(code, statement), message).with_fix(fix)
+}
+
+/// What an argument for the given instruction needs to look like, if the instruction
+/// takes an argument.
+fn argument_expectation(instruction_name: &str) -> Option<&'static str> {
+    let instruction = ALL_INSTRUCTIONS
+        .into_iter()
+        .find(|instruction| instruction.name() == instruction_name)?;
+    let expectation = match instruction {
+        AnInstruction::Push(_) | AnInstruction::AddI(_) => "a field element",
+        AnInstruction::Call(_) => "a label",
+        AnInstruction::Pick(_)
+        | AnInstruction::Place(_)
+        | AnInstruction::Dup(_)
+        | AnInstruction::Swap(_) => "a stack register from 0 to 15",
+        AnInstruction::Pop(_)
+        | AnInstruction::Divine(_)
+        | AnInstruction::ReadMem(_)
+        | AnInstruction::WriteMem(_)
+        | AnInstruction::ReadIo(_)
+        | AnInstruction::WriteIo(_) => "1, 2, 3, 4, or 5",
+        _ => return None,
+    };
+    Some(expectation)
+}
+
+/// Turn errors about missing or duplicate labels into diagnostics. Labels that are
+/// missing from a module might be defined in other modules and are not diagnosed.
+fn diagnose_label_errors<'a>(
+    code: &'a str,
+    err: ParseError<'a>,
+    labels: &'a HashSet<&'a str>,
+    is_module: bool,
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    err.errors
+        .errors
+        .into_iter()
+        .filter_map(move |(token_str, kind)| {
+            if kind != VerboseErrorKind::Context("missing label") {
+                let label = word(token_str).trim_end_matches(':');
+                let span = span_of(code, &token_str[..label.len()]);
+                let message = format!("duplicate label `{label}`");
+                let diagnostic = Diagnostic::error(DiagnosticKind::DuplicateLabel, span, message);
+                return Some(diagnostic);
+            }
+            if is_module {
+                return None;
+            }
+
+            let (label, label_location) = words_after(token_str);
+            let span = span_of_word(code, label_location);
+            let message = format!("missing label `{label}`");
+            let fix = closest_match(label, labels.iter().copied()).map(Fix::did_you_mean);
+            let diagnostic = Diagnostic::error(DiagnosticKind::MissingLabel, span, message);
+            Some(diagnostic.with_fix(fix))
+        })
+}
+
+fn diagnose_misplaced_assertion_contexts<'a>(
+    code: &'a str,
+    err: ParseError<'a>,
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    err.errors.errors.into_iter().map(|(token_str, _)| {
+        let (_, argument_location) = words_after(token_str);
+        let start = code.offset(token_str);
+        let end = code.offset(argument_location) + word(argument_location).len();
+        let message = "assertion context must immediately follow `assert` or `assert_vector`";
+        let fix = Fix {
+            description: "remove the assertion context".to_string(),
+            replacement: String::new(),
+        };
+        let kind = DiagnosticKind::MisplacedAssertionContext;
+        Diagnostic::error(kind, start..end, message).with_fix(Some(fix))
+    })
+}
+
+fn diagnose_overlapping_data_sections<'a>(
+    code: &'a str,
+    err: ParseError<'a>,
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    err.errors.errors.into_iter().map(|(token_str, _)| {
+        let (name, name_location) = words_after(token_str);
+        let start = code.offset(token_str);
+        let end = code.offset(name_location) + name.trim_end_matches(':').len();
+        let message = "data section overlaps a preceding data section";
+        Diagnostic::error(DiagnosticKind::OverlappingDataSections, start..end, message)
+    })
+}
+
+fn diagnose_relative_calls_out_of_range<'a>(
+    code: &'a str,
+    err: ParseError<'a>,
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    err.errors.errors.into_iter().map(|(token_str, _)| {
+        let (target, target_location) = words_after(token_str);
+        let span = span_of_word(code, target_location);
+        let message = format!("relative call target `{target}` is out of range");
+        Diagnostic::error(DiagnosticKind::RelativeCallOutOfRange, span, message)
+    })
+}
+
+fn diagnose_unused_labels<'a>(
+    code: &'a str,
+    tokens: &'a [InstructionToken],
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    let called_labels = tokens
+        .iter()
+        .filter_map(|token| match token {
+            InstructionToken::Instruction(AnInstruction::Call(label), _) => Some(label),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    tokens.iter().filter_map(move |token| {
+        let InstructionToken::Label(label, token_str) = token else {
+            return None;
+        };
+        if called_labels.contains(label) {
+            return None;
+        }
+        let span = span_of(code, &token_str[..label.len()]);
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            kind: DiagnosticKind::UnusedLabel,
+            span,
+            message: format!("label `{label}` is never called"),
+            fix: None,
+        };
+        Some(diagnostic)
+    })
+}
+
+/// The word at the start of the given string.
+fn word(s: &str) -> &str {
+    &s[..parser::word_length(s)]
+}
+
+/// The word following the word at the start of the given string, skipping whitespace and
+/// comments, as well as the rest of the string starting at that word.
+fn words_after(s: &str) -> (&str, &str) {
+    let mut rest = s[word(s).len()..].trim_start();
+    while rest.starts_with("//") {
+        let comment_end = rest.find(['\r', '\n']).unwrap_or(rest.len());
+        rest = rest[comment_end..].trim_start();
+    }
+    (word(rest), rest)
+}
+
+fn span_of_word(code: &str, s: &str) -> Range<usize> {
+    span_of(code, word(s))
+}
+
+fn span_of(code: &str, s: &str) -> Range<usize> {
+    let start = code.offset(s);
+    start..start + s.len()
+}
+
+/// The candidate that is most similar to the given word, if any is similar enough.
+fn closest_match<'c>(word: &str, candidates: impl IntoIterator<Item = &'c str>) -> Option<&'c str> {
+    let max_distance = (word.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The [Levenshtein distance](https://en.wikipedia.org/wiki/Levenshtein_distance)
+/// between the two given strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect_vec();
+    let mut distances = (0..=b.len()).collect_vec();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != b_char);
+            diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(diagonal + 1);
+        }
+    }
+    distances[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use proptest::prelude::*;
+    use proptest_arbitrary_interop::arb;
+    use test_strategy::proptest;
+
+    use crate::program::Program;
+
+    use super::*;
+
+    fn errors(code: &str) -> Vec<Diagnostic> {
+        let diagnostics = diagnose(code).into_iter();
+        diagnostics
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect()
+    }
+
+    fn spanned_code<'c>(code: &'c str, diagnostic: &Diagnostic) -> &'c str {
+        &code[diagnostic.span.clone()]
+    }
+
+    #[test]
+    fn valid_code_has_no_diagnostics() {
+        let code = "
+            call foo halt
+            foo:
+                hint x: u32 = stack[0]
+                push 1 assert error_id 7
+                return
+        ";
+        assert!(diagnose(code).is_empty());
+    }
+
+    #[test]
+    fn all_errors_are_found_in_one_pass() {
+        let code = "push 1 foo swap 16 call bar assert_vector\nerror_id 3 nop error_id 4 halt";
+        let diagnostics = errors(code);
+        let spans = diagnostics
+            .iter()
+            .map(|d| spanned_code(code, d))
+            .collect_vec();
+        assert!(vec!["foo", "16", "bar", "error_id 4"] == spans);
+    }
+
+    #[test]
+    fn unknown_instruction_comes_with_suggestion() {
+        let code = "push 0 recurse_or_retrun";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("recurse_or_retrun" == spanned_code(code, diagnostic));
+        assert!(diagnostic.message.contains("unknown instruction"));
+        assert!(DiagnosticKind::Syntax == diagnostic.kind);
+
+        let_assert!(Some(fix) = &diagnostic.fix);
+        assert!("recurse_or_return" == fix.replacement);
+        assert!("did you mean `recurse_or_return`?" == fix.description);
+    }
+
+    #[test]
+    fn unknown_instruction_without_similar_instruction_has_no_suggestion() {
+        let diagnostics = errors("push 0 frobnicate");
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!(diagnostic.fix.is_none());
+    }
+
+    #[test]
+    fn errors_in_bodies_of_control_flow_constructs_are_diagnosed() {
+        let code = "if push 1 recurse_or_retrun endif halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("recurse_or_retrun" == spanned_code(code, diagnostic));
+        let_assert!(Some(fix) = &diagnostic.fix);
+        assert!("recurse_or_return" == fix.replacement);
+    }
+
+    #[test]
+    fn unmatched_ends_of_control_flow_constructs_are_diagnosed() {
+        let code = "while push 0 endif halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("endif" == spanned_code(code, diagnostic));
+        assert!("`endif` without matching `if`" == diagnostic.message);
+    }
+
+    #[test]
+    fn illegal_and_missing_arguments_are_diagnosed() {
+        let code = "dup 17 push";
+        let diagnostics = errors(code);
+        let_assert!([illegal, missing] = diagnostics.as_slice());
+        assert!("17" == spanned_code(code, illegal));
+        assert!(illegal.message.contains("stack register"));
+        assert!("push" == spanned_code(code, missing));
+        assert!(missing.message.contains("missing argument"));
+    }
+
+    #[test]
+    fn missing_label_comes_with_suggestion() {
+        let code = "call subroutine halt subrutine: return";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("subroutine" == spanned_code(code, diagnostic));
+        assert!(DiagnosticKind::MissingLabel == diagnostic.kind);
+        let_assert!(Some(fix) = &diagnostic.fix);
+        assert!("subrutine" == fix.replacement);
+    }
+
+    #[test]
+    fn duplicate_labels_are_diagnosed() {
+        let code = "call foo halt foo: return foo: return";
+        let diagnostics = errors(code);
+        assert!(2 == diagnostics.len());
+        for diagnostic in diagnostics {
+            assert!("foo" == spanned_code(code, &diagnostic));
+            assert!(DiagnosticKind::DuplicateLabel == diagnostic.kind);
+        }
+    }
+
+    #[test]
+    fn missing_labels_in_modules_are_not_diagnosed() {
+        assert!(errors("import lib\ncall lib::foo halt").is_empty());
+    }
+
+    #[test]
+    fn relative_call_out_of_range_is_diagnosed() {
+        let code = "call +2 call -3 halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("-3" == spanned_code(code, diagnostic));
+        assert!(DiagnosticKind::RelativeCallOutOfRange == diagnostic.kind);
+        assert!(diagnostic.message.contains("out of range"));
+    }
+
+    #[test]
+    fn misplaced_assertion_context_can_be_removed() {
+        let code = "push 1 pop 1 error_message \"oops\" halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("error_message \"oops\"" == spanned_code(code, diagnostic));
+        assert!(DiagnosticKind::MisplacedAssertionContext == diagnostic.kind);
+        let_assert!(Some(fix) = &diagnostic.fix);
+        assert!(fix.replacement.is_empty());
+    }
+
+    #[test]
+    fn unused_label_is_a_warning() {
+        let diagnostics = diagnose("halt unused: return");
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!(Severity::Warning == diagnostic.severity);
+        assert!(DiagnosticKind::UnusedLabel == diagnostic.kind);
+        assert!("warning: label `unused` is never called" == diagnostic.to_string());
+    }
+
+    #[test]
+    fn unparsable_macro_definition_is_skipped_entirely() {
+        let code = "macro foo(x, x) push x endmacro halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("macro" == spanned_code(code, diagnostic));
+        assert!("duplicate macro parameter" == diagnostic.message);
+    }
+
+    #[test]
+    fn error_in_macro_expansion_is_diagnosed() {
+        let code = "macro foo push 1 pop 1 endmacro\nfoo! bar! halt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        assert!("bar!" == spanned_code(code, diagnostic));
+        assert!("unknown macro" == diagnostic.message);
+    }
+
+    #[test]
+    fn source_location_of_diagnostic_is_correct() {
+        let code = "push 1\n  push 2\n  ad\nhalt";
+        let diagnostics = errors(code);
+        let_assert!([diagnostic] = diagnostics.as_slice());
+        let location = diagnostic.source_location(code);
+        assert!((3, 3) == (location.line, location.column));
+    }
+
+    #[test]
+    fn edit_distance_is_computed_correctly() {
+        assert!(0 == edit_distance("halt", "halt"));
+        assert!(1 == edit_distance("hal", "halt"));
+        assert!(1 == edit_distance("hxlt", "halt"));
+        assert!(3 == edit_distance("kitten", "sitting"));
+        assert!(4 == edit_distance("", "halt"));
+    }
+
+    #[proptest]
+    fn code_of_arbitrary_program_has_no_errors(#[strategy(arb())] program: Program) {
+        prop_assert!(errors(&program.to_string()).is_empty());
+    }
+
+    #[proptest]
+    fn code_has_errors_if_and_only_if_parsing_fails(
+        #[strategy(arb())] program: Program,
+        #[strategy(prop_oneof!["foo", "pop 0", "dup 16", "call missing", "error_id 1"])]
+        suffix: String,
+    ) {
+        let code = format!("{program}\n{suffix}\n");
+        let parsing_fails = Program::from_code(&code).is_err();
+        prop_assert_eq!(parsing_fails, !errors(&code).is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::fmt::Write;
+
+use itertools::Itertools;
+use thiserror::Error;
+use twenty_first::prelude::*;
+
+use crate::formatter;
+use crate::instruction::Instruction;
+use crate::instruction::LabelledInstruction;
+use crate::program::Program;
+use crate::program::ProgramDecodingError;
+
+/// The label of the code starting at address 0.
+const ENTRY_POINT_LABEL: &str = "main";
+
+/// A [`Program`] recovered from its [bwords](Program::to_bwords) or its
+/// [encoding](BFieldCodec::encode), together with the structure of its code.
+///
+/// Encoded programs contain neither labels nor any other debug information. The
+/// disassembler recovers the boundaries of subroutines from the targets of `call`
+/// instructions: every call target starts a subroutine, which ends where the next
+/// subroutine starts. The code starting at address 0 is labelled `main`, and the other
+/// subroutines are labelled `subroutine_0`, `subroutine_1`, … in order of their addresses.
+/// Since the labels only depend on the structure of the program, disassembling the same
+/// program always gives the same labels. Encoded programs do not contain
+/// [data sections](Program::data_sections), but their
+/// [data prologue](Program::len_data_prologue) is disassembled like any other code.
+///
+/// The [`Display`] implementation produces formatted Triton assembly annotated with the
+/// addresses of all instructions. Parsing it gives back the original program, _i.e._, a
+/// program with the same [hash](Program::hash).
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use triton_isa::disassembler::Disassembly;
+/// # use triton_isa::program::Program;
+/// let program = triton_program!(push 5 call double halt double: dup 0 add return);
+/// let disassembly = Disassembly::from_bwords(&program.to_bwords())?;
+/// let expected = "\
+/// // main: addresses 0..5
+/// main:
+///     push 5            // 0
+///     call subroutine_0 // 2
+///     halt              // 4
+///
+/// // subroutine_0: addresses 5..9, called from 2
+/// subroutine_0:
+///     dup 0  // 5
+///     add    // 7
+///     return // 8
+/// ";
+/// assert_eq!(expected, disassembly.to_string());
+///
+/// let reassembled = Program::from_code(&disassembly.to_string()).unwrap();
+/// assert_eq!(program.hash(), reassembled.hash());
+/// # Ok::<(), triton_isa::disassembler::DisassemblyError>(())
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Disassembly {
+    /// The disassembled program, labelled according to its [subroutines](Self::subroutines).
+    pub program: Program,
+
+    /// The subroutines of the program, sorted by address. The first one is the entry point.
+    pub subroutines: Vec<Subroutine>,
+}
+
+/// A contiguous part of a disassembled program that starts at a `call` target, or at
+/// address 0.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Subroutine {
+    /// The label of the subroutine's first instruction.
+    pub label: String,
+
+    /// The address of the subroutine's first instruction.
+    pub start: u64,
+
+    /// The address following the subroutine's last instruction.
+    pub end: u64,
+
+    /// The addresses of all `call` instructions targeting this subroutine, sorted.
+    pub call_sites: Vec<u64>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum DisassemblyError {
+    #[error(transparent)]
+    Decoding(#[from] ProgramDecodingError),
+
+    #[error(
+        "`call` at address {call_site} targets address {target}, \
+        which is not the start of an instruction"
+    )]
+    UnlabelableCallTarget { call_site: u64, target: u64 },
+}
+
+impl Display for Subroutine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: addresses {}..{}", self.label, self.start, self.end)?;
+        if !self.call_sites.is_empty() {
+            write!(f, ", called from {}", self.call_sites.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let mut code = String::new();
+        for subroutine in &self.subroutines {
+            writeln!(code, "\n// {subroutine}\n{}:", subroutine.label)?;
+            let mut address = subroutine.start;
+            while address < subroutine.end {
+                let instruction = self.program.instructions[address as usize];
+                let instruction = instruction
+                    .map_call_address(|target| self.program.label_for_address(target.value()));
+                writeln!(code, "{instruction} // {address}")?;
+                address += instruction.size() as u64;
+            }
+        }
+
+        let code = formatter::format(&code).map_err(|_| fmt::Error)?;
+        write!(f, "{code}")
+    }
+}
+
+impl Disassembly {
+    /// Disassemble a program given as a sequence of [bwords](Program::to_bwords).
+    pub fn from_bwords(bwords: &[BFieldElement]) -> Result<Self, DisassemblyError> {
+        let encoding = [vec![bfe!(bwords.len() as u64)], bwords.to_vec()].concat();
+        Self::from_encoding(&encoding)
+    }
+
+    /// Disassemble a program given in its [encoding](BFieldCodec::encode).
+    pub fn from_encoding(encoding: &[BFieldElement]) -> Result<Self, DisassemblyError> {
+        let program = Program::decode(encoding)?;
+        Self::disassemble(&program.instructions)
+    }
+
+    fn disassemble(instructions: &[Instruction]) -> Result<Self, DisassemblyError> {
+        let mut instruction_starts = BTreeSet::new();
+        let mut call_sites = BTreeMap::<_, Vec<_>>::new();
+        let mut address = 0;
+        while let Some(instruction) = instructions.get(address as usize) {
+            instruction_starts.insert(address);
+            if let Instruction::Call(target) = instruction {
+                call_sites.entry(target.value()).or_default().push(address);
+            }
+            address += instruction.size() as u64;
+        }
+        instruction_starts.insert(address);
+
+        for (&target, sites) in &call_sites {
+            if !instruction_starts.contains(&target) {
+                let call_site = sites[0];
+                return Err(DisassemblyError::UnlabelableCallTarget { call_site, target });
+            }
+        }
+
+        let mut subroutine_starts = call_sites.keys().copied().collect::<BTreeSet<_>>();
+        subroutine_starts.insert(0);
+        let subroutine_ends = subroutine_starts.iter().skip(1).copied().chain([address]);
+        let subroutines = subroutine_starts
+            .iter()
+            .zip(subroutine_ends)
+            .enumerate()
+            .map(|(i, (&start, end))| Subroutine {
+                label: match start {
+                    0 => ENTRY_POINT_LABEL.to_string(),
+                    _ => format!("subroutine_{}", i - 1),
+                },
+                start,
+                end,
+                call_sites: call_sites.get(&start).cloned().unwrap_or_default(),
+            })
+            .collect_vec();
+
+        let label_for_address = |address: u64| {
+            let subroutine = subroutines.iter().find(|s| s.start == address);
+            subroutine.map(|s| s.label.clone()).unwrap_or_default()
+        };
+        let mut labelled_instructions = vec![];
+        for subroutine in &subroutines {
+            let label = LabelledInstruction::Label(subroutine.label.clone());
+            labelled_instructions.push(label);
+            let mut address = subroutine.start;
+            while address < subroutine.end {
+                let instruction = instructions[address as usize];
+                let labelled_instruction =
+                    instruction.map_call_address(|target| label_for_address(target.value()));
+                labelled_instructions.push(LabelledInstruction::Instruction(labelled_instruction));
+                address += instruction.size() as u64;
+            }
+        }
+
+        let program = Program::new(&labelled_instructions);
+        Ok(Self {
+            program,
+            subroutines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use proptest::prelude::*;
+    use proptest_arbitrary_interop::arb;
+    use test_strategy::proptest;
+
+    use crate::triton_program;
+
+    use super::*;
+
+    #[test]
+    fn subroutines_are_recovered_from_call_targets() {
+        let program = triton_program!(
+            call foo call bar halt
+            foo: push 1 call bar return
+            bar: push 2 return
+        );
+        let_assert!(Ok(disassembly) = Disassembly::from_bwords(&program.to_bwords()));
+        let subroutines = disassembly.subroutines;
+        assert!(3 == subroutines.len());
+
+        assert!("main" == subroutines[0].label);
+        assert!((0, 5) == (subroutines[0].start, subroutines[0].end));
+        assert!(subroutines[0].call_sites.is_empty());
+
+        assert!("subroutine_0" == subroutines[1].label);
+        assert!((5, 10) == (subroutines[1].start, subroutines[1].end));
+        assert!(vec![0] == subroutines[1].call_sites);
+
+        assert!("subroutine_1" == subroutines[2].label);
+        assert!((10, 13) == (subroutines[2].start, subroutines[2].end));
+        assert!(vec![2, 7] == subroutines[2].call_sites);
+    }
+
+    #[test]
+    fn disassembly_is_annotated_with_addresses_and_subroutines() {
+        let program = triton_program!(push 5 call double halt double: dup 0 add return);
+        let_assert!(Ok(disassembly) = Disassembly::from_bwords(&program.to_bwords()));
+        let expected = "\
+            // main: addresses 0..5\n\
+            main:\n    \
+                push 5            // 0\n    \
+                call subroutine_0 // 2\n    \
+                halt              // 4\n\
+            \n\
+            // subroutine_0: addresses 5..9, called from 2\n\
+            subroutine_0:\n    \
+                dup 0  // 5\n    \
+                add    // 7\n    \
+                return // 8\n";
+        assert!(expected == disassembly.to_string());
+    }
+
+    #[test]
+    fn called_entry_point_is_labelled_main() {
+        let program = triton_program!(main: push 0 skiz call main halt);
+        let_assert!(Ok(disassembly) = Disassembly::from_bwords(&program.to_bwords()));
+        assert!(1 == disassembly.subroutines.len());
+        assert!(vec![3] == disassembly.subroutines[0].call_sites);
+        assert!("main" == disassembly.program.label_for_address(0));
+    }
+
+    #[test]
+    fn labels_do_not_depend_on_original_labels() {
+        let program = triton_program!(call foo halt foo: return);
+        let renamed = triton_program!(call bar halt bar: return);
+        let_assert!(Ok(disassembly) = Disassembly::from_bwords(&program.to_bwords()));
+        let_assert!(Ok(renamed_disassembly) = Disassembly::from_bwords(&renamed.to_bwords()));
+        assert!(disassembly.to_string() == renamed_disassembly.to_string());
+    }
+
+    #[test]
+    fn call_to_end_of_program_can_be_disassembled() {
+        let program = triton_program!(call end halt end:);
+        let_assert!(Ok(disassembly) = Disassembly::from_bwords(&program.to_bwords()));
+        let_assert!([_, end] = disassembly.subroutines.as_slice());
+        assert!((3, 3) == (end.start, end.end));
+
+        let code = disassembly.to_string();
+        let_assert!(Ok(reassembled) = Program::from_code(&code));
+        assert!(program.hash() == reassembled.hash());
+    }
+
+    #[test]
+    fn call_into_middle_of_instruction_cannot_be_disassembled() {
+        let call = Instruction::Call(bfe!(1));
+        let bwords = [call.opcode_b(), bfe!(1)];
+        let_assert!(Err(err) = Disassembly::from_bwords(&bwords));
+        let_assert!(DisassemblyError::UnlabelableCallTarget { call_site, target } = err);
+        assert!((0, 1) == (call_site, target));
+    }
+
+    #[test]
+    fn invalid_encoding_cannot_be_disassembled() {
+        let_assert!(Err(err) = Disassembly::from_encoding(&[]));
+        let_assert!(DisassemblyError::Decoding(ProgramDecodingError::EmptySequence) = err);
+    }
+
+    #[proptest]
+    fn disassembly_parses_to_program_with_same_hash(#[strategy(arb())] program: Program) {
+        let disassembly = Disassembly::from_encoding(&program.encode())?;
+        prop_assert_eq!(&program, &disassembly.program);
+
+        let reassembled = Program::from_code(&disassembly.to_string())?;
+        prop_assert_eq!(program.hash(), reassembled.hash());
+    }
+
+    #[proptest]
+    fn disassembling_from_bwords_and_from_encoding_is_equivalent(
+        #[strategy(arb())] program: Program,
+    ) {
+        let from_bwords = Disassembly::from_bwords(&program.to_bwords())?;
+        let from_encoding = Disassembly::from_encoding(&program.encode())?;
+        prop_assert_eq!(from_bwords, from_encoding);
+    }
+}
@@ -1,7 +1,11 @@
+pub use crate::disassembler::DisassemblyError;
 pub use crate::instruction::AssertionError;
 pub use crate::instruction::InstructionError;
+pub use crate::linker::LinkError;
 pub use crate::op_stack::NumberOfWordsError;
 pub use crate::op_stack::OpStackElementError;
 pub use crate::op_stack::OpStackError;
 pub use crate::parser::ParseError;
 pub use crate::program::ProgramDecodingError;
+pub use crate::program_file::ProgramFileError;
+pub use crate::stack_depth::StackDepthError;
@@ -0,0 +1,716 @@
+use std::fmt::Write;
+use std::iter;
+
+use itertools::Itertools;
+
+use crate::parser;
+use crate::parser::takes_argument;
+use crate::parser::word_length;
+use crate::parser::ParseError;
+
+/// The indentation used for one level of nesting.
+const INDENTATION: &str = "    ";
+
+/// Format Triton assembly in a canonical way.
+///
+/// Every instruction, label, type hint, data section, and breakpoint is put on a line of
+/// its own. [Assertion contexts](crate::instruction::AssertionContext) stay on the line
+/// of their assertion. Code following a label is indented, as are the bodies of macro
+/// definitions and of control-flow constructs like `if … else … endif`. Comments are
+/// kept: comments on a line of their own stay on a line of their own, and trailing
+/// comments of consecutive lines are aligned. Multiple consecutive empty lines are
+/// collapsed into one.
+///
+/// Formatting is idempotent, _i.e._, formatting already formatted code does not change it.
+/// Only valid code is formatted. Since files might be [modules](crate::linker::Linker),
+/// calls to undefined labels are allowed.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::formatter::format;
+/// let code = "push 1 call foo halt foo: // the subroutine\n dup 0 add return";
+/// let expected = "\
+/// push 1
+/// call foo
+/// halt
+/// foo: // the subroutine
+///     dup 0
+///     add
+///     return
+/// ";
+/// assert_eq!(expected, format(code)?);
+/// # Ok::<(), triton_isa::parser::ParseError>(())
+/// ```
+pub fn format(code: &str) -> Result<String, ParseError> {
+    parser::parse_module(code, "")?;
+
+    let lexemes = lex(code);
+    let (items, _) = Grouper::new(&lexemes).items_until(None);
+    let mut lines = vec![];
+    layout(&items, 0, None, &mut lines);
+
+    Ok(render(&lines))
+}
+
+/// The smallest unit of source code the formatter cares about.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Lexeme<'a> {
+    /// Anything that is neither whitespace nor a comment. String literals, including any
+    /// whitespace they contain, are part of a word.
+    Word {
+        text: &'a str,
+        line: usize,
+    },
+    Comment {
+        text: &'a str,
+        line: usize,
+    },
+}
+
+/// One unit of formatting.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Item {
+    Label(Span),
+    AssertionContext(Span),
+    MacroDefinition {
+        header: Span,
+        body: Vec<Item>,
+        last_line: usize,
+    },
+
+    /// A construct like `while … endwhile`. The `else` of an `if` is part of the body.
+    ControlFlow {
+        header: Span,
+        body: Vec<Item>,
+
+        /// The end of the construct, unless it is missing from a macro that is never
+        /// invoked.
+        end: Option<Span>,
+    },
+    Else(Span),
+    Comment(Span),
+    Other(Span),
+}
+
+/// Normalized text and the source lines it was taken from.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Span {
+    text: String,
+    first_line: usize,
+    last_line: usize,
+}
+
+/// One line of formatted code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Line {
+    indentation_level: usize,
+    code: String,
+    comment: Option<String>,
+    has_empty_line_before: bool,
+}
+
+impl Lexeme<'_> {
+    fn line(self) -> usize {
+        match self {
+            Self::Word { line, .. } | Self::Comment { line, .. } => line,
+        }
+    }
+}
+
+impl Item {
+    fn span(&self) -> &Span {
+        match self {
+            Self::Label(span)
+            | Self::AssertionContext(span)
+            | Self::MacroDefinition { header: span, .. }
+            | Self::ControlFlow { header: span, .. }
+            | Self::Else(span)
+            | Self::Comment(span)
+            | Self::Other(span) => span,
+        }
+    }
+
+    fn last_line(&self) -> usize {
+        match self {
+            &Self::MacroDefinition { last_line, .. } => last_line,
+            Self::ControlFlow { header, body, end } => match end {
+                Some(end) => end.last_line,
+                None => body.last().map_or(header.last_line, Self::last_line),
+            },
+            _ => self.span().last_line,
+        }
+    }
+}
+
+fn lex(code: &str) -> Vec<Lexeme> {
+    let mut lexemes = vec![];
+    let mut line = 1;
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            line += 1;
+        }
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if rest.starts_with("//") {
+            let end = rest.find(['\r', '\n']).unwrap_or(rest.len());
+            let text = rest[..end].trim_end();
+            lexemes.push(Lexeme::Comment { text, line });
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = word_length(rest);
+        lexemes.push(Lexeme::Word {
+            text: &rest[..end],
+            line,
+        });
+        rest = &rest[end..];
+    }
+
+    lexemes
+}
+
+/// Groups [`Lexeme`]s into [`Item`]s.
+struct Grouper<'l, 'a> {
+    lexemes: &'l [Lexeme<'a>],
+    index: usize,
+
+    /// Comments that were encountered in the middle of an item.
+    interrupting_comments: Vec<Item>,
+}
+
+impl<'l, 'a> Grouper<'l, 'a> {
+    fn new(lexemes: &'l [Lexeme<'a>]) -> Self {
+        Self {
+            lexemes,
+            index: 0,
+            interrupting_comments: vec![],
+        }
+    }
+
+    /// Group lexemes into items until the given terminating word or the end of the lexemes.
+    /// The terminating word is consumed. The body of a control-flow construct also ends
+    /// at an `endmacro`, which is left for the enclosing macro definition. Returns the
+    /// items and whether the terminating word was found.
+    fn items_until(&mut self, terminator: Option<&str>) -> (Vec<Item>, bool) {
+        let mut items = vec![];
+        loop {
+            let is_in_construct = terminator.is_some_and(|t| t != "endmacro");
+            if is_in_construct && self.peek_word() == Some("endmacro") {
+                return (items, false);
+            }
+            let Some(lexeme) = self.next() else {
+                return (items, false);
+            };
+            let (word, line) = match lexeme {
+                Lexeme::Comment { text, line } => {
+                    items.push(Item::Comment(Span::new(text, line, line)));
+                    continue;
+                }
+                Lexeme::Word { text, line } => (text, line),
+            };
+            if Some(word) == terminator {
+                return (items, true);
+            }
+            let item = self.item(word, line);
+            items.push(item);
+            items.append(&mut self.interrupting_comments);
+        }
+    }
+
+    fn item(&mut self, word: &str, line: usize) -> Item {
+        match word {
+            "macro" => {
+                let name = self.word();
+                let header = self.words_until_balanced(format!("macro {name}"));
+                let header = normalize_parentheses(&header);
+                let header = Span::new(&header, line, self.line());
+                let (body, _) = self.items_until(Some("endmacro"));
+                let last_line = self.line();
+                Item::MacroDefinition {
+                    header,
+                    body,
+                    last_line,
+                }
+            }
+            "if" | "while" | "for" => {
+                let header = match word {
+                    "for" => self.with_argument(word),
+                    _ => word.to_string(),
+                };
+                let header = Span::new(&header, line, self.line());
+                let end = format!("end{word}");
+                let (body, is_terminated) = self.items_until(Some(&end));
+                let end = is_terminated.then(|| Span::new(&end, self.line(), self.line()));
+                Item::ControlFlow { header, body, end }
+            }
+            "else" => Item::Else(Span::new(word, line, line)),
+            "hint" => {
+                let hint = self.words_until(String::new(), |hint| hint.contains(']'));
+                let text = match hint.split_once('=') {
+                    Some((variable, range)) => {
+                        let variable = variable.replace(':', ": ");
+                        format!("hint {variable} = {range}")
+                    }
+                    None => format!("hint {hint}"),
+                };
+                Item::Other(Span::new(&text, line, self.line()))
+            }
+            "data" => {
+                let data = self.words_until(String::new(), |data| data.contains(']'));
+                let data = data
+                    .replace(':', ": ")
+                    .replace('@', " @ ")
+                    .replace('=', " = ")
+                    .replace(',', ", ");
+                let text = format!("data {data}");
+                Item::Other(Span::new(&text, line, self.line()))
+            }
+            "const" => {
+                let is_complete =
+                    |definition: &str| definition.contains('=') && !definition.ends_with('=');
+                let definition = self.words_until(String::new(), is_complete);
+                let text = match definition.split_once('=') {
+                    Some((name, value)) => format!("const {name} = {value}"),
+                    None => format!("const {definition}"),
+                };
+                Item::Other(Span::new(&text, line, self.line()))
+            }
+            "error_id" | "error_message" => {
+                let text = self.with_argument(word);
+                Item::AssertionContext(Span::new(&text, line, self.line()))
+            }
+            _ if word.ends_with(':') => Item::Label(Span::new(word, line, line)),
+            _ if self.peek_word() == Some(":") => {
+                self.word();
+                Item::Label(Span::new(&format!("{word}:"), line, line))
+            }
+            _ if word.contains('!') => {
+                let invocation = self.words_until_balanced(word.to_string());
+                let invocation = normalize_parentheses(&invocation);
+                Item::Other(Span::new(&invocation, line, self.line()))
+            }
+            _ if takes_argument(word) => {
+                let text = self.with_argument(word);
+                Item::Other(Span::new(&text, line, self.line()))
+            }
+            _ => Item::Other(Span::new(word, line, line)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Lexeme<'a>> {
+        let lexeme = self.lexemes.get(self.index).copied()?;
+        self.index += 1;
+        Some(lexeme)
+    }
+
+    /// The next word. Comments in between are moved after the item they interrupt.
+    fn word(&mut self) -> &'a str {
+        while let Some(lexeme) = self.next() {
+            match lexeme {
+                Lexeme::Word { text, .. } => return text,
+                Lexeme::Comment { text, line } => {
+                    let comment = Item::Comment(Span::new(text, line, line));
+                    self.interrupting_comments.push(comment);
+                }
+            }
+        }
+        ""
+    }
+
+    fn peek_word(&self) -> Option<&'a str> {
+        let mut lexemes = self.lexemes.iter().skip(self.index);
+        lexemes.find_map(|&lexeme| match lexeme {
+            Lexeme::Word { text, .. } => Some(text),
+            Lexeme::Comment { .. } => None,
+        })
+    }
+
+    /// The line of the most recently consumed lexeme.
+    fn line(&self) -> usize {
+        self.lexemes[..self.index].last().map_or(1, |l| l.line())
+    }
+
+    /// The next word, unless there is none or it ends a macro definition. Macros that are
+    /// never invoked are not parsed, so their bodies can contain incomplete items.
+    fn argument(&mut self) -> &'a str {
+        match self.peek_word() {
+            None | Some("endmacro") => "",
+            Some(_) => self.word(),
+        }
+    }
+
+    /// The given word followed by its [argument](Self::argument), if there is one.
+    fn with_argument(&mut self, word: &str) -> String {
+        match self.argument() {
+            "" => word.to_string(),
+            argument => format!("{word} {argument}"),
+        }
+    }
+
+    /// Extend the given text by subsequent [arguments](Self::argument) until it is
+    /// complete, or until there are no more arguments.
+    fn words_until(&mut self, mut text: String, is_complete: impl Fn(&str) -> bool) -> String {
+        while !is_complete(&text) {
+            let argument = self.argument();
+            if argument.is_empty() {
+                break;
+            }
+            text.push_str(argument);
+        }
+        text
+    }
+
+    /// Extend the given text by subsequent words until its parentheses are balanced. Also
+    /// consumes an opening parenthesis that is separated from the text by whitespace.
+    fn words_until_balanced(&mut self, mut text: String) -> String {
+        if !text.contains('(') && self.peek_word().is_some_and(|w| w.starts_with('(')) {
+            text.push_str(self.word());
+        }
+        let is_balanced = |text: &str| text.matches('(').count() <= text.matches(')').count();
+        self.words_until(text, is_balanced)
+    }
+}
+
+impl Span {
+    fn new(text: &str, first_line: usize, last_line: usize) -> Self {
+        let text = text.to_string();
+        Self {
+            text,
+            first_line,
+            last_line,
+        }
+    }
+}
+
+/// Turn `name ( a,b )` into `name(a, b)`.
+fn normalize_parentheses(text: &str) -> String {
+    let Some((name, arguments)) = text.split_once('(') else {
+        return text.to_string();
+    };
+    let arguments = arguments.trim_end_matches(')').split(',');
+    let mut arguments = arguments.map(|argument| argument.split_whitespace().join(""));
+    format!("{}({})", name.trim_end(), arguments.join(", "))
+}
+
+fn layout(
+    items: &[Item],
+    indentation_level: usize,
+    mut previous_last_line: Option<usize>,
+    lines: &mut Vec<Line>,
+) {
+    let mut body_indentation_level = indentation_level;
+    for (i, item) in items.iter().enumerate() {
+        let span = item.span();
+        let has_empty_line_before =
+            previous_last_line.is_some_and(|previous_line| span.first_line > previous_line + 1);
+        let is_on_previous_line = previous_last_line == Some(span.first_line);
+        previous_last_line = Some(item.last_line());
+
+        let new_line = |level, code: &str| Line {
+            indentation_level: level,
+            code: code.to_string(),
+            comment: None,
+            has_empty_line_before,
+        };
+        match item {
+            Item::Comment(comment) if is_on_previous_line && !lines.is_empty() => {
+                lines.last_mut().unwrap().comment = Some(comment.text.clone());
+            }
+            Item::Comment(comment) => {
+                let next_item = items[i..].iter().find(|i| !matches!(i, Item::Comment(_)));
+                let level = match next_item {
+                    Some(Item::Label(_)) => indentation_level,
+                    _ => body_indentation_level,
+                };
+                let mut line = new_line(level, "");
+                line.comment = Some(comment.text.clone());
+                lines.push(line);
+            }
+            Item::Label(label) => {
+                lines.push(new_line(indentation_level, &label.text));
+                body_indentation_level = indentation_level + 1;
+            }
+            Item::AssertionContext(context) => match lines.last_mut() {
+                Some(line) if line.comment.is_none() => {
+                    write!(line.code, " {}", context.text).unwrap();
+                }
+                _ => lines.push(new_line(body_indentation_level, &context.text)),
+            },
+            Item::MacroDefinition { header, body, .. } => {
+                lines.push(new_line(indentation_level, &header.text));
+                layout(body, indentation_level + 1, Some(header.last_line), lines);
+                let mut endmacro = new_line(indentation_level, "endmacro");
+                endmacro.has_empty_line_before = false;
+                lines.push(endmacro);
+            }
+            Item::ControlFlow { header, body, end } => {
+                lines.push(new_line(body_indentation_level, &header.text));
+                let body_level = body_indentation_level + 1;
+                layout(body, body_level, Some(header.last_line), lines);
+                if let Some(end) = end {
+                    let mut end = new_line(body_indentation_level, &end.text);
+                    end.has_empty_line_before = false;
+                    lines.push(end);
+                }
+            }
+            Item::Else(_) => {
+                let level = indentation_level.saturating_sub(1);
+                lines.push(new_line(level, "else"));
+            }
+            Item::Other(other) => lines.push(new_line(body_indentation_level, &other.text)),
+        }
+    }
+}
+
+fn render(lines: &[Line]) -> String {
+    let indentation = |line: &Line| INDENTATION.repeat(line.indentation_level);
+    let code_width = |line: &Line| indentation(line).len() + line.code.chars().count();
+
+    // Trailing comments of consecutive lines are aligned. A group of consecutive lines
+    // ends with an empty line or with a comment on a line of its own.
+    let mut group_ids = vec![];
+    let mut group_id = 0_usize;
+    let previous_lines = iter::once(None).chain(lines.iter().map(Some));
+    for (previous_line, line) in previous_lines.zip(lines) {
+        let is_after_comment_line = previous_line.is_some_and(|l| l.code.is_empty());
+        if line.has_empty_line_before || line.code.is_empty() || is_after_comment_line {
+            group_id += 1;
+        }
+        group_ids.push(group_id);
+    }
+    let mut comment_columns = vec![];
+    for (_, group) in &lines.iter().zip(group_ids).chunk_by(|&(_, id)| id) {
+        let group = group.map(|(line, _)| line).collect_vec();
+        let commented_lines = group.iter().filter(|line| line.comment.is_some());
+        let column = commented_lines.map(|line| code_width(line)).max();
+        comment_columns.extend(group.iter().map(|_| column.unwrap_or_default()));
+    }
+
+    let mut formatted = String::new();
+    for (line, comment_column) in lines.iter().zip(comment_columns) {
+        if line.has_empty_line_before && !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        let mut formatted_line = format!("{}{}", indentation(line), line.code);
+        if let Some(comment) = &line.comment {
+            if !line.code.is_empty() {
+                let padding = comment_column - code_width(line) + 1;
+                formatted_line.push_str(&" ".repeat(padding));
+            }
+            formatted_line.push_str(comment);
+        }
+        formatted.push_str(&formatted_line);
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use proptest::prelude::*;
+    use proptest_arbitrary_interop::arb;
+    use test_strategy::proptest;
+
+    use crate::program::Program;
+
+    use super::*;
+
+    /// Words that, in macros that are never invoked, can form incomplete items.
+    const MACRO_BODY_WORDS: [&str; 18] = [
+        "hint",
+        "data",
+        "const",
+        "for",
+        "if",
+        "else",
+        "endif",
+        "x",
+        ":",
+        "=",
+        "[",
+        "]",
+        "(",
+        ")",
+        "m!(",
+        "push",
+        "error_id",
+        "// comment\n",
+    ];
+
+    fn assert_formatting(code: &str, expected: &str) {
+        let_assert!(Ok(formatted) = format(code));
+        assert!(expected == formatted);
+        let_assert!(Ok(formatted_again) = format(&formatted));
+        assert!(formatted == formatted_again);
+    }
+
+    #[test]
+    fn instructions_are_put_on_lines_of_their_own() {
+        assert_formatting(
+            "push 1   push 2 add\n\n\n\npop 1",
+            "push 1\npush 2\nadd\n\npop 1\n",
+        );
+    }
+
+    #[test]
+    fn subroutine_bodies_are_indented() {
+        let code = "call foo halt\nfoo: call bar return\nbar:return";
+        let expected = "call foo\nhalt\nfoo:\n    call bar\n    return\nbar:\n    return\n";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn bodies_of_control_flow_constructs_are_indented() {
+        let code = "foo: dup 0 if push 1 else while push 0 endwhile endif for 3 nop endfor return";
+        let expected = "\
+foo:
+    dup 0
+    if
+        push 1
+    else
+        while
+            push 0
+        endwhile
+    endif
+    for 3
+        nop
+    endfor
+    return
+";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn trailing_comments_are_aligned() {
+        let code = "
+            push 1 // one
+            push 1337 // many
+            add
+
+            pop 1 // after an empty line
+        ";
+        let expected = "\
+            push 1    // one\n\
+            push 1337 // many\n\
+            add\n\
+            \n\
+            pop 1 // after an empty line\n";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn comments_on_lines_of_their_own_are_kept() {
+        let code = "
+            call foo halt
+            // documentation of foo
+            // spans two lines
+            foo:
+            // explanation
+            return
+        ";
+        let expected = "\
+            call foo\n\
+            halt\n\
+            // documentation of foo\n\
+            // spans two lines\n\
+            foo:\n    \
+                // explanation\n    \
+                return\n";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn type_hints_and_assertion_contexts_are_normalized() {
+        let code = r#"
+            hint   x:u32=stack[0 .. 2]  hint y = stack[2]
+            push 0 assert error_id 42 assert_vector
+            error_message "don't // split"
+        "#;
+        let expected = "\
+            hint x: u32 = stack[0..2]\n\
+            hint y = stack[2]\n\
+            push 0\n\
+            assert error_id 42\n\
+            assert_vector error_message \"don't // split\"\n";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn macros_and_constants_are_formatted() {
+        let code = "
+            const N=3
+            macro add_n ( x , y ) push x push N add endmacro
+            add_n!(1,  -2) break call mod::foo
+        ";
+        let expected = "\
+            const N = 3\n\
+            macro add_n(x, y)\n    \
+                push x\n    \
+                push N\n    \
+                add\n\
+            endmacro\n\
+            add_n!(1, -2)\n\
+            break\n\
+            call mod::foo\n";
+        assert_formatting(code, expected);
+    }
+
+    #[test]
+    fn module_directives_are_formatted() {
+        let code = "import  foo include bar\npush 1";
+        assert_formatting(code, "import foo\ninclude bar\npush 1\n");
+    }
+
+    #[test]
+    fn labels_can_have_whitespace_before_colon_and_none_after() {
+        assert_formatting("foo :push 1 bar:halt", "foo:\n    push 1\nbar:\n    halt\n");
+    }
+
+    #[test]
+    fn invalid_code_is_not_formatted() {
+        assert!(let Err(_) = format("push"));
+        assert!(let Err(_) = format("pop 6"));
+    }
+
+    #[test]
+    fn incomplete_items_in_uninvoked_macros_are_formatted() {
+        let code = "macro m hint x endmacro halt";
+        assert_formatting(code, "macro m\n    hint x\nendmacro\nhalt\n");
+
+        let code = "macro m if push endmacro halt";
+        assert_formatting(code, "macro m\n    if\n        push\nendmacro\nhalt\n");
+    }
+
+    #[proptest]
+    fn formatting_terminates_for_arbitrary_macro_bodies(
+        #[strategy(prop::collection::vec(prop::sample::select(&MACRO_BODY_WORDS[..]), 0..12))]
+        words: Vec<&'static str>,
+    ) {
+        let code = format!("macro m() {} endmacro halt", words.join(" "));
+        if parser::parse_module(&code, "").is_ok() {
+            let formatted = format(&code)?;
+            prop_assert!(parser::parse_module(&formatted, "").is_ok());
+        }
+    }
+
+    #[proptest]
+    fn formatting_preserves_semantics_and_is_idempotent(#[strategy(arb())] program: Program) {
+        let code = program.to_string();
+        let formatted = format(&code)?;
+        prop_assert_eq!(&formatted, &format(&formatted)?);
+
+        let reparsed = Program::from_code(&formatted)?;
+        prop_assert_eq!(
+            program.labelled_instructions(),
+            reparsed.labelled_instructions()
+        );
+    }
+}
@@ -20,6 +20,7 @@ use thiserror::Error;
 use twenty_first::prelude::*;
 
 use crate::op_stack::NumberOfWords;
+use crate::op_stack::OpStack;
 use crate::op_stack::OpStackElement;
 use crate::op_stack::OpStackError;
 
@@ -28,55 +29,6 @@ type Result<T> = result::Result<T, InstructionError>;
 /// An `Instruction` has `call` addresses encoded as absolute integers.
 pub type Instruction = AnInstruction<BFieldElement>;
 
-pub const ALL_INSTRUCTIONS: [Instruction; Instruction::COUNT] = [
-    Instruction::Pop(NumberOfWords::N1),
-    Instruction::Push(BFieldElement::ZERO),
-    Instruction::Divine(NumberOfWords::N1),
-    Instruction::Pick(OpStackElement::ST0),
-    Instruction::Place(OpStackElement::ST0),
-    Instruction::Dup(OpStackElement::ST0),
-    Instruction::Swap(OpStackElement::ST0),
-    Instruction::Halt,
-    Instruction::Nop,
-    Instruction::Skiz,
-    Instruction::Call(BFieldElement::ZERO),
-    Instruction::Return,
-    Instruction::Recurse,
-    Instruction::RecurseOrReturn,
-    Instruction::Assert,
-    Instruction::ReadMem(NumberOfWords::N1),
-    Instruction::WriteMem(NumberOfWords::N1),
-    Instruction::Hash,
-    Instruction::AssertVector,
-    Instruction::SpongeInit,
-    Instruction::SpongeAbsorb,
-    Instruction::SpongeAbsorbMem,
-    Instruction::SpongeSqueeze,
-    Instruction::Add,
-    Instruction::AddI(BFieldElement::ZERO),
-    Instruction::Mul,
-    Instruction::Invert,
-    Instruction::Eq,
-    Instruction::Split,
-    Instruction::Lt,
-    Instruction::And,
-    Instruction::Xor,
-    Instruction::Log2Floor,
-    Instruction::Pow,
-    Instruction::DivMod,
-    Instruction::PopCount,
-    Instruction::XxAdd,
-    Instruction::XxMul,
-    Instruction::XInvert,
-    Instruction::XbMul,
-    Instruction::ReadIo(NumberOfWords::N1),
-    Instruction::WriteIo(NumberOfWords::N1),
-    Instruction::MerkleStep,
-    Instruction::MerkleStepMem,
-    Instruction::XxDotStep,
-    Instruction::XbDotStep,
-];
-
 pub const ALL_INSTRUCTION_NAMES: [&str; Instruction::COUNT] = {
     let mut names = [""; Instruction::COUNT];
     let mut i = 0;
@@ -113,10 +65,13 @@ pub enum LabelledInstruction {
     TypeHint(TypeHint),
 
     AssertionContext(AssertionContext),
+
+    DataSection(DataSection),
 }
 
 /// A hint about a range of stack elements. Helps debugging programs written for Triton VM.
-/// **Does not enforce types.**
+/// **Does not enforce types.** See [`TypeCheck`](crate::type_check::TypeCheck) for static
+/// checks of the hinted types.
 ///
 /// Usually constructed by parsing special annotations in the assembly code, for example:
 /// ```tasm
@@ -141,7 +96,48 @@ pub struct TypeHint {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, GetSize, Arbitrary)]
 pub enum AssertionContext {
     ID(i128),
-    // Message(String),
+
+    /// A human-readable message. Placeholders `{st0}` through `{st15}` are replaced
+    /// with the respective op stack element at the time the assertion fails; see
+    /// [`interpolate`](Self::interpolate).
+    Message(String),
+}
+
+/// Constant data that is written to random-access memory by the
+/// [program's](crate::program::Program) data prologue, before any other instruction is
+/// executed. Through the prologue, the data contributes to the program's
+/// [digest](crate::program::Program::hash).
+///
+/// Usually constructed by parsing data directives in the assembly code, for example:
+/// ```tasm
+/// data primes: u32 @ 1000 = [2, 3, 5, 7]
+/// data generators: xfe @ 2000 = [(1, 0, 0), (0, 1, 0)]
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, GetSize)]
+pub struct DataSection {
+    /// The name of the data. In assembly code, it can be used in place of the
+    /// [address](Self::address), for example in `push primes`.
+    pub name: String,
+    pub data_type: DataType,
+
+    /// The address of the first word.
+    pub address: BFieldElement,
+
+    /// The words placed in consecutive memory cells, starting at the
+    /// [address](Self::address). Elements spanning multiple words are flattened.
+    pub words: Vec<BFieldElement>,
+}
+
+/// The type of the elements of a [`DataSection`].
+#[derive(
+    Debug, Copy, Clone, Eq, PartialEq, Hash, EnumIter, Serialize, Deserialize, GetSize, Arbitrary,
+)]
+pub enum DataType {
+    BFieldElement,
+    XFieldElement,
+    Digest,
+    U32,
+    Bool,
 }
 
 impl LabelledInstruction {
@@ -161,6 +157,7 @@ impl Display for LabelledInstruction {
             LabelledInstruction::Breakpoint => write!(f, "break"),
             LabelledInstruction::TypeHint(type_hint) => write!(f, "{type_hint}"),
             LabelledInstruction::AssertionContext(ctx) => write!(f, "{ctx}"),
+            LabelledInstruction::DataSection(data) => write!(f, "{data}"),
         }
     }
 }
@@ -185,8 +182,113 @@ impl Display for TypeHint {
 
 impl Display for AssertionContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        let Self::ID(id) = self;
-        write!(f, "error_id {id}")
+        match self {
+            Self::ID(id) => write!(f, "error_id {id}"),
+            Self::Message(message) => {
+                let message = message.replace('\\', r"\\").replace('"', r#"\""#);
+                write!(f, r#"error_message "{message}""#)
+            }
+        }
+    }
+}
+
+impl Display for DataSection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self {
+            name,
+            data_type,
+            address,
+            words,
+        } = self;
+
+        let format_element = |element: &[BFieldElement]| match element {
+            [word] => word.value().to_string(),
+            _ => format!("({})", element.iter().map(|w| w.value()).join(", ")),
+        };
+        let elements = words
+            .chunks(data_type.size())
+            .map(format_element)
+            .join(", ");
+        let address = address.value();
+
+        write!(f, "data {name}: {data_type} @ {address} = [{elements}]")
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let name = match self {
+            Self::BFieldElement => "bfe",
+            Self::XFieldElement => "xfe",
+            Self::Digest => "digest",
+            Self::U32 => "u32",
+            Self::Bool => "bool",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl DataType {
+    /// The number of words one element of this type spans.
+    pub const fn size(self) -> usize {
+        match self {
+            Self::XFieldElement => x_field_element::EXTENSION_DEGREE,
+            Self::Digest => Digest::LEN,
+            Self::BFieldElement | Self::U32 | Self::Bool => 1,
+        }
+    }
+
+    /// Whether the given word is legal in an element of this type.
+    pub fn admits(self, word: BFieldElement) -> bool {
+        match self {
+            Self::U32 => word.value() <= u64::from(u32::MAX),
+            Self::Bool => word.value() <= 1,
+            Self::BFieldElement | Self::XFieldElement | Self::Digest => true,
+        }
+    }
+}
+
+impl AssertionContext {
+    /// Replace the placeholders `{st0}` through `{st15}` in a
+    /// [message](Self::Message) with the respective elements of the given
+    /// [`OpStack`]. Unknown placeholders are left untouched. Other kinds of context
+    /// are returned unchanged.
+    #[must_use]
+    pub fn interpolate(self, op_stack: &OpStack) -> Self {
+        let Self::Message(mut message) = self else {
+            return self;
+        };
+        for stack_element in OpStackElement::iter() {
+            let placeholder = format!("{{st{stack_element}}}");
+            let value = op_stack[stack_element].to_string();
+            message = message.replace(&placeholder, &value);
+        }
+        Self::Message(message)
+    }
+}
+
+impl<'a> Arbitrary<'a> for DataSection {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let name = u.arbitrary::<InstructionLabel>()?.into();
+        let data_type: DataType = u.arbitrary()?;
+        let num_words = data_type.size() * u.arbitrary_len::<[u64; Digest::LEN]>()?;
+        let mut words = Vec::with_capacity(num_words);
+        for _ in 0..num_words {
+            let word = match data_type {
+                DataType::U32 => bfe!(u.arbitrary::<u32>()?),
+                DataType::Bool => bfe!(u64::from(u.arbitrary::<bool>()?)),
+                _ => u.arbitrary()?,
+            };
+            words.push(word);
+        }
+
+        let data_section = Self {
+            name,
+            data_type,
+            address: u.arbitrary()?,
+            words,
+        };
+        Ok(data_section)
     }
 }
 
@@ -211,411 +313,520 @@ impl<'a> Arbitrary<'a> for TypeHint {
     }
 }
 
-/// A Triton VM instruction. See the
-/// [Instruction Set Architecture](https://triton-vm.org/spec/isa.html)
-/// for more details.
+/// Invokes the given macro with a declarative description of every instruction of the
+/// [instruction set architecture][isa], in the order in which the instructions appear in
+/// [`AnInstruction`]. This is the single source of truth for the instruction set: the
+/// enum [`AnInstruction`] and its metadata, the dispatch in Triton VM's `VMState::step`,
+/// and the dispatch of the processor table's instruction-specific transition constraints
+/// are all derived from it.
 ///
-/// The type parameter `Dest` describes the type of addresses (absolute or labels).
-#[derive(
-    Debug,
-    Copy,
-    Clone,
-    Eq,
-    PartialEq,
-    Hash,
-    EnumCount,
-    EnumIter,
-    Serialize,
-    Deserialize,
-    GetSize,
-    Arbitrary,
-)]
-pub enum AnInstruction<Dest: PartialEq + Default> {
-    // OpStack manipulation
-    Pop(NumberOfWords),
-    Push(BFieldElement),
-    Divine(NumberOfWords),
-    Pick(OpStackElement),
-    Place(OpStackElement),
-    Dup(OpStackElement),
-    Swap(OpStackElement),
-
-    // Control flow
-    Halt,
-    Nop,
-    Skiz,
-    Call(Dest),
-    Return,
-    Recurse,
-    RecurseOrReturn,
-    Assert,
-
-    // Memory access
-    ReadMem(NumberOfWords),
-    WriteMem(NumberOfWords),
-
-    // Hashing-related
-    Hash,
-    AssertVector,
-    SpongeInit,
-    SpongeAbsorb,
-    SpongeAbsorbMem,
-    SpongeSqueeze,
-
-    // Base field arithmetic on stack
-    Add,
-    AddI(BFieldElement),
-    Mul,
-    Invert,
-    Eq,
-
-    // Bitwise arithmetic on stack
-    Split,
-    Lt,
-    And,
-    Xor,
-    Log2Floor,
-    Pow,
-    DivMod,
-    PopCount,
-
-    // Extension field arithmetic on stack
-    XxAdd,
-    XxMul,
-    XInvert,
-    XbMul,
-
-    // Read/write
-    ReadIo(NumberOfWords),
-    WriteIo(NumberOfWords),
-
-    // Many-in-One
-    MerkleStep,
-    MerkleStepMem,
-    XxDotStep,
-    XbDotStep,
+/// Every instruction is described by
+/// - its variant of [`AnInstruction`], followed by the name and type of its argument,
+///   if it has one,
+/// - `name`: its name in Triton assembly,
+/// - `stack`: its influence on the size of the op stack, which may depend on the
+///   argument,
+/// - `u32`: whether it operates on u32s,
+/// - `execute`: the method of `VMState` implementing its semantics, and
+/// - `constraints`: the function in the processor table of `triton-air` that builds its
+///   transition constraints.
+///
+/// The opcode is not part of the description. Instead, it is derived from the
+/// instruction's [class](AnInstruction::opcode_class) and the instruction's position
+/// within that class. In order not to change the opcodes of existing instructions, new
+/// instructions should be appended.
+///
+/// Anything in parentheses following the name of the callback is passed on to the
+/// callback, enclosed in brackets. This allows passing context like `self`.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::for_each_instruction;
+/// macro_rules! count_instructions {
+///     ([] $($variant:ident $(($arg:ident: $ty:ident))? { $($description:tt)* })*) => {
+///         [$(stringify!($variant)),*].len()
+///     };
+/// }
+///
+/// let num_instructions = for_each_instruction!(count_instructions);
+/// assert_eq!(49, num_instructions);
+/// ```
+///
+/// [isa]: https://triton-vm.org/spec/isa.html
+#[macro_export]
+macro_rules! for_each_instruction {
+    ($callback:ident $(($($context:tt)*))?) => {
+        $callback! {
+            [$($($context)*)?]
+
+            // OpStack manipulation
+            Pop(n: NumberOfWords) {
+                name: "pop", stack: -(n.num_words() as i32), u32: false,
+                execute: pop, constraints: instruction_pop,
+            }
+            Push(element: BFieldElement) {
+                name: "push", stack: 1, u32: false,
+                execute: push, constraints: instruction_push,
+            }
+            Divine(n: NumberOfWords) {
+                name: "divine", stack: n.num_words() as i32, u32: false,
+                execute: divine, constraints: instruction_divine,
+            }
+            Pick(st: OpStackElement) {
+                name: "pick", stack: 0, u32: false,
+                execute: pick, constraints: instruction_pick,
+            }
+            Place(st: OpStackElement) {
+                name: "place", stack: 0, u32: false,
+                execute: place, constraints: instruction_place,
+            }
+            Dup(st: OpStackElement) {
+                name: "dup", stack: 1, u32: false,
+                execute: dup, constraints: instruction_dup,
+            }
+            Swap(st: OpStackElement) {
+                name: "swap", stack: 0, u32: false,
+                execute: swap, constraints: instruction_swap,
+            }
+
+            // Control flow
+            Halt {
+                name: "halt", stack: 0, u32: false,
+                execute: halt, constraints: instruction_halt,
+            }
+            Nop {
+                name: "nop", stack: 0, u32: false,
+                execute: nop, constraints: instruction_nop,
+            }
+            Skiz {
+                name: "skiz", stack: -1, u32: false,
+                execute: skiz, constraints: instruction_skiz,
+            }
+            Call(address: Dest) {
+                name: "call", stack: 0, u32: false,
+                execute: call, constraints: instruction_call,
+            }
+            Return {
+                name: "return", stack: 0, u32: false,
+                execute: return_from_call, constraints: instruction_return,
+            }
+            Recurse {
+                name: "recurse", stack: 0, u32: false,
+                execute: recurse, constraints: instruction_recurse,
+            }
+            RecurseOrReturn {
+                name: "recurse_or_return", stack: 0, u32: false,
+                execute: recurse_or_return, constraints: instruction_recurse_or_return,
+            }
+            Assert {
+                name: "assert", stack: -1, u32: false,
+                execute: assert, constraints: instruction_assert,
+            }
+
+            // Memory access
+            ReadMem(n: NumberOfWords) {
+                name: "read_mem", stack: n.num_words() as i32, u32: false,
+                execute: read_mem, constraints: instruction_read_mem,
+            }
+            WriteMem(n: NumberOfWords) {
+                name: "write_mem", stack: -(n.num_words() as i32), u32: false,
+                execute: write_mem, constraints: instruction_write_mem,
+            }
+
+            // Hashing-related
+            Hash {
+                name: "hash", stack: -5, u32: false,
+                execute: hash, constraints: instruction_hash,
+            }
+            AssertVector {
+                name: "assert_vector", stack: -5, u32: false,
+                execute: assert_vector, constraints: instruction_assert_vector,
+            }
+            SpongeInit {
+                name: "sponge_init", stack: 0, u32: false,
+                execute: sponge_init, constraints: instruction_sponge_init,
+            }
+            SpongeAbsorb {
+                name: "sponge_absorb", stack: -10, u32: false,
+                execute: sponge_absorb, constraints: instruction_sponge_absorb,
+            }
+            SpongeAbsorbMem {
+                name: "sponge_absorb_mem", stack: 0, u32: false,
+                execute: sponge_absorb_mem, constraints: instruction_sponge_absorb_mem,
+            }
+            SpongeSqueeze {
+                name: "sponge_squeeze", stack: 10, u32: false,
+                execute: sponge_squeeze, constraints: instruction_sponge_squeeze,
+            }
+
+            // Base field arithmetic on stack
+            Add {
+                name: "add", stack: -1, u32: false,
+                execute: add, constraints: instruction_add,
+            }
+            AddI(summand: BFieldElement) {
+                name: "addi", stack: 0, u32: false,
+                execute: addi, constraints: instruction_addi,
+            }
+            Mul {
+                name: "mul", stack: -1, u32: false,
+                execute: mul, constraints: instruction_mul,
+            }
+            Invert {
+                name: "invert", stack: 0, u32: false,
+                execute: invert, constraints: instruction_invert,
+            }
+            Eq {
+                name: "eq", stack: -1, u32: false,
+                execute: eq, constraints: instruction_eq,
+            }
+
+            // Bitwise arithmetic on stack
+            Split {
+                name: "split", stack: 1, u32: true,
+                execute: split, constraints: instruction_split,
+            }
+            Lt {
+                name: "lt", stack: -1, u32: true,
+                execute: lt, constraints: instruction_lt,
+            }
+            And {
+                name: "and", stack: -1, u32: true,
+                execute: and, constraints: instruction_and,
+            }
+            Xor {
+                name: "xor", stack: -1, u32: true,
+                execute: xor, constraints: instruction_xor,
+            }
+            Log2Floor {
+                name: "log_2_floor", stack: 0, u32: true,
+                execute: log_2_floor, constraints: instruction_log_2_floor,
+            }
+            Pow {
+                name: "pow", stack: -1, u32: true,
+                execute: pow, constraints: instruction_pow,
+            }
+            DivMod {
+                name: "div_mod", stack: 0, u32: true,
+                execute: div_mod, constraints: instruction_div_mod,
+            }
+            PopCount {
+                name: "pop_count", stack: 0, u32: true,
+                execute: pop_count, constraints: instruction_pop_count,
+            }
+
+            // Extension field arithmetic on stack
+            XxAdd {
+                name: "xx_add", stack: -3, u32: false,
+                execute: xx_add, constraints: instruction_xx_add,
+            }
+            XxMul {
+                name: "xx_mul", stack: -3, u32: false,
+                execute: xx_mul, constraints: instruction_xx_mul,
+            }
+            XInvert {
+                name: "x_invert", stack: 0, u32: false,
+                execute: x_invert, constraints: instruction_xinv,
+            }
+            XbMul {
+                name: "xb_mul", stack: -1, u32: false,
+                execute: xb_mul, constraints: instruction_xb_mul,
+            }
+
+            // Read/write
+            ReadIo(n: NumberOfWords) {
+                name: "read_io", stack: n.num_words() as i32, u32: false,
+                execute: read_io, constraints: instruction_read_io,
+            }
+            WriteIo(n: NumberOfWords) {
+                name: "write_io", stack: -(n.num_words() as i32), u32: false,
+                execute: write_io, constraints: instruction_write_io,
+            }
+
+            // Many-in-One
+            MerkleStep {
+                name: "merkle_step", stack: 0, u32: true,
+                execute: merkle_step_non_determinism, constraints: instruction_merkle_step,
+            }
+            MerkleStepMem {
+                name: "merkle_step_mem", stack: 0, u32: true,
+                execute: merkle_step_mem, constraints: instruction_merkle_step_mem,
+            }
+            XxDotStep {
+                name: "xx_dot_step", stack: 0, u32: false,
+                execute: xx_dot_step, constraints: instruction_xx_dot_step,
+            }
+            XbDotStep {
+                name: "xb_dot_step", stack: 0, u32: false,
+                execute: xb_dot_step, constraints: instruction_xb_dot_step,
+            }
+
+            // Permutation check
+            PushPerm {
+                name: "push_perm", stack: -5, u32: false,
+                execute: push_perm, constraints: instruction_push_perm,
+            }
+            PopPerm {
+                name: "pop_perm", stack: -5, u32: false,
+                execute: pop_perm, constraints: instruction_pop_perm,
+            }
+            AssertPerm {
+                name: "assert_perm", stack: 0, u32: false,
+                execute: assert_perm, constraints: instruction_assert_perm,
+            }
+        }
+    };
 }
 
-impl<Dest: PartialEq + Default> AnInstruction<Dest> {
-    /// Assign a unique positive integer to each `Instruction`.
-    pub const fn opcode(&self) -> u32 {
-        match self {
-            AnInstruction::Pop(_) => 3,
-            AnInstruction::Push(_) => 1,
-            AnInstruction::Divine(_) => 9,
-            AnInstruction::Pick(_) => 17,
-            AnInstruction::Place(_) => 25,
-            AnInstruction::Dup(_) => 33,
-            AnInstruction::Swap(_) => 41,
-            AnInstruction::Halt => 0,
-            AnInstruction::Nop => 8,
-            AnInstruction::Skiz => 2,
-            AnInstruction::Call(_) => 49,
-            AnInstruction::Return => 16,
-            AnInstruction::Recurse => 24,
-            AnInstruction::RecurseOrReturn => 32,
-            AnInstruction::Assert => 10,
-            AnInstruction::ReadMem(_) => 57,
-            AnInstruction::WriteMem(_) => 11,
-            AnInstruction::Hash => 18,
-            AnInstruction::AssertVector => 26,
-            AnInstruction::SpongeInit => 40,
-            AnInstruction::SpongeAbsorb => 34,
-            AnInstruction::SpongeAbsorbMem => 48,
-            AnInstruction::SpongeSqueeze => 56,
-            AnInstruction::Add => 42,
-            AnInstruction::AddI(_) => 65,
-            AnInstruction::Mul => 50,
-            AnInstruction::Invert => 64,
-            AnInstruction::Eq => 58,
-            AnInstruction::Split => 4,
-            AnInstruction::Lt => 6,
-            AnInstruction::And => 14,
-            AnInstruction::Xor => 22,
-            AnInstruction::Log2Floor => 12,
-            AnInstruction::Pow => 30,
-            AnInstruction::DivMod => 20,
-            AnInstruction::PopCount => 28,
-            AnInstruction::XxAdd => 66,
-            AnInstruction::XxMul => 74,
-            AnInstruction::XInvert => 72,
-            AnInstruction::XbMul => 82,
-            AnInstruction::ReadIo(_) => 73,
-            AnInstruction::WriteIo(_) => 19,
-            AnInstruction::MerkleStep => 36,
-            AnInstruction::MerkleStepMem => 44,
-            AnInstruction::XxDotStep => 80,
-            AnInstruction::XbDotStep => 88,
+/// Whether an instruction with the given argument type has an argument.
+macro_rules! has_argument {
+    () => {
+        false
+    };
+    ($ty:ident) => {
+        true
+    };
+}
+
+/// The argument used for the representative of an instruction in [`ALL_INSTRUCTIONS`].
+macro_rules! default_argument {
+    (NumberOfWords) => {
+        NumberOfWords::N1
+    };
+    (OpStackElement) => {
+        OpStackElement::ST0
+    };
+    (BFieldElement) => {
+        BFieldElement::ZERO
+    };
+    (Dest) => {
+        BFieldElement::ZERO
+    };
+}
+
+/// The argument of an instruction as a [`BFieldElement`], if the instruction has one.
+macro_rules! optional_argument {
+    () => {
+        None
+    };
+    ($arg:ident) => {
+        Some((*$arg).into())
+    };
+}
+
+/// Map the argument of an instruction; only call addresses are actually changed.
+macro_rules! map_argument {
+    (Dest, $arg:ident, $f:ident) => {
+        $f($arg)
+    };
+    ($ty:ident, $arg:ident, $f:ident) => {
+        *$arg
+    };
+}
+
+/// The instruction with the given new argument, or an early return of the given error if
+/// the argument is illegal for the instruction.
+macro_rules! with_new_argument {
+    ($variant:ident, $new_arg:ident, $error:ident) => {
+        return Err($error())
+    };
+    ($variant:ident(BFieldElement), $new_arg:ident, $error:ident) => {
+        AnInstruction::$variant($new_arg)
+    };
+    ($variant:ident(Dest), $new_arg:ident, $error:ident) => {
+        AnInstruction::$variant($new_arg)
+    };
+    ($variant:ident($ty:ident), $new_arg:ident, $error:ident) => {
+        AnInstruction::$variant($new_arg.try_into().map_err(|_| $error())?)
+    };
+}
+
+/// Derives [`AnInstruction`], [`ALL_INSTRUCTIONS`], and the instructions' metadata from
+/// the descriptions in [`for_each_instruction`].
+macro_rules! define_instructions {
+    ([] $(
+        $variant:ident $(($arg:ident: $ty:ident))? {
+            name: $name:literal,
+            stack: $stack:expr,
+            u32: $u32:literal,
+            execute: $execute:ident,
+            constraints: $constraints:ident $(,)?
+        }
+    )*) => {
+        pub const ALL_INSTRUCTIONS: [Instruction; Instruction::COUNT] =
+            [$(Instruction::$variant $((default_argument!($ty)))?),*];
+
+        /// A Triton VM instruction. See the
+        /// [Instruction Set Architecture](https://triton-vm.org/spec/isa.html)
+        /// for more details.
+        ///
+        /// The type parameter `Dest` describes the type of addresses (absolute or labels).
+        ///
+        /// The instructions are defined in [`for_each_instruction`](crate::for_each_instruction).
+        #[derive(
+            Debug,
+            Copy,
+            Clone,
+            Eq,
+            PartialEq,
+            Hash,
+            EnumCount,
+            EnumIter,
+            Serialize,
+            Deserialize,
+            GetSize,
+            Arbitrary,
+        )]
+        pub enum AnInstruction<Dest: PartialEq + Default> {
+            $($variant $(($ty))?,)*
         }
-    }
 
-    pub const fn name(&self) -> &'static str {
-        match self {
-            AnInstruction::Pop(_) => "pop",
-            AnInstruction::Push(_) => "push",
-            AnInstruction::Divine(_) => "divine",
-            AnInstruction::Pick(_) => "pick",
-            AnInstruction::Place(_) => "place",
-            AnInstruction::Dup(_) => "dup",
-            AnInstruction::Swap(_) => "swap",
-            AnInstruction::Halt => "halt",
-            AnInstruction::Nop => "nop",
-            AnInstruction::Skiz => "skiz",
-            AnInstruction::Call(_) => "call",
-            AnInstruction::Return => "return",
-            AnInstruction::Recurse => "recurse",
-            AnInstruction::RecurseOrReturn => "recurse_or_return",
-            AnInstruction::Assert => "assert",
-            AnInstruction::ReadMem(_) => "read_mem",
-            AnInstruction::WriteMem(_) => "write_mem",
-            AnInstruction::Hash => "hash",
-            AnInstruction::AssertVector => "assert_vector",
-            AnInstruction::SpongeInit => "sponge_init",
-            AnInstruction::SpongeAbsorb => "sponge_absorb",
-            AnInstruction::SpongeAbsorbMem => "sponge_absorb_mem",
-            AnInstruction::SpongeSqueeze => "sponge_squeeze",
-            AnInstruction::Add => "add",
-            AnInstruction::AddI(_) => "addi",
-            AnInstruction::Mul => "mul",
-            AnInstruction::Invert => "invert",
-            AnInstruction::Eq => "eq",
-            AnInstruction::Split => "split",
-            AnInstruction::Lt => "lt",
-            AnInstruction::And => "and",
-            AnInstruction::Xor => "xor",
-            AnInstruction::Log2Floor => "log_2_floor",
-            AnInstruction::Pow => "pow",
-            AnInstruction::DivMod => "div_mod",
-            AnInstruction::PopCount => "pop_count",
-            AnInstruction::XxAdd => "xx_add",
-            AnInstruction::XxMul => "xx_mul",
-            AnInstruction::XInvert => "x_invert",
-            AnInstruction::XbMul => "xb_mul",
-            AnInstruction::ReadIo(_) => "read_io",
-            AnInstruction::WriteIo(_) => "write_io",
-            AnInstruction::MerkleStep => "merkle_step",
-            AnInstruction::MerkleStepMem => "merkle_step_mem",
-            AnInstruction::XxDotStep => "xx_dot_step",
-            AnInstruction::XbDotStep => "xb_dot_step",
+        /// The position of each instruction in [`ALL_INSTRUCTIONS`].
+        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        enum InstructionIndex {
+            $($variant,)*
         }
-    }
 
-    pub const fn opcode_b(&self) -> BFieldElement {
-        BFieldElement::new(self.opcode() as u64)
-    }
+        impl<Dest: PartialEq + Default> AnInstruction<Dest> {
+            pub const fn name(&self) -> &'static str {
+                match self {
+                    $(AnInstruction::$variant { .. } => $name,)*
+                }
+            }
 
-    /// Number of words required to represent the instruction.
-    pub fn size(&self) -> usize {
-        match self {
-            AnInstruction::Pop(_) | AnInstruction::Push(_) => 2,
-            AnInstruction::Divine(_) => 2,
-            AnInstruction::Pick(_) | AnInstruction::Place(_) => 2,
-            AnInstruction::Dup(_) | AnInstruction::Swap(_) => 2,
-            AnInstruction::Call(_) => 2,
-            AnInstruction::ReadMem(_) | AnInstruction::WriteMem(_) => 2,
-            AnInstruction::AddI(_) => 2,
-            AnInstruction::ReadIo(_) | AnInstruction::WriteIo(_) => 2,
-            _ => 1,
-        }
-    }
+            /// Number of words required to represent the instruction.
+            pub const fn size(&self) -> usize {
+                match self {
+                    $(AnInstruction::$variant { .. } => {
+                        if has_argument!($($ty)?) { 2 } else { 1 }
+                    })*
+                }
+            }
 
-    /// Get the i'th instruction bit
-    pub fn ib(&self, arg: InstructionBit) -> BFieldElement {
-        bfe!((self.opcode() >> usize::from(arg)) & 1)
-    }
+            #[allow(unused_variables)]
+            pub const fn op_stack_size_influence(&self) -> i32 {
+                match self {
+                    $(AnInstruction::$variant $(($arg))? => $stack,)*
+                }
+            }
 
-    pub fn map_call_address<F, NewDest>(&self, f: F) -> AnInstruction<NewDest>
-    where
-        F: FnOnce(&Dest) -> NewDest,
-        NewDest: PartialEq + Default,
-    {
-        match self {
-            AnInstruction::Pop(x) => AnInstruction::Pop(*x),
-            AnInstruction::Push(x) => AnInstruction::Push(*x),
-            AnInstruction::Divine(x) => AnInstruction::Divine(*x),
-            AnInstruction::Pick(x) => AnInstruction::Pick(*x),
-            AnInstruction::Place(x) => AnInstruction::Place(*x),
-            AnInstruction::Dup(x) => AnInstruction::Dup(*x),
-            AnInstruction::Swap(x) => AnInstruction::Swap(*x),
-            AnInstruction::Halt => AnInstruction::Halt,
-            AnInstruction::Nop => AnInstruction::Nop,
-            AnInstruction::Skiz => AnInstruction::Skiz,
-            AnInstruction::Call(label) => AnInstruction::Call(f(label)),
-            AnInstruction::Return => AnInstruction::Return,
-            AnInstruction::Recurse => AnInstruction::Recurse,
-            AnInstruction::RecurseOrReturn => AnInstruction::RecurseOrReturn,
-            AnInstruction::Assert => AnInstruction::Assert,
-            AnInstruction::ReadMem(x) => AnInstruction::ReadMem(*x),
-            AnInstruction::WriteMem(x) => AnInstruction::WriteMem(*x),
-            AnInstruction::Hash => AnInstruction::Hash,
-            AnInstruction::AssertVector => AnInstruction::AssertVector,
-            AnInstruction::SpongeInit => AnInstruction::SpongeInit,
-            AnInstruction::SpongeAbsorb => AnInstruction::SpongeAbsorb,
-            AnInstruction::SpongeAbsorbMem => AnInstruction::SpongeAbsorbMem,
-            AnInstruction::SpongeSqueeze => AnInstruction::SpongeSqueeze,
-            AnInstruction::Add => AnInstruction::Add,
-            AnInstruction::AddI(x) => AnInstruction::AddI(*x),
-            AnInstruction::Mul => AnInstruction::Mul,
-            AnInstruction::Invert => AnInstruction::Invert,
-            AnInstruction::Eq => AnInstruction::Eq,
-            AnInstruction::Split => AnInstruction::Split,
-            AnInstruction::Lt => AnInstruction::Lt,
-            AnInstruction::And => AnInstruction::And,
-            AnInstruction::Xor => AnInstruction::Xor,
-            AnInstruction::Log2Floor => AnInstruction::Log2Floor,
-            AnInstruction::Pow => AnInstruction::Pow,
-            AnInstruction::DivMod => AnInstruction::DivMod,
-            AnInstruction::PopCount => AnInstruction::PopCount,
-            AnInstruction::XxAdd => AnInstruction::XxAdd,
-            AnInstruction::XxMul => AnInstruction::XxMul,
-            AnInstruction::XInvert => AnInstruction::XInvert,
-            AnInstruction::XbMul => AnInstruction::XbMul,
-            AnInstruction::ReadIo(x) => AnInstruction::ReadIo(*x),
-            AnInstruction::WriteIo(x) => AnInstruction::WriteIo(*x),
-            AnInstruction::MerkleStep => AnInstruction::MerkleStep,
-            AnInstruction::MerkleStepMem => AnInstruction::MerkleStepMem,
-            AnInstruction::XxDotStep => AnInstruction::XxDotStep,
-            AnInstruction::XbDotStep => AnInstruction::XbDotStep,
+            /// Indicates whether the instruction operates on base field elements that are also u32s.
+            pub const fn is_u32_instruction(&self) -> bool {
+                match self {
+                    $(AnInstruction::$variant { .. } => $u32,)*
+                }
+            }
+
+            pub fn map_call_address<F, NewDest>(&self, f: F) -> AnInstruction<NewDest>
+            where
+                F: FnOnce(&Dest) -> NewDest,
+                NewDest: PartialEq + Default,
+            {
+                match self {
+                    $(AnInstruction::$variant $(($arg))? => {
+                        AnInstruction::$variant $((map_argument!($ty, $arg, f)))?
+                    })*
+                }
+            }
+
+            const fn index(&self) -> usize {
+                match self {
+                    $(AnInstruction::$variant { .. } => InstructionIndex::$variant as usize,)*
+                }
+            }
         }
-    }
 
-    pub const fn op_stack_size_influence(&self) -> i32 {
-        match self {
-            AnInstruction::Pop(n) => -(n.num_words() as i32),
-            AnInstruction::Push(_) => 1,
-            AnInstruction::Divine(n) => n.num_words() as i32,
-            AnInstruction::Pick(_) => 0,
-            AnInstruction::Place(_) => 0,
-            AnInstruction::Dup(_) => 1,
-            AnInstruction::Swap(_) => 0,
-            AnInstruction::Halt => 0,
-            AnInstruction::Nop => 0,
-            AnInstruction::Skiz => -1,
-            AnInstruction::Call(_) => 0,
-            AnInstruction::Return => 0,
-            AnInstruction::Recurse => 0,
-            AnInstruction::RecurseOrReturn => 0,
-            AnInstruction::Assert => -1,
-            AnInstruction::ReadMem(n) => n.num_words() as i32,
-            AnInstruction::WriteMem(n) => -(n.num_words() as i32),
-            AnInstruction::Hash => -5,
-            AnInstruction::AssertVector => -5,
-            AnInstruction::SpongeInit => 0,
-            AnInstruction::SpongeAbsorb => -10,
-            AnInstruction::SpongeAbsorbMem => 0,
-            AnInstruction::SpongeSqueeze => 10,
-            AnInstruction::Add => -1,
-            AnInstruction::AddI(_) => 0,
-            AnInstruction::Mul => -1,
-            AnInstruction::Invert => 0,
-            AnInstruction::Eq => -1,
-            AnInstruction::Split => 1,
-            AnInstruction::Lt => -1,
-            AnInstruction::And => -1,
-            AnInstruction::Xor => -1,
-            AnInstruction::Log2Floor => 0,
-            AnInstruction::Pow => -1,
-            AnInstruction::DivMod => 0,
-            AnInstruction::PopCount => 0,
-            AnInstruction::XxAdd => -3,
-            AnInstruction::XxMul => -3,
-            AnInstruction::XInvert => 0,
-            AnInstruction::XbMul => -1,
-            AnInstruction::ReadIo(n) => n.num_words() as i32,
-            AnInstruction::WriteIo(n) => -(n.num_words() as i32),
-            AnInstruction::MerkleStep => 0,
-            AnInstruction::MerkleStepMem => 0,
-            AnInstruction::XxDotStep => 0,
-            AnInstruction::XbDotStep => 0,
+        impl<Dest: Display + PartialEq + Default> Display for AnInstruction<Dest> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+                write!(f, "{}", self.name())?;
+                match self {
+                    $(AnInstruction::$variant $(($arg))? => {
+                        $(write!(f, " {}", $arg)?;)?
+                    })*
+                }
+                Ok(())
+            }
         }
-    }
 
-    /// Indicates whether the instruction operates on base field elements that are also u32s.
-    pub fn is_u32_instruction(&self) -> bool {
-        matches!(
-            self,
-            AnInstruction::Split
-                | AnInstruction::Lt
-                | AnInstruction::And
-                | AnInstruction::Xor
-                | AnInstruction::Log2Floor
-                | AnInstruction::Pow
-                | AnInstruction::DivMod
-                | AnInstruction::PopCount
-                | AnInstruction::MerkleStep
-                | AnInstruction::MerkleStepMem
-        )
-    }
-}
+        impl Instruction {
+            /// Get the argument of the instruction, if it has one.
+            pub fn arg(&self) -> Option<BFieldElement> {
+                match self {
+                    $(AnInstruction::$variant $(($arg))? => optional_argument!($($arg)?),)*
+                }
+            }
 
-impl<Dest: Display + PartialEq + Default> Display for AnInstruction<Dest> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{}", self.name())?;
-        match self {
-            AnInstruction::Push(arg) => write!(f, " {arg}"),
-            AnInstruction::Pick(arg) => write!(f, " {arg}"),
-            AnInstruction::Place(arg) => write!(f, " {arg}"),
-            AnInstruction::Pop(arg) => write!(f, " {arg}"),
-            AnInstruction::Divine(arg) => write!(f, " {arg}"),
-            AnInstruction::Dup(arg) => write!(f, " {arg}"),
-            AnInstruction::Swap(arg) => write!(f, " {arg}"),
-            AnInstruction::Call(arg) => write!(f, " {arg}"),
-            AnInstruction::ReadMem(arg) => write!(f, " {arg}"),
-            AnInstruction::WriteMem(arg) => write!(f, " {arg}"),
-            AnInstruction::AddI(arg) => write!(f, " {arg}"),
-            AnInstruction::ReadIo(arg) => write!(f, " {arg}"),
-            AnInstruction::WriteIo(arg) => write!(f, " {arg}"),
-            _ => Ok(()),
+            /// Change the argument of the instruction, if it has one. Returns an `Err` if the instruction
+            /// does not have an argument or if the argument is out of range.
+            pub fn change_arg(self, new_arg: BFieldElement) -> Result<Self> {
+                let illegal_argument_error = || InstructionError::IllegalArgument(self, new_arg);
+                let new_instruction = match self {
+                    $(AnInstruction::$variant { .. } => {
+                        with_new_argument!($variant $(($ty))?, new_arg, illegal_argument_error)
+                    })*
+                };
+
+                Ok(new_instruction)
+            }
         }
-    }
+    };
 }
 
-impl Instruction {
-    /// Get the argument of the instruction, if it has one.
-    pub fn arg(&self) -> Option<BFieldElement> {
-        match self {
-            AnInstruction::Push(arg) => Some(*arg),
-            AnInstruction::Call(arg) => Some(*arg),
-            AnInstruction::Pop(arg) => Some(arg.into()),
-            AnInstruction::Divine(arg) => Some(arg.into()),
-            AnInstruction::Pick(arg) => Some(arg.into()),
-            AnInstruction::Place(arg) => Some(arg.into()),
-            AnInstruction::Dup(arg) => Some(arg.into()),
-            AnInstruction::Swap(arg) => Some(arg.into()),
-            AnInstruction::ReadMem(arg) => Some(arg.into()),
-            AnInstruction::WriteMem(arg) => Some(arg.into()),
-            AnInstruction::AddI(arg) => Some(*arg),
-            AnInstruction::ReadIo(arg) => Some(arg.into()),
-            AnInstruction::WriteIo(arg) => Some(arg.into()),
-            _ => None,
-        }
+for_each_instruction!(define_instructions);
+
+/// The opcodes of all instructions, in the order of [`ALL_INSTRUCTIONS`]. See
+/// [`AnInstruction::opcode`] for details.
+const OPCODES: [u32; Instruction::COUNT] = {
+    let mut opcodes = [0; Instruction::COUNT];
+    let mut num_instructions_per_class = [0; 1 << NUM_OPCODE_CLASS_BITS];
+    let mut i = 0;
+    while i < Instruction::COUNT {
+        let class = ALL_INSTRUCTIONS[i].opcode_class();
+        let index_within_class = num_instructions_per_class[class as usize];
+        opcodes[i] = (index_within_class << NUM_OPCODE_CLASS_BITS) | class;
+        num_instructions_per_class[class as usize] += 1;
+        i += 1;
     }
+    opcodes
+};
 
-    /// Change the argument of the instruction, if it has one. Returns an `Err` if the instruction
-    /// does not have an argument or if the argument is out of range.
-    pub fn change_arg(self, new_arg: BFieldElement) -> Result<Self> {
-        let illegal_argument_error = || InstructionError::IllegalArgument(self, new_arg);
-        let num_words = new_arg.try_into().map_err(|_| illegal_argument_error());
-        let op_stack_element = new_arg.try_into().map_err(|_| illegal_argument_error());
-
-        let new_instruction = match self {
-            AnInstruction::Pop(_) => AnInstruction::Pop(num_words?),
-            AnInstruction::Push(_) => AnInstruction::Push(new_arg),
-            AnInstruction::Divine(_) => AnInstruction::Divine(num_words?),
-            AnInstruction::Pick(_) => AnInstruction::Pick(op_stack_element?),
-            AnInstruction::Place(_) => AnInstruction::Place(op_stack_element?),
-            AnInstruction::Dup(_) => AnInstruction::Dup(op_stack_element?),
-            AnInstruction::Swap(_) => AnInstruction::Swap(op_stack_element?),
-            AnInstruction::Call(_) => AnInstruction::Call(new_arg),
-            AnInstruction::ReadMem(_) => AnInstruction::ReadMem(num_words?),
-            AnInstruction::WriteMem(_) => AnInstruction::WriteMem(num_words?),
-            AnInstruction::AddI(_) => AnInstruction::AddI(new_arg),
-            AnInstruction::ReadIo(_) => AnInstruction::ReadIo(num_words?),
-            AnInstruction::WriteIo(_) => AnInstruction::WriteIo(num_words?),
-            _ => return Err(illegal_argument_error()),
-        };
+/// The number of least significant bits of an opcode that encode the instruction's
+/// [class](AnInstruction::opcode_class).
+const NUM_OPCODE_CLASS_BITS: u32 = 3;
 
-        Ok(new_instruction)
+impl<Dest: PartialEq + Default> AnInstruction<Dest> {
+    /// Assign a unique positive integer to each `Instruction`.
+    ///
+    /// The least significant bits of the opcode are the instruction's
+    /// [class](Self::opcode_class). The remaining bits enumerate the instructions within
+    /// the same class.
+    pub const fn opcode(&self) -> u32 {
+        OPCODES[self.index()]
+    }
+
+    /// The class of the instruction, encoding
+    /// - in bit 0, whether the instruction has an argument,
+    /// - in bit 1, whether the instruction shrinks the op stack, and
+    /// - in bit 2, whether the instruction [operates on u32s](Self::is_u32_instruction).
+    ///
+    /// The processor table relies on these bits to group instructions when building
+    /// constraints.
+    pub const fn opcode_class(&self) -> u32 {
+        let has_argument = self.size() > 1;
+        let shrinks_op_stack = self.op_stack_size_influence() < 0;
+        let is_u32_instruction = self.is_u32_instruction();
+
+        has_argument as u32 | (shrinks_op_stack as u32) << 1 | (is_u32_instruction as u32) << 2
+    }
+
+    pub const fn opcode_b(&self) -> BFieldElement {
+        BFieldElement::new(self.opcode() as u64)
+    }
+
+    /// Get the i'th instruction bit
+    pub fn ib(&self, arg: InstructionBit) -> BFieldElement {
+        bfe!((self.opcode() >> usize::from(arg)) & 1)
     }
 }
 
@@ -718,6 +929,7 @@ impl<'a> Arbitrary<'a> for LabelledInstruction {
             2 => return Ok(Self::Breakpoint),
             3 => return Ok(Self::TypeHint(u.arbitrary()?)),
             4 => return Ok(Self::AssertionContext(u.arbitrary()?)),
+            5 => return Ok(Self::DataSection(u.arbitrary()?)),
             _ => unreachable!(),
         };
         let legal_label = String::from(u.arbitrary::<InstructionLabel>()?);
@@ -855,6 +1067,15 @@ pub enum InstructionError {
     #[error("Triton VM has halted and cannot execute any further instructions")]
     MachineHalted,
 
+    #[error("element {0} was never pushed to the permutation check")]
+    PermutationCheckElementNotFound(Digest),
+
+    #[error("permutation check failed: {0} elements remain")]
+    PermutationCheckFailed(usize),
+
+    #[error("type hint `{0}` is violated: {1} is not a u32")]
+    ViolatedU32TypeHint(TypeHint, BFieldElement),
+
     #[error(transparent)]
     OpStackError(#[from] OpStackError),
 }
@@ -871,9 +1092,9 @@ pub struct AssertionError {
 
     /// A user-defined error ID. Only has user-defined, no inherent, semantics.
     pub id: Option<i128>,
-    //
-    // /// A user-defined error message supplying context to the failed assertion.
-    // pub message: Option<String>,
+
+    /// A user-defined error message supplying context to the failed assertion.
+    pub message: Option<String>,
 }
 
 impl Display for AssertionError {
@@ -881,6 +1102,9 @@ impl Display for AssertionError {
         if let Some(id) = self.id {
             write!(f, "[{id}] ")?;
         }
+        if let Some(message) = &self.message {
+            write!(f, "{message}: ")?;
+        }
         write!(f, "expected {}, got {}", self.expected, self.actual)
     }
 }
@@ -891,6 +1115,7 @@ impl AssertionError {
             expected: expected.into(),
             actual: actual.into(),
             id: None,
+            message: None,
         }
     }
 
@@ -898,6 +1123,7 @@ impl AssertionError {
     pub fn with_context(mut self, context: AssertionContext) -> Self {
         match context {
             AssertionContext::ID(id) => self.id = Some(id),
+            AssertionContext::Message(message) => self.message = Some(message),
         };
         self
     }
@@ -1197,4 +1423,31 @@ pub mod tests {
             }
         }
     }
+
+    #[test]
+    fn assertion_error_displays_id_and_message() {
+        let error = AssertionError::new(1, 0);
+        assert!("expected 1, got 0" == error.to_string());
+
+        let error = error.with_context(AssertionContext::ID(42));
+        assert!("[42] expected 1, got 0" == error.to_string());
+
+        let error = error.with_context(AssertionContext::Message("too poor".to_string()));
+        assert!("[42] too poor: expected 1, got 0" == error.to_string());
+    }
+
+    #[test]
+    fn assertion_context_message_is_interpolated_with_op_stack_elements() {
+        let mut op_stack = OpStack::new(Digest::default());
+        op_stack.push(bfe!(42));
+        op_stack.push(bfe!(7));
+
+        let message = "balance {st0} <= {st1}; {st16} and {st} are untouched".to_string();
+        let context = AssertionContext::Message(message).interpolate(&op_stack);
+        let expected = "balance 7 <= 42; {st16} and {st} are untouched".to_string();
+        assert!(AssertionContext::Message(expected) == context);
+
+        let context = AssertionContext::ID(42).interpolate(&op_stack);
+        assert!(AssertionContext::ID(42) == context);
+    }
 }
@@ -1,10 +1,21 @@
 pub use twenty_first;
 
+pub mod control_flow;
+pub mod dead_code;
+pub mod diagnostic;
+pub mod disassembler;
 pub mod error;
+pub mod formatter;
 pub mod instruction;
+pub mod linker;
+pub mod object;
 pub mod op_stack;
 pub mod parser;
+pub mod peephole;
 pub mod program;
+pub mod program_file;
+pub mod stack_depth;
+pub mod type_check;
 
 /// Compile an entire program written in [Triton assembly][tasm].
 /// Triton VM can run the resulting [`Program`](program::Program); see there for
@@ -54,6 +65,20 @@ pub mod program;
 /// );
 /// ```
 ///
+/// Constant [data](instruction::DataSection) is written to RAM before any other instruction.
+/// Its name stands for its address:
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use twenty_first::prelude::*;
+/// let program = triton_program!(
+///     data primes: u32 @ 1000 = [2, 3, 5, 7]
+///     push primes read_mem 1 pop 1
+///     halt
+/// );
+/// assert_eq!(Some(&bfe!(7)), program.initial_ram().get(&bfe!(1003)));
+/// ```
+///
 /// # Panics
 ///
 /// **Panics** if the program cannot be parsed.
@@ -87,7 +112,11 @@ macro_rules! triton_program {
 ///
 /// The labels for instruction `call`, if any, are also parsed. Instruction `call` can refer to
 /// a label defined later in the program, _i.e.,_ labels are not checked for existence or
-/// uniqueness by this parser.
+/// uniqueness by this parser. Instead of a label, instruction `call` can take a target
+/// relative to its own address, like `call +4` or `call -2`. Such code does not depend on
+/// where it is placed; see [`Program::snippet`](program::Program::snippet). A relative
+/// `call` and its target must not be separated by structured control flow, since the
+/// code generated for it would shift the target.
 ///
 /// # Examples
 ///
@@ -142,6 +171,45 @@ macro_rules! triton_program {
 /// # assert_eq!(LabelledInstruction::Instruction(Push(one)), surrounding_code[4]);
 ///```
 ///
+/// Named constants and assembler macros, which are expanded in place:
+///
+/// ```
+/// # use triton_isa::triton_asm;
+/// let instructions = triton_asm!(
+///     const N = 3
+///     macro push_twice(x)
+///         push x push x
+///     endmacro
+///     push_twice!(N)
+///     pop 2
+/// );
+/// assert_eq!(triton_asm!(push 3 push 3 pop 2), instructions);
+/// ```
+///
+/// Structured control flow, which is lowered to plain instructions. Both
+/// `if … else … endif` and `while … endwhile` pop their condition off the stack, and
+/// `for n … endfor` executes its body `n` times. Every body becomes a subroutine with a
+/// generated label, placed after the next `halt`, `return`, `recurse`, or
+/// `recurse_or_return` that is not made conditional by a preceding `skiz`:
+///
+/// ```
+/// # use triton_isa::triton_asm;
+/// let instructions = triton_asm!(
+///     foo:
+///         if push 1 endif
+///         return
+/// );
+/// let expected = triton_asm!(
+///     foo:
+///         skiz call foo__then_0
+///         return
+///     foo__then_0:
+///         push 1
+///         return
+/// );
+/// assert_eq!(expected, instructions);
+/// ```
+///
 /// # Panics
 ///
 /// **Panics** if the instructions cannot be parsed.
@@ -186,6 +254,43 @@ macro_rules! triton_asm {
             $($tail)*
         )
     };
+    (@fmt $fmt:expr, $($args:expr,)*;
+        data $name:ident: $ty:ident @ $address:literal = [$($elements:tt)*] $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " data {}: {} @ {} = [{}] "),
+            $($args,)* stringify!($name), stringify!($ty), stringify!($address),
+            stringify!($($elements)*),;
+            $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; error_message $message:literal $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " error_message {} "), $($args,)* stringify!($message),; $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; const $name:ident = $value:literal $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " const ", stringify!($name), " = ", stringify!($value), " "),
+            $($args,)*; $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; macro $name:ident($($parameters:tt)*) $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " macro ", stringify!($name), "(", stringify!($($parameters)*), ") "),
+            $($args,)*; $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; $name:ident!($($arguments:tt)*) $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " ", stringify!($name), "!(", stringify!($($arguments)*), ") "),
+            $($args,)*; $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; $name:ident! $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " ", stringify!($name), "! "), $($args,)*; $($tail)*
+        )
+    };
     (@fmt $fmt:expr, $($args:expr,)*; $label_declaration:ident: $($tail:tt)*) => {
         $crate::triton_asm!(@fmt
             concat!($fmt, " ", stringify!($label_declaration), ": "), $($args,)*; $($tail)*
@@ -196,6 +301,16 @@ macro_rules! triton_asm {
             concat!($fmt, " ", stringify!($instruction), " "), $($args,)*; $($tail)*
         )
     };
+    (@fmt $fmt:expr, $($args:expr,)*; + $relative_call_target:literal $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " +", stringify!($relative_call_target), " "), $($args,)*; $($tail)*
+        )
+    };
+    (@fmt $fmt:expr, $($args:expr,)*; - $negative_literal:literal $($tail:tt)*) => {
+        $crate::triton_asm!(@fmt
+            concat!($fmt, " -", stringify!($negative_literal), " "), $($args,)*; $($tail)*
+        )
+    };
     (@fmt $fmt:expr, $($args:expr,)*; $instruction_argument:literal $($tail:tt)*) => {
         $crate::triton_asm!(@fmt
             concat!($fmt, " ", stringify!($instruction_argument), " "), $($args,)*; $($tail)*
@@ -333,19 +448,36 @@ mod tests {
     fn public_types_implement_usual_auto_traits() {
         fn implements_auto_traits<T: Sized + Send + Sync + Unpin>() {}
 
+        implements_auto_traits::<control_flow::CallGraph>();
+        implements_auto_traits::<control_flow::ControlFlowGraph>();
+
+        implements_auto_traits::<dead_code::DeadCodeElimination>();
+
+        implements_auto_traits::<diagnostic::Diagnostic>();
+        implements_auto_traits::<diagnostic::Fix>();
+        implements_auto_traits::<diagnostic::Severity>();
+
+        implements_auto_traits::<disassembler::Disassembly>();
+        implements_auto_traits::<disassembler::Subroutine>();
+
         implements_auto_traits::<error::AssertionError>();
+        implements_auto_traits::<error::DisassemblyError>();
         implements_auto_traits::<error::InstructionError>();
+        implements_auto_traits::<error::LinkError>();
         implements_auto_traits::<error::NumberOfWordsError>();
         implements_auto_traits::<error::OpStackElementError>();
         implements_auto_traits::<error::OpStackError>();
         implements_auto_traits::<error::ParseError>();
         implements_auto_traits::<error::ProgramDecodingError>();
+        implements_auto_traits::<error::StackDepthError>();
 
         implements_auto_traits::<instruction::Instruction>();
         implements_auto_traits::<instruction::AnInstruction<usize>>();
         implements_auto_traits::<instruction::InstructionBit>();
         implements_auto_traits::<instruction::TypeHint>();
 
+        implements_auto_traits::<linker::Linker>();
+
         implements_auto_traits::<op_stack::NumberOfWords>();
         implements_auto_traits::<op_stack::OpStack>();
         implements_auto_traits::<op_stack::OpStackElement>();
@@ -353,7 +485,15 @@ mod tests {
 
         implements_auto_traits::<parser::InstructionToken>();
 
+        implements_auto_traits::<peephole::Optimization>();
+        implements_auto_traits::<peephole::PeepholeRule>();
+
         implements_auto_traits::<program::InstructionIter>();
         implements_auto_traits::<program::Program>();
+
+        implements_auto_traits::<stack_depth::StackDepthAnalysis>();
+
+        implements_auto_traits::<type_check::TypeCheck>();
+        implements_auto_traits::<type_check::TypeWarning>();
     }
 }
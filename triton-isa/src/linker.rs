@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+
+use nom::error::VerboseError;
+use nom::error::VerboseErrorKind;
+use thiserror::Error;
+
+use crate::instruction::AnInstruction;
+use crate::parser;
+use crate::parser::InstructionToken;
+use crate::parser::ModuleDirective;
+use crate::parser::ParseError;
+use crate::parser::ParsedModule;
+use crate::program::Program;
+
+/// Separates the name of a module from the labels defined in that module, like in
+/// `module::label`.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// Links multiple modules of [Triton assembly][tasm] into a single [`Program`].
+///
+/// A module is a named piece of Triton assembly. It can refer to other modules through
+/// directives, which must come before the module's first instruction:
+///
+/// - `import <module>` makes the labels of the imported module available in the
+///   namespace of that module, _i.e._, as `<module>::<label>`.
+/// - `include <module>` places the labels of the included module in the namespace of the
+///   including module, as if the included module's code was part of the including
+///   module.
+///
+/// The code of the entry module starts at address 0. Its labels are not namespaced. The
+/// code of all other modules follows in the order in which they were first imported.
+/// Modules that are not (transitively) imported by the entry module are not linked.
+///
+/// To link code that has been assembled ahead of time, see [`Object`](crate::object::Object).
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::linker::Linker;
+/// let main = "
+///     import math
+///     push 3 call math::square halt
+/// ";
+/// let math = "
+///     square:
+///         dup 0 mul return
+/// ";
+/// let linker = Linker::default()
+///     .with_module("main", main)
+///     .with_module("math", math);
+/// let program = linker.link("main").unwrap();
+/// assert_eq!("math::square", program.label_for_address(5));
+/// ```
+///
+/// [tasm]: https://triton-vm.org/spec/instructions.html
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Linker {
+    sources: HashMap<String, String>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum LinkError {
+    #[error("unknown module \"{0}\"")]
+    UnknownModule(String),
+
+    #[error("module \"{0}\" includes itself")]
+    CyclicInclude(String),
+
+    #[error("invalid module \"{module}\":\n{message}")]
+    InvalidModule { module: String, message: String },
+
+    #[error("label \"{0}\" is defined more than once")]
+    DuplicateLabel(String),
+
+    #[error("label \"{label}\", called from object \"{object}\", is not defined")]
+    UndefinedLabel { object: String, label: String },
+
+    #[error("data of object \"{0}\" overlaps data of a preceding object")]
+    OverlappingData(String),
+}
+
+/// A module together with all the modules it includes. They share one namespace.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+struct CompilationUnit<'a> {
+    imports: Vec<&'a str>,
+
+    /// Every token is paired with the name of the module it originates from.
+    tokens: Vec<(&'a str, InstructionToken<'a>)>,
+}
+
+impl Linker {
+    /// Add a module with the given name and source code.
+    #[must_use]
+    pub fn with_module(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.sources.insert(name.into(), source.into());
+        self
+    }
+
+    /// Add the module stored in the given file. The module's name is the file's name
+    /// without extension.
+    pub fn with_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let Some(name) = path.file_stem().and_then(OsStr::to_str) else {
+            let reason = format!("cannot derive module name from path {}", path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+        };
+        let source = std::fs::read_to_string(path)?;
+
+        Ok(self.with_module(name, source))
+    }
+
+    /// Link the given entry module and all modules it (transitively) imports.
+    pub fn link(&self, entry: &str) -> Result<Program, LinkError> {
+        let (entry, _) = self.module(entry)?;
+
+        let mut link_order = vec![entry];
+        let mut linked_tokens = vec![];
+        let mut calls_into_unimported_modules = vec![];
+        let mut index = 0;
+        while let Some(&namespace) = link_order.get(index) {
+            let unit = self.compilation_unit(namespace)?;
+            let qualify = |module: &str, label: &str| {
+                if module == entry {
+                    label.to_string()
+                } else {
+                    format!("{module}{NAMESPACE_SEPARATOR}{label}")
+                }
+            };
+            let resolve_call = |label: &str| match label.split_once(NAMESPACE_SEPARATOR) {
+                _ if parser::relative_call_offset(label).is_some() => Some(label.to_string()),
+                Some((module, label)) if unit.imports.contains(&module) => {
+                    Some(qualify(module, label))
+                }
+                Some((module, _)) if self.sources.contains_key(module) => None,
+                _ => Some(qualify(namespace, label)),
+            };
+
+            for (module, token) in unit.tokens {
+                let token = match token {
+                    InstructionToken::Label(label, token_str) => {
+                        InstructionToken::Label(qualify(namespace, &label), token_str)
+                    }
+                    InstructionToken::Instruction(AnInstruction::Call(label), token_str) => {
+                        let Some(label) = resolve_call(&label) else {
+                            calls_into_unimported_modules.push((module, token_str));
+                            continue;
+                        };
+                        InstructionToken::Instruction(AnInstruction::Call(label), token_str)
+                    }
+                    _ => token,
+                };
+                linked_tokens.push((module, token));
+            }
+            for import in unit.imports {
+                if !link_order.contains(&import) {
+                    link_order.push(import);
+                }
+            }
+            index += 1;
+        }
+
+        self.ensure_all_labels_resolve(&linked_tokens, &calls_into_unimported_modules)?;
+
+        let labelled_instructions = linked_tokens
+            .iter()
+            .map(|(_, token)| token.to_labelled_instruction())
+            .collect::<Vec<_>>();
+        let source_locations = linked_tokens
+            .iter()
+            .map(|(module, token)| (self.sources[*module].as_str(), Some(*module), token));
+        let program = Program::new(&labelled_instructions).with_source_locations(source_locations);
+
+        Ok(program)
+    }
+
+    /// The name and source code of the module with the given name.
+    fn module(&self, name: &str) -> Result<(&str, &str), LinkError> {
+        self.sources
+            .get_key_value(name)
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .ok_or_else(|| LinkError::UnknownModule(name.to_string()))
+    }
+
+    fn parse_module(&self, name: &str) -> Result<ParsedModule, LinkError> {
+        let (name, source) = self.module(name)?;
+        parser::parse_module(source, name).map_err(|error| LinkError::InvalidModule {
+            module: name.to_string(),
+            message: error.to_string(),
+        })
+    }
+
+    fn compilation_unit<'a>(&'a self, module: &'a str) -> Result<CompilationUnit<'a>, LinkError> {
+        let mut unit = CompilationUnit::default();
+        self.include(module, &mut unit, &mut vec![])?;
+        Ok(unit)
+    }
+
+    fn include<'a>(
+        &'a self,
+        module: &str,
+        unit: &mut CompilationUnit<'a>,
+        include_stack: &mut Vec<&'a str>,
+    ) -> Result<(), LinkError> {
+        let (module, _) = self.module(module)?;
+        if include_stack.contains(&module) {
+            return Err(LinkError::CyclicInclude(module.to_string()));
+        }
+        include_stack.push(module);
+
+        let parsed_module = self.parse_module(module)?;
+        let tokens = parsed_module.instructions.into_iter();
+        unit.tokens.extend(tokens.map(|token| (module, token)));
+        for directive in parsed_module.directives {
+            match directive {
+                ModuleDirective::Import(import) => unit.imports.push(self.module(&import)?.0),
+                ModuleDirective::Include(include) => self.include(&include, unit, include_stack)?,
+            }
+        }
+
+        include_stack.pop();
+        Ok(())
+    }
+
+    /// Like [parsing a single program](parser::parse), ensure that there are no missing or
+    /// duplicate labels, but across modules. Additionally, calls into modules that have not
+    /// been imported, as well as overlapping data sections, are rejected. Errors are
+    /// reported for the first module, in link order, that has any.
+    fn ensure_all_labels_resolve<'a>(
+        &self,
+        linked_tokens: &[(&'a str, InstructionToken<'a>)],
+        calls_into_unimported_modules: &[(&'a str, &'a str)],
+    ) -> Result<(), LinkError> {
+        let mut label_occurrences = HashMap::<_, Vec<_>>::new();
+        for (module, token) in linked_tokens {
+            if let InstructionToken::Label(label, _) = token {
+                label_occurrences
+                    .entry(label.as_str())
+                    .or_default()
+                    .push((*module, token));
+            }
+        }
+
+        let duplicate_labels = label_occurrences
+            .values()
+            .filter(|occurrences| occurrences.len() > 1)
+            .flatten()
+            .map(|&(module, token)| (module, token.token_str(), "duplicate label"));
+        let missing_labels = linked_tokens
+            .iter()
+            .filter(|(_, token)| match token {
+                InstructionToken::Instruction(AnInstruction::Call(label), _) => {
+                    parser::relative_call_offset(label).is_none()
+                        && !label_occurrences.contains_key(label.as_str())
+                }
+                _ => false,
+            })
+            .map(|(module, token)| (*module, token.token_str(), "missing label"));
+        let missing_imports = calls_into_unimported_modules
+            .iter()
+            .map(|&(module, token_str)| (module, token_str, "module not imported"));
+        let tokens = linked_tokens.iter().map(|(_, token)| token);
+        let overlapping_data_sections = parser::indices_of_overlapping_data_sections(tokens)
+            .into_iter()
+            .map(|index| &linked_tokens[index])
+            .map(|(module, token)| (*module, token.token_str(), "overlapping data section"));
+
+        let mut errors_per_module = HashMap::<_, Vec<_>>::new();
+        for (module, token_str, context) in duplicate_labels
+            .chain(missing_labels)
+            .chain(missing_imports)
+            .chain(overlapping_data_sections)
+        {
+            let error = (token_str, VerboseErrorKind::Context(context));
+            errors_per_module.entry(module).or_default().push(error);
+        }
+
+        let modules_in_link_order = linked_tokens.iter().map(|&(module, _)| module);
+        let mut seen_modules = HashSet::new();
+        for module in modules_in_link_order.filter(|module| seen_modules.insert(*module)) {
+            let Some(mut errors) = errors_per_module.remove(module) else {
+                continue;
+            };
+            // sort by position in the module's source code for deterministic error messages
+            errors.sort_by_key(|(token_str, _)| std::cmp::Reverse(token_str.len()));
+            let (module, input) = self.module(module)?;
+            let errors = VerboseError { errors };
+            let message = ParseError { input, errors }.to_string();
+            let module = module.to_string();
+            return Err(LinkError::InvalidModule { module, message });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use crate::instruction::LabelledInstruction;
+    use crate::triton_program;
+
+    use super::*;
+
+    fn math_library() -> &'static str {
+        "
+        square:
+            dup 0 call multiply return
+        multiply:
+            mul return
+        "
+    }
+
+    #[test]
+    fn link_program_with_imported_module() {
+        let main = "import math push 3 call math::square write_io 1 halt";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("math", math_library());
+        let_assert!(Ok(program) = linker.link("main"));
+
+        let expected = triton_program!(
+            push 3 call math_square write_io 1 halt
+            math_square: dup 0 call math_multiply return
+            math_multiply: mul return
+        );
+        assert!(expected.hash() == program.hash());
+        assert!("math::square" == program.label_for_address(7));
+        assert!("math::multiply" == program.label_for_address(12));
+    }
+
+    #[test]
+    fn linked_program_knows_source_locations_across_modules() {
+        let main = "import math\npush 3 call math::square halt";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("math", math_library());
+        let_assert!(Ok(program) = linker.link("main"));
+
+        let_assert!(Some(location) = program.source_location_at(2));
+        assert!(Some("main") == location.file.as_deref());
+        assert!((2, 8) == (location.line, location.column));
+
+        let_assert!(Some(location) = program.source_location_at(5));
+        assert!(Some("math") == location.file.as_deref());
+        assert!((3, 13) == (location.line, location.column));
+    }
+
+    #[test]
+    fn linked_program_can_be_parsed_from_its_string_representation() {
+        let main = "import math push 3 call math::square halt";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("math", math_library());
+        let_assert!(Ok(program) = linker.link("main"));
+        let code = program.to_string();
+        let_assert!(Ok(reparsed) = Program::from_code(&code));
+        assert!(program == reparsed);
+    }
+
+    #[test]
+    fn included_module_shares_namespace_of_including_module() {
+        let main = "include helpers call helper halt";
+        let helpers = "helper: return";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("helpers", helpers);
+        let_assert!(Ok(program) = linker.link("main"));
+        assert!("helper" == program.label_for_address(3));
+    }
+
+    #[test]
+    fn control_flow_in_included_modules_does_not_clash() {
+        let main = "include lib push 1 if nop endif halt";
+        let lib = "push 1 if nop endif halt";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("lib", lib);
+        let_assert!(Ok(program) = linker.link("main"));
+
+        let labels = program.labelled_instructions().into_iter();
+        let labels = labels
+            .filter_map(|instruction| match instruction {
+                LabelledInstruction::Label(label) => Some(label),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(vec!["main__then_0", "lib__then_0"] == labels);
+    }
+
+    #[test]
+    fn modules_can_import_each_other() {
+        let main = "import even call even::is_even halt";
+        let even = "import odd is_even: call odd::is_odd return";
+        let odd = "import even is_odd: call even::is_even return";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("even", even)
+            .with_module("odd", odd);
+        let_assert!(Ok(program) = linker.link("main"));
+        assert!("even::is_even" == program.label_for_address(3));
+        assert!("odd::is_odd" == program.label_for_address(6));
+    }
+
+    #[test]
+    fn labels_of_different_modules_do_not_clash() {
+        let main = "import lib call lib::foo call foo halt foo: return";
+        let lib = "foo: return";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("lib", lib);
+        let_assert!(Ok(program) = linker.link("main"));
+        assert!("foo" == program.label_for_address(5));
+        assert!("lib::foo" == program.label_for_address(6));
+    }
+
+    #[test]
+    fn relative_calls_in_modules_stay_within_their_module() {
+        let main = "import lib call lib::foo halt";
+        let lib = "foo: call +2 return";
+        let linker = Linker::default()
+            .with_module("main", main)
+            .with_module("lib", lib);
+        let_assert!(Ok(program) = linker.link("main"));
+
+        let expected = triton_program!(call foo halt foo: call bar bar: return);
+        assert!(expected == program);
+    }
+
+    #[test]
+    fn unimported_modules_are_not_linked() {
+        let linker = Linker::default()
+            .with_module("main", "halt")
+            .with_module("unused", "this is not valid code");
+        let_assert!(Ok(program) = linker.link("main"));
+        assert!(1 == program.len_bwords());
+    }
+
+    #[test]
+    fn linking_unknown_module_fails() {
+        let linker = Linker::default().with_module("main", "import nope halt");
+        let_assert!(Err(LinkError::UnknownModule(module)) = linker.link("main"));
+        assert!("nope" == module);
+
+        let_assert!(Err(LinkError::UnknownModule(module)) = linker.link("other"));
+        assert!("other" == module);
+    }
+
+    #[test]
+    fn cyclic_includes_are_rejected() {
+        let linker = Linker::default()
+            .with_module("main", "include a halt")
+            .with_module("a", "include b")
+            .with_module("b", "include a");
+        let_assert!(Err(LinkError::CyclicInclude(module)) = linker.link("main"));
+        assert!("a" == module);
+    }
+
+    #[test]
+    fn duplicate_labels_across_included_modules_are_rejected() {
+        let linker = Linker::default()
+            .with_module("main", "include lib call foo halt foo: return")
+            .with_module("lib", "foo: return");
+        let_assert!(Err(LinkError::InvalidModule { module, message }) = linker.link("main"));
+        assert!("main" == module);
+        assert!(message.contains("duplicate label"));
+    }
+
+    #[test]
+    fn missing_labels_in_imported_module_are_reported_for_that_module() {
+        let linker = Linker::default()
+            .with_module("main", "import lib call lib::foo halt")
+            .with_module("lib", "foo: call bar return");
+        let_assert!(Err(LinkError::InvalidModule { module, message }) = linker.link("main"));
+        assert!("lib" == module);
+        assert!(message.contains("missing label"));
+    }
+
+    #[test]
+    fn calling_into_module_without_importing_it_is_rejected() {
+        let linker = Linker::default()
+            .with_module("main", "import a call a::foo call b::bar halt")
+            .with_module("a", "import b foo: call b::bar return")
+            .with_module("b", "bar: return");
+        let_assert!(Err(LinkError::InvalidModule { module, message }) = linker.link("main"));
+        assert!("main" == module);
+        assert!(1 == message.matches("module not imported").count());
+    }
+
+    #[test]
+    fn parse_errors_are_attributed_to_the_offending_module() {
+        let linker = Linker::default()
+            .with_module("main", "import lib halt")
+            .with_module("lib", "foo: pop 7 return");
+        let_assert!(Err(LinkError::InvalidModule { module, .. }) = linker.link("main"));
+        assert!("lib" == module);
+    }
+
+    #[test]
+    fn directives_must_precede_instructions() {
+        let linker = Linker::default()
+            .with_module("main", "halt import lib")
+            .with_module("lib", "foo: return");
+        let_assert!(Err(LinkError::InvalidModule { module, .. }) = linker.link("main"));
+        assert!("main" == module);
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use get_size2::GetSize;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde::Serialize;
+use twenty_first::prelude::*;
+
+use crate::instruction::DataSection;
+use crate::instruction::LabelledInstruction;
+use crate::linker::LinkError;
+use crate::linker::NAMESPACE_SEPARATOR;
+use crate::parser;
+use crate::parser::ParseError;
+use crate::program::Program;
+
+/// A separately assembled, relocatable piece of [Triton assembly][tasm], for example, a
+/// library of snippets. Objects can be stored, _e.g._, by serializing them with
+/// [serde], and later be [linked](link) into a [`Program`].
+///
+/// An object consists of instructions, the labels it defines, the labels it calls but
+/// does not define, its [data](DataSection), and debug information. The addresses of the
+/// instructions and labels are relative to the start of the object. Calls to labels that
+/// are not defined in the object are resolved during linking. The data of all objects is
+/// written to RAM by the [data prologue](Program::len_data_prologue) of the linked
+/// program.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::object::link;
+/// # use triton_isa::object::Object;
+/// let main = Object::assemble("push 3 call math::square halt").unwrap();
+/// let math = Object::assemble("square: dup 0 mul return").unwrap();
+/// assert_eq!(Some(&0), math.labels().get("square"));
+///
+/// let program = link([("main", &main), ("math", &math)]).unwrap();
+/// assert_eq!("math::square", program.label_for_address(5));
+/// ```
+///
+/// [tasm]: https://triton-vm.org/spec/instructions.html
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, GetSize)]
+pub struct Object {
+    program: Program,
+
+    /// The labels defined in the object, with their addresses.
+    labels: BTreeMap<String, u64>,
+
+    /// The labels called by, but not defined in, the object, keyed by the address of the
+    /// respective `call`.
+    external_calls: BTreeMap<u64, String>,
+
+    data_sections: Vec<DataSection>,
+}
+
+impl Object {
+    pub fn new(labelled_instructions: &[LabelledInstruction]) -> Self {
+        let data_sections = labelled_instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                LabelledInstruction::DataSection(data) => Some(data.clone()),
+                _ => None,
+            })
+            .collect();
+        let code = labelled_instructions
+            .iter()
+            .filter(|instruction| !matches!(instruction, LabelledInstruction::DataSection(_)))
+            .cloned()
+            .collect_vec();
+        let (program, external_calls) = Program::with_unresolved_calls(&code);
+        let labels = parser::build_label_to_address_map(&code);
+
+        Self {
+            program,
+            labels: labels.into_iter().collect(),
+            external_calls,
+            data_sections,
+        }
+    }
+
+    /// Assemble an object from source code. Calls to labels that are not defined in the
+    /// code are permitted; all other problems that prevent
+    /// [parsing a program](Program::from_code) prevent assembling an object, too.
+    pub fn assemble(code: &str) -> Result<Self, ParseError> {
+        let tokens = parser::parse_object(code)?;
+        let labelled_instructions = parser::to_labelled_instructions(&tokens);
+        let mut object = Self::new(&labelled_instructions);
+        let source_locations = tokens.iter().map(|token| (code, None, token));
+        object.program = object.program.with_source_locations(source_locations);
+
+        Ok(object)
+    }
+
+    /// The labels defined in the object, with their addresses relative to the start of
+    /// the object.
+    pub fn labels(&self) -> &BTreeMap<String, u64> {
+        &self.labels
+    }
+
+    /// The labels called by, but not defined in, the object, keyed by the address of the
+    /// respective `call`, relative to the start of the object.
+    pub fn external_calls(&self) -> &BTreeMap<u64, String> {
+        &self.external_calls
+    }
+
+    /// The length of the object's code as `BFieldElement`s. Double-word instructions
+    /// contribute two `BFieldElement`s.
+    pub fn len_bwords(&self) -> usize {
+        self.program.len_bwords()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.is_empty()
+    }
+}
+
+/// Link the given objects, each paired with its name, into a [`Program`].
+///
+/// The first object is the entry point: its code starts right after the
+/// [data prologue](Program::len_data_prologue), and its labels keep their names. The
+/// code of all other objects follows in the given order. Like for
+/// [modules](crate::linker::Linker), their labels are placed in the namespace of the
+/// respective object, _i.e._, they become `<object>::<label>`. Calls to labels that an
+/// object does not define are resolved in that object's namespace, unless they name
+/// another namespace explicitly.
+///
+/// Since addresses are assigned in the given order only, linking the same objects in the
+/// same order always results in the same program, and thus the same
+/// [digest](Program::hash).
+pub fn link<'o>(
+    objects: impl IntoIterator<Item = (&'o str, &'o Object)>,
+) -> Result<Program, LinkError> {
+    let objects = objects.into_iter().collect::<Vec<_>>();
+    let entry = objects.first().map(|&(name, _)| name).unwrap_or_default();
+    let qualify = |object: &str, label: &str| {
+        if object == entry {
+            label.to_string()
+        } else {
+            format!("{object}{NAMESPACE_SEPARATOR}{label}")
+        }
+    };
+
+    let mut ram_addresses = HashSet::new();
+    let mut data_sections = vec![];
+    for &(name, object) in &objects {
+        let object_ram = object.data_sections.iter().flat_map(|data| {
+            (0..data.words.len()).map(|offset| (data.address + bfe!(offset)).value())
+        });
+        for address in object_ram {
+            if !ram_addresses.insert(address) {
+                return Err(LinkError::OverlappingData(name.to_string()));
+            }
+        }
+        let object_data = object.data_sections.iter().cloned();
+        data_sections.extend(object_data.map(LabelledInstruction::DataSection));
+    }
+    let mut program = Program::new(&data_sections);
+
+    let mut label_to_address = BTreeMap::new();
+    let mut offset = program.len_bwords() as u64;
+    for &(name, object) in &objects {
+        for (label, &address) in &object.labels {
+            let label = qualify(name, label);
+            if label_to_address
+                .insert(label.clone(), offset + address)
+                .is_some()
+            {
+                return Err(LinkError::DuplicateLabel(label));
+            }
+        }
+        offset += object.len_bwords() as u64;
+    }
+
+    for (name, object) in objects {
+        let mut redirected_calls = BTreeMap::new();
+        for (&address, label) in &object.external_calls {
+            let qualified_label = if label.contains(NAMESPACE_SEPARATOR) {
+                label.clone()
+            } else {
+                qualify(name, label)
+            };
+            let Some(&target) = label_to_address.get(&qualified_label) else {
+                let object = name.to_string();
+                let label = qualified_label;
+                return Err(LinkError::UndefinedLabel { object, label });
+            };
+            redirected_calls.insert(address, target);
+        }
+
+        let rename = |label: &str| qualify(name, label);
+        program.append(&object.program, rename, &redirected_calls);
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use crate::linker::Linker;
+    use crate::triton_program;
+
+    use super::*;
+
+    fn math_library() -> &'static str {
+        "
+        square:
+            dup 0 call multiply return
+        multiply:
+            mul return
+        "
+    }
+
+    #[test]
+    fn object_knows_its_labels_and_external_calls() {
+        let_assert!(Ok(object) = Object::assemble("foo: push 1 call bar call foo return"));
+        assert!(Some(&0) == object.labels().get("foo"));
+        assert!(Some("bar") == object.external_calls().get(&2).map(String::as_str));
+        assert!(1 == object.external_calls().len());
+        assert!(7 == object.len_bwords());
+    }
+
+    #[test]
+    fn object_cannot_define_label_twice() {
+        let_assert!(Err(err) = Object::assemble("foo: nop foo: halt"));
+        assert!(err.to_string().contains("duplicate label"));
+    }
+
+    #[test]
+    fn linking_objects_is_equivalent_to_linking_modules() {
+        let main = "push 3 call math::square write_io 1 halt";
+        let_assert!(Ok(main_object) = Object::assemble(main));
+        let_assert!(Ok(math_object) = Object::assemble(math_library()));
+        let_assert!(Ok(program) = link([("main", &main_object), ("math", &math_object)]));
+
+        let linker = Linker::default()
+            .with_module("main", format!("import math {main}"))
+            .with_module("math", math_library());
+        let_assert!(Ok(expected) = linker.link("main"));
+        assert!(expected == program);
+        assert!(expected.hash() == program.hash());
+        assert!("math::multiply" == program.label_for_address(12));
+    }
+
+    #[test]
+    fn linked_program_keeps_debug_information_of_objects() {
+        let_assert!(Ok(main) = Object::assemble("call lib::foo halt"));
+        let_assert!(Ok(lib) = Object::assemble("foo:\n  break\n  assert error_id 7 return"));
+        let_assert!(Ok(program) = link([("main", &main), ("lib", &lib)]));
+
+        assert!(program.is_breakpoint(3));
+        let_assert!(Some(location) = program.source_location_at(3));
+        assert!((3, 3) == (location.line, location.column));
+        assert!(program.assertion_context_at(3).is_some());
+    }
+
+    #[test]
+    fn data_of_all_objects_is_written_by_data_prologue_of_linked_program() {
+        let main = "data a: bfe @ 0 = [1, 2] call lib::foo halt";
+        let lib = "data b: u32 @ 2 = [3] foo: return";
+        let_assert!(Ok(main_object) = Object::assemble(main));
+        let_assert!(Ok(lib_object) = Object::assemble(lib));
+        assert!(0 == main_object.program.len_data_prologue());
+        let_assert!(Ok(program) = link([("main", &main_object), ("lib", &lib_object)]));
+
+        let linker = Linker::default()
+            .with_module("main", format!("import lib {main}"))
+            .with_module("lib", lib);
+        let_assert!(Ok(expected) = linker.link("main"));
+        assert!(expected == program);
+        assert!(3 == program.initial_ram().len());
+
+        let entry = program.len_data_prologue() as u64;
+        assert!(0 < entry);
+        assert!("lib::foo" == program.label_for_address(entry + 3));
+    }
+
+    #[test]
+    fn object_survives_serialization() {
+        let code = "data d: bfe @ 7 = [1, 2] foo: push 1 call lib::bar call foo return";
+        let_assert!(Ok(object) = Object::assemble(code));
+        let_assert!(Ok(serialized) = serde_json::to_string(&object));
+        let_assert!(Ok(deserialized) = serde_json::from_str::<Object>(&serialized));
+        assert!(object == deserialized);
+        assert!(object.labels() == deserialized.labels());
+
+        let_assert!(Ok(library) = Object::assemble("bar: return"));
+        let_assert!(Ok(program) = link([("main", &object), ("lib", &library)]));
+        let_assert!(Ok(other_program) = link([("main", &deserialized), ("lib", &library)]));
+        assert!(program.hash() == other_program.hash());
+    }
+
+    #[test]
+    fn address_assignment_depends_only_on_order_of_objects() {
+        let_assert!(Ok(main) = Object::assemble("call a::foo call b::foo halt"));
+        let_assert!(Ok(a) = Object::assemble("foo: push 1 return"));
+        let_assert!(Ok(b) = Object::assemble("foo: push 2 return"));
+
+        let_assert!(Ok(program) = link([("main", &main), ("a", &a), ("b", &b)]));
+        let expected = triton_program!(
+            call a_foo call b_foo halt
+            a_foo: push 1 return
+            b_foo: push 2 return
+        );
+        assert!(expected.hash() == program.hash());
+
+        let_assert!(Ok(swapped) = link([("main", &main), ("b", &b), ("a", &a)]));
+        assert!(program.hash() != swapped.hash());
+    }
+
+    #[test]
+    fn calls_without_namespace_are_resolved_in_namespace_of_caller() {
+        let_assert!(Ok(main) = Object::assemble("foo: call lib::bar halt"));
+        let_assert!(Ok(lib) = Object::assemble("bar: call foo return"));
+        let_assert!(Err(err) = link([("main", &main), ("lib", &lib)]));
+        let_assert!(LinkError::UndefinedLabel { object, label } = err);
+        assert!("lib" == object);
+        assert!("lib::foo" == label);
+    }
+
+    #[test]
+    fn library_routine_has_same_position_independent_digest_wherever_it_is_linked() {
+        let_assert!(Ok(math) = Object::assemble(math_library()));
+        let_assert!(Ok(main) = Object::assemble("push 3 call math::square halt"));
+        let_assert!(Ok(other_main) = Object::assemble("push 3 dup 0 call math::square halt"));
+        let_assert!(Ok(program) = link([("main", &main), ("math", &math)]));
+        let_assert!(Ok(other_program) = link([("main", &other_main), ("math", &math)]));
+
+        let linked_code = program.to_bwords()[main.len_bwords()..].to_vec();
+        let other_linked_code = other_program.to_bwords()[other_main.len_bwords()..].to_vec();
+        assert!(linked_code != other_linked_code);
+
+        let library_routine = |program: &Program, main: &Object| {
+            let start = main.len_bwords() as u64;
+            program.snippet(start..start + math.len_bwords() as u64)
+        };
+        let routine = library_routine(&program, &main);
+        let other_routine = library_routine(&other_program, &other_main);
+        assert!(routine.position_independent_hash() == other_routine.position_independent_hash());
+
+        let_assert!(Ok(standalone) = Program::from_code(math_library()));
+        assert!(standalone.position_independent_hash() == routine.position_independent_hash());
+    }
+
+    #[test]
+    fn relative_calls_are_not_external_calls() {
+        let_assert!(Ok(object) = Object::assemble("call +2 return"));
+        assert!(object.external_calls().is_empty());
+
+        let_assert!(Ok(main) = Object::assemble("call lib::foo halt"));
+        let_assert!(Ok(lib) = Object::assemble("foo: call +2 return"));
+        let_assert!(Ok(program) = link([("main", &main), ("lib", &lib)]));
+        let expected = triton_program!(call foo halt foo: call bar bar: return);
+        assert!(expected == program);
+    }
+
+    #[test]
+    fn linking_fails_for_undefined_label() {
+        let_assert!(Ok(main) = Object::assemble("call missing halt"));
+        let_assert!(Err(LinkError::UndefinedLabel { label, .. }) = link([("main", &main)]));
+        assert!("missing" == label);
+    }
+
+    #[test]
+    fn linking_fails_for_duplicate_label() {
+        let_assert!(Ok(main) = Object::assemble("call lib::foo halt"));
+        let_assert!(Ok(lib) = Object::assemble("foo: return"));
+        let_assert!(Err(err) = link([("main", &main), ("lib", &lib), ("lib", &lib)]));
+        assert!(LinkError::DuplicateLabel("lib::foo".to_string()) == err);
+    }
+
+    #[test]
+    fn linking_fails_for_overlapping_data() {
+        let_assert!(Ok(main) = Object::assemble("data a: bfe @ 0 = [1, 2] halt"));
+        let_assert!(Ok(lib) = Object::assemble("data b: bfe @ 1 = [3]"));
+        let_assert!(Err(err) = link([("main", &main), ("lib", &lib)]));
+        assert!(LinkError::OverlappingData("lib".to_string()) == err);
+    }
+}
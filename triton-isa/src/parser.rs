@@ -5,7 +5,9 @@ use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
+use std::mem;
 
+use itertools::Itertools;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::bytes::complete::take_while;
@@ -14,32 +16,55 @@ use nom::character::complete::digit1;
 use nom::combinator::cut;
 use nom::combinator::eof;
 use nom::combinator::fail;
+use nom::combinator::map;
 use nom::combinator::opt;
+use nom::combinator::recognize;
 use nom::error::ErrorKind;
 use nom::error::VerboseError;
 use nom::error::VerboseErrorKind;
 use nom::multi::many0;
 use nom::multi::many1;
+use nom::multi::separated_list0;
+use nom::sequence::pair;
+use nom::sequence::preceded;
 use nom::Finish;
 use nom::IResult;
+use nom::Offset;
+use strum::IntoEnumIterator;
 use twenty_first::bfe;
 use twenty_first::prelude::BFieldElement;
 
 use crate::instruction::AnInstruction;
 use crate::instruction::AssertionContext;
+use crate::instruction::DataSection;
+use crate::instruction::DataType;
 use crate::instruction::Instruction;
 use crate::instruction::LabelledInstruction;
 use crate::instruction::TypeHint;
+use crate::instruction::ALL_INSTRUCTIONS;
 use crate::instruction::ALL_INSTRUCTION_NAMES;
+use crate::linker::NAMESPACE_SEPARATOR;
 use crate::op_stack::NumberOfWords;
 use crate::op_stack::OpStackElement;
+use crate::program::SourceLocation;
 
-const KEYWORDS: [&str; 3] = [
+pub(crate) const KEYWORDS: [&str; 9] = [
     "hint",
     "error_id",
-    "error_message", // reserved for future use
+    "error_message",
+    "const",
+    "macro",
+    "endmacro",
+    "import",
+    "include",
+    "data",
 ];
 
+/// Keywords of [structured control flow](structured_control_flow). Unlike [`KEYWORDS`],
+/// they can be used as labels, since they are never followed by a colon.
+pub(crate) const CONTROL_FLOW_KEYWORDS: [&str; 7] =
+    ["if", "else", "endif", "while", "endwhile", "for", "endfor"];
+
 #[derive(Debug, PartialEq)]
 pub struct ParseError<'a> {
     pub input: &'a str,
@@ -55,6 +80,7 @@ pub enum InstructionToken<'a> {
     Breakpoint(&'a str),
     TypeHint(TypeHint, &'a str),
     AssertionContext(AssertionContext, &'a str),
+    DataSection(DataSection, &'a str),
 }
 
 impl Display for ParseError<'_> {
@@ -81,9 +107,21 @@ impl<'a> InstructionToken<'a> {
             Self::Breakpoint(token_str) => token_str,
             Self::TypeHint(_, token_str) => token_str,
             Self::AssertionContext(_, token_str) => token_str,
+            Self::DataSection(_, token_str) => token_str,
         }
     }
 
+    /// The location of this token in the given source code, which is in the given file,
+    /// if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token was not parsed from the given source code.
+    pub fn source_location(&self, source: &str, file: Option<&str>) -> SourceLocation {
+        let mut locations = source_locations(source, file, &[self]);
+        locations.remove(0)
+    }
+
     pub fn to_labelled_instruction(&self) -> LabelledInstruction {
         match self {
             Self::Instruction(instr, _) => LabelledInstruction::Instruction(instr.to_owned()),
@@ -91,8 +129,52 @@ impl<'a> InstructionToken<'a> {
             Self::Breakpoint(_) => LabelledInstruction::Breakpoint,
             Self::TypeHint(type_hint, _) => LabelledInstruction::TypeHint(type_hint.to_owned()),
             Self::AssertionContext(ctx, _) => LabelledInstruction::AssertionContext(ctx.to_owned()),
+            Self::DataSection(data, _) => LabelledInstruction::DataSection(data.to_owned()),
+        }
+    }
+}
+
+/// The locations of the given tokens in the given source code, which is in the given
+/// file, if any. Unlike [locating](InstructionToken::source_location) the tokens one by
+/// one, this takes only one pass over the source code.
+///
+/// # Panics
+///
+/// Panics if any of the tokens was not parsed from the given source code.
+pub(crate) fn source_locations(
+    source: &str,
+    file: Option<&str>,
+    tokens: &[&InstructionToken],
+) -> Vec<SourceLocation> {
+    let offsets = tokens
+        .iter()
+        .map(|token| source.offset(token.token_str()))
+        .collect_vec();
+    assert!(
+        offsets.iter().all(|&offset| offset <= source.len()),
+        "token must be part of the source code"
+    );
+
+    let mut locations = vec![None; tokens.len()];
+    let (mut line, mut column, mut position) = (1, 1, 0);
+    for index in (0..tokens.len()).sorted_by_key(|&index| offsets[index]) {
+        for char in source[position..offsets[index]].chars() {
+            if char == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
         }
+        position = offsets[index];
+        locations[index] = Some(SourceLocation {
+            file: file.map(str::to_string),
+            line,
+            column,
+        });
     }
+
+    locations.into_iter().flatten().collect()
 }
 
 pub fn to_labelled_instructions(instructions: &[InstructionToken]) -> Vec<LabelledInstruction> {
@@ -102,6 +184,65 @@ pub fn to_labelled_instructions(instructions: &[InstructionToken]) -> Vec<Labell
         .collect()
 }
 
+/// A directive at the very beginning of a module, referring to some other module.
+/// See [`Linker`](crate::linker::Linker).
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub(crate) enum ModuleDirective {
+    Import(String),
+    Include(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ParsedModule<'a> {
+    pub directives: Vec<ModuleDirective>,
+    pub instructions: Vec<InstructionToken<'a>>,
+}
+
+/// Parse a module with the given name. Unlike [`parse`], labels are not checked for
+/// existence or uniqueness, since they can be defined in, or called from, other modules.
+/// Labels generated for control-flow constructs that follow no label are derived from
+/// the module's name, keeping them unique across modules that share a namespace.
+pub(crate) fn parse_module<'a>(
+    input: &'a str,
+    name: &str,
+) -> Result<ParsedModule<'a>, ParseError<'a>> {
+    let (_, (directives, instructions)) = module(input, name)
+        .finish()
+        .map_err(|errors| ParseError { input, errors })?;
+
+    ensure_assertion_context_is_matched_with_assertion(input, &instructions)?;
+    ensure_data_sections_are_disjoint(input, &instructions)?;
+    ensure_relative_calls_are_in_range(input, &instructions)?;
+
+    let module = ParsedModule {
+        directives,
+        instructions,
+    };
+    Ok(module)
+}
+
+/// Parse code that is assembled separately into an [`Object`](crate::object::Object).
+/// Like a program, it must not define any label more than once. Like a module, it can
+/// call labels that it does not define.
+pub(crate) fn parse_object(input: &str) -> Result<Vec<InstructionToken>, ParseError> {
+    let (_, instructions) = tokenize(input)
+        .finish()
+        .map_err(|errors| ParseError { input, errors })?;
+
+    if let Err(mut err) = ensure_no_missing_or_duplicate_labels(input, &instructions) {
+        let missing_label = VerboseErrorKind::Context("missing label");
+        err.errors.errors.retain(|(_, kind)| *kind != missing_label);
+        if !err.errors.errors.is_empty() {
+            return Err(err);
+        }
+    }
+    ensure_assertion_context_is_matched_with_assertion(input, &instructions)?;
+    ensure_data_sections_are_disjoint(input, &instructions)?;
+    ensure_relative_calls_are_in_range(input, &instructions)?;
+
+    Ok(instructions)
+}
+
 /// Parse a program
 pub(crate) fn parse(input: &str) -> Result<Vec<InstructionToken>, ParseError> {
     let (_, instructions) = tokenize(input)
@@ -110,11 +251,13 @@ pub(crate) fn parse(input: &str) -> Result<Vec<InstructionToken>, ParseError> {
 
     ensure_no_missing_or_duplicate_labels(input, &instructions)?;
     ensure_assertion_context_is_matched_with_assertion(input, &instructions)?;
+    ensure_data_sections_are_disjoint(input, &instructions)?;
+    ensure_relative_calls_are_in_range(input, &instructions)?;
 
     Ok(instructions)
 }
 
-fn ensure_no_missing_or_duplicate_labels<'a>(
+pub(crate) fn ensure_no_missing_or_duplicate_labels<'a>(
     input: &'a str,
     instructions: &[InstructionToken<'a>],
 ) -> Result<(), ParseError<'a>> {
@@ -149,7 +292,8 @@ fn identify_missing_labels<'a>(
     let mut missing_labels = HashSet::default();
     for instruction in instructions {
         if let InstructionToken::Instruction(AnInstruction::Call(label), _) = instruction {
-            if !seen_labels.contains_key(label.as_str()) {
+            let is_relative = relative_call_offset(label).is_some();
+            if !is_relative && !seen_labels.contains_key(label.as_str()) {
                 missing_labels.insert(instruction.to_owned());
             }
         }
@@ -183,7 +327,7 @@ fn errors_for_labels_with_context(
         .collect()
 }
 
-fn ensure_assertion_context_is_matched_with_assertion<'a>(
+pub(crate) fn ensure_assertion_context_is_matched_with_assertion<'a>(
     input: &'a str,
     instructions: &[InstructionToken<'a>],
 ) -> Result<(), ParseError<'a>> {
@@ -197,7 +341,7 @@ fn ensure_assertion_context_is_matched_with_assertion<'a>(
             IT::Instruction(AnInstruction::Assert | AnInstruction::AssertVector, _) => {
                 accept_id = true;
             }
-            IT::AssertionContext(AssertionContext::ID(_), _) => {
+            IT::AssertionContext(_, _) => {
                 if !accept_id {
                     incorrectly_placed_contexts.insert(instruction.clone());
                 }
@@ -220,321 +364,1112 @@ fn ensure_assertion_context_is_matched_with_assertion<'a>(
     }
 }
 
+pub(crate) fn ensure_data_sections_are_disjoint<'a>(
+    input: &'a str,
+    instructions: &[InstructionToken<'a>],
+) -> Result<(), ParseError<'a>> {
+    let overlapping_sections = indices_of_overlapping_data_sections(instructions)
+        .into_iter()
+        .map(|index| instructions[index].clone())
+        .collect();
+    let parser_context = VerboseErrorKind::Context("overlapping data section");
+    let errors = errors_for_labels_with_context(overlapping_sections, parser_context);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        let errors = VerboseError { errors };
+        Err(ParseError { input, errors })
+    }
+}
+
+/// Ensure that every relative `call` targets the start of an instruction in the same
+/// code, or the end of that code. Anything else could only be resolved by knowing where
+/// the code ends up, defeating the purpose of relative calls.
+pub(crate) fn ensure_relative_calls_are_in_range<'a>(
+    input: &'a str,
+    instructions: &[InstructionToken<'a>],
+) -> Result<(), ParseError<'a>> {
+    let mut address = 0_u64;
+    let mut valid_targets = HashSet::from([0]);
+    let mut relative_calls = vec![];
+    for token in instructions {
+        let InstructionToken::Instruction(instruction, _) = token else {
+            continue;
+        };
+        if let AnInstruction::Call(target) = instruction {
+            if let Some(offset) = relative_call_offset(target) {
+                relative_calls.push((address.checked_add_signed(offset), token));
+            }
+        }
+        address += instruction.size() as u64;
+        valid_targets.insert(address);
+    }
+
+    let out_of_range_calls = relative_calls
+        .into_iter()
+        .filter(|(target, _)| !target.is_some_and(|t| valid_targets.contains(&t)))
+        .map(|(_, token)| token.clone())
+        .collect();
+    let parser_context = VerboseErrorKind::Context("relative call target out of range");
+    let errors = errors_for_labels_with_context(out_of_range_calls, parser_context);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        let errors = VerboseError { errors };
+        Err(ParseError { input, errors })
+    }
+}
+
+/// The indices of all data sections that place a word at an address where some
+/// preceding data section already places a word.
+pub(crate) fn indices_of_overlapping_data_sections<'t, 'a: 't>(
+    tokens: impl IntoIterator<Item = &'t InstructionToken<'a>>,
+) -> Vec<usize> {
+    let mut occupied_addresses = HashSet::new();
+    let mut indices = vec![];
+    for (index, token) in tokens.into_iter().enumerate() {
+        let InstructionToken::DataSection(data, _) = token else {
+            continue;
+        };
+        let mut is_overlapping = false;
+        for offset in 0..data.words.len() {
+            let address = data.address + bfe!(offset);
+            is_overlapping |= !occupied_addresses.insert(address);
+        }
+        if is_overlapping {
+            indices.push(index);
+        }
+    }
+
+    indices
+}
+
 /// Auxiliary type alias: `IResult` defaults to `nom::error::Error` as concrete
 /// error type, but we want `nom::error::VerboseError` as it allows `context()`.
 type ParseResult<'input, Out> = IResult<&'input str, Out, VerboseError<&'input str>>;
 
-pub fn tokenize(s: &str) -> ParseResult<Vec<InstructionToken>> {
-    let (s, _) = comment_or_whitespace0(s)?;
-    let (s, instructions) = many0(alt((
-        label,
-        labelled_instruction,
-        breakpoint,
-        type_hint,
-        assertion_context,
-    )))(s)?;
-    let (s, _) = nom::error::context("expecting label, instruction or eof", eof)(s)?;
+/// Auxiliary type alias for the error of a failed [`ParseResult`].
+pub(crate) type ParseFailure<'input> = nom::Err<VerboseError<&'input str>>;
+
+/// The named constants and macros that are visible at some point in the source code, as
+/// well as the arguments of the macro that is currently being expanded, if any.
+#[derive(Debug, Default, Clone)]
+struct Scope<'a> {
+    constants: HashMap<String, &'a str>,
+    macros: HashMap<String, MacroDefinition<'a>>,
+    macro_arguments: HashMap<String, &'a str>,
+    expansion_stack: Vec<String>,
+    lowering: Lowering<'a>,
+}
 
-    Ok((s, instructions))
+/// The state of lowering [structured control flow](structured_control_flow). Unlike the
+/// rest of the [`Scope`], it is shared with the bodies of expanded macros.
+#[derive(Debug, Default, Clone)]
+struct Lowering<'a> {
+    /// The most recent label. Generated labels are derived from it.
+    enclosing_label: String,
+
+    /// The number of control-flow constructs since the most recent label.
+    num_constructs: usize,
+
+    /// Generated subroutines that are yet to be placed. They are placed right after the
+    /// next unconditional `halt`, `return`, `recurse`, or `recurse_or_return`, where they
+    /// cannot be reached by accident.
+    pending_subroutines: Vec<InstructionToken<'a>>,
+
+    /// Whether the most recent instruction is `skiz`, which makes the next instruction
+    /// conditional.
+    follows_skiz: bool,
+
+    /// The address of the next instruction in the code as written, _i.e._, without the
+    /// code generated for control-flow constructs.
+    address: u64,
+
+    /// The addresses in the code as written where generated code is inserted.
+    insertion_points: Vec<u64>,
+
+    /// All relative `call`s, together with their addresses in the code as written.
+    relative_calls: Vec<(u64, InstructionToken<'a>)>,
 }
 
-fn label(label_s: &str) -> ParseResult<InstructionToken> {
-    let (s, addr) = label_addr(label_s)?;
-    let (s, _) = whitespace0(s)?; // whitespace between label and ':' is allowed
-    let (s, _) = token0(":")(s)?; // don't require space after ':'
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ConstantDefinition<'a> {
+    name: String,
+    value: &'a str,
+    token_str: &'a str,
+}
 
-    // Checking if `<label>:` is an instruction must happen after parsing `:`, since
-    // otherwise `cut` will reject the alternative parsers of `label`, like
-    // `labelled_instruction`, where valid instructions names *are* allowed.
-    if is_illegal_label(&addr) {
-        let failure_reason = "label must be neither instruction nor keyword";
-        return cut(nom::error::context(failure_reason, fail))(label_s);
-    }
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct MacroDefinition<'a> {
+    name: String,
+    parameters: Vec<String>,
 
-    Ok((s, InstructionToken::Label(addr, label_s)))
+    /// The macro's body is only tokenized once the macro is invoked, since only then
+    /// are the values of its parameters known.
+    body: &'a str,
+    token_str: &'a str,
 }
 
-fn breakpoint(breakpoint_s: &str) -> ParseResult<InstructionToken> {
-    let (s, _) = token1("break")(breakpoint_s)?;
-    Ok((s, InstructionToken::Breakpoint(breakpoint_s)))
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct MacroInvocation<'a> {
+    name: String,
+    arguments: Vec<&'a str>,
+    token_str: &'a str,
 }
 
-fn labelled_instruction(s_instr: &str) -> ParseResult<InstructionToken> {
-    let (s, instr) = an_instruction(s_instr)?;
-    Ok((s, InstructionToken::Instruction(instr, s_instr)))
+/// Anything [`tokenize`] can encounter. Only some items turn into [`InstructionToken`]s
+/// directly, others change the [`Scope`] or expand into multiple [`InstructionToken`]s.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Item<'a> {
+    Token(InstructionToken<'a>),
+    ConstantDefinition(ConstantDefinition<'a>),
+    MacroDefinition(MacroDefinition<'a>),
+    MacroInvocation(MacroInvocation<'a>),
+
+    /// A data section also defines a constant for its address.
+    DataSection(InstructionToken<'a>, ConstantDefinition<'a>),
 }
 
-fn an_instruction(s: &str) -> ParseResult<AnInstruction<String>> {
-    // OpStack manipulation
-    let pop = pop_instruction();
-    let push = push_instruction();
-    let divine = divine_instruction();
-    let pick = pick_instruction();
-    let place = place_instruction();
-    let dup = dup_instruction();
-    let swap = swap_instruction();
+impl<'a> Scope<'a> {
+    /// The value of a symbol that is used in place of a literal instruction argument.
+    /// Macro arguments shadow constants.
+    fn resolve_argument(&self, symbol: &str) -> Option<&'a str> {
+        self.macro_arguments
+            .get(symbol)
+            .or_else(|| self.constants.get(symbol))
+            .copied()
+    }
 
-    let opstack_manipulation = alt((pop, push, divine, pick, place, dup, swap));
+    /// The value of a symbol that is used in place of a label. Only macro arguments can
+    /// stand in for labels.
+    fn resolve_label(&self, symbol: &str) -> Option<&'a str> {
+        self.macro_arguments.get(symbol).copied()
+    }
 
-    // Control flow
-    let halt = instruction("halt", AnInstruction::Halt);
-    let nop = instruction("nop", AnInstruction::Nop);
-    let skiz = instruction("skiz", AnInstruction::Skiz);
-    let call = call_instruction();
-    let return_ = instruction("return", AnInstruction::Return);
-    let recurse = instruction("recurse", AnInstruction::Recurse);
-    let recurse_or_return = instruction("recurse_or_return", AnInstruction::RecurseOrReturn);
-    let assert = instruction("assert", AnInstruction::Assert);
+    fn define_constant(
+        &mut self,
+        constant: ConstantDefinition<'a>,
+    ) -> Result<(), ParseFailure<'a>> {
+        let Entry::Vacant(entry) = self.constants.entry(constant.name) else {
+            return Err(failure(constant.token_str, "duplicate constant"));
+        };
+        entry.insert(constant.value);
+        Ok(())
+    }
 
-    let control_flow = alt((nop, skiz, call, return_, halt));
+    fn define_macro(&mut self, definition: MacroDefinition<'a>) -> Result<(), ParseFailure<'a>> {
+        let Entry::Vacant(entry) = self.macros.entry(definition.name.clone()) else {
+            return Err(failure(definition.token_str, "duplicate macro"));
+        };
+        entry.insert(definition);
+        Ok(())
+    }
 
-    // Memory access
-    let read_mem = read_mem_instruction();
-    let write_mem = write_mem_instruction();
+    /// Expand the invoked macro's body into [`InstructionToken`]s. Errors are reported
+    /// both where they occur in the macro's definition and at the site of the invocation.
+    fn expand(
+        &mut self,
+        invocation: MacroInvocation<'a>,
+    ) -> Result<Vec<InstructionToken<'a>>, ParseFailure<'a>> {
+        let call_site = invocation.token_str;
+        let Some(definition) = self.macros.get(&invocation.name) else {
+            return Err(failure(call_site, "unknown macro"));
+        };
+        let with_definition_site = |mut failure: ParseFailure<'a>| {
+            if let nom::Err::Failure(ref mut error) = failure {
+                let context = VerboseErrorKind::Context("macro defined here");
+                error.errors.push((definition.token_str, context));
+            }
+            failure
+        };
 
-    let memory_access = alt((read_mem, write_mem));
+        if self.expansion_stack.contains(&invocation.name) {
+            let failure = failure(call_site, "recursive macro invocation");
+            return Err(with_definition_site(failure));
+        }
+        if definition.parameters.len() != invocation.arguments.len() {
+            let failure = failure(call_site, "wrong number of macro arguments");
+            return Err(with_definition_site(failure));
+        }
 
-    // Hashing-related instructions
-    let hash = instruction("hash", AnInstruction::Hash);
-    let assert_vector = instruction("assert_vector", AnInstruction::AssertVector);
-    let sponge_init = instruction("sponge_init", AnInstruction::SpongeInit);
-    let sponge_absorb = instruction("sponge_absorb", AnInstruction::SpongeAbsorb);
-    let sponge_absorb_mem = instruction("sponge_absorb_mem", AnInstruction::SpongeAbsorbMem);
-    let sponge_squeeze = instruction("sponge_squeeze", AnInstruction::SpongeSqueeze);
+        let arguments = definition.parameters.iter().zip(invocation.arguments);
+        let arguments = arguments
+            .map(|(parameter, argument)| {
+                let value = self.resolve_argument(argument).unwrap_or(argument);
+                (parameter.clone(), value)
+            })
+            .collect();
 
-    let hashing_related = alt((hash, sponge_init, sponge_squeeze));
+        let mut body_scope = self.clone();
+        body_scope.macro_arguments = arguments;
+        body_scope.expansion_stack.push(invocation.name);
 
-    // Arithmetic on stack instructions
-    let add = instruction("add", AnInstruction::Add);
-    let addi = addi_instruction();
-    let mul = instruction("mul", AnInstruction::Mul);
-    let invert = instruction("invert", AnInstruction::Invert);
-    let eq = instruction("eq", AnInstruction::Eq);
-    let split = instruction("split", AnInstruction::Split);
-    let lt = instruction("lt", AnInstruction::Lt);
-    let and = instruction("and", AnInstruction::And);
-    let xor = instruction("xor", AnInstruction::Xor);
-    let log_2_floor = instruction("log_2_floor", AnInstruction::Log2Floor);
-    let pow = instruction("pow", AnInstruction::Pow);
-    let div_mod = instruction("div_mod", AnInstruction::DivMod);
-    let pop_count = instruction("pop_count", AnInstruction::PopCount);
-    let xx_add = instruction("xx_add", AnInstruction::XxAdd);
-    let xx_mul = instruction("xx_mul", AnInstruction::XxMul);
-    let x_invert = instruction("x_invert", AnInstruction::XInvert);
-    let xb_mul = instruction("xb_mul", AnInstruction::XbMul);
-
-    let base_field_arithmetic_on_stack = alt((mul, invert, eq));
-    let bitwise_arithmetic_on_stack =
-        alt((split, lt, and, xor, log_2_floor, pow, div_mod, pop_count));
-    let extension_field_arithmetic_on_stack = alt((xx_add, xx_mul, x_invert, xb_mul));
-    let arithmetic_on_stack = alt((
-        base_field_arithmetic_on_stack,
-        bitwise_arithmetic_on_stack,
-        extension_field_arithmetic_on_stack,
-    ));
+        let mut expand_body = |body| {
+            let (body, _) = comment_or_whitespace0(body)?;
+            let (body, tokens) = tokens_in_scope(body, &mut body_scope)?;
+            nom::error::context("expecting label, instruction or eof", eof)(body)?;
+            Ok(tokens)
+        };
 
-    // Read/write
-    let read_io = read_io_instruction();
-    let write_io = write_io_instruction();
+        let tokens = expand_body(definition.body).map_err(|failure: ParseFailure<'a>| {
+            let (nom::Err::Error(mut error) | nom::Err::Failure(mut error)) = failure else {
+                return failure;
+            };
+            let context = VerboseErrorKind::Context("in expansion of macro");
+            error.errors.push((call_site, context));
+            with_definition_site(nom::Err::Failure(error))
+        });
+        self.lowering = body_scope.lowering;
+        tokens
+    }
+}
 
-    let read_write = alt((read_io, write_io));
+impl<'a> Lowering<'a> {
+    /// Keep track of the given token. Returns the token, followed by all pending
+    /// subroutines if they can be placed after it.
+    fn follow(&mut self, token: InstructionToken<'a>) -> Vec<InstructionToken<'a>> {
+        if let InstructionToken::Label(label, _) = &token {
+            self.enclosing_label.clone_from(label);
+            self.num_constructs = 0;
+        }
 
-    // Many-in-One
-    let merkle_step = instruction("merkle_step", AnInstruction::MerkleStep);
-    let merkle_step_mem = instruction("merkle_step_mem", AnInstruction::MerkleStepMem);
-    let xx_dot_step = instruction("xx_dot_step", AnInstruction::XxDotStep);
-    let xb_dot_step = instruction("xb_dot_step", AnInstruction::XbDotStep);
-
-    let many_to_one = alt((xx_dot_step, xb_dot_step));
-
-    // Because of common prefixes, the following parsers are sensitive to order.
-    // Successfully parsing "assert" before trying "assert_vector" can lead to
-    // picking the wrong one. By trying them in the order of longest first, less
-    // backtracking is necessary.
-    let syntax_ambiguous = alt((
-        recurse_or_return,
-        recurse,
-        assert_vector,
-        assert,
-        sponge_absorb_mem,
-        sponge_absorb,
-        addi,
-        add,
-        merkle_step_mem,
-        merkle_step,
-    ));
+        let InstructionToken::Instruction(instruction, _) = &token else {
+            return vec![token];
+        };
+        let is_final = !self.follows_skiz
+            && matches!(
+                instruction,
+                AnInstruction::Halt
+                    | AnInstruction::Return
+                    | AnInstruction::Recurse
+                    | AnInstruction::RecurseOrReturn
+            );
+        self.follows_skiz = matches!(instruction, AnInstruction::Skiz);
+
+        let call_address = self.address;
+        self.address += instruction.size() as u64;
+        if let AnInstruction::Call(target) = instruction {
+            if relative_call_offset(target).is_some() {
+                self.relative_calls.push((call_address, token.clone()));
+            }
+        }
 
-    alt((
-        opstack_manipulation,
-        control_flow,
-        memory_access,
-        hashing_related,
-        arithmetic_on_stack,
-        read_write,
-        many_to_one,
-        syntax_ambiguous,
-    ))(s)
-}
+        let mut tokens = vec![token];
+        if is_final && !self.pending_subroutines.is_empty() {
+            tokens.append(&mut self.pending_subroutines);
+            self.insertion_points.push(self.address);
+        }
+        tokens
+    }
 
-fn is_illegal_label(s: &str) -> bool {
-    ALL_INSTRUCTION_NAMES.contains(&s) || KEYWORDS.contains(&s)
-}
+    /// Record that generated code is inserted at the current address.
+    fn insert_generated_code(&mut self) {
+        self.insertion_points.push(self.address);
+    }
 
-fn instruction<'a>(
-    name: &'a str,
-    instruction: AnInstruction<String>,
-) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> {
-    move |s: &'a str| {
-        let (s, _) = token1(name)(s)?;
-        Ok((s, instruction.clone()))
+    /// A label for the body of the current control-flow construct, like
+    /// `foo__then_0` for the first `if` following label `foo`.
+    fn generate_label(&self, kind: &str) -> String {
+        format!("{}__{kind}_{}", self.enclosing_label, self.num_constructs)
     }
-}
 
-fn pop_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("pop")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::Pop(arg)))
+    fn ensure_all_subroutines_are_placed(&self) -> Result<(), ParseFailure<'a>> {
+        let Some(subroutine) = self.pending_subroutines.first() else {
+            return Ok(());
+        };
+        let context = "control flow must be followed by an unconditional `halt`, `return`, \
+            `recurse`, or `recurse_or_return`";
+        Err(failure(subroutine.token_str(), context))
+    }
+
+    /// Ensure that no code is generated between any relative `call` and its target.
+    /// Otherwise, the target would depend on the lowering of control-flow constructs.
+    fn ensure_relative_calls_span_no_generated_code(&self) -> Result<(), ParseFailure<'a>> {
+        for (call_address, call) in &self.relative_calls {
+            let InstructionToken::Instruction(AnInstruction::Call(target), _) = call else {
+                continue;
+            };
+            let Some(offset) = relative_call_offset(target) else {
+                continue;
+            };
+            let Some(target_address) = call_address.checked_add_signed(offset) else {
+                continue;
+            };
+            let is_spanned = |&point: &u64| {
+                if offset < 0 {
+                    target_address < point && point <= *call_address
+                } else {
+                    *call_address < point && point <= target_address
+                }
+            };
+            if self.insertion_points.iter().any(is_spanned) {
+                let context = "relative call must not span control flow";
+                return Err(failure(call.token_str(), context));
+            }
+        }
+
+        Ok(())
     }
 }
 
-fn push_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("push")(s)?;
-        let (s, elem) = field_element(s)?;
-        Ok((s, AnInstruction::Push(elem)))
-    }
+/// A [`ParseFailure`] that cannot be recovered from, with the given context.
+fn failure<'a>(s: &'a str, context: &'static str) -> ParseFailure<'a> {
+    let errors = vec![(s, VerboseErrorKind::Context(context))];
+    nom::Err::Failure(VerboseError { errors })
 }
 
-fn addi_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("addi")(s)?;
-        let (s, elem) = field_element(s)?;
-        Ok((s, AnInstruction::AddI(elem)))
-    }
+pub fn tokenize(s: &str) -> ParseResult<Vec<InstructionToken>> {
+    tokenize_module(s, "")
 }
 
-fn divine_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("divine")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::Divine(arg)))
-    }
+/// Tokenize like [`tokenize`], deriving generated labels from the given module name
+/// until the first label. See [`parse_module`].
+fn tokenize_module<'a>(s: &'a str, name: &str) -> ParseResult<'a, Vec<InstructionToken<'a>>> {
+    let mut scope = Scope::default();
+    scope.lowering.enclosing_label = name.to_string();
+    let (s, _) = comment_or_whitespace0(s)?;
+    let (s, instructions) = tokens_in_scope(s, &mut scope)?;
+    let (s, _) = nom::error::context("expecting label, instruction or eof", eof)(s)?;
+    scope.lowering.ensure_all_subroutines_are_placed()?;
+    scope
+        .lowering
+        .ensure_relative_calls_span_no_generated_code()?;
+
+    Ok((s, instructions))
 }
 
-fn pick_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("pick")(s)?;
-        let (s, arg) = stack_register(s)?;
-        Ok((s, AnInstruction::Pick(arg)))
-    }
+fn module<'a>(
+    s: &'a str,
+    name: &str,
+) -> ParseResult<'a, (Vec<ModuleDirective>, Vec<InstructionToken<'a>>)> {
+    let (s, directives) = module_directives(s)?;
+    let (s, instructions) = tokenize_module(s, name)?;
+
+    Ok((s, (directives, instructions)))
 }
 
-fn place_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("place")(s)?;
-        let (s, arg) = stack_register(s)?;
-        Ok((s, AnInstruction::Place(arg)))
-    }
+fn module_directives(s: &str) -> ParseResult<Vec<ModuleDirective>> {
+    let (s, _) = comment_or_whitespace0(s)?;
+    many0(alt((import_directive, include_directive)))(s)
 }
 
-fn dup_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("dup")(s)?; // require space before argument
-        let (s, stack_register) = stack_register(s)?;
-        Ok((s, AnInstruction::Dup(stack_register)))
+/// Parse a module like [`parse_module`], but instead of stopping at the first
+/// statement that cannot be parsed, skip it and continue with the next one. The
+/// statements that could not be parsed are returned alongside the reason.
+pub(crate) fn parse_module_with_recovery(input: &str) -> (ParsedModule, Vec<(&str, ParseFailure)>) {
+    let (mut s, directives) = module_directives(input).unwrap_or((input, vec![]));
+    let mut scope = Scope::default();
+    let mut instructions = vec![];
+    let mut failures = vec![];
+    loop {
+        (s, _) = comment_or_whitespace0(s).unwrap_or((s, ""));
+        if s.is_empty() {
+            break;
+        }
+        match next_tokens(s, &mut scope) {
+            Ok((rest, tokens)) => {
+                instructions.extend(tokens);
+                s = rest;
+            }
+            Err(failure) => {
+                failures.push((s, failure));
+                s = skip_statement(s);
+            }
+        }
+    }
+    if let Err(failure) = scope.lowering.ensure_all_subroutines_are_placed() {
+        let statement = match &failure {
+            nom::Err::Error(error) | nom::Err::Failure(error) => error.errors[0].0,
+            nom::Err::Incomplete(_) => input,
+        };
+        failures.push((statement, failure));
+    }
+    if let Err(failure) = scope
+        .lowering
+        .ensure_relative_calls_span_no_generated_code()
+    {
+        let statement = match &failure {
+            nom::Err::Error(error) | nom::Err::Failure(error) => error.errors[0].0,
+            nom::Err::Incomplete(_) => input,
+        };
+        failures.push((statement, failure));
     }
+
+    let module = ParsedModule {
+        directives,
+        instructions,
+    };
+    (module, failures)
 }
 
-fn swap_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("swap")(s)?;
-        let (s, stack_register) = stack_register(s)?;
-        Ok((s, AnInstruction::Swap(stack_register)))
-    }
+/// Tokenize like [`tokenize`], but skip all statements that cannot be parsed instead
+/// of failing. Useful for tools working on incomplete code, like editor integrations.
+/// See [`diagnose`](crate::diagnostic::diagnose) for the reasons why statements were
+/// skipped.
+pub fn tokenize_with_recovery(input: &str) -> Vec<InstructionToken> {
+    let (module, _) = parse_module_with_recovery(input);
+    module.instructions
 }
 
-fn call_instruction<'a>() -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> {
-    move |s: &'a str| {
-        let (s, _) = token1("call")(s)?;
-        let (s, label) = label_addr(s)?;
-        let (s, _) = comment_or_whitespace1(s)?;
+/// Skip the statement at the start of the given string, which could not be parsed.
+/// Statements spanning multiple words are skipped entirely where that is possible
+/// without parsing them, to avoid follow-up errors.
+fn skip_statement(s: &str) -> &str {
+    let first_word = &s[..word_length(s)];
+    let mut rest = &s[first_word.len()..];
+    let mut next_word = || {
+        rest = rest.trim_start();
+        while let Ok((after_comment, ())) = comment1(rest) {
+            rest = after_comment.trim_start();
+        }
+        let word = &rest[..word_length(rest)];
+        rest = &rest[word.len()..];
+        word
+    };
 
-        // This check cannot be moved into `label_addr`, since `label_addr` is shared
-        // between the scenarios `<label>:` and `call <label>`; the former requires
-        // parsing the `:` before rejecting a possible instruction name in the label.
-        if is_illegal_label(&label) {
-            let failure_reason = "label must be neither instruction nor keyword";
-            return cut(nom::error::context(failure_reason, fail))(s);
+    match first_word {
+        "macro" => while !matches!(next_word(), "endmacro" | "") {},
+        "if" | "while" | "for" => {
+            let mut nesting_depth = 1;
+            while nesting_depth > 0 {
+                match next_word() {
+                    "if" | "while" | "for" => nesting_depth += 1,
+                    "endif" | "endwhile" | "endfor" => nesting_depth -= 1,
+                    "" => break,
+                    _ => (),
+                }
+            }
+        }
+        "hint" | "const" | "data" => return s.find(is_linebreak).map_or("", |end| &s[end..]),
+        _ if takes_argument(first_word) => _ = next_word(),
+        _ => {
+            let mut invocation = first_word.to_string();
+            while invocation.matches('(').count() > invocation.matches(')').count() {
+                let word = next_word();
+                if word.is_empty() {
+                    break;
+                }
+                invocation.push_str(word);
+            }
         }
-
-        Ok((s, AnInstruction::Call(label)))
     }
+
+    rest
 }
 
-fn read_mem_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("read_mem")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::ReadMem(arg)))
-    }
+/// Whether the given word is an instruction or keyword that is followed by an argument.
+pub(crate) fn takes_argument(word: &str) -> bool {
+    let is_keyword_with_argument = matches!(
+        word,
+        "error_id" | "error_message" | "import" | "include" | "for"
+    );
+    let is_instruction_with_argument = ALL_INSTRUCTIONS
+        .iter()
+        .any(|instruction| instruction.name() == word && instruction.arg().is_some());
+    is_keyword_with_argument || is_instruction_with_argument
 }
 
-fn write_mem_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("write_mem")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::WriteMem(arg)))
+/// The length of the word at the start of the given string, in bytes. A word ends with
+/// whitespace, the start of a comment, or a label's colon. String literals, including
+/// any whitespace they contain, are part of a word.
+pub(crate) fn word_length(s: &str) -> usize {
+    let mut is_in_string_literal = false;
+    let mut is_escaped = false;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if is_in_string_literal {
+            match c {
+                _ if is_escaped => is_escaped = false,
+                '\\' => is_escaped = true,
+                '"' => is_in_string_literal = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        let next = chars.peek().map(|&(_, next)| next);
+        match c {
+            '"' => is_in_string_literal = true,
+            _ if c.is_whitespace() => return i,
+            '/' if next == Some('/') => return i,
+            ':' if next == Some(':') => _ = chars.next(),
+            ':' => return i + 1,
+            _ => (),
+        }
     }
+
+    s.len()
 }
 
-fn read_io_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("read_io")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::ReadIo(arg)))
-    }
+fn import_directive(s: &str) -> ParseResult<ModuleDirective> {
+    let (s, _) = token1("import")(s)?;
+    let (s, module) = module_name(s)?;
+    Ok((s, ModuleDirective::Import(module)))
 }
 
-fn write_io_instruction() -> impl Fn(&str) -> ParseResult<AnInstruction<String>> {
-    move |s: &str| {
-        let (s, _) = token1("write_io")(s)?;
-        let (s, arg) = number_of_words(s)?;
-        Ok((s, AnInstruction::WriteIo(arg)))
-    }
+fn include_directive(s: &str) -> ParseResult<ModuleDirective> {
+    let (s, _) = token1("include")(s)?;
+    let (s, module) = module_name(s)?;
+    Ok((s, ModuleDirective::Include(module)))
 }
 
-fn field_element(s_orig: &str) -> ParseResult<BFieldElement> {
-    let (s, negative) = opt(token0("-"))(s_orig)?;
-    let (s, n) = digit1(s)?;
+fn module_name(s: &str) -> ParseResult<String> {
+    let (s, name) = label_addr(s)?;
     let (s, _) = comment_or_whitespace1(s)?;
+    Ok((s, name))
+}
 
-    let Ok(mut n): Result<i128, _> = n.parse() else {
-        return nom::error::context("out-of-bounds constant", fail)(s);
-    };
-
-    let quotient = i128::from(BFieldElement::P);
-    if n >= quotient {
-        return nom::error::context("out-of-bounds constant", fail)(s_orig);
-    }
-
-    if negative.is_some() {
-        n *= -1;
-        n += quotient;
+/// Parse [`InstructionToken`]s for as long as possible. Constants and macros are added to
+/// the [`Scope`] as they are defined, and macro invocations are expanded in place.
+fn tokens_in_scope<'a>(
+    mut s: &'a str,
+    scope: &mut Scope<'a>,
+) -> ParseResult<'a, Vec<InstructionToken<'a>>> {
+    let mut tokens = vec![];
+    loop {
+        let (rest, next_tokens) = match next_tokens(s, scope) {
+            Ok(parsed) => parsed,
+            Err(nom::Err::Error(_)) => return Ok((s, tokens)),
+            Err(err) => return Err(err),
+        };
+        tokens.extend(next_tokens);
+        s = rest;
     }
+}
 
-    Ok((s, BFieldElement::new(n as u64)))
+/// Parse the next [`Item`], and turn it into the [`InstructionToken`]s it stands for.
+fn next_tokens<'a>(
+    s: &'a str,
+    scope: &mut Scope<'a>,
+) -> ParseResult<'a, Vec<InstructionToken<'a>>> {
+    match structured_control_flow(s, scope) {
+        Err(nom::Err::Error(_)) => (),
+        result => return result,
+    }
+
+    let (s, item) = alt((
+        map(constant_definition, Item::ConstantDefinition),
+        map(macro_definition, Item::MacroDefinition),
+        map(macro_invocation, Item::MacroInvocation),
+        map(data_section, |(token, constant)| {
+            Item::DataSection(token, constant)
+        }),
+        map(token(scope), Item::Token),
+    ))(s)?;
+
+    let tokens = match item {
+        Item::Token(token) => scope.lowering.follow(token),
+        Item::ConstantDefinition(constant) => {
+            scope.define_constant(constant)?;
+            vec![]
+        }
+        Item::MacroDefinition(definition) => {
+            scope.define_macro(definition)?;
+            vec![]
+        }
+        Item::MacroInvocation(invocation) => scope.expand(invocation)?,
+        Item::DataSection(token, constant) => {
+            scope.define_constant(constant)?;
+            scope.lowering.follow(token)
+        }
+    };
+    Ok((s, tokens))
 }
 
-fn stack_register(s: &str) -> ParseResult<OpStackElement> {
-    let (s, n) = digit1(s)?;
-    let stack_register = match n {
-        "0" => OpStackElement::ST0,
-        "1" => OpStackElement::ST1,
-        "2" => OpStackElement::ST2,
-        "3" => OpStackElement::ST3,
-        "4" => OpStackElement::ST4,
-        "5" => OpStackElement::ST5,
+/// Parse structured control flow and lower it to plain instructions. The constructs are:
+///
+/// - `if … endif` and `if … else … endif`: pop the top of the stack and execute the
+///   first body if it is non-zero, the `else` body otherwise.
+/// - `while … endwhile`: pop the top of the stack and, if it is non-zero, execute the
+///   body and repeat. The body must push the condition for the next iteration.
+/// - `for n … endfor`: execute the body `n` times. During execution of the body, the
+///   number of remaining iterations, from `n` down to 1, is on top of the stack. The
+///   body must leave it there. It is popped after the last iteration.
+///
+/// Every body becomes a generated subroutine, which is
+/// [placed](Lowering::pending_subroutines) after the next unconditional `halt`,
+/// `return`, `recurse`, or `recurse_or_return`.
+/// Since `skiz` only skips one instruction, only `while` can follow `skiz`: it is the
+/// only construct that is lowered to a single instruction.
+fn structured_control_flow<'a>(
+    s_keyword: &'a str,
+    scope: &mut Scope<'a>,
+) -> ParseResult<'a, Vec<InstructionToken<'a>>> {
+    type I = AnInstruction<String>;
+    let token = |instruction| InstructionToken::Instruction(instruction, s_keyword);
+    let label = |label: &String| InstructionToken::Label(label.clone(), s_keyword);
+    let call = |label: &String| token(I::Call(label.clone()));
+
+    let (s, keyword) = alt((token1("if"), token1("while"), token1("for")))(s_keyword)
+        .map(|(s, ())| (s, word(s_keyword)))?;
+    let follows_skiz = scope.lowering.follows_skiz;
+    let (s, num_iterations) = match keyword {
+        "for" => argument(scope, num_iterations)(s).map_err(|err| match err {
+            nom::Err::Error(_) => failure(s, "expecting number of iterations"),
+            failure => failure,
+        })?,
+        _ => (s, 0),
+    };
+
+    let body_label = scope.lowering.generate_label(match keyword {
+        "if" => "then",
+        keyword => keyword,
+    });
+    let else_label = scope.lowering.generate_label("else");
+    scope.lowering.num_constructs += 1;
+
+    scope.lowering.insert_generated_code();
+    let (s, body) = construct_body(s, scope, &body_label)?;
+    scope.lowering.insert_generated_code();
+    let (s, else_body) = match keyword {
+        "if" => match token1("else")(s) {
+            Ok((s, ())) => {
+                let (s, else_body) = construct_body(s, scope, &else_label)?;
+                scope.lowering.insert_generated_code();
+                let (s, ()) = end_of_construct(s, s_keyword, "endif", "expecting `endif`")?;
+                (s, Some(else_body))
+            }
+            Err(_) => {
+                let expectation = "expecting `else` or `endif`";
+                let (s, ()) = end_of_construct(s, s_keyword, "endif", expectation)?;
+                (s, None)
+            }
+        },
+        "while" => end_of_construct(s, s_keyword, "endwhile", "expecting `endwhile`")
+            .map(|(s, ())| (s, None))?,
+        _ => end_of_construct(s, s_keyword, "endfor", "expecting `endfor`")
+            .map(|(s, ())| (s, None))?,
+    };
+
+    let (tokens, subroutine) = match (keyword, else_body) {
+        ("if", None) => {
+            let tokens = vec![token(I::Skiz), call(&body_label)];
+            let subroutine = [vec![label(&body_label)], body, vec![token(I::Return)]];
+            (tokens, subroutine.concat())
+        }
+        ("if", Some(else_body)) => {
+            let tokens = vec![
+                token(I::Push(bfe!(1))),
+                token(I::Swap(OpStackElement::ST1)),
+                token(I::Skiz),
+                call(&body_label),
+                token(I::Skiz),
+                call(&else_label),
+            ];
+            let then_prologue = vec![label(&body_label), token(I::Pop(NumberOfWords::N1))];
+            let then_epilogue = vec![token(I::Push(bfe!(0))), token(I::Return)];
+            let else_prologue = vec![label(&else_label)];
+            let else_epilogue = vec![token(I::Return)];
+            let subroutine = [
+                then_prologue,
+                body,
+                then_epilogue,
+                else_prologue,
+                else_body,
+                else_epilogue,
+            ];
+            (tokens, subroutine.concat())
+        }
+        ("while", _) => {
+            let tokens = vec![call(&body_label)];
+            let prologue = vec![
+                label(&body_label),
+                token(I::Push(bfe!(0))),
+                token(I::Eq),
+                token(I::Skiz),
+                token(I::Return),
+            ];
+            let subroutine = [prologue, body, vec![token(I::Recurse)]];
+            (tokens, subroutine.concat())
+        }
+        _ => {
+            let tokens = vec![
+                token(I::Push(bfe!(num_iterations))),
+                call(&body_label),
+                token(I::Pop(NumberOfWords::N1)),
+            ];
+            let prologue = vec![
+                label(&body_label),
+                token(I::Dup(OpStackElement::ST0)),
+                token(I::Push(bfe!(0))),
+                token(I::Eq),
+                token(I::Skiz),
+                token(I::Return),
+            ];
+            let epilogue = vec![token(I::AddI(-bfe!(1))), token(I::Recurse)];
+            let subroutine = [prologue, body, epilogue];
+            (tokens, subroutine.concat())
+        }
+    };
+
+    // `skiz` only skips the first of the instructions the construct is lowered to
+    if follows_skiz && tokens.len() > 1 {
+        return Err(failure(
+            s_keyword,
+            "control-flow construct must not follow `skiz`",
+        ));
+    }
+
+    scope.lowering.pending_subroutines.extend(subroutine);
+    scope.lowering.follows_skiz = false;
+    Ok((s, tokens))
+}
+
+/// Parse the body of a control-flow construct. Constructs in the body derive their
+/// labels from the body's label.
+fn construct_body<'a>(
+    s: &'a str,
+    scope: &mut Scope<'a>,
+    label: &str,
+) -> ParseResult<'a, Vec<InstructionToken<'a>>> {
+    let enclosing_label = mem::replace(&mut scope.lowering.enclosing_label, label.to_string());
+    let num_constructs = mem::take(&mut scope.lowering.num_constructs);
+    let body = tokens_in_scope(s, scope);
+    scope.lowering.enclosing_label = enclosing_label;
+    scope.lowering.num_constructs = num_constructs;
+
+    body
+}
+
+/// Parse the keyword ending the control-flow construct starting at `s_keyword`.
+fn end_of_construct<'a>(
+    s: &'a str,
+    s_keyword: &'a str,
+    end_keyword: &'static str,
+    expectation: &'static str,
+) -> ParseResult<'a, ()> {
+    if let Ok(parsed) = token1(end_keyword)(s) {
+        return Ok(parsed);
+    }
+    if s.is_empty() {
+        let context = match end_keyword {
+            "endif" => "missing `endif`",
+            "endwhile" => "missing `endwhile`",
+            _ => "missing `endfor`",
+        };
+        return Err(failure(s_keyword, context));
+    }
+    Err(failure(s, expectation))
+}
+
+/// The first word of the given string.
+fn word(s: &str) -> &str {
+    &s[..word_length(s)]
+}
+
+fn token<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, InstructionToken<'a>> + 's {
+    move |s: &'a str| {
+        alt((
+            label,
+            labelled_instruction(scope),
+            breakpoint,
+            type_hint,
+            assertion_context(scope),
+        ))(s)
+    }
+}
+
+fn label(label_s: &str) -> ParseResult<InstructionToken> {
+    let (s, addr) = qualified_label(label_s)?;
+    let (s, _) = whitespace0(s)?; // whitespace between label and ':' is allowed
+    let (s, _) = token0(":")(s)?; // don't require space after ':'
+
+    // Checking if `<label>:` is an instruction must happen after parsing `:`, since
+    // otherwise `cut` will reject the alternative parsers of `label`, like
+    // `labelled_instruction`, where valid instructions names *are* allowed.
+    if is_illegal_label(&addr) {
+        let failure_reason = "label must be neither instruction nor keyword";
+        return cut(nom::error::context(failure_reason, fail))(label_s);
+    }
+
+    Ok((s, InstructionToken::Label(addr, label_s)))
+}
+
+fn breakpoint(breakpoint_s: &str) -> ParseResult<InstructionToken> {
+    let (s, _) = token1("break")(breakpoint_s)?;
+    Ok((s, InstructionToken::Breakpoint(breakpoint_s)))
+}
+
+fn labelled_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, InstructionToken<'a>> + 's {
+    move |s_instr: &'a str| {
+        let (s, instr) = an_instruction(scope, s_instr)?;
+        Ok((s, InstructionToken::Instruction(instr, s_instr)))
+    }
+}
+
+fn an_instruction<'a>(scope: &Scope<'a>, s: &'a str) -> ParseResult<'a, AnInstruction<String>> {
+    // OpStack manipulation
+    let pop = pop_instruction(scope);
+    let push = push_instruction(scope);
+    let divine = divine_instruction(scope);
+    let pick = pick_instruction(scope);
+    let place = place_instruction(scope);
+    let dup = dup_instruction(scope);
+    let swap = swap_instruction(scope);
+
+    let opstack_manipulation = alt((pop, push, divine, pick, place, dup, swap));
+
+    // Control flow
+    let call = call_instruction(scope);
+
+    // Memory access
+    let read_mem = read_mem_instruction(scope);
+    let write_mem = write_mem_instruction(scope);
+
+    let memory_access = alt((read_mem, write_mem));
+
+    // Arithmetic on stack instructions
+    let addi = addi_instruction(scope);
+
+    // Read/write
+    let read_io = read_io_instruction(scope);
+    let write_io = write_io_instruction(scope);
+
+    let read_write = alt((read_io, write_io));
+
+    alt((
+        opstack_manipulation,
+        call,
+        memory_access,
+        addi,
+        read_write,
+        argument_free_instruction,
+    ))(s)
+}
+
+/// Parse any instruction that has no argument. Instructions with arguments need
+/// dedicated parsers.
+fn argument_free_instruction(s: &str) -> ParseResult<'_, AnInstruction<String>> {
+    let argument_free_instructions = ALL_INSTRUCTIONS.iter().filter(|i| i.arg().is_none());
+    for instruction in argument_free_instructions {
+        if let Ok((s, ())) = token1(instruction.name())(s) {
+            let instruction = instruction.map_call_address(|_| unreachable!());
+            return Ok((s, instruction));
+        }
+    }
+
+    fail(s)
+}
+
+fn is_illegal_label(s: &str) -> bool {
+    ALL_INSTRUCTION_NAMES.contains(&s) || KEYWORDS.contains(&s)
+}
+
+fn pop_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("pop")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::Pop(arg)))
+    }
+}
+
+fn push_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("push")(s)?;
+        let (s, elem) = argument(scope, field_element)(s)?;
+        Ok((s, AnInstruction::Push(elem)))
+    }
+}
+
+fn addi_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("addi")(s)?;
+        let (s, elem) = argument(scope, field_element)(s)?;
+        Ok((s, AnInstruction::AddI(elem)))
+    }
+}
+
+fn divine_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("divine")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::Divine(arg)))
+    }
+}
+
+fn pick_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("pick")(s)?;
+        let (s, arg) = argument(scope, stack_register)(s)?;
+        Ok((s, AnInstruction::Pick(arg)))
+    }
+}
+
+fn place_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("place")(s)?;
+        let (s, arg) = argument(scope, stack_register)(s)?;
+        Ok((s, AnInstruction::Place(arg)))
+    }
+}
+
+fn dup_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("dup")(s)?; // require space before argument
+        let (s, stack_register) = argument(scope, stack_register)(s)?;
+        Ok((s, AnInstruction::Dup(stack_register)))
+    }
+}
+
+fn swap_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("swap")(s)?;
+        let (s, stack_register) = argument(scope, stack_register)(s)?;
+        Ok((s, AnInstruction::Swap(stack_register)))
+    }
+}
+
+fn call_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("call")(s)?;
+        let (s, relative_target) = opt(relative_call_target)(s)?;
+        if let Some(target) = relative_target {
+            return Ok((s, AnInstruction::Call(target)));
+        }
+
+        let (s, label) = qualified_label(s)?;
+        let (s, _) = comment_or_whitespace1(s)?;
+        let label = match scope.resolve_label(&label) {
+            Some(argument) => substituted_label(argument)?,
+            None => label,
+        };
+
+        // This check cannot be moved into `qualified_label`, since it is shared
+        // between the scenarios `<label>:` and `call <label>`; the former requires
+        // parsing the `:` before rejecting a possible instruction name in the label.
+        if is_illegal_label(&label) {
+            let failure_reason = "label must be neither instruction nor keyword";
+            return cut(nom::error::context(failure_reason, fail))(s);
+        }
+
+        Ok((s, AnInstruction::Call(label)))
+    }
+}
+
+fn read_mem_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("read_mem")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::ReadMem(arg)))
+    }
+}
+
+fn write_mem_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("write_mem")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::WriteMem(arg)))
+    }
+}
+
+fn read_io_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("read_io")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::ReadIo(arg)))
+    }
+}
+
+fn write_io_instruction<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, AnInstruction<String>> + 's {
+    move |s: &'a str| {
+        let (s, _) = token1("write_io")(s)?;
+        let (s, arg) = argument(scope, number_of_words)(s)?;
+        Ok((s, AnInstruction::WriteIo(arg)))
+    }
+}
+
+/// Parse the argument of an instruction. The argument is either a literal, or a symbol
+/// that refers to a named constant or a macro parameter, in which case the symbol's value
+/// is parsed as the literal.
+fn argument<'s, 'a: 's, T: 's>(
+    scope: &'s Scope<'a>,
+    literal: fn(&'a str) -> ParseResult<'a, T>,
+) -> impl Fn(&'a str) -> ParseResult<'a, T> + 's {
+    move |s: &'a str| {
+        let Ok((s_after_symbol, symbol)) = label_addr(s) else {
+            return literal(s);
+        };
+        let Some(value) = scope.resolve_argument(&symbol) else {
+            return cut(nom::error::context("unknown constant", fail))(s);
+        };
+        let (_, argument) = literal(value)?;
+        let (s, _) = comment_or_whitespace1(s_after_symbol)?;
+        Ok((s, argument))
+    }
+}
+
+/// Parse a label that was passed to a macro as an argument.
+fn substituted_label(argument: &str) -> Result<String, ParseFailure> {
+    let (rest, label) = qualified_label(argument)?;
+    if !rest.is_empty() || is_illegal_label(&label) {
+        return Err(failure(argument, "invalid label"));
+    }
+    Ok(label)
+}
+
+fn field_element(s_orig: &str) -> ParseResult<BFieldElement> {
+    let (s, negative) = opt(token0("-"))(s_orig)?;
+    let (s, n) = digit1(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
+
+    let Ok(mut n): Result<i128, _> = n.parse() else {
+        return nom::error::context("out-of-bounds constant", fail)(s);
+    };
+
+    let quotient = i128::from(BFieldElement::P);
+    if n >= quotient {
+        return nom::error::context("out-of-bounds constant", fail)(s_orig);
+    }
+
+    if negative.is_some() {
+        n *= -1;
+        n += quotient;
+    }
+
+    Ok((s, BFieldElement::new(n as u64)))
+}
+
+fn stack_register(s: &str) -> ParseResult<OpStackElement> {
+    let (s, n) = digit1(s)?;
+    let stack_register = match n {
+        "0" => OpStackElement::ST0,
+        "1" => OpStackElement::ST1,
+        "2" => OpStackElement::ST2,
+        "3" => OpStackElement::ST3,
+        "4" => OpStackElement::ST4,
+        "5" => OpStackElement::ST5,
         "6" => OpStackElement::ST6,
         "7" => OpStackElement::ST7,
         "8" => OpStackElement::ST8,
@@ -570,6 +1505,58 @@ fn number_of_words(s: &str) -> ParseResult<NumberOfWords> {
     Ok((s, arg))
 }
 
+fn num_iterations(s: &str) -> ParseResult<u32> {
+    let (s, n) = digit1(s)?;
+    let Ok(n) = n.parse() else {
+        return cut(nom::error::context(
+            "number of iterations must fit in a u32",
+            fail,
+        ))(s);
+    };
+    let (s, _) = comment_or_whitespace1(s)?;
+
+    Ok((s, n))
+}
+
+/// Parse a label that is possibly qualified by the namespaces of the modules it
+/// belongs to, like `module::label`. See also [`Linker`](crate::linker::Linker).
+fn qualified_label(s: &str) -> ParseResult<String> {
+    let (s, namespace_or_label) = label_addr(s)?;
+    let (s, path) = many0(preceded(tag(NAMESPACE_SEPARATOR), label_addr))(s)?;
+    let label = [namespace_or_label]
+        .into_iter()
+        .chain(path)
+        .join(NAMESPACE_SEPARATOR);
+
+    Ok((s, label))
+}
+
+/// Parse the target of a relative `call`, like `+4` or `-12`. See
+/// [`relative_call_offset`].
+fn relative_call_target(s: &str) -> ParseResult<String> {
+    let (s_after_target, target) = recognize(pair(alt((tag("+"), tag("-"))), digit1))(s)?;
+    let Some(offset) = relative_call_offset(target) else {
+        return cut(nom::error::context(
+            "relative call target out of range",
+            fail,
+        ))(s);
+    };
+    let (s, _) = comment_or_whitespace1(s_after_target)?;
+
+    Ok((s, format!("{offset:+}")))
+}
+
+/// The offset of a relative call target, like `+4` or `-12`, or [`None`] if the target
+/// is a label. The offset is the distance, in `BFieldElement`s, from the address of the
+/// `call` to the address of its target. Since labels must start with an alphabetic
+/// character or an underscore, relative targets and labels cannot be confused.
+pub(crate) fn relative_call_offset(target: &str) -> Option<i64> {
+    if !target.starts_with(['+', '-']) {
+        return None;
+    }
+    target.parse().ok()
+}
+
 /// Parse a label address. This is used in "`<label>:`" and in "`call <label>`".
 fn label_addr(s_orig: &str) -> ParseResult<String> {
     let (s, addr_part_0) = take_while1(is_label_start_char)(s_orig)?;
@@ -753,35 +1740,329 @@ fn parse_str_to_usize(s: &str) -> ParseResult<usize> {
     }
 }
 
-fn assertion_context(s_ctx: &str) -> ParseResult<InstructionToken> {
-    let (s, assertion_context) = assertion_context_id(s_ctx)?;
-    let assertion_context = InstructionToken::AssertionContext(assertion_context, s_ctx);
+fn assertion_context<'s, 'a: 's>(
+    scope: &'s Scope<'a>,
+) -> impl Fn(&'a str) -> ParseResult<'a, InstructionToken<'a>> + 's {
+    move |s_ctx: &'a str| {
+        let (s, assertion_context) = alt((
+            |s| assertion_context_id(scope, s),
+            assertion_context_message,
+        ))(s_ctx)?;
+        let assertion_context = InstructionToken::AssertionContext(assertion_context, s_ctx);
 
-    Ok((s, assertion_context))
+        Ok((s, assertion_context))
+    }
 }
 
-fn assertion_context_id(s_ctx: &str) -> ParseResult<AssertionContext> {
+fn assertion_context_id<'a>(
+    scope: &Scope<'a>,
+    s_ctx: &'a str,
+) -> ParseResult<'a, AssertionContext> {
     let (s, _) = token1("error_id")(s_ctx)?;
-    let (s, id) = nom::character::complete::i128(s)?;
-    let (s, _) = comment_or_whitespace1(s)?;
+    let (s, id) = argument(scope, error_id)(s)?;
 
     let assertion_context = AssertionContext::ID(id);
     Ok((s, assertion_context))
 }
 
-pub(crate) fn build_label_to_address_map(program: &[LabelledInstruction]) -> HashMap<String, u64> {
-    let mut label_map = HashMap::new();
-    let mut instruction_pointer = 0;
+fn error_id(s: &str) -> ParseResult<i128> {
+    let (s, id) = nom::character::complete::i128(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
+    Ok((s, id))
+}
 
-    for labelled_instruction in program {
-        if let LabelledInstruction::Instruction(instruction) = labelled_instruction {
-            instruction_pointer += instruction.size() as u64;
-            continue;
-        }
+fn assertion_context_message(s_ctx: &str) -> ParseResult<AssertionContext> {
+    let (s, _) = token1("error_message")(s_ctx)?;
+    let (s, message) = string_literal(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
 
-        let LabelledInstruction::Label(label) = labelled_instruction else {
-            continue;
-        };
+    let assertion_context = AssertionContext::Message(message);
+    Ok((s, assertion_context))
+}
+
+/// Parse one double-quoted string literal. The only supported escape sequences are
+/// `\"` and `\\`.
+fn string_literal(s_string: &str) -> ParseResult<String> {
+    let (mut s, _) = tag("\"")(s_string)?;
+    let mut string = String::new();
+    loop {
+        let mut chars = s.chars();
+        match chars.next() {
+            None => return Err(failure(s_string, "unterminated string literal")),
+            Some('"') => return Ok((chars.as_str(), string)),
+            Some('\\') => match chars.next() {
+                Some(c @ ('"' | '\\')) => string.push(c),
+                _ => return Err(failure(s, "unknown escape sequence")),
+            },
+            Some(c) => string.push(c),
+        }
+        s = chars.as_str();
+    }
+}
+
+/// Parse one data section, as well as the constant it defines for its address.
+///
+/// Data sections look like this:
+///
+/// ```text
+/// data <name>: <type> @ <address> = [<element>, …]
+/// ```
+///
+/// Elements of types spanning multiple words, like `xfe` and `digest`, are written as
+/// tuples `(<word>, …)`.
+fn data_section(s_data: &str) -> ParseResult<(InstructionToken, ConstantDefinition)> {
+    let (s, _) = token1("data")(s_data)?;
+    let (s, name) = label_addr(s)?;
+    if is_illegal_label(&name) {
+        let failure_reason = "data section name must be neither instruction nor keyword";
+        return cut(nom::error::context(failure_reason, fail))(s_data);
+    }
+    let (s, _) = whitespace0(s)?;
+    let (s, _) = expect(token0(":"), "missing `:` after name of data section")(s)?;
+    let (s, data_type) = data_type(s)?;
+    let (s, _) = expect(token0("@"), "missing `@` after data type")(s)?;
+    let (s, address) = expect(
+        recognize(pair(opt(token0("-")), digit1)),
+        "missing address of data section",
+    )(s)?;
+    let (_, address_value) = data_word(DataType::BFieldElement)(address)?;
+    let (s, _) = comment_or_whitespace0(s)?;
+    let (s, _) = expect(token0("="), "missing `=` after address")(s)?;
+    let (s, _) = expect(token0("["), "missing `[` before data")(s)?;
+    let (s, elements) = separated_list0(token0(","), data_element(data_type))(s)?;
+    let (s, _) = expect(token0("]"), "missing `,` or `]` after element")(s)?;
+
+    let data = DataSection {
+        name: name.clone(),
+        data_type,
+        address: address_value,
+        words: elements.concat(),
+    };
+    let token = InstructionToken::DataSection(data, s_data);
+    let constant = ConstantDefinition {
+        name,
+        value: address,
+        token_str: s_data,
+    };
+    Ok((s, (token, constant)))
+}
+
+/// Like the given parser, but failing irrecoverably with the given context.
+fn expect<'a, T>(
+    mut parser: impl FnMut(&'a str) -> ParseResult<'a, T>,
+    context: &'static str,
+) -> impl FnMut(&'a str) -> ParseResult<'a, T> {
+    move |s| parser(s).map_err(|_| failure(s, context))
+}
+
+fn data_type(s: &str) -> ParseResult<DataType> {
+    let (s_after_type, type_name) = take_while(is_type_hint_type_name_character)(s)?;
+    let Some(data_type) = DataType::iter().find(|t| t.to_string() == type_name) else {
+        return Err(failure(s, "unknown data type"));
+    };
+    let (s, _) = comment_or_whitespace0(s_after_type)?;
+    Ok((s, data_type))
+}
+
+/// Parse one element of a data section, which spans as many words as its type.
+fn data_element<'a>(
+    data_type: DataType,
+) -> impl Fn(&'a str) -> ParseResult<'a, Vec<BFieldElement>> {
+    move |s_element: &'a str| {
+        // the end of the data is not an element, even if a tuple is expected
+        if s_element.starts_with(']') {
+            return fail(s_element);
+        }
+        if data_type.size() == 1 {
+            let (s, word) = data_word(data_type)(s_element)?;
+            return Ok((s, vec![word]));
+        }
+
+        let (s, _) = expect(token0("("), "missing `(` before element")(s_element)?;
+        let (s, words) = separated_list0(token0(","), data_word(data_type))(s)?;
+        let (s, _) = expect(token0(")"), "missing `,` or `)` after word")(s)?;
+        if words.len() != data_type.size() {
+            return Err(failure(s_element, "wrong number of words in element"));
+        }
+        Ok((s, words))
+    }
+}
+
+/// Parse one word of a data section and ensure that it is legal for the given type.
+fn data_word<'a>(data_type: DataType) -> impl Fn(&'a str) -> ParseResult<'a, BFieldElement> {
+    move |s_word: &'a str| {
+        let (s, negative) = opt(token0("-"))(s_word)?;
+        let (s, n) = digit1(s)?;
+        let (s, _) = comment_or_whitespace0(s)?;
+
+        let quotient = i128::from(BFieldElement::P);
+        let n = match n.parse::<i128>() {
+            Ok(n) if n < quotient => n,
+            _ => return Err(failure(s_word, "out-of-bounds constant")),
+        };
+        let n = if negative.is_some() { quotient - n } else { n };
+        let word = BFieldElement::new(n as u64);
+
+        if !data_type.admits(word) {
+            let context = match data_type {
+                DataType::Bool => "word must be 0 or 1",
+                _ => "word must be a u32",
+            };
+            return Err(failure(s_word, context));
+        }
+        Ok((s, word))
+    }
+}
+
+/// Parse one constant definition.
+///
+/// Constant definitions look like this:
+///
+/// ```text
+/// const <name> = <value>
+/// ```
+///
+/// The value is only checked once the constant is used as an instruction argument,
+/// since only then is it known which values are legal.
+fn constant_definition(s_const: &str) -> ParseResult<ConstantDefinition> {
+    let (s, _) = token1("const")(s_const)?;
+    let (s, name) = label_addr(s)?;
+    if is_illegal_label(&name) {
+        let failure_reason = "constant name must be neither instruction nor keyword";
+        return cut(nom::error::context(failure_reason, fail))(s_const);
+    }
+    let (s, _) = whitespace0(s)?;
+    let (s, _) = token0("=")(s)?;
+    let (s, value) = recognize(pair(opt(token0("-")), digit1))(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
+
+    let constant = ConstantDefinition {
+        name,
+        value,
+        token_str: s_const,
+    };
+    Ok((s, constant))
+}
+
+/// Parse one macro definition.
+///
+/// Macro definitions look like this:
+///
+/// ```text
+/// macro <name>(<parameter>, …)
+///     <body>
+/// endmacro
+/// ```
+///
+/// The parentheses can be omitted if the macro has no parameters. Inside the body,
+/// parameters can be used in place of instruction arguments and labels. Macros cannot
+/// be defined inside other macros.
+fn macro_definition(s_macro: &str) -> ParseResult<MacroDefinition> {
+    let (s, _) = token1("macro")(s_macro)?;
+    let (s, name) = label_addr(s)?;
+    if is_illegal_label(&name) {
+        let failure_reason = "macro name must be neither instruction nor keyword";
+        return cut(nom::error::context(failure_reason, fail))(s_macro);
+    }
+    let (s, parameters) = opt(macro_parameters)(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
+    let (s, body) = macro_body(s)?;
+    let (s, _) = token1("endmacro")(s)?;
+
+    let parameters = parameters.unwrap_or_default();
+    if !parameters.iter().all_unique() {
+        return cut(nom::error::context("duplicate macro parameter", fail))(s_macro);
+    }
+
+    let definition = MacroDefinition {
+        name,
+        parameters,
+        body,
+        token_str: s_macro,
+    };
+    Ok((s, definition))
+}
+
+fn macro_parameters(s: &str) -> ParseResult<Vec<String>> {
+    let (s, _) = whitespace0(s)?;
+    let (s, _) = token0("(")(s)?;
+    let (s, parameters) = separated_list0(token0(","), macro_parameter)(s)?;
+    let (s, _) = tag(")")(s)?;
+    Ok((s, parameters))
+}
+
+fn macro_parameter(s: &str) -> ParseResult<String> {
+    let (s, parameter) = label_addr(s)?;
+    let (s, _) = whitespace0(s)?;
+    Ok((s, parameter))
+}
+
+/// Find the end of a macro's body, which is delimited by keyword `endmacro`.
+fn macro_body(s_body: &str) -> ParseResult<&str> {
+    let mut s = s_body;
+    loop {
+        let (rest, _) = comment_or_whitespace0(s)?;
+        if token1("endmacro")(rest).is_ok() {
+            let body_length = s_body.len() - rest.len();
+            return Ok((rest, &s_body[..body_length]));
+        }
+        if rest.is_empty() {
+            let failure_reason = "macro definition must end with `endmacro`";
+            return cut(nom::error::context(failure_reason, fail))(s_body);
+        }
+        let word = take_while1(|c: char| !c.is_whitespace() && c != '/');
+        (s, _) = alt((word, tag("/")))(rest)?;
+    }
+}
+
+/// Parse one macro invocation.
+///
+/// Macro invocations look like this:
+///
+/// ```text
+/// <name>!(<argument>, …)
+/// ```
+///
+/// The parentheses can be omitted if the macro has no parameters.
+fn macro_invocation(s_invocation: &str) -> ParseResult<MacroInvocation> {
+    let (s, name) = label_addr(s_invocation)?;
+    let (s, _) = tag("!")(s)?;
+    let (s, arguments) = opt(macro_arguments)(s)?;
+    let (s, _) = comment_or_whitespace1(s)?;
+
+    let invocation = MacroInvocation {
+        name,
+        arguments: arguments.unwrap_or_default(),
+        token_str: s_invocation,
+    };
+    Ok((s, invocation))
+}
+
+fn macro_arguments(s: &str) -> ParseResult<Vec<&str>> {
+    let (s, _) = token0("(")(s)?;
+    let (s, arguments) = separated_list0(token0(","), macro_argument)(s)?;
+    let (s, _) = tag(")")(s)?;
+    Ok((s, arguments))
+}
+
+fn macro_argument(s: &str) -> ParseResult<&str> {
+    let (s, argument) = take_while1(|c| c != ',' && c != ')' && !is_linebreak(c))(s)?;
+    Ok((s, argument.trim()))
+}
+
+pub(crate) fn build_label_to_address_map(program: &[LabelledInstruction]) -> HashMap<String, u64> {
+    let mut label_map = HashMap::new();
+    let mut instruction_pointer = 0;
+
+    for labelled_instruction in program {
+        if let LabelledInstruction::Instruction(instruction) = labelled_instruction {
+            instruction_pointer += instruction.size() as u64;
+            continue;
+        }
+
+        let LabelledInstruction::Label(label) = labelled_instruction else {
+            continue;
+        };
         let Entry::Vacant(new_label_map_entry) = label_map.entry(label.clone()) else {
             panic!("Duplicate label: {label}");
         };
@@ -791,33 +2072,38 @@ pub(crate) fn build_label_to_address_map(program: &[LabelledInstruction]) -> Has
     label_map
 }
 
+/// Turn the labels of all `call`s into absolute addresses. Relative call targets are
+/// resolved with respect to the address of the respective `call`.
 pub(crate) fn turn_labels_into_addresses(
     labelled_instructions: &[LabelledInstruction],
     label_to_address: &HashMap<String, u64>,
 ) -> Vec<Instruction> {
-    fn turn_label_to_address_for_instruction(
-        labelled_instruction: &LabelledInstruction,
+    fn address_for_label(
+        label: &str,
+        call_address: u64,
         label_map: &HashMap<String, u64>,
-    ) -> Option<Instruction> {
-        let LabelledInstruction::Instruction(instruction) = labelled_instruction else {
-            return None;
-        };
-
-        let instruction_with_absolute_address =
-            instruction.map_call_address(|label| address_for_label(label, label_map));
-        Some(instruction_with_absolute_address)
-    }
-
-    fn address_for_label(label: &str, label_map: &HashMap<String, u64>) -> BFieldElement {
+    ) -> BFieldElement {
+        if let Some(offset) = relative_call_offset(label) {
+            return bfe!(call_address) + bfe!(offset);
+        }
         let maybe_address = label_map.get(label).map(|&a| bfe!(a));
         maybe_address.unwrap_or_else(|| panic!("Label not found: {label}"))
     }
 
-    labelled_instructions
-        .iter()
-        .filter_map(|inst| turn_label_to_address_for_instruction(inst, label_to_address))
-        .flat_map(|inst| vec![inst; inst.size()])
-        .collect()
+    let mut address = 0;
+    let mut instructions = vec![];
+    for labelled_instruction in labelled_instructions {
+        let LabelledInstruction::Instruction(instruction) = labelled_instruction else {
+            continue;
+        };
+        let instruction_with_absolute_address = instruction
+            .map_call_address(|label| address_for_label(label, address, label_to_address));
+        let size = instruction_with_absolute_address.size();
+        instructions.extend(vec![instruction_with_absolute_address; size]);
+        address += size as u64;
+    }
+
+    instructions
 }
 
 #[cfg(test)]
@@ -833,6 +2119,7 @@ pub(crate) mod tests {
     use test_strategy::proptest;
     use test_strategy::Arbitrary;
     use twenty_first::bfe;
+    use twenty_first::bfe_vec;
     use twenty_first::prelude::Digest;
 
     use crate::triton_asm;
@@ -1113,6 +2400,122 @@ pub(crate) mod tests {
             message: "labels that share a common suffix with instruction are labels",
         }
         .run();
+
+        TestCase {
+            input: "module::pop: call module::pop",
+            expected: vec![Instruction::Call(bfe!(0)), Instruction::Call(bfe!(0))],
+            message: "labels can be qualified by a module's namespace",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_program_with_relative_calls() {
+        let call_2 = Instruction::Call(bfe!(2));
+        TestCase {
+            input: "call +2 return",
+            expected: vec![call_2, call_2, Instruction::Return],
+            message: "relative call targets are relative to the address of the call",
+        }
+        .run();
+
+        let call_0 = Instruction::Call(bfe!(0));
+        TestCase {
+            input: "return call -1",
+            expected: vec![Instruction::Return, call_0, call_0],
+            message: "relative call targets can be negative",
+        }
+        .run();
+
+        let call_3 = Instruction::Call(bfe!(3));
+        TestCase {
+            input: "nop call +2 // comment\n call +0",
+            expected: vec![Instruction::Nop, call_3, call_3, call_3, call_3],
+            message: "relative call targets can be followed by comments",
+        }
+        .run();
+
+        TestCase {
+            input: "call +2",
+            expected: vec![call_2, call_2],
+            message: "relative calls can target the end of the program",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "call +1 halt call -4 call +5 halt",
+            expected_error: "relative call target out of range",
+            expected_error_count: 3,
+            message: "relative calls must target the start of an instruction",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "call +99999999999999999999",
+            expected_error: "relative call target out of range",
+            expected_error_count: 1,
+            message: "relative call targets must not overflow",
+        }
+        .run();
+    }
+
+    #[test]
+    fn relative_calls_must_not_span_control_flow() {
+        let call_4 = Instruction::Call(bfe!(4));
+        let call_6 = Instruction::Call(bfe!(6));
+        TestCase {
+            input: "if call +2 return endif halt",
+            expected: vec![
+                Instruction::Skiz,
+                call_4,
+                call_4,
+                Instruction::Halt,
+                call_6,
+                call_6,
+                Instruction::Return,
+                Instruction::Return,
+            ],
+            message: "relative calls within the body of a control-flow construct are fine",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "call +3 if nop endif return",
+            expected_error: "relative call must not span control flow",
+            expected_error_count: 1,
+            message: "relative call must not span control-flow construct",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "if nop endif call +3 halt return",
+            expected_error: "relative call must not span control flow",
+            expected_error_count: 1,
+            message: "relative call must not span placed subroutines",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "nop if call -1 endif return",
+            expected_error: "relative call must not span control flow",
+            expected_error_count: 1,
+            message: "relative call must not leave the body of a control-flow construct",
+        }
+        .run();
+    }
+
+    #[test]
+    fn relative_call_targets_are_not_labels() {
+        assert!(Some(4) == relative_call_offset("+4"));
+        assert!(Some(-12) == relative_call_offset("-12"));
+        assert!(None == relative_call_offset("foo"));
+        assert!(None == relative_call_offset("4"));
+
+        let instructions = triton_asm!(call +4 call -2 call foo);
+        let_assert!([call_forward, call_backward, call_foo] = instructions.as_slice());
+        assert!("call +4" == call_forward.to_string());
+        assert!("call -2" == call_backward.to_string());
+        assert!("call foo" == call_foo.to_string());
     }
 
     #[test]
@@ -1140,351 +2543,1074 @@ pub(crate) mod tests {
             message: "there is no dup 16 instruction",
         }
         .run();
-    }
+    }
+
+    #[test]
+    fn parse_program_bracket_syntax() {
+        NegativeTestCase {
+            input: "foo: [foo]",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "brackets as call syntax sugar are unsupported",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "foo: [bar]",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "brackets as call syntax sugar are unsupported",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_program_label_must_start_with_alphabetic_character_or_underscore() {
+        NegativeTestCase {
+            input: "1foo: call 1foo",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "labels cannot start with a digit",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "-foo: call -foo",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "labels cannot start with a dash",
+        }
+        .run();
+
+        TestCase {
+            input: "_foo: call _foo",
+            expected: vec![Instruction::Call(bfe!(0)), Instruction::Call(bfe!(0))],
+            message: "labels can start with an underscore",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_zero_length_range() {
+        NegativeTestCase {
+            input: "hint foo: Type = stack[0..0]",
+            expected_error: "range end must be greater than range start",
+            expected_error_count: 1,
+            message: "parse type hint with zero-length range",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_closing_bracket() {
+        NegativeTestCase {
+            input: "hint foo: Type = stack[2..5",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing closing bracket",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_opening_bracket() {
+        NegativeTestCase {
+            input: "hint foo: Type = stack2..5]",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing opening bracket",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_equals_sign() {
+        NegativeTestCase {
+            input: "hint foo: Type stack[2..5];",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing equals sign",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_type_name() {
+        NegativeTestCase {
+            input: "hint foo: = stack[2..5]",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing type name",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_variable_name() {
+        NegativeTestCase {
+            input: "hint : Type = stack[2..5]",
+            expected_error: "label must be neither instruction nor keyword",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing variable name",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_colon() {
+        NegativeTestCase {
+            input: "hint foo Type = stack[2..5]",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing colon",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_type_hint_with_range_and_offset_and_missing_hint() {
+        NegativeTestCase {
+            input: "foo: Type = stack[2..5];",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "parse type hint with range and offset and missing hint",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_simple_assertion_contexts() {
+        TestCase {
+            input: "assert",
+            expected: vec![Instruction::Assert],
+            message: "naked assert",
+        }
+        .run();
+        TestCase {
+            input: "assert_vector",
+            expected: vec![Instruction::AssertVector],
+            message: "naked assert_vector",
+        }
+        .run();
+
+        TestCase {
+            input: "assert error_id 42",
+            expected: vec![Instruction::Assert],
+            message: "assert, then id",
+        }
+        .run();
+        TestCase {
+            input: "assert_vector error_id 42",
+            expected: vec![Instruction::AssertVector],
+            message: "assert_vector, then id",
+        }
+        .run();
+
+        TestCase {
+            input: "assert error_id -42",
+            expected: vec![Instruction::Assert],
+            message: "assert, then negative id",
+        }
+        .run();
+        TestCase {
+            input: "assert_vector error_id -42",
+            expected: vec![Instruction::AssertVector],
+            message: "assert_vector, then negative id",
+        }
+        .run();
+
+        TestCase {
+            input: "assert error_id 42 nop",
+            expected: vec![Instruction::Assert, Instruction::Nop],
+            message: "assert, then id, then nop",
+        }
+        .run();
+        TestCase {
+            input: "assert_vector error_id 42 nop",
+            expected: vec![Instruction::AssertVector, Instruction::Nop],
+            message: "assert_vector, then id, then nop",
+        }
+        .run();
+    }
+
+    #[test]
+    fn assertion_context_error_id_can_handle_edge_case_ids() {
+        let instructions = [
+            ("assert", Instruction::Assert),
+            ("assert_vector", Instruction::AssertVector),
+        ];
+        let ids = [i128::MIN, -1, 0, 1, i128::MAX];
+
+        for ((instruction_str, instruction), id) in instructions.into_iter().cartesian_product(ids)
+        {
+            TestCase {
+                input: &format!("{instruction_str} error_id {id}"),
+                expected: vec![instruction],
+                message: "assert, then edge case id",
+            }
+            .run();
+        }
+    }
+
+    #[test]
+    fn assertion_context_error_id_fails_on_too_large_error_ids() {
+        NegativeTestCase {
+            input: "assert error_id -170141183460469231731687303715884105729",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "error id smaller than i128::MIN",
+        }
+        .run();
+        NegativeTestCase {
+            input: "assert error_id 170141183460469231731687303715884105728",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "error id larger than i128::MAX",
+        }
+        .run();
+    }
+
+    #[proptest]
+    fn assertion_context_error_id_can_handle_any_valid_id(id: i128) {
+        TestCase {
+            input: &format!("assert error_id {id}"),
+            expected: vec![Instruction::Assert],
+            message: "assert, then random id",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_erroneous_assertion_contexts_for_assert() {
+        NegativeTestCase {
+            input: "assert error_id",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "missing id after keyword `error_id`",
+        }
+        .run();
+        NegativeTestCase {
+            input: "assert error id 42",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "incorrect keyword `error id`",
+        }
+        .run();
+        NegativeTestCase {
+            input: "error_id 42",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "standalone assertion context",
+        }
+        .run();
+        NegativeTestCase {
+            input: "error_id 42 error_id 42",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 2,
+            message: "multiple standalone assertion contexts",
+        }
+        .run();
+        NegativeTestCase {
+            input: "nop error_id 42",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "error id without assertion",
+        }
+        .run();
+        NegativeTestCase {
+            input: "assert error_id 42 error_id 1337",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "multiple error ids for the same assertion",
+        }
+        .run();
+        NegativeTestCase {
+            input: "assert_vector error_id 42 error_id 1337",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "multiple error ids for the same vector assertion",
+        }
+        .run();
+        NegativeTestCase {
+            input: "error_id 42 assert",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "error id precedes assertion",
+        }
+        .run();
+        NegativeTestCase {
+            input: "error_id 42 assert error_id 1337",
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "first error id precedes assertion",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_assertion_context_messages() {
+        for input in [
+            r#"assert error_message "balance too low""#,
+            r#"assert error_message "expected {st0} <= {st1}" // comment"#,
+            r#"assert error_message "with \"quotes\" and \\backslash""#,
+            r#"assert error_message "// not a comment""#,
+            r#"assert error_message """#,
+        ] {
+            TestCase {
+                input,
+                expected: vec![Instruction::Assert],
+                message: "assert, then message",
+            }
+            .run();
+        }
+
+        let program = r#"assert_vector error_message "digests differ: {st5} != {st0}" halt"#;
+        let tokens = parse(program).unwrap();
+        let labelled_instructions = to_labelled_instructions(&tokens);
+        let_assert!(LabelledInstruction::AssertionContext(context) = &labelled_instructions[1]);
+        let expected = AssertionContext::Message("digests differ: {st5} != {st0}".to_string());
+        assert!(&expected == context);
+    }
+
+    #[test]
+    fn parse_erroneous_assertion_context_messages() {
+        NegativeTestCase {
+            input: r#"assert error_message "unterminated"#,
+            expected_error: "unterminated string literal",
+            expected_error_count: 1,
+            message: "unterminated message",
+        }
+        .run();
+        NegativeTestCase {
+            input: r#"assert error_message "unknown \escape""#,
+            expected_error: "unknown escape sequence",
+            expected_error_count: 1,
+            message: "unsupported escape sequence",
+        }
+        .run();
+        NegativeTestCase {
+            input: "assert error_message no_quotes",
+            expected_error: "expecting label, instruction or eof",
+            expected_error_count: 1,
+            message: "message without quotes",
+        }
+        .run();
+        NegativeTestCase {
+            input: r#"nop error_message "misplaced""#,
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "message without assertion",
+        }
+        .run();
+        NegativeTestCase {
+            input: r#"assert error_id 42 error_message "too much context""#,
+            expected_error: "incorrectly placed assertion context",
+            expected_error_count: 1,
+            message: "both id and message for the same assertion",
+        }
+        .run();
+    }
+
+    #[proptest]
+    fn assertion_context_error_id_fails_for_invalid_id(
+        #[strategy(proptest::strategy::Union::new(["assert", "assert_vector"]))]
+        instruction: String,
+        #[filter(#id.parse::<i128>().is_err())] id: String,
+    ) {
+        // A valid ID followed by a comment is valid, and therefore not relevant here.
+        // This might be a monkey-patch, but I don't know how to generate better input.
+        if let Some(comment_idx) = id.find("//") {
+            let (id, _) = id.split_at(comment_idx);
+            prop_assume!(id.parse::<i128>().is_err());
+        }
+
+        // not using `NegativeTest`: different `id`s trigger different errors
+        let input = format!("{instruction} error_id {id}");
+        prop_assert!(parse(&input).is_err());
+    }
+
+    #[proptest]
+    fn type_hint_to_string_to_type_hint_is_identity(#[strategy(arb())] type_hint: TypeHint) {
+        let type_hint_string = type_hint.to_string();
+        let instruction_tokens =
+            parse(&type_hint_string).map_err(|err| TestCaseError::Fail(format!("{err}").into()))?;
+        let labelled_instructions = to_labelled_instructions(&instruction_tokens);
+        prop_assert_eq!(1, labelled_instructions.len());
+        let first_labelled_instruction = labelled_instructions[0].clone();
+        let_assert!(LabelledInstruction::TypeHint(parsed_type_hint) = first_labelled_instruction);
+        prop_assert_eq!(type_hint, parsed_type_hint);
+    }
+
+    #[proptest]
+    fn assertion_context_to_string_to_assertion_context_is_identity(
+        #[strategy(arb())] context: AssertionContext,
+    ) {
+        let assert_with_context = format!("assert {context}");
+        let instruction_tokens = parse(&assert_with_context)
+            .map_err(|err| TestCaseError::Fail(format!("{err}").into()))?;
+        let labelled_instructions = to_labelled_instructions(&instruction_tokens);
+        prop_assert_eq!(2, labelled_instructions.len());
+        let_assert!(LabelledInstruction::AssertionContext(parsed) = &labelled_instructions[1]);
+        prop_assert_eq!(&context, parsed);
+    }
+
+    #[proptest]
+    fn data_section_to_string_to_data_section_is_identity(#[strategy(arb())] data: DataSection) {
+        let data_string = data.to_string();
+        let instruction_tokens =
+            parse(&data_string).map_err(|err| TestCaseError::Fail(format!("{err}").into()))?;
+        let labelled_instructions = to_labelled_instructions(&instruction_tokens);
+        prop_assert_eq!(1, labelled_instructions.len());
+        let_assert!(LabelledInstruction::DataSection(parsed) = &labelled_instructions[0]);
+        prop_assert_eq!(&data, parsed);
+    }
+
+    #[test]
+    fn parse_data_sections() {
+        let code = "
+            data primes: u32 @ 1000 = [2, 3, 5, 7]
+            data flags: bool @ 1004 = []
+            data point: xfe @ -3 = [(1, -1, 0)]
+            data nothing: digest @ 0 = []
+            push primes push point halt
+        ";
+        let_assert!(Ok(tokens) = parse(code));
+        let labelled_instructions = to_labelled_instructions(&tokens);
+
+        let_assert!(LabelledInstruction::DataSection(primes) = &labelled_instructions[0]);
+        assert!("primes" == primes.name);
+        assert!(DataType::U32 == primes.data_type);
+        assert!(bfe!(1000) == primes.address);
+        assert!(bfe_vec![2, 3, 5, 7] == primes.words);
+
+        let_assert!(LabelledInstruction::DataSection(flags) = &labelled_instructions[1]);
+        assert!(flags.words.is_empty());
+
+        let_assert!(LabelledInstruction::DataSection(point) = &labelled_instructions[2]);
+        assert!(bfe!(-3) == point.address);
+        assert!(bfe_vec![1, -1, 0] == point.words);
+
+        let push_primes = AnInstruction::Push(bfe!(1000));
+        let push_point = AnInstruction::Push(bfe!(-3));
+        assert!(LabelledInstruction::Instruction(push_primes) == labelled_instructions[4]);
+        assert!(LabelledInstruction::Instruction(push_point) == labelled_instructions[5]);
+    }
+
+    #[test]
+    fn parse_erroneous_data_sections() {
+        NegativeTestCase {
+            input: "data x: u32 @ 0 = [4294967296] halt",
+            expected_error: "word must be a u32",
+            expected_error_count: 1,
+            message: "u32 out of range",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: bool @ 0 = [0, 1, 2] halt",
+            expected_error: "word must be 0 or 1",
+            expected_error_count: 1,
+            message: "bool out of range",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: digest @ 0 = [(1, 2, 3)] halt",
+            expected_error: "wrong number of words in element",
+            expected_error_count: 1,
+            message: "digest with too few words",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: xfe @ 0 = [1, 2, 3] halt",
+            expected_error: "missing `(` before element",
+            expected_error_count: 1,
+            message: "extension field element without parentheses",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: u64 @ 0 = [1] halt",
+            expected_error: "unknown data type",
+            expected_error_count: 1,
+            message: "unknown type",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: bfe @ 0 = [1, 2 halt",
+            expected_error: "missing `,` or `]` after element",
+            expected_error_count: 1,
+            message: "unterminated data",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data halt: bfe @ 0 = [1] halt",
+            expected_error: "data section name must be neither instruction nor keyword",
+            expected_error_count: 1,
+            message: "data section named like an instruction",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "data x: bfe @ 0 = [1, 2] data y: bfe @ 1 = [3] halt",
+            expected_error: "overlapping data section",
+            expected_error_count: 1,
+            message: "overlapping data sections",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "const x = 1 data x: bfe @ 0 = [1] halt",
+            expected_error: "duplicate constant",
+            expected_error_count: 1,
+            message: "data section named like a constant",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_program_with_constants() {
+        TestCase {
+            input: "const N = 5 push N pop N",
+            expected: vec![
+                Instruction::Push(bfe!(5)),
+                Instruction::Push(bfe!(5)),
+                Instruction::Pop(NumberOfWords::N5),
+                Instruction::Pop(NumberOfWords::N5),
+            ],
+            message: "constant as field element and as number of words",
+        }
+        .run();
+
+        TestCase {
+            input: "const MINUS_ONE = -1 const ST = 3 push MINUS_ONE swap ST",
+            expected: vec![
+                Instruction::Push(-bfe!(1)),
+                Instruction::Push(-bfe!(1)),
+                Instruction::Swap(OpStackElement::ST3),
+                Instruction::Swap(OpStackElement::ST3),
+            ],
+            message: "negative constant, and constant as stack register",
+        }
+        .run();
+
+        TestCase {
+            input: "const ERR = 42 assert error_id ERR",
+            expected: vec![Instruction::Assert],
+            message: "constant as error id",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_erroneous_constants() {
+        NegativeTestCase {
+            input: "push N",
+            expected_error: "unknown constant",
+            expected_error_count: 1,
+            message: "undefined constant",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "push N const N = 5",
+            expected_error: "unknown constant",
+            expected_error_count: 1,
+            message: "constant must be defined before use",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "const N = 5 const N = 6",
+            expected_error: "duplicate constant",
+            expected_error_count: 1,
+            message: "constant defined twice",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "const pop = 5",
+            expected_error: "constant name must be neither instruction nor keyword",
+            expected_error_count: 1,
+            message: "constant with name of an instruction",
+        }
+        .run();
+
+        NegativeTestCase {
+            input: "const N = 17 dup N",
+            expected_error: "n/a",
+            expected_error_count: 0,
+            message: "constant is not a valid stack register",
+        }
+        .run();
+    }
+
+    #[test]
+    fn parse_program_with_macros() {
+        TestCase {
+            input: "macro double(x) push x dup 0 add endmacro double!(3) double!(4)",
+            expected: vec![
+                Instruction::Push(bfe!(3)),
+                Instruction::Push(bfe!(3)),
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Add,
+                Instruction::Push(bfe!(4)),
+                Instruction::Push(bfe!(4)),
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Add,
+            ],
+            message: "macro with one parameter",
+        }
+        .run();
+
+        TestCase {
+            input: "macro two_pops pop 1 pop 1 endmacro two_pops! two_pops!()",
+            expected: vec![Instruction::Pop(NumberOfWords::N1); 8],
+            message: "macro without parameters, invoked with and without parentheses",
+        }
+        .run();
 
-    #[test]
-    fn parse_program_bracket_syntax() {
-        NegativeTestCase {
-            input: "foo: [foo]",
-            expected_error: "expecting label, instruction or eof",
-            expected_error_count: 1,
-            message: "brackets as call syntax sugar are unsupported",
+        TestCase {
+            input: "
+                const N = 2
+                macro swap_and_call(n, subroutine)
+                    swap n
+                    call subroutine
+                endmacro
+                swap_and_call!(N, foo)
+                foo:
+                    return
+            ",
+            expected: vec![
+                Instruction::Swap(OpStackElement::ST2),
+                Instruction::Swap(OpStackElement::ST2),
+                Instruction::Call(bfe!(4)),
+                Instruction::Call(bfe!(4)),
+                Instruction::Return,
+            ],
+            message: "macro with constant as argument and label as argument",
         }
         .run();
 
-        NegativeTestCase {
-            input: "foo: [bar]",
-            expected_error: "expecting label, instruction or eof",
-            expected_error_count: 1,
-            message: "brackets as call syntax sugar are unsupported",
+        TestCase {
+            input: "
+                macro inner(x) push x endmacro
+                macro outer(y) inner!(y) inner!(y) endmacro
+                outer!(7)
+            ",
+            expected: vec![Instruction::Push(bfe!(7)); 4],
+            message: "macro invoking another macro",
+        }
+        .run();
+
+        TestCase {
+            input: "
+                macro local_constant const N = 1 push N endmacro
+                local_constant! local_constant!
+            ",
+            expected: vec![Instruction::Push(bfe!(1)); 4],
+            message: "constants defined in a macro's body are local to each expansion",
         }
         .run();
     }
 
     #[test]
-    fn parse_program_label_must_start_with_alphabetic_character_or_underscore() {
+    fn parse_erroneous_macros() {
         NegativeTestCase {
-            input: "1foo: call 1foo",
-            expected_error: "expecting label, instruction or eof",
+            input: "foo!(1)",
+            expected_error: "unknown macro",
             expected_error_count: 1,
-            message: "labels cannot start with a digit",
+            message: "undefined macro",
         }
         .run();
 
         NegativeTestCase {
-            input: "-foo: call -foo",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo(x) push x endmacro foo!(1, 2)",
+            expected_error: "wrong number of macro arguments",
             expected_error_count: 1,
-            message: "labels cannot start with a dash",
+            message: "too many macro arguments",
         }
         .run();
 
-        TestCase {
-            input: "_foo: call _foo",
-            expected: vec![Instruction::Call(bfe!(0)), Instruction::Call(bfe!(0))],
-            message: "labels can start with an underscore",
+        NegativeTestCase {
+            input: "macro foo(x) push x endmacro foo!",
+            expected_error: "macro defined here",
+            expected_error_count: 1,
+            message: "argument count mismatch points at the definition",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_zero_length_range() {
         NegativeTestCase {
-            input: "hint foo: Type = stack[0..0]",
-            expected_error: "range end must be greater than range start",
+            input: "macro foo(x) push y endmacro foo!(1)",
+            expected_error: "unknown constant",
             expected_error_count: 1,
-            message: "parse type hint with zero-length range",
+            message: "undefined parameter in macro body",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_closing_bracket() {
         NegativeTestCase {
-            input: "hint foo: Type = stack[2..5",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo(x) push y endmacro foo!(1)",
+            expected_error: "in expansion of macro",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing closing bracket",
+            message: "error in macro body points at the invocation",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_opening_bracket() {
         NegativeTestCase {
-            input: "hint foo: Type = stack2..5]",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo bar! endmacro macro bar foo! endmacro foo!",
+            expected_error: "recursive macro invocation",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing opening bracket",
+            message: "mutually recursive macros",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_equals_sign() {
         NegativeTestCase {
-            input: "hint foo: Type stack[2..5];",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo pop 1 endmacro macro foo pop 2 endmacro",
+            expected_error: "duplicate macro",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing equals sign",
+            message: "macro defined twice",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_type_name() {
         NegativeTestCase {
-            input: "hint foo: = stack[2..5]",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo(x, x) push x endmacro",
+            expected_error: "duplicate macro parameter",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing type name",
+            message: "macro with repeated parameter name",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_variable_name() {
         NegativeTestCase {
-            input: "hint : Type = stack[2..5]",
-            expected_error: "label must be neither instruction nor keyword",
+            input: "macro foo pop 1",
+            expected_error: "macro definition must end with `endmacro`",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing variable name",
+            message: "macro definition without end",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_colon() {
         NegativeTestCase {
-            input: "hint foo Type = stack[2..5]",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro hint pop 1 endmacro",
+            expected_error: "macro name must be neither instruction nor keyword",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing colon",
+            message: "macro with name of a keyword",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_type_hint_with_range_and_offset_and_missing_hint() {
         NegativeTestCase {
-            input: "foo: Type = stack[2..5];",
-            expected_error: "expecting label, instruction or eof",
+            input: "macro foo(l) call l endmacro foo!(push)",
+            expected_error: "invalid label",
             expected_error_count: 1,
-            message: "parse type hint with range and offset and missing hint",
+            message: "macro argument used as label must be a legal label",
         }
         .run();
     }
 
     #[test]
-    fn parse_simple_assertion_contexts() {
-        TestCase {
-            input: "assert",
-            expected: vec![Instruction::Assert],
-            message: "naked assert",
-        }
-        .run();
+    fn parse_program_with_structured_control_flow() {
         TestCase {
-            input: "assert_vector",
-            expected: vec![Instruction::AssertVector],
-            message: "naked assert_vector",
+            input: "if push 1 endif halt",
+            expected: vec![
+                Instruction::Skiz,
+                Instruction::Call(bfe!(4)),
+                Instruction::Call(bfe!(4)),
+                Instruction::Halt,
+                Instruction::Push(bfe!(1)),
+                Instruction::Push(bfe!(1)),
+                Instruction::Return,
+            ],
+            message: "if without else",
         }
         .run();
 
         TestCase {
-            input: "assert error_id 42",
-            expected: vec![Instruction::Assert],
-            message: "assert, then id",
-        }
-        .run();
-        TestCase {
-            input: "assert_vector error_id 42",
-            expected: vec![Instruction::AssertVector],
-            message: "assert_vector, then id",
+            input: "if push 1 else push 2 endif halt",
+            expected: vec![
+                Instruction::Push(bfe!(1)),
+                Instruction::Push(bfe!(1)),
+                Instruction::Swap(OpStackElement::ST1),
+                Instruction::Swap(OpStackElement::ST1),
+                Instruction::Skiz,
+                Instruction::Call(bfe!(11)),
+                Instruction::Call(bfe!(11)),
+                Instruction::Skiz,
+                Instruction::Call(bfe!(18)),
+                Instruction::Call(bfe!(18)),
+                Instruction::Halt,
+                Instruction::Pop(NumberOfWords::N1),
+                Instruction::Pop(NumberOfWords::N1),
+                Instruction::Push(bfe!(1)),
+                Instruction::Push(bfe!(1)),
+                Instruction::Push(bfe!(0)),
+                Instruction::Push(bfe!(0)),
+                Instruction::Return,
+                Instruction::Push(bfe!(2)),
+                Instruction::Push(bfe!(2)),
+                Instruction::Return,
+            ],
+            message: "if with else",
         }
         .run();
 
         TestCase {
-            input: "assert error_id -42",
-            expected: vec![Instruction::Assert],
-            message: "assert, then negative id",
+            input: "while push 0 endwhile halt",
+            expected: vec![
+                Instruction::Call(bfe!(3)),
+                Instruction::Call(bfe!(3)),
+                Instruction::Halt,
+                Instruction::Push(bfe!(0)),
+                Instruction::Push(bfe!(0)),
+                Instruction::Eq,
+                Instruction::Skiz,
+                Instruction::Return,
+                Instruction::Push(bfe!(0)),
+                Instruction::Push(bfe!(0)),
+                Instruction::Recurse,
+            ],
+            message: "while",
         }
         .run();
+
         TestCase {
-            input: "assert_vector error_id -42",
-            expected: vec![Instruction::AssertVector],
-            message: "assert_vector, then negative id",
+            input: "const N = 3 for N nop endfor halt",
+            expected: vec![
+                Instruction::Push(bfe!(3)),
+                Instruction::Push(bfe!(3)),
+                Instruction::Call(bfe!(7)),
+                Instruction::Call(bfe!(7)),
+                Instruction::Pop(NumberOfWords::N1),
+                Instruction::Pop(NumberOfWords::N1),
+                Instruction::Halt,
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Dup(OpStackElement::ST0),
+                Instruction::Push(bfe!(0)),
+                Instruction::Push(bfe!(0)),
+                Instruction::Eq,
+                Instruction::Skiz,
+                Instruction::Return,
+                Instruction::Nop,
+                Instruction::AddI(-bfe!(1)),
+                Instruction::AddI(-bfe!(1)),
+                Instruction::Recurse,
+            ],
+            message: "for with constant number of iterations",
         }
         .run();
 
         TestCase {
-            input: "assert error_id 42 nop",
-            expected: vec![Instruction::Assert, Instruction::Nop],
-            message: "assert, then id, then nop",
+            input: "macro maybe_nop if nop endif endmacro maybe_nop! maybe_nop! halt",
+            expected: vec![
+                Instruction::Skiz,
+                Instruction::Call(bfe!(7)),
+                Instruction::Call(bfe!(7)),
+                Instruction::Skiz,
+                Instruction::Call(bfe!(9)),
+                Instruction::Call(bfe!(9)),
+                Instruction::Halt,
+                Instruction::Nop,
+                Instruction::Return,
+                Instruction::Nop,
+                Instruction::Return,
+            ],
+            message: "labels generated in macro expansions are unique",
         }
         .run();
+
         TestCase {
-            input: "assert_vector error_id 42 nop",
-            expected: vec![Instruction::AssertVector, Instruction::Nop],
-            message: "assert_vector, then id, then nop",
+            input: "call else halt else: return",
+            expected: vec![
+                Instruction::Call(bfe!(3)),
+                Instruction::Call(bfe!(3)),
+                Instruction::Halt,
+                Instruction::Return,
+            ],
+            message: "control-flow keywords can be labels",
         }
         .run();
     }
 
     #[test]
-    fn assertion_context_error_id_can_handle_edge_case_ids() {
-        let instructions = [
-            ("assert", Instruction::Assert),
-            ("assert_vector", Instruction::AssertVector),
-        ];
-        let ids = [i128::MIN, -1, 0, 1, i128::MAX];
-
-        for ((instruction_str, instruction), id) in instructions.into_iter().cartesian_product(ids)
-        {
-            TestCase {
-                input: &format!("{instruction_str} error_id {id}"),
-                expected: vec![instruction],
-                message: "assert, then edge case id",
-            }
-            .run();
-        }
-    }
-
-    #[test]
-    fn assertion_context_error_id_fails_on_too_large_error_ids() {
+    fn parse_erroneous_structured_control_flow() {
         NegativeTestCase {
-            input: "assert error_id -170141183460469231731687303715884105729",
-            expected_error: "expecting label, instruction or eof",
+            input: "if nop halt",
+            expected_error: "missing `endif`",
             expected_error_count: 1,
-            message: "error id smaller than i128::MIN",
+            message: "if without end",
         }
         .run();
+
         NegativeTestCase {
-            input: "assert error_id 170141183460469231731687303715884105728",
-            expected_error: "expecting label, instruction or eof",
+            input: "if nop endwhile halt",
+            expected_error: "expecting `else` or `endif`",
             expected_error_count: 1,
-            message: "error id larger than i128::MAX",
-        }
-        .run();
-    }
-
-    #[proptest]
-    fn assertion_context_error_id_can_handle_any_valid_id(id: i128) {
-        TestCase {
-            input: &format!("assert error_id {id}"),
-            expected: vec![Instruction::Assert],
-            message: "assert, then random id",
+            message: "if with end of while",
         }
         .run();
-    }
 
-    #[test]
-    fn parse_erroneous_assertion_contexts_for_assert() {
         NegativeTestCase {
-            input: "assert error_id",
-            expected_error: "expecting label, instruction or eof",
+            input: "while nop else nop endwhile halt",
+            expected_error: "expecting `endwhile`",
             expected_error_count: 1,
-            message: "missing id after keyword `error_id`",
+            message: "while with else",
         }
         .run();
+
         NegativeTestCase {
-            input: "assert error id 42",
+            input: "endfor halt",
             expected_error: "expecting label, instruction or eof",
             expected_error_count: 1,
-            message: "incorrect keyword `error id`",
+            message: "end of for without for",
         }
         .run();
+
         NegativeTestCase {
-            input: "error_id 42",
-            expected_error: "incorrectly placed assertion context",
+            input: "for -1 nop endfor halt",
+            expected_error: "expecting number of iterations",
             expected_error_count: 1,
-            message: "standalone assertion context",
+            message: "for with negative number of iterations",
         }
         .run();
+
         NegativeTestCase {
-            input: "error_id 42 error_id 42",
-            expected_error: "incorrectly placed assertion context",
-            expected_error_count: 2,
-            message: "multiple standalone assertion contexts",
+            input: "for 4294967296 nop endfor halt",
+            expected_error: "number of iterations must fit in a u32",
+            expected_error_count: 1,
+            message: "for with too many iterations",
         }
         .run();
+
         NegativeTestCase {
-            input: "nop error_id 42",
-            expected_error: "incorrectly placed assertion context",
+            input: "push 1 if nop endif",
+            expected_error: "control flow must be followed by an unconditional",
             expected_error_count: 1,
-            message: "error id without assertion",
+            message: "generated subroutine cannot be placed",
         }
         .run();
+
         NegativeTestCase {
-            input: "assert error_id 42 error_id 1337",
-            expected_error: "incorrectly placed assertion context",
+            input: "if nop endif push 0 skiz halt",
+            expected_error: "control flow must be followed by an unconditional",
             expected_error_count: 1,
-            message: "multiple error ids for the same assertion",
+            message: "generated subroutine cannot be placed after conditional instruction",
         }
         .run();
+
         NegativeTestCase {
-            input: "assert_vector error_id 42 error_id 1337",
-            expected_error: "incorrectly placed assertion context",
+            input: "push 0 skiz if push 7 pop 1 endif halt",
+            expected_error: "control-flow construct must not follow `skiz`",
             expected_error_count: 1,
-            message: "multiple error ids for the same vector assertion",
+            message: "if after skiz",
         }
         .run();
+
         NegativeTestCase {
-            input: "error_id 42 assert",
-            expected_error: "incorrectly placed assertion context",
+            input: "push 0 skiz if push 7 else push 8 endif pop 1 halt",
+            expected_error: "control-flow construct must not follow `skiz`",
             expected_error_count: 1,
-            message: "error id precedes assertion",
+            message: "if with else after skiz",
         }
         .run();
+
         NegativeTestCase {
-            input: "error_id 42 assert error_id 1337",
-            expected_error: "incorrectly placed assertion context",
+            input: "push 0 skiz for 3 nop endfor halt",
+            expected_error: "control-flow construct must not follow `skiz`",
             expected_error_count: 1,
-            message: "first error id precedes assertion",
+            message: "for after skiz",
         }
         .run();
     }
 
-    #[proptest]
-    fn assertion_context_error_id_fails_for_invalid_id(
-        #[strategy(proptest::strategy::Union::new(["assert", "assert_vector"]))]
-        instruction: String,
-        #[filter(#id.parse::<i128>().is_err())] id: String,
-    ) {
-        // A valid ID followed by a comment is valid, and therefore not relevant here.
-        // This might be a monkey-patch, but I don't know how to generate better input.
-        if let Some(comment_idx) = id.find("//") {
-            let (id, _) = id.split_at(comment_idx);
-            prop_assume!(id.parse::<i128>().is_err());
-        }
+    #[test]
+    fn while_loop_can_follow_skiz() {
+        let instructions = triton_asm!(
+            foo:
+                push 1 skiz while push 0 endwhile
+                return
+        );
+        let expected = triton_asm!(
+            foo:
+                push 1 skiz call foo__while_0
+                return
+            foo__while_0:
+                push 0 eq skiz return
+                push 0
+                recurse
+        );
+        assert!(expected == instructions);
+    }
 
-        // not using `NegativeTest`: different `id`s trigger different errors
-        let input = format!("{instruction} error_id {id}");
-        prop_assert!(parse(&input).is_err());
+    #[test]
+    fn generated_subroutines_are_placed_after_unconditional_terminator() {
+        let instructions = triton_asm!(
+            foo:
+                if push 7 endif
+                push 0 skiz return
+                push 5
+                return
+        );
+        let expected = triton_asm!(
+            foo:
+                skiz call foo__then_0
+                push 0 skiz return
+                push 5
+                return
+            foo__then_0:
+                push 7
+                return
+        );
+        assert!(expected == instructions);
     }
 
-    #[proptest]
-    fn type_hint_to_string_to_type_hint_is_identity(#[strategy(arb())] type_hint: TypeHint) {
-        let type_hint_string = type_hint.to_string();
-        let instruction_tokens =
-            parse(&type_hint_string).map_err(|err| TestCaseError::Fail(format!("{err}").into()))?;
-        let labelled_instructions = to_labelled_instructions(&instruction_tokens);
-        prop_assert_eq!(1, labelled_instructions.len());
-        let first_labelled_instruction = labelled_instructions[0].clone();
-        let_assert!(LabelledInstruction::TypeHint(parsed_type_hint) = first_labelled_instruction);
-        prop_assert_eq!(type_hint, parsed_type_hint);
+    #[test]
+    fn generated_subroutines_can_be_placed_after_recurse_or_return() {
+        let instructions = triton_asm!(
+            foo:
+                if push 7 endif
+                recurse_or_return
+        );
+        let expected = triton_asm!(
+            foo:
+                skiz call foo__then_0
+                recurse_or_return
+            foo__then_0:
+                push 7
+                return
+        );
+        assert!(expected == instructions);
     }
 
-    #[proptest]
-    fn assertion_context_to_string_to_assertion_context_is_identity(
-        #[strategy(arb())] context: AssertionContext,
-    ) {
-        let assert_with_context = format!("assert {context}");
-        let instruction_tokens = parse(&assert_with_context)
-            .map_err(|err| TestCaseError::Fail(format!("{err}").into()))?;
-        let labelled_instructions = to_labelled_instructions(&instruction_tokens);
-        prop_assert_eq!(2, labelled_instructions.len());
-        let_assert!(LabelledInstruction::AssertionContext(parsed) = &labelled_instructions[1]);
-        prop_assert_eq!(&context, parsed);
+    #[test]
+    fn labels_generated_for_structured_control_flow_derive_from_enclosing_label() {
+        let instructions = triton_asm!(
+            foo:
+                if
+                    if nop endif
+                else
+                    nop
+                endif
+                return
+        );
+        let expected = triton_asm!(
+            foo:
+                push 1 swap 1 skiz call foo__then_0 skiz call foo__else_0
+                return
+            foo__then_0__then_0:
+                nop
+                return
+            foo__then_0:
+                pop 1
+                skiz call foo__then_0__then_0
+                push 0
+                return
+            foo__else_0:
+                nop
+                return
+        );
+        assert_eq!(expected, instructions);
     }
 
     #[test]
@@ -1645,6 +3771,20 @@ pub(crate) mod tests {
         assert_eq!(expected_instructions, instructions);
     }
 
+    #[test]
+    fn triton_asm_macro_supports_assembler_macros_and_constants() {
+        let instructions = triton_asm!(
+            const N = 3
+            macro push_twice(x)
+                push x push x
+            endmacro
+            push_twice!(N)
+            pop N
+        );
+        let expected = triton_asm!(push 3 push 3 pop 3);
+        assert_eq!(expected, instructions);
+    }
+
     #[test]
     fn break_gets_turned_into_labelled_instruction() {
         let instructions = triton_asm![break];
@@ -1657,4 +3797,13 @@ pub(crate) mod tests {
         let program = triton_program! { break halt break };
         assert_eq!(1, program.len_bwords());
     }
+
+    #[test]
+    fn tokenizing_with_recovery_skips_unparsable_statements() {
+        let code = "push 1 frobnicate pop 6 call foo foo: return";
+        let tokens = tokenize_with_recovery(code);
+        let token_strs = tokens.iter().map(|token| token.token_str()).collect_vec();
+        let expected = [code, &code[24..], &code[33..], &code[38..]];
+        assert_eq!(expected.as_slice(), token_strs);
+    }
 }
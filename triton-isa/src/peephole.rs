@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use num_traits::One;
+use num_traits::Zero;
+use strum::Display;
+use strum::EnumCount;
+use strum::EnumIter;
+
+use crate::instruction::AnInstruction;
+use crate::instruction::LabelledInstruction;
+use crate::op_stack::NumberOfWords;
+use crate::op_stack::OpStackElement;
+use crate::parser;
+
+/// A semantics-preserving rewrite of a short sequence of instructions.
+///
+/// Every rule removes at least one instruction. Since every instruction takes one clock
+/// cycle, every application of a rule saves at least one cycle whenever the rewritten
+/// code is executed.
+#[derive(
+    Debug, Display, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, EnumCount, EnumIter,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum PeepholeRule {
+    /// `push 0 add` has no effect.
+    AddZero,
+
+    /// `push 1 mul` has no effect.
+    MultiplyByOne,
+
+    /// `swap 0`, `pick 0`, and `place 0` have no effect.
+    NoOpStackManipulation,
+
+    /// `swap i swap i` has no effect.
+    DoubleSwap,
+
+    /// Any sequence of `pick i` and `place i` rotates the top `i + 1` stack elements.
+    /// It is fused into the shortest sequence rotating them equally. For example,
+    /// `pick i place i` has no effect, and `pick 2 pick 2` is the same as `place 2`.
+    PickPlace,
+
+    /// `dup i pop 1` and `push a pop 1` have no effect.
+    PushPop,
+
+    /// `pop n pop m` is the same as `pop (n + m)` if `n + m` is at most 5.
+    FusePops,
+
+    /// `push a push b add` is the same as `push (a + b)`.
+    FoldAddition,
+
+    /// `push a push b mul` is the same as `push (a · b)`.
+    FoldMultiplication,
+}
+
+/// The result of [`optimize`].
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Optimization {
+    /// The optimized instructions.
+    pub instructions: Vec<LabelledInstruction>,
+
+    /// How often each [rule](PeepholeRule) was applied.
+    pub applied_rules: BTreeMap<PeepholeRule, usize>,
+
+    /// The number of instructions removed by the optimization. Every removed instruction
+    /// saves one clock cycle each time the optimized code is executed.
+    pub saved_cycles: usize,
+}
+
+/// Optimize the given instructions by repeatedly rewriting short sequences of
+/// instructions into shorter, semantically equivalent ones. See [`PeepholeRule`] for the
+/// list of rewrites.
+///
+/// Only sequences of consecutive instructions are rewritten. Anything that is not an
+/// instruction, like a label, a type hint, or a breakpoint, separates sequences. The
+/// instruction following [`skiz`](AnInstruction::Skiz) is never rewritten because it is
+/// executed conditionally. Relative `call`s keep their targets: instructions between a
+/// relative `call` and its target can be rewritten, but the target itself is a barrier.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_asm;
+/// # use triton_isa::peephole::optimize;
+/// let instructions = triton_asm!(push 2 push 3 add push 0 add swap 1 swap 1 halt);
+/// let optimization = optimize(&instructions);
+/// assert_eq!(triton_asm!(push 5 halt), optimization.instructions);
+/// assert_eq!(6, optimization.saved_cycles);
+/// ```
+pub fn optimize(instructions: &[LabelledInstruction]) -> Optimization {
+    let (instructions, generated_labels) = label_relative_call_targets(instructions);
+    let mut optimization = Optimization::default();
+    let mut optimized = Vec::with_capacity(instructions.len());
+
+    // Instructions before this index must not be rewritten.
+    let mut num_protected_instructions = 0;
+
+    for labelled_instruction in &instructions {
+        let follows_skiz = matches!(
+            optimized.last(),
+            Some(LabelledInstruction::Instruction(AnInstruction::Skiz))
+        );
+        optimized.push(labelled_instruction.clone());
+
+        let LabelledInstruction::Instruction(_) = labelled_instruction else {
+            num_protected_instructions = optimized.len();
+            continue;
+        };
+        if follows_skiz {
+            num_protected_instructions = optimized.len();
+            continue;
+        }
+
+        // Rewriting can enable further rewrites of the preceding instructions.
+        while let Some((rule, num_replaced, replacement)) =
+            rewrite(&optimized[num_protected_instructions..])
+        {
+            optimized.truncate(optimized.len() - num_replaced);
+            let num_removed = num_replaced - replacement.len();
+            let replacement = replacement
+                .into_iter()
+                .map(LabelledInstruction::Instruction);
+            optimized.extend(replacement);
+
+            *optimization.applied_rules.entry(rule).or_default() += 1;
+            optimization.saved_cycles += num_removed;
+        }
+    }
+
+    optimization.instructions = restore_relative_call_targets(optimized, &generated_labels);
+    optimization
+}
+
+/// Replace the target of every relative `call` by a label generated at that target, such
+/// that rewriting instructions cannot change the target. Returns the generated labels,
+/// which [`restore_relative_call_targets`] turns back into relative targets.
+fn label_relative_call_targets(
+    instructions: &[LabelledInstruction],
+) -> (Vec<LabelledInstruction>, HashSet<String>) {
+    let existing_labels = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            LabelledInstruction::Label(label) => Some(label.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut label_candidates = (0..)
+        .map(|i| format!("__relative_call_target_{i}"))
+        .filter(|label| !existing_labels.contains(label.as_str()));
+
+    let mut address = 0_u64;
+    let mut boundaries = HashSet::from([0]);
+    let mut targets = vec![];
+    for instruction in instructions {
+        let LabelledInstruction::Instruction(instruction) = instruction else {
+            continue;
+        };
+        if let AnInstruction::Call(target) = instruction {
+            let offset = parser::relative_call_offset(target);
+            targets.push(offset.and_then(|offset| address.checked_add_signed(offset)));
+        }
+        address += instruction.size() as u64;
+        boundaries.insert(address);
+    }
+
+    // targets in the middle of an instruction or outside the code cannot be labelled
+    let mut target_labels = HashMap::new();
+    for &target in targets.iter().flatten() {
+        if boundaries.contains(&target) && !target_labels.contains_key(&target) {
+            target_labels.insert(target, label_candidates.next().unwrap());
+        }
+    }
+
+    let mut address = 0;
+    let mut targets = targets.into_iter();
+    let mut labelled = Vec::with_capacity(instructions.len() + target_labels.len());
+    for instruction in instructions {
+        let LabelledInstruction::Instruction(instruction) = instruction else {
+            labelled.push(instruction.clone());
+            continue;
+        };
+        if let Some(label) = target_labels.get(&address) {
+            labelled.push(LabelledInstruction::Label(label.clone()));
+        }
+        let target = match instruction {
+            AnInstruction::Call(_) => targets.next().flatten(),
+            _ => None,
+        };
+        let instruction = match target.and_then(|target| target_labels.get(&target)) {
+            Some(label) => AnInstruction::Call(label.clone()),
+            None => instruction.clone(),
+        };
+        address += instruction.size() as u64;
+        labelled.push(LabelledInstruction::Instruction(instruction));
+    }
+    if let Some(label) = target_labels.get(&address) {
+        labelled.push(LabelledInstruction::Label(label.clone()));
+    }
+
+    let generated_labels = target_labels.into_values().collect();
+    (labelled, generated_labels)
+}
+
+/// Undo [`label_relative_call_targets`]: remove the generated labels and turn `call`s to
+/// them into relative `call`s.
+fn restore_relative_call_targets(
+    instructions: Vec<LabelledInstruction>,
+    generated_labels: &HashSet<String>,
+) -> Vec<LabelledInstruction> {
+    if generated_labels.is_empty() {
+        return instructions;
+    }
+
+    let mut address = 0_u64;
+    let mut label_addresses = HashMap::new();
+    for instruction in &instructions {
+        match instruction {
+            LabelledInstruction::Label(label) if generated_labels.contains(label) => {
+                label_addresses.insert(label.clone(), address);
+            }
+            LabelledInstruction::Instruction(instruction) => {
+                address += instruction.size() as u64;
+            }
+            _ => (),
+        }
+    }
+
+    let mut address = 0_u64;
+    let mut restored = Vec::with_capacity(instructions.len());
+    for instruction in instructions {
+        match instruction {
+            LabelledInstruction::Label(label) if generated_labels.contains(&label) => (),
+            LabelledInstruction::Instruction(instruction) => {
+                let size = instruction.size() as u64;
+                let instruction = match instruction {
+                    AnInstruction::Call(label) if generated_labels.contains(&label) => {
+                        let offset = label_addresses[&label] as i64 - address as i64;
+                        AnInstruction::Call(format!("{offset:+}"))
+                    }
+                    instruction => instruction,
+                };
+                restored.push(LabelledInstruction::Instruction(instruction));
+                address += size;
+            }
+            instruction => restored.push(instruction),
+        }
+    }
+
+    restored
+}
+
+/// Find a rule that applies to the end of the given instructions. If one is found, return
+/// it together with the number of instructions at the end to replace, and their
+/// replacement.
+fn rewrite(
+    instructions: &[LabelledInstruction],
+) -> Option<(PeepholeRule, usize, Vec<AnInstruction<String>>)> {
+    let instruction_at_end = |offset: usize| {
+        let index = instructions.len().checked_sub(offset)?;
+        let LabelledInstruction::Instruction(instruction) = &instructions[index] else {
+            return None;
+        };
+        Some(instruction)
+    };
+
+    let last = instruction_at_end(1)?;
+    match last {
+        AnInstruction::Swap(OpStackElement::ST0)
+        | AnInstruction::Pick(OpStackElement::ST0)
+        | AnInstruction::Place(OpStackElement::ST0) => {
+            return Some((PeepholeRule::NoOpStackManipulation, 1, vec![]));
+        }
+        _ => (),
+    }
+
+    if let Some((num_replaced, fused)) = fuse_picks_and_places(instructions) {
+        return Some((PeepholeRule::PickPlace, num_replaced, fused));
+    }
+
+    let second_to_last = instruction_at_end(2)?;
+    match (second_to_last, last) {
+        (AnInstruction::Push(a), AnInstruction::Add) if a.is_zero() => {
+            return Some((PeepholeRule::AddZero, 2, vec![]));
+        }
+        (AnInstruction::Push(a), AnInstruction::Mul) if a.is_one() => {
+            return Some((PeepholeRule::MultiplyByOne, 2, vec![]));
+        }
+        (AnInstruction::Swap(i), AnInstruction::Swap(j)) if i == j => {
+            return Some((PeepholeRule::DoubleSwap, 2, vec![]));
+        }
+        (AnInstruction::Dup(_) | AnInstruction::Push(_), AnInstruction::Pop(NumberOfWords::N1)) => {
+            return Some((PeepholeRule::PushPop, 2, vec![]));
+        }
+        (AnInstruction::Pop(n), AnInstruction::Pop(m)) => {
+            let num_words = usize::from(n) + usize::from(m);
+            if let Ok(num_words) = NumberOfWords::try_from(num_words) {
+                let fused = vec![AnInstruction::Pop(num_words)];
+                return Some((PeepholeRule::FusePops, 2, fused));
+            }
+        }
+        _ => (),
+    }
+
+    let third_to_last = instruction_at_end(3)?;
+    match (third_to_last, second_to_last, last) {
+        (AnInstruction::Push(a), AnInstruction::Push(b), AnInstruction::Add) => {
+            let folded = vec![AnInstruction::Push(*a + *b)];
+            Some((PeepholeRule::FoldAddition, 3, folded))
+        }
+        (AnInstruction::Push(a), AnInstruction::Push(b), AnInstruction::Mul) => {
+            let folded = vec![AnInstruction::Push(*a * *b)];
+            Some((PeepholeRule::FoldMultiplication, 3, folded))
+        }
+        _ => None,
+    }
+}
+
+/// Fuse the longest sequence of `pick i` and `place i`, for one `i`, at the end of the
+/// given instructions. Returns the number of instructions to replace and their
+/// replacement, unless the sequence is already as short as possible.
+fn fuse_picks_and_places(
+    instructions: &[LabelledInstruction],
+) -> Option<(usize, Vec<AnInstruction<String>>)> {
+    let mut stack_element = None;
+    let mut num_instructions = 0;
+    let mut num_net_picks = 0_i64;
+    for instruction in instructions.iter().rev() {
+        let (st, num_picks) = match instruction {
+            LabelledInstruction::Instruction(AnInstruction::Pick(st)) => (*st, 1),
+            LabelledInstruction::Instruction(AnInstruction::Place(st)) => (*st, -1),
+            _ => break,
+        };
+        if *stack_element.get_or_insert(st) != st {
+            break;
+        }
+        num_instructions += 1;
+        num_net_picks += num_picks;
+    }
+
+    // `i + 1` picks, or `i + 1` places, rotate the top `i + 1` elements back into place
+    let st = stack_element?;
+    let num_rotated_elements = usize::from(st) + 1;
+    let num_picks = num_net_picks.rem_euclid(num_rotated_elements as i64) as usize;
+    let num_places = (num_rotated_elements - num_picks) % num_rotated_elements;
+    let fused = if num_picks <= num_places {
+        vec![AnInstruction::Pick(st); num_picks]
+    } else {
+        vec![AnInstruction::Place(st); num_places]
+    };
+
+    (fused.len() < num_instructions).then_some((num_instructions, fused))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+
+    use crate::triton_asm;
+
+    use super::*;
+
+    #[test]
+    fn every_rule_applies_to_its_example() {
+        let examples = [
+            (PeepholeRule::AddZero, triton_asm!(push 0 add)),
+            (PeepholeRule::MultiplyByOne, triton_asm!(push 1 mul)),
+            (PeepholeRule::NoOpStackManipulation, triton_asm!(swap 0)),
+            (PeepholeRule::NoOpStackManipulation, triton_asm!(pick 0)),
+            (PeepholeRule::NoOpStackManipulation, triton_asm!(place 0)),
+            (PeepholeRule::DoubleSwap, triton_asm!(swap 7 swap 7)),
+            (PeepholeRule::PickPlace, triton_asm!(pick 3 place 3)),
+            (PeepholeRule::PickPlace, triton_asm!(place 3 pick 3)),
+            (PeepholeRule::PickPlace, triton_asm!(pick 2 pick 2)),
+            (PeepholeRule::PushPop, triton_asm!(dup 4 pop 1)),
+            (PeepholeRule::PushPop, triton_asm!(push 42 pop 1)),
+            (PeepholeRule::FusePops, triton_asm!(pop 2 pop 3)),
+            (PeepholeRule::FoldAddition, triton_asm!(push 2 push 3 add)),
+            (
+                PeepholeRule::FoldMultiplication,
+                triton_asm!(push 2 push 3 mul),
+            ),
+        ];
+        for (rule, instructions) in examples {
+            let optimization = optimize(&instructions);
+            assert!(Some(&1) == optimization.applied_rules.get(&rule), "{rule}");
+            assert!(
+                optimization.instructions.len() < instructions.len(),
+                "{rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let optimization = optimize(&triton_asm!(push 2 push 3 add push 4 mul push -1 add));
+        assert!(triton_asm!(push 19) == optimization.instructions);
+        assert!(6 == optimization.saved_cycles);
+    }
+
+    #[test]
+    fn rewrites_cascade() {
+        let instructions = triton_asm!(swap 1 push 0 push 0 add add swap 1);
+        let optimization = optimize(&instructions);
+        assert!(optimization.instructions.is_empty());
+        assert!(6 == optimization.saved_cycles);
+    }
+
+    #[test]
+    fn picks_and_places_are_fused() {
+        let optimization = optimize(&triton_asm!(pick 2 pick 2));
+        assert!(triton_asm!(place 2) == optimization.instructions);
+
+        let optimization = optimize(&triton_asm!(place 1 place 1 pick 4 pick 4 pick 4));
+        assert!(triton_asm!(place 4 place 4) == optimization.instructions);
+
+        let optimization = optimize(&triton_asm!(pick 3 place 3 pick 3 pick 3 place 3));
+        assert!(triton_asm!(pick 3) == optimization.instructions);
+
+        let optimization = optimize(&triton_asm!(pick 2 pick 2 pick 2 pick 2 swap 1));
+        assert!(triton_asm!(pick 2 swap 1) == optimization.instructions);
+    }
+
+    #[test]
+    fn picks_and_places_of_different_elements_are_not_fused() {
+        let instructions = triton_asm!(pick 2 place 3 pick 3 pick 4);
+        let optimization = optimize(&instructions);
+        assert!(triton_asm!(pick 2 pick 4) == optimization.instructions);
+
+        let instructions = triton_asm!(pick 2 pick 3 place 4);
+        let optimization = optimize(&instructions);
+        assert!(instructions == optimization.instructions);
+    }
+
+    #[test]
+    fn pops_are_only_fused_if_result_is_legal() {
+        let optimization = optimize(&triton_asm!(pop 3 pop 3));
+        assert!(triton_asm!(pop 3 pop 3) == optimization.instructions);
+        assert!(0 == optimization.saved_cycles);
+    }
+
+    #[test]
+    fn labels_and_hints_are_barriers() {
+        let instructions = triton_asm!(push 0 foo: add push 1 hint x = stack[0] mul halt);
+        let optimization = optimize(&instructions);
+        assert!(instructions == optimization.instructions);
+    }
+
+    #[test]
+    fn relative_calls_keep_their_targets() {
+        let instructions = triton_asm!(call +5 push 0 add return);
+        let optimization = optimize(&instructions);
+        assert!(triton_asm!(call +2 return) == optimization.instructions);
+
+        let instructions = triton_asm!(return push 0 add call -4 halt);
+        let optimization = optimize(&instructions);
+        assert!(triton_asm!(return call -1 halt) == optimization.instructions);
+
+        let instructions = triton_asm!(call +6 push 1 mul return);
+        let optimization = optimize(&instructions);
+        assert!(triton_asm!(call +3 return) == optimization.instructions);
+    }
+
+    #[test]
+    fn targets_of_relative_calls_are_barriers() {
+        let instructions = triton_asm!(call +4 push 0 add return);
+        let optimization = optimize(&instructions);
+        assert!(instructions == optimization.instructions);
+    }
+
+    #[test]
+    fn instruction_after_skiz_is_not_rewritten() {
+        let instructions = triton_asm!(skiz push 0 add halt);
+        let optimization = optimize(&instructions);
+        assert!(instructions == optimization.instructions);
+
+        let optimization = optimize(&triton_asm!(skiz halt push 0 add));
+        assert!(triton_asm!(skiz halt) == optimization.instructions);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Display;
@@ -6,6 +7,7 @@ use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
 use std::hash::Hash;
 use std::io::Cursor;
+use std::ops::Range;
 
 use arbitrary::Arbitrary;
 use get_size2::GetSize;
@@ -17,30 +19,41 @@ use twenty_first::prelude::*;
 
 use crate::instruction::AnInstruction;
 use crate::instruction::AssertionContext;
+use crate::instruction::DataSection;
 use crate::instruction::Instruction;
 use crate::instruction::InstructionError;
 use crate::instruction::LabelledInstruction;
 use crate::instruction::TypeHint;
+use crate::op_stack::NumberOfWords;
 use crate::parser;
+use crate::parser::InstructionToken;
 use crate::parser::ParseError;
 
 /// A program for Triton VM. Triton VM can run and profile such programs,
 /// and trace its execution in order to generate a proof of correct execution.
 /// See there for details.
 ///
-/// A program may contain debug information, such as label names and breakpoints.
-/// Access this information through methods [`label_for_address()`][label_for_address] and
-/// [`is_breakpoint()`][is_breakpoint]. Some operations, most notably
+/// Besides its instructions, a program can contain [data](DataSection). The data is
+/// written to random-access memory by the program's
+/// [data prologue](Self::len_data_prologue), which is part of the program's
+/// [digest](Self::hash) like any other instruction.
+///
+/// A program may contain debug information, such as label names, breakpoints, and the
+/// locations of instructions in the source code. Access this information through methods
+/// [`label_for_address()`][label_for_address], [`is_breakpoint()`][is_breakpoint], and
+/// [`source_location_at()`][source_location_at]. Some operations, most notably
 /// [BField-encoding](BFieldCodec::encode), discard this debug information.
 ///
 /// [program attestation]: https://triton-vm.org/spec/program-attestation.html
 /// [label_for_address]: Program::label_for_address
 /// [is_breakpoint]: Program::is_breakpoint
+/// [source_location_at]: Program::source_location_at
 #[derive(Debug, Clone, Eq, Serialize, Deserialize, GetSize)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
-    address_to_label: HashMap<u64, String>,
-    debug_information: DebugInformation,
+    pub(crate) data_sections: Vec<DataSection>,
+    pub(crate) address_to_label: HashMap<u64, String>,
+    pub(crate) debug_information: DebugInformation,
 }
 
 impl Display for Program {
@@ -105,6 +118,7 @@ impl BFieldCodec for Program {
 
         Ok(Box::new(Program {
             instructions,
+            data_sections: vec![],
             address_to_label: HashMap::default(),
             debug_information: DebugInformation::default(),
         }))
@@ -154,6 +168,10 @@ impl<'a> Arbitrary<'a> for Program {
                 // assertion context must come after an assertion
                 continue;
             }
+            if let LabelledInstruction::DataSection(_) = labelled_instruction {
+                // data sections must not overlap, which is unlikely to hold for random ones
+                continue;
+            }
 
             let is_assertion = is_assertion(&labelled_instruction);
             labelled_instructions.push(labelled_instruction);
@@ -216,26 +234,95 @@ impl IntoIterator for Program {
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, Arbitrary, GetSize)]
-struct DebugInformation {
-    breakpoints: Vec<bool>,
-    type_hints: HashMap<u64, Vec<TypeHint>>,
-    assertion_context: HashMap<u64, AssertionContext>,
+pub(crate) struct DebugInformation {
+    pub(crate) breakpoints: Vec<bool>,
+    pub(crate) type_hints: HashMap<u64, Vec<TypeHint>>,
+    pub(crate) assertion_context: HashMap<u64, AssertionContext>,
+    pub(crate) source_locations: HashMap<u64, SourceLocation>,
+}
+
+/// A location in the source code a [`Program`] was parsed from.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Arbitrary, GetSize)]
+pub struct SourceLocation {
+    /// The file or [module](crate::linker::Linker) containing the source code, if known.
+    pub file: Option<String>,
+
+    /// The line, starting at 1.
+    pub line: usize,
+
+    /// The column, starting at 1. Counts characters, not bytes.
+    pub column: usize,
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 impl Program {
     pub fn new(labelled_instructions: &[LabelledInstruction]) -> Self {
-        let label_to_address = parser::build_label_to_address_map(labelled_instructions);
+        let (program, unresolved_calls) = Self::with_unresolved_calls(labelled_instructions);
+        if let Some(label) = unresolved_calls.values().next() {
+            panic!("Label not found: {label}");
+        }
+        program
+    }
+
+    /// Like [`Program::new`], but `call`s to labels that are not defined are permitted.
+    /// They target address 0. The labels they refer to are returned alongside the
+    /// program, keyed by the address of the respective `call`.
+    ///
+    /// If there are [`DataSection`]s, the program starts with a
+    /// [data prologue](Self::len_data_prologue).
+    pub(crate) fn with_unresolved_calls(
+        labelled_instructions: &[LabelledInstruction],
+    ) -> (Self, BTreeMap<u64, String>) {
+        let data_sections = labelled_instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                LabelledInstruction::DataSection(data) => Some(data.clone()),
+                _ => None,
+            })
+            .collect_vec();
+        let data_prologue = Self::data_prologue(&data_sections);
+        let labelled_instructions = &[data_prologue, labelled_instructions.to_vec()].concat();
+
+        let mut label_to_address = parser::build_label_to_address_map(labelled_instructions);
+        let mut unresolved_calls = BTreeMap::new();
+        let mut address = 0;
+        for instruction in labelled_instructions {
+            let LabelledInstruction::Instruction(instruction) = instruction else {
+                continue;
+            };
+            if let AnInstruction::Call(label) = instruction {
+                let is_relative = parser::relative_call_offset(label).is_some();
+                if !is_relative && !label_to_address.contains_key(label) {
+                    unresolved_calls.insert(address, label.clone());
+                }
+            }
+            address += instruction.size() as u64;
+        }
+
+        let address_to_label = Self::flip_map(label_to_address.clone());
+        for label in unresolved_calls.values() {
+            label_to_address.insert(label.clone(), 0);
+        }
         let instructions =
             parser::turn_labels_into_addresses(labelled_instructions, &label_to_address);
-        let address_to_label = Self::flip_map(label_to_address);
         let debug_information = Self::extract_debug_information(labelled_instructions);
 
         debug_assert_eq!(instructions.len(), debug_information.breakpoints.len());
-        Program {
+        let program = Program {
             instructions,
+            data_sections,
             address_to_label,
             debug_information,
-        }
+        };
+        (program, unresolved_calls)
     }
 
     fn flip_map<Key, Value: Eq + Hash>(map: HashMap<Key, Value>) -> HashMap<Value, Key> {
@@ -256,7 +343,7 @@ impl Program {
                     break_before_next_instruction = false;
                     address += instruction.size() as u64;
                 }
-                LabelledInstruction::Label(_) => (),
+                LabelledInstruction::Label(_) | LabelledInstruction::DataSection(_) => (),
                 LabelledInstruction::Breakpoint => break_before_next_instruction = true,
                 LabelledInstruction::TypeHint(hint) => match debug_info.type_hints.entry(address) {
                     Entry::Occupied(mut entry) => entry.get_mut().push(hint.clone()),
@@ -274,11 +361,148 @@ impl Program {
         debug_info
     }
 
-    /// Create a `Program` by parsing source code.
+    /// Create a `Program` by parsing source code. The program's instructions can be
+    /// traced back to the source code using [`source_location_at`](Self::source_location_at).
     pub fn from_code(code: &str) -> Result<Self, ParseError> {
-        parser::parse(code)
-            .map(|tokens| parser::to_labelled_instructions(&tokens))
-            .map(|instructions| Program::new(&instructions))
+        let tokens = parser::parse(code)?;
+        let labelled_instructions = parser::to_labelled_instructions(&tokens);
+        let source_locations = tokens.iter().map(|token| (code, None, token));
+        let program = Program::new(&labelled_instructions).with_source_locations(source_locations);
+
+        Ok(program)
+    }
+
+    /// Record where in the source code the program's instructions come from. Each
+    /// [`InstructionToken`] is accompanied by the source code it was parsed from and
+    /// the name of the file containing that source code, if any. The tokens must be
+    /// in the same order as the [`LabelledInstruction`]s the program was created from.
+    pub(crate) fn with_source_locations<'a>(
+        mut self,
+        tokens: impl IntoIterator<Item = (&'a str, Option<&'a str>, &'a InstructionToken<'a>)>,
+    ) -> Self {
+        let mut address = self.len_data_prologue() as u64;
+        let mut instructions = vec![];
+        for (source, file, token) in tokens {
+            let InstructionToken::Instruction(instruction, _) = token else {
+                continue;
+            };
+            instructions.push((address, source, file, token));
+            address += instruction.size() as u64;
+        }
+
+        // locating all tokens of one source code at once avoids re-reading it per token
+        let runs = instructions
+            .into_iter()
+            .chunk_by(|&(_, source, file, _)| (source.as_ptr(), file));
+        for (_, run) in &runs {
+            let run = run.collect_vec();
+            let (_, source, file, _) = run[0];
+            let tokens = run.iter().map(|&(_, _, _, token)| token).collect_vec();
+            let locations = parser::source_locations(source, file, &tokens);
+            for (&(address, ..), location) in run.iter().zip(locations) {
+                self.debug_information
+                    .source_locations
+                    .insert(address, location);
+            }
+        }
+
+        self
+    }
+
+    /// A program containing only those instructions whose addresses are keys in the given
+    /// map, each moved to the address it maps to. Labels and debug information move along
+    /// with their instructions, and `call` instructions are updated to target the new
+    /// addresses. The map must not make instructions overlap.
+    pub(crate) fn relocated(&self, address_map: &BTreeMap<u64, u64>) -> Self {
+        let debug_info = &self.debug_information;
+        let program_len = address_map
+            .iter()
+            .map(|(&old, &new)| new + self.instructions[old as usize].size() as u64)
+            .max()
+            .unwrap_or_default();
+        let mut instructions = vec![Instruction::Nop; program_len as usize];
+        let mut breakpoints = vec![false; program_len as usize];
+        for (&old_address, &new_address) in address_map {
+            let instruction = self.instructions[old_address as usize].map_call_address(|arg| {
+                let new_address = address_map.get(&arg.value());
+                new_address.map_or(*arg, |&address| bfe!(address))
+            });
+            let old_address = old_address as usize;
+            let new_address = new_address as usize;
+            for offset in 0..instruction.size() {
+                instructions[new_address + offset] = instruction;
+                breakpoints[new_address + offset] = debug_info.breakpoints[old_address + offset];
+            }
+        }
+
+        let debug_information = DebugInformation {
+            breakpoints,
+            type_hints: Self::relocate_keys(&debug_info.type_hints, address_map),
+            assertion_context: Self::relocate_keys(&debug_info.assertion_context, address_map),
+            source_locations: Self::relocate_keys(&debug_info.source_locations, address_map),
+        };
+
+        Program {
+            instructions,
+            data_sections: self.data_sections.clone(),
+            address_to_label: Self::relocate_keys(&self.address_to_label, address_map),
+            debug_information,
+        }
+    }
+
+    fn relocate_keys<Value: Clone>(
+        map: &HashMap<u64, Value>,
+        address_map: &BTreeMap<u64, u64>,
+    ) -> HashMap<u64, Value> {
+        map.iter()
+            .filter_map(|(address, value)| Some((*address_map.get(address)?, value.clone())))
+            .collect()
+    }
+
+    /// Append the given program, moving it to start where this program ends. The labels
+    /// of the appended program are renamed using the given function. Its `call`s are
+    /// relocated along with it, except for those at the given addresses, which are
+    /// redirected to the given absolute addresses instead.
+    pub(crate) fn append(
+        &mut self,
+        other: &Self,
+        rename: impl Fn(&str) -> String,
+        redirected_calls: &BTreeMap<u64, u64>,
+    ) {
+        let offset = self.len_bwords() as u64;
+        let mut address = 0;
+        while let Some(instruction) = other.instructions.get(address) {
+            let redirection = redirected_calls.get(&(address as u64));
+            let instruction = instruction.map_call_address(|target| match redirection {
+                Some(&new_target) => bfe!(new_target),
+                None => *target + bfe!(offset),
+            });
+            self.instructions
+                .extend(vec![instruction; instruction.size()]);
+            address += instruction.size();
+        }
+
+        let labels = Self::shift_keys(&other.address_to_label, offset);
+        let labels = labels.map(|(address, label)| (address, rename(&label)));
+        self.address_to_label.extend(labels);
+
+        let debug_info = &other.debug_information;
+        let own_debug_info = &mut self.debug_information;
+        let type_hints = Self::shift_keys(&debug_info.type_hints, offset);
+        let assertion_context = Self::shift_keys(&debug_info.assertion_context, offset);
+        let source_locations = Self::shift_keys(&debug_info.source_locations, offset);
+        own_debug_info.breakpoints.extend(&debug_info.breakpoints);
+        own_debug_info.type_hints.extend(type_hints);
+        own_debug_info.assertion_context.extend(assertion_context);
+        own_debug_info.source_locations.extend(source_locations);
+    }
+
+    fn shift_keys<Value: Clone>(
+        map: &HashMap<u64, Value>,
+        offset: u64,
+    ) -> impl Iterator<Item = (u64, Value)> + '_ {
+        map.iter()
+            .map(move |(address, value)| (address + offset, value.clone()))
     }
 
     pub fn labelled_instructions(&self) -> Vec<LabelledInstruction> {
@@ -287,9 +511,15 @@ impl Program {
             instruction.map_call_address(|&address| self.label_for_address(address.value()))
         });
 
-        let mut labelled_instructions = vec![];
-        let mut address = 0;
-        let mut instruction_stream = instructions_with_labels.into_iter();
+        let mut labelled_instructions = self
+            .data_sections
+            .iter()
+            .cloned()
+            .map(LabelledInstruction::DataSection)
+            .collect_vec();
+        let len_data_prologue = self.len_data_prologue();
+        let mut address = len_data_prologue as u64;
+        let mut instruction_stream = instructions_with_labels.skip(len_data_prologue);
         while let Some(instruction) = instruction_stream.next() {
             let instruction_size = instruction.size() as u64;
             if call_targets.contains(&address) {
@@ -360,6 +590,17 @@ impl Program {
             .cloned()
     }
 
+    /// The location in the source code of the instruction at the given address, if known.
+    /// Only programs that were parsed from source code, for example through
+    /// [`from_code`](Self::from_code) or a [`Linker`](crate::linker::Linker), know the
+    /// locations of their instructions.
+    pub fn source_location_at(&self, address: u64) -> Option<SourceLocation> {
+        self.debug_information
+            .source_locations
+            .get(&address)
+            .cloned()
+    }
+
     /// Turn the program into a sequence of `BFieldElement`s. Each instruction is encoded as its
     /// opcode, followed by its argument (if any).
     ///
@@ -379,6 +620,172 @@ impl Program {
             .collect()
     }
 
+    /// Like [`to_bwords()`](Self::to_bwords), but the argument of every `call` is the
+    /// distance from the `call` to its target instead of the target's absolute address.
+    /// Moving the program, or any part of it, to a different address does not change
+    /// the corresponding part of this sequence.
+    pub fn to_position_independent_bwords(&self) -> Vec<BFieldElement> {
+        let mut address = 0;
+        let mut sequence = Vec::with_capacity(self.len_bwords());
+        for instruction in self.clone() {
+            sequence.push(instruction.opcode_b());
+            if let Instruction::Call(target) = instruction {
+                sequence.push(target - bfe!(address));
+            } else if let Some(arg) = instruction.arg() {
+                sequence.push(arg);
+            }
+            address += instruction.size() as u64;
+        }
+        sequence
+    }
+
+    /// A digest of the program's instructions that does not depend on where they are
+    /// placed. For example, a library routine has the same position-independent digest
+    /// no matter where it is [linked](crate::object::link); extract it from the linked
+    /// program using [`snippet()`](Self::snippet).
+    ///
+    /// Like the canonical [digest](Self::hash), the position-independent digest commits to
+    /// the program's [`DataSection`]s through its
+    /// [data prologue](Self::len_data_prologue).
+    pub fn position_independent_hash(&self) -> Digest {
+        Tip5::hash_varlen(&self.to_position_independent_bwords())
+    }
+
+    /// The instructions at the given addresses as a program of their own, starting at
+    /// address 0. Every `call` keeps the distance to its target, even if that target lies
+    /// outside the snippet, so the snippet's
+    /// [position-independent digest](Self::position_independent_hash) only depends on the
+    /// instructions at the given addresses. Labels and debug information move along with
+    /// their instructions. The snippet has no [`DataSection`]s.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given addresses do not start and end at the boundaries of
+    /// instructions in the program.
+    #[must_use]
+    pub fn snippet(&self, addresses: Range<u64>) -> Self {
+        let mut boundaries = HashSet::from([0]);
+        let mut address = 0;
+        for instruction in self.clone() {
+            address += instruction.size() as u64;
+            boundaries.insert(address);
+        }
+        assert!(
+            boundaries.contains(&addresses.start) && boundaries.contains(&addresses.end),
+            "snippet must start and end at instruction boundaries"
+        );
+
+        let start = bfe!(addresses.start);
+        let range = addresses.start as usize..addresses.end as usize;
+        let instructions = self.instructions[range.clone()]
+            .iter()
+            .map(|instruction| instruction.map_call_address(|&target| target - start))
+            .collect();
+
+        let address_map = addresses
+            .clone()
+            .map(|address| (address, address - addresses.start))
+            .collect();
+        let debug_info = &self.debug_information;
+        let debug_information = DebugInformation {
+            breakpoints: debug_info
+                .breakpoints
+                .get(range)
+                .unwrap_or_default()
+                .to_vec(),
+            type_hints: Self::relocate_keys(&debug_info.type_hints, &address_map),
+            assertion_context: Self::relocate_keys(&debug_info.assertion_context, &address_map),
+            source_locations: Self::relocate_keys(&debug_info.source_locations, &address_map),
+        };
+
+        Program {
+            instructions,
+            data_sections: vec![],
+            address_to_label: Self::relocate_keys(&self.address_to_label, &address_map),
+            debug_information,
+        }
+    }
+
+    /// The program's [`DataSection`]s, in the order they were declared in.
+    pub fn data_sections(&self) -> &[DataSection] {
+        &self.data_sections
+    }
+
+    /// The content of random-access memory defined by the program's [`DataSection`]s. The
+    /// program's [data prologue](Self::len_data_prologue) writes it to RAM before any
+    /// other instruction is executed.
+    pub fn initial_ram(&self) -> HashMap<BFieldElement, BFieldElement> {
+        Self::ram_defined_by(&self.data_sections)
+    }
+
+    fn ram_defined_by(data_sections: &[DataSection]) -> HashMap<BFieldElement, BFieldElement> {
+        let mut ram = HashMap::new();
+        for data in data_sections {
+            for (offset, &word) in data.words.iter().enumerate() {
+                ram.insert(data.address + bfe!(offset), word);
+            }
+        }
+        ram
+    }
+
+    /// The length of the instructions that write the program's
+    /// [initial RAM](Self::initial_ram) to memory. They are the first instructions of
+    /// every program with [`DataSection`]s, leave the op stack unchanged, and are part of
+    /// the program's [digest](Self::hash). Like all other instructions, their execution
+    /// is proven, which attests the program's data.
+    ///
+    /// The prologue writes runs of consecutive addresses in ascending order, in chunks of
+    /// at most five words. Hence, it only depends on the content
+    /// of the initial RAM, not on its partitioning into sections.
+    pub fn len_data_prologue(&self) -> usize {
+        Self::data_prologue(&self.data_sections)
+            .iter()
+            .map(|instruction| match instruction {
+                LabelledInstruction::Instruction(instruction) => instruction.size(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Whether the program starts with the data prologue for its [`DataSection`]s.
+    pub(crate) fn has_data_prologue(&self) -> bool {
+        let data_sections = self.data_sections.iter().cloned();
+        let data_sections = data_sections
+            .map(LabelledInstruction::DataSection)
+            .collect_vec();
+        let data_prologue = Program::new(&data_sections).instructions;
+        self.instructions.starts_with(&data_prologue)
+    }
+
+    fn data_prologue(data_sections: &[DataSection]) -> Vec<LabelledInstruction> {
+        let ram = Self::ram_defined_by(data_sections);
+        let mut runs: Vec<(BFieldElement, Vec<BFieldElement>)> = vec![];
+        for (address, word) in ram.into_iter().sorted_by_key(|(a, _)| a.value()) {
+            match runs.last_mut() {
+                Some((start, words)) if *start + bfe!(words.len()) == address => words.push(word),
+                _ => runs.push((address, vec![word])),
+            }
+        }
+
+        let mut prologue = vec![];
+        for (start, words) in runs {
+            let mut address = start;
+            for chunk in words.chunks(NumberOfWords::N5.num_words()) {
+                let num_words = NumberOfWords::try_from(chunk.len()).unwrap();
+                let pushes = chunk.iter().rev().map(|&word| AnInstruction::Push(word));
+                let write = [
+                    AnInstruction::Push(address),
+                    AnInstruction::WriteMem(num_words),
+                    AnInstruction::Pop(NumberOfWords::N1),
+                ];
+                let chunk_prologue = pushes.chain(write).map(LabelledInstruction::Instruction);
+                prologue.extend(chunk_prologue);
+                address += bfe!(chunk.len());
+            }
+        }
+        prologue
+    }
+
     /// The total length of the program as `BFieldElement`s. Double-word instructions contribute
     /// two `BFieldElement`s.
     pub fn len_bwords(&self) -> usize {
@@ -391,12 +798,17 @@ impl Program {
 
     /// Produces the program's canonical hash digest. Uses [`Tip5`], the
     /// canonical hash function for Triton VM.
+    ///
+    /// Through the [data prologue](Self::len_data_prologue), the digest also commits to
+    /// the program's [initial RAM](Self::initial_ram).
     pub fn hash(&self) -> Digest {
         // not encoded using `BFieldCodec` because that would prepend the length
         Tip5::hash_varlen(&self.to_bwords())
     }
 
     /// The label for the given address, or a deterministic, unique substitute if no label is found.
+    ///
+    /// See also [`source_location_at`](Self::source_location_at).
     pub fn label_for_address(&self, address: u64) -> String {
         // Uniqueness of the label is relevant for printing and subsequent parsing:
         // Parsing fails on duplicate labels.
@@ -450,6 +862,61 @@ mod tests {
         prop_assert_eq!(program, decoding);
     }
 
+    #[test]
+    fn program_parsed_from_code_knows_source_locations_of_its_instructions() {
+        let code = "push 1\n  call foo // comment\nhalt\n\nfoo: pop 1 return";
+        let_assert!(Ok(program) = Program::from_code(code));
+
+        let location = |line, column| {
+            let file = None;
+            Some(SourceLocation { file, line, column })
+        };
+        assert!(location(1, 1) == program.source_location_at(0));
+        assert!(program.source_location_at(1).is_none());
+        assert!(location(2, 3) == program.source_location_at(2));
+        assert!(location(3, 1) == program.source_location_at(4));
+        assert!(location(5, 6) == program.source_location_at(5));
+        assert!(location(5, 12) == program.source_location_at(7));
+        assert!(program.source_location_at(8).is_none());
+    }
+
+    #[test]
+    fn source_locations_survive_lowering_of_control_flow() {
+        let code = "push 1\nif\n  push 2 pop 1\nendif\nhalt";
+        let_assert!(Ok(program) = Program::from_code(code));
+
+        let line_and_column = |instruction| {
+            let address = program
+                .instructions
+                .iter()
+                .position(|&i| i == instruction)?;
+            let location = program.source_location_at(address as u64)?;
+            Some((location.line, location.column))
+        };
+        assert!(Some((3, 3)) == line_and_column(Instruction::Push(bfe!(2))));
+        assert!(Some((3, 10)) == line_and_column(Instruction::Pop(NumberOfWords::N1)));
+        assert!(Some((5, 1)) == line_and_column(Instruction::Halt));
+    }
+
+    #[test]
+    fn program_from_macro_knows_no_source_locations() {
+        let program = triton_program!(push 1 pop 1 halt);
+        assert!(program.source_location_at(0).is_none());
+    }
+
+    #[test]
+    fn source_location_display_includes_file_if_known() {
+        let mut location = SourceLocation {
+            file: None,
+            line: 3,
+            column: 14,
+        };
+        assert!("3:14" == location.to_string());
+
+        location.file = Some("lib.tasm".to_string());
+        assert!("lib.tasm:3:14" == location.to_string());
+    }
+
     #[test]
     fn decode_program_with_missing_argument_as_last_instruction() {
         let program = triton_program!(push 3 push 3 eq assert push 3);
@@ -588,6 +1055,90 @@ mod tests {
         parser::parse(&program.to_string())?;
     }
 
+    #[test]
+    fn program_with_data_sections_knows_its_initial_ram() {
+        let program = triton_program! {
+            data primes: u32 @ 10 = [2, 3, 5, 7]
+            data point: xfe @ 20 = [(1, 2, 3)]
+            halt
+        };
+        let ram = program.initial_ram();
+        assert!(7 == ram.len());
+        assert!(Some(&bfe!(2)) == ram.get(&bfe!(10)));
+        assert!(Some(&bfe!(7)) == ram.get(&bfe!(13)));
+        assert!(Some(&bfe!(3)) == ram.get(&bfe!(22)));
+    }
+
+    #[test]
+    fn digest_commits_to_data_but_not_to_its_partitioning_into_sections() {
+        let without_data = triton_program!(halt);
+        let with_data = triton_program!(data d: bfe @ 5 = [1, 2, 3] halt);
+        let with_other_data = triton_program!(data d: bfe @ 5 = [1, 2, 4] halt);
+        let with_split_data = triton_program!(
+            data d: bfe @ 7 = [3]
+            data e: u32 @ 5 = [1, 2]
+            halt
+        );
+
+        assert!(without_data.hash() != with_data.hash());
+        assert!(with_data.hash() != with_other_data.hash());
+        assert!(with_data.hash() == with_split_data.hash());
+        assert!(with_data == with_split_data);
+
+        let expected = triton_program!(push 3 push 2 push 1 push 5 write_mem 3 pop 1 halt);
+        assert!(expected == with_data);
+        assert!(12 == with_data.len_data_prologue());
+    }
+
+    #[test]
+    fn data_prologue_writes_long_runs_in_chunks() {
+        let program = triton_program!(data d: u32 @ 3 = [1, 2, 3, 4, 5, 6, 7] halt);
+        let expected = triton_program!(
+            push 5 push 4 push 3 push 2 push 1 push 3 write_mem 5 pop 1
+            push 7 push 6 push 8 write_mem 2 pop 1
+            halt
+        );
+        assert!(expected == program);
+    }
+
+    #[test]
+    fn printed_program_with_data_can_be_parsed_again() {
+        let program = triton_program!(
+            data big: bfe @ -1 = [17]
+            data small: u32 @ 3 = [4, 5]
+            foo: push 1 pop 1 call foo halt
+        );
+        let reparsed = Program::from_code(&program.to_string()).unwrap();
+        assert!(program == reparsed);
+        assert!(program.data_sections() == reparsed.data_sections());
+        assert!("foo" == reparsed.label_for_address(reparsed.len_data_prologue() as u64));
+    }
+
+    #[test]
+    fn decoding_encoded_program_keeps_data_prologue_but_not_data_sections() {
+        let program = triton_program!(
+            data big: bfe @ -1 = [17]
+            data small: u32 @ 3 = [4, 5]
+            push 1 pop 1 halt
+        );
+        let_assert!(Ok(decoded) = Program::decode(&program.encode()));
+        assert!(program == *decoded);
+        assert!(program.hash() == decoded.hash());
+        assert!(decoded.data_sections().is_empty());
+        assert!(0 == decoded.len_data_prologue());
+
+        let reparsed = Program::from_code(&decoded.to_string()).unwrap();
+        assert!(program == reparsed);
+    }
+
+    #[test]
+    fn decoding_fails_for_trailing_words() {
+        let program = triton_program!(data d: bfe @ 5 = [1] halt);
+        let mut encoding = program.encode();
+        encoding.push(bfe!(0));
+        let_assert!(Err(ProgramDecodingError::SequenceTooLong) = Program::decode(&encoding));
+    }
+
     struct TypeHintTestCase {
         expected: TypeHint,
         input: &'static str,
@@ -744,4 +1295,34 @@ mod tests {
         let printed_program = format!("{program}");
         assert_eq!(source_code, &printed_program);
     }
+
+    #[test]
+    fn relative_calls_make_code_position_independent() {
+        let routine = "call +3 return dup 0 mul return";
+        let_assert!(Ok(program) = Program::from_code(routine));
+        let moved_code = format!("push 1 pop 1 {routine}");
+        let_assert!(Ok(moved_program) = Program::from_code(&moved_code));
+        assert!(program.to_bwords() != moved_program.to_bwords()[4..]);
+
+        let moved_routine = moved_program.snippet(4..4 + program.len_bwords() as u64);
+        assert!(program.to_bwords() == moved_routine.to_bwords());
+        assert!(program.position_independent_hash() == moved_routine.position_independent_hash());
+    }
+
+    #[test]
+    fn snippet_keeps_distance_to_call_targets_outside_of_it() {
+        let program = triton_program!(foo: return call foo halt);
+        let snippet = program.snippet(1..4);
+        assert!(Instruction::Call(-bfe!(1)) == snippet.instructions[0]);
+
+        let bwords = program.to_position_independent_bwords();
+        assert!(bwords[1..] == snippet.to_position_independent_bwords());
+    }
+
+    #[test]
+    #[should_panic(expected = "instruction boundaries")]
+    fn snippet_must_not_start_in_the_middle_of_an_instruction() {
+        let program = triton_program!(push 1 halt);
+        let _snippet = program.snippet(1..3);
+    }
 }
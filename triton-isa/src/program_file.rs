@@ -0,0 +1,669 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use itertools::Itertools;
+use thiserror::Error;
+use twenty_first::prelude::*;
+
+use crate::instruction::AssertionContext;
+use crate::instruction::DataSection;
+use crate::instruction::DataType;
+use crate::instruction::TypeHint;
+use crate::program::Program;
+use crate::program::ProgramDecodingError;
+use crate::program::SourceLocation;
+
+/// The conventional extension of files written by a [`ProgramWriter`].
+pub const FILE_EXTENSION: &str = "tvm";
+
+/// The first bytes of every file written by a [`ProgramWriter`].
+pub const MAGIC: [u8; 4] = *b"TVM\0";
+
+/// The version of the file format written by [`ProgramWriter`].
+pub const VERSION: u32 = 1;
+
+/// Flag in the [`Header`] signifying that the file contains debug information.
+const DEBUG_INFORMATION_FLAG: u32 = 1;
+
+// Tags of the records in the debug-information section.
+const LABEL: u8 = 0;
+const BREAKPOINT: u8 = 1;
+const TYPE_HINT: u8 = 2;
+const ASSERTION_CONTEXT_ID: u8 = 3;
+const ASSERTION_CONTEXT_MESSAGE: u8 = 4;
+const SOURCE_LOCATION: u8 = 5;
+const DATA_SECTION: u8 = 6;
+
+// Tags of the element types of data sections.
+const BFIELD_ELEMENT: u8 = 0;
+const XFIELD_ELEMENT: u8 = 1;
+const DIGEST: u8 = 2;
+const U32: u8 = 3;
+const BOOL: u8 = 4;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ProgramFileError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("not a Triton VM program file")]
+    InvalidMagic,
+
+    #[error("unsupported file format version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("word {0} is not a canonical field element")]
+    NonCanonicalWord(u64),
+
+    #[error(transparent)]
+    InvalidProgram(#[from] ProgramDecodingError),
+
+    #[error("program digest {actual} does not match digest {claimed} in file header")]
+    DigestMismatch { claimed: Digest, actual: Digest },
+
+    #[error("malformed debug information: {0}")]
+    MalformedDebugInformation(&'static str),
+}
+
+/// The header of a program file. It can be [read](ProgramReader::read_header) without
+/// reading the rest of the file, for example, to learn the program's digest.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Header {
+    /// The version of the file format.
+    pub version: u32,
+
+    /// The [digest](Program::hash) of the program contained in the file.
+    pub digest: Digest,
+
+    /// Whether the file contains the program's debug information, like labels, type
+    /// hints, assertion contexts, breakpoints, and source locations.
+    pub has_debug_information: bool,
+}
+
+/// Writes [`Program`]s in Triton VM's binary program file format, conventionally stored
+/// in files with extension [`.tvm`](FILE_EXTENSION).
+///
+/// All integers are stored in little-endian byte order. A file consists of:
+///
+/// 1. The [header](Header): the bytes [`MAGIC`], the [`VERSION`] as a `u32`, flags as a
+///    `u32`, and the program's [digest](Program::hash) as [`Digest::LEN`] field elements.
+///    Flag 1 signifies that the file contains debug information.
+/// 1. The program's [encoding](BFieldCodec::encode): its length as a `u64`, followed by
+///    that many field elements. Each field element is stored as a `u64` in canonical
+///    representation.
+/// 1. If present, the debug information: the number of records as a `u64`, followed by
+///    the records. Each record is a tag as a `u8`, an address as a `u64`, and, depending
+///    on the tag, a label, a type hint, an assertion context, a source location, or a
+///    data section. For data sections, the address is the section's index, and the
+///    record contains the section's name, type, address, and words. The data sections
+///    must match the program's [data prologue](Program::len_data_prologue).
+///    Strings are stored as their length in bytes as a `u64`, followed by their UTF-8
+///    encoding.
+///
+/// Use a [`ProgramReader`] to read programs back. The reader guarantees that the digest
+/// in the header matches the program.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::program_file::ProgramReader;
+/// # use triton_isa::program_file::ProgramWriter;
+/// # use triton_isa::triton_program;
+/// let program = triton_program!(push 1 call foo halt foo: return);
+/// let mut writer = ProgramWriter::new(vec![]);
+/// writer.write(&program).unwrap();
+///
+/// let file = writer.into_inner();
+/// let mut reader = ProgramReader::new(file.as_slice());
+/// assert_eq!(program.hash(), reader.read_header().unwrap().digest);
+///
+/// let read_program = reader.read_program().unwrap();
+/// assert_eq!(program, read_program);
+/// assert_eq!("foo", read_program.label_for_address(5));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgramWriter<W> {
+    writer: W,
+    include_debug_information: bool,
+}
+
+/// Reads [`Program`]s written by a [`ProgramWriter`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgramReader<R> {
+    reader: R,
+
+    /// The header of the program to be read next, if it has been read already.
+    header: Option<Header>,
+}
+
+impl<W: Write> ProgramWriter<W> {
+    /// A writer that includes the debug information of the written programs.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            include_debug_information: true,
+        }
+    }
+
+    /// Set whether the debug information of the written programs is included.
+    #[must_use]
+    pub fn with_debug_information(mut self, include_debug_information: bool) -> Self {
+        self.include_debug_information = include_debug_information;
+        self
+    }
+
+    pub fn write(&mut self, program: &Program) -> io::Result<()> {
+        let flags = if self.include_debug_information {
+            DEBUG_INFORMATION_FLAG
+        } else {
+            0
+        };
+        self.writer.write_all(&MAGIC)?;
+        self.writer.write_all(&VERSION.to_le_bytes())?;
+        self.writer.write_all(&flags.to_le_bytes())?;
+        for word in program.hash().values() {
+            self.write_word(word)?;
+        }
+
+        let encoding = program.encode();
+        self.write_u64(encoding.len() as u64)?;
+        for word in encoding {
+            self.write_word(word)?;
+        }
+
+        if self.include_debug_information {
+            self.write_debug_information(program)?;
+        }
+        Ok(())
+    }
+
+    fn write_debug_information(&mut self, program: &Program) -> io::Result<()> {
+        let debug_info = &program.debug_information;
+        let breakpoints = (0..program.len_bwords() as u64)
+            .filter(|&address| program.is_breakpoint(address))
+            .collect::<Vec<_>>();
+        let num_type_hints = debug_info.type_hints.values().map(Vec::len).sum::<usize>();
+        let num_records = program.address_to_label.len()
+            + breakpoints.len()
+            + num_type_hints
+            + debug_info.assertion_context.len()
+            + debug_info.source_locations.len()
+            + program.data_sections.len();
+        self.write_u64(num_records as u64)?;
+
+        // sort everything by address for deterministic output
+        for (&address, label) in program.address_to_label.iter().sorted_by_key(|&(&a, _)| a) {
+            self.write_record_start(LABEL, address)?;
+            self.write_string(label)?;
+        }
+        for address in breakpoints {
+            self.write_record_start(BREAKPOINT, address)?;
+        }
+        for (&address, hints) in debug_info.type_hints.iter().sorted_by_key(|&(&a, _)| a) {
+            for hint in hints {
+                self.write_record_start(TYPE_HINT, address)?;
+                self.write_u64(hint.starting_index as u64)?;
+                self.write_u64(hint.length as u64)?;
+                self.write_optional_string(hint.type_name.as_deref())?;
+                self.write_string(&hint.variable_name)?;
+            }
+        }
+        let contexts = debug_info.assertion_context.iter();
+        for (&address, context) in contexts.sorted_by_key(|&(&a, _)| a) {
+            match context {
+                AssertionContext::ID(id) => {
+                    self.write_record_start(ASSERTION_CONTEXT_ID, address)?;
+                    self.writer.write_all(&id.to_le_bytes())?;
+                }
+                AssertionContext::Message(message) => {
+                    self.write_record_start(ASSERTION_CONTEXT_MESSAGE, address)?;
+                    self.write_string(message)?;
+                }
+            }
+        }
+        let locations = debug_info.source_locations.iter();
+        for (&address, location) in locations.sorted_by_key(|&(&a, _)| a) {
+            self.write_record_start(SOURCE_LOCATION, address)?;
+            self.write_optional_string(location.file.as_deref())?;
+            self.write_u64(location.line as u64)?;
+            self.write_u64(location.column as u64)?;
+        }
+
+        // the program's encoding only contains the data prologue, not the data sections
+        for (index, data_section) in program.data_sections.iter().enumerate() {
+            self.write_record_start(DATA_SECTION, index as u64)?;
+            self.write_string(&data_section.name)?;
+            let data_type = match data_section.data_type {
+                DataType::BFieldElement => BFIELD_ELEMENT,
+                DataType::XFieldElement => XFIELD_ELEMENT,
+                DataType::Digest => DIGEST,
+                DataType::U32 => U32,
+                DataType::Bool => BOOL,
+            };
+            self.writer.write_all(&[data_type])?;
+            self.write_word(data_section.address)?;
+            self.write_u64(data_section.words.len() as u64)?;
+            for &word in &data_section.words {
+                self.write_word(word)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_record_start(&mut self, tag: u8, address: u64) -> io::Result<()> {
+        self.writer.write_all(&[tag])?;
+        self.write_u64(address)
+    }
+
+    fn write_word(&mut self, word: BFieldElement) -> io::Result<()> {
+        self.write_u64(word.value())
+    }
+
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    fn write_string(&mut self, string: &str) -> io::Result<()> {
+        self.write_u64(string.len() as u64)?;
+        self.writer.write_all(string.as_bytes())
+    }
+
+    fn write_optional_string(&mut self, string: Option<&str>) -> io::Result<()> {
+        match string {
+            Some(string) => {
+                self.writer.write_all(&[1])?;
+                self.write_string(string)
+            }
+            None => self.writer.write_all(&[0]),
+        }
+    }
+
+    /// Unwrap this writer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<R: Read> ProgramReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            header: None,
+        }
+    }
+
+    /// Read the header of the next program, unless it has been read already.
+    pub fn read_header(&mut self) -> Result<Header, ProgramFileError> {
+        if let Some(header) = self.header {
+            return Ok(header);
+        }
+
+        let mut magic = [0; MAGIC.len()];
+        self.reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ProgramFileError::InvalidMagic);
+        }
+        let version = self.read_u32()?;
+        if version != VERSION {
+            return Err(ProgramFileError::UnsupportedVersion(version));
+        }
+        let flags = self.read_u32()?;
+        let mut digest = [bfe!(0); Digest::LEN];
+        for word in &mut digest {
+            *word = self.read_word()?;
+        }
+
+        let header = Header {
+            version,
+            digest: Digest::new(digest),
+            has_debug_information: flags & DEBUG_INFORMATION_FLAG != 0,
+        };
+        self.header = Some(header);
+        Ok(header)
+    }
+
+    /// Read the next program. Fails if the program's digest does not match the one in
+    /// the [header](Self::read_header).
+    pub fn read_program(&mut self) -> Result<Program, ProgramFileError> {
+        let header = self.read_header()?;
+        self.header = None;
+
+        // instantiating with claimed capacity is a potential DOS vector
+        let mut encoding = vec![];
+        let encoding_length = self.read_u64()?;
+        for _ in 0..encoding_length {
+            encoding.push(self.read_word()?);
+        }
+        let mut program = *Program::decode(&encoding)?;
+        let digest = program.hash();
+        if digest != header.digest {
+            let claimed = header.digest;
+            return Err(ProgramFileError::DigestMismatch {
+                claimed,
+                actual: digest,
+            });
+        }
+
+        if header.has_debug_information {
+            self.read_debug_information(&mut program)?;
+        }
+        Ok(program)
+    }
+
+    fn read_debug_information(&mut self, program: &mut Program) -> Result<(), ProgramFileError> {
+        let debug_info = &mut program.debug_information;
+        debug_info.breakpoints = vec![false; program.instructions.len()];
+
+        let num_records = self.read_u64()?;
+        for _ in 0..num_records {
+            let tag = self.read_u8()?;
+            let address = self.read_u64()?;
+            match tag {
+                LABEL => {
+                    let label = self.read_string()?;
+                    program.address_to_label.insert(address, label);
+                }
+                BREAKPOINT => {
+                    let Some(breakpoint) = debug_info.breakpoints.get_mut(address as usize) else {
+                        let reason = "breakpoint out of bounds";
+                        return Err(ProgramFileError::MalformedDebugInformation(reason));
+                    };
+                    *breakpoint = true;
+                }
+                TYPE_HINT => {
+                    let hint = TypeHint {
+                        starting_index: self.read_usize()?,
+                        length: self.read_usize()?,
+                        type_name: self.read_optional_string()?,
+                        variable_name: self.read_string()?,
+                    };
+                    debug_info.type_hints.entry(address).or_default().push(hint);
+                }
+                ASSERTION_CONTEXT_ID => {
+                    let mut id = [0; size_of::<i128>()];
+                    self.reader.read_exact(&mut id)?;
+                    let context = AssertionContext::ID(i128::from_le_bytes(id));
+                    debug_info.assertion_context.insert(address, context);
+                }
+                ASSERTION_CONTEXT_MESSAGE => {
+                    let context = AssertionContext::Message(self.read_string()?);
+                    debug_info.assertion_context.insert(address, context);
+                }
+                SOURCE_LOCATION => {
+                    let location = SourceLocation {
+                        file: self.read_optional_string()?,
+                        line: self.read_usize()?,
+                        column: self.read_usize()?,
+                    };
+                    debug_info.source_locations.insert(address, location);
+                }
+                DATA_SECTION => {
+                    let name = self.read_string()?;
+                    let data_type = match self.read_u8()? {
+                        BFIELD_ELEMENT => Some(DataType::BFieldElement),
+                        XFIELD_ELEMENT => Some(DataType::XFieldElement),
+                        DIGEST => Some(DataType::Digest),
+                        U32 => Some(DataType::U32),
+                        BOOL => Some(DataType::Bool),
+                        _ => None,
+                    };
+                    let data_address = self.read_word()?;
+                    let num_words = self.read_u64()?;
+                    let mut words = vec![];
+                    for _ in 0..num_words {
+                        words.push(self.read_word()?);
+                    }
+                    let is_next_section = address == program.data_sections.len() as u64;
+                    let (Some(data_type), true) = (data_type, is_next_section) else {
+                        let reason = "invalid data section";
+                        return Err(ProgramFileError::MalformedDebugInformation(reason));
+                    };
+                    program.data_sections.push(DataSection {
+                        name,
+                        data_type,
+                        address: data_address,
+                        words,
+                    });
+                }
+                _ => return Err(ProgramFileError::MalformedDebugInformation("unknown tag")),
+            }
+        }
+
+        if !program.has_data_prologue() {
+            let reason = "data sections do not match data prologue";
+            return Err(ProgramFileError::MalformedDebugInformation(reason));
+        }
+        Ok(())
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut bytes = [0; 1];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes[0])
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; size_of::<u32>()];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; size_of::<u64>()];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_usize(&mut self) -> Result<usize, ProgramFileError> {
+        let value = self.read_u64()?;
+        usize::try_from(value)
+            .map_err(|_| ProgramFileError::MalformedDebugInformation("integer too large"))
+    }
+
+    fn read_word(&mut self) -> Result<BFieldElement, ProgramFileError> {
+        let value = self.read_u64()?;
+        BFieldElement::try_from(value.to_le_bytes())
+            .map_err(|_| ProgramFileError::NonCanonicalWord(value))
+    }
+
+    fn read_string(&mut self) -> Result<String, ProgramFileError> {
+        let length = self.read_u64()?;
+        let mut bytes = vec![];
+        (&mut self.reader).take(length).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        String::from_utf8(bytes)
+            .map_err(|_| ProgramFileError::MalformedDebugInformation("invalid UTF-8"))
+    }
+
+    fn read_optional_string(&mut self) -> Result<Option<String>, ProgramFileError> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.read_string()?)),
+            _ => Err(ProgramFileError::MalformedDebugInformation(
+                "invalid option",
+            )),
+        }
+    }
+
+    /// Unwrap this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use proptest::prelude::*;
+    use proptest_arbitrary_interop::arb;
+    use test_strategy::proptest;
+
+    use crate::triton_program;
+
+    use super::*;
+
+    fn write(program: &Program) -> Vec<u8> {
+        let mut writer = ProgramWriter::new(vec![]);
+        writer.write(program).unwrap();
+        writer.into_inner()
+    }
+
+    fn read(file: &[u8]) -> Result<Program, ProgramFileError> {
+        ProgramReader::new(file).read_program()
+    }
+
+    #[proptest]
+    fn written_program_can_be_read_again(#[strategy(arb())] program: Program) {
+        let file = write(&program);
+        let read_program = read(&file).unwrap();
+        prop_assert_eq!(&program, &read_program);
+        prop_assert_eq!(
+            program.labelled_instructions(),
+            read_program.labelled_instructions()
+        );
+    }
+
+    #[test]
+    fn debug_information_survives_writing_and_reading() {
+        let code = "
+            data primes: u32 @ 10 = [2, 3, 5]
+            hint n: u32 = stack[0]
+            push 1 call foo
+            break
+            halt
+            foo:
+                assert error_message \"not one\"
+                push 0 push 0 assert error_id 42
+                return
+            unused:";
+        let_assert!(Ok(program) = Program::from_code(code));
+        let_assert!(Ok(read_program) = read(&write(&program)));
+
+        assert!(program.labelled_instructions() == read_program.labelled_instructions());
+        let entry = program.len_data_prologue() as u64;
+        assert!(12 == entry);
+        assert!("unused" == read_program.label_for_address(entry + 12));
+        assert!(read_program.is_breakpoint(entry + 4));
+        assert!(program.data_sections() == read_program.data_sections());
+        for address in 0..program.len_bwords() as u64 {
+            let location = program.source_location_at(address);
+            assert!(location == read_program.source_location_at(address));
+        }
+    }
+
+    #[test]
+    fn debug_information_can_be_omitted() {
+        let program = triton_program!(push 1 call foo halt foo: break return);
+        let mut writer = ProgramWriter::new(vec![]).with_debug_information(false);
+        writer.write(&program).unwrap();
+        let file = writer.into_inner();
+
+        let mut reader = ProgramReader::new(file.as_slice());
+        let_assert!(Ok(header) = reader.read_header());
+        assert!(!header.has_debug_information);
+        let_assert!(Ok(read_program) = reader.read_program());
+        assert!(program == read_program);
+        assert!("address_4" == read_program.label_for_address(4));
+        assert!(!read_program.is_breakpoint(4));
+        assert!(file.len() < write(&program).len());
+    }
+
+    #[test]
+    fn multiple_programs_can_be_streamed() {
+        let programs = [triton_program!(halt), triton_program!(push 2 pop 1 halt)];
+        let mut writer = ProgramWriter::new(vec![]);
+        for program in &programs {
+            writer.write(program).unwrap();
+        }
+        let file = writer.into_inner();
+
+        let mut reader = ProgramReader::new(file.as_slice());
+        for program in programs {
+            let_assert!(Ok(read_program) = reader.read_program());
+            assert!(program == read_program);
+        }
+        let_assert!(Err(ProgramFileError::Io(_)) = reader.read_program());
+    }
+
+    #[test]
+    fn reading_fails_if_digest_does_not_match() {
+        let mut file = write(&triton_program!(push 1 halt));
+        let digest_start = MAGIC.len() + 2 * size_of::<u32>();
+        file[digest_start] ^= 1;
+        let_assert!(Err(ProgramFileError::DigestMismatch { .. }) = read(&file));
+    }
+
+    #[test]
+    fn reading_fails_if_program_is_tampered_with() {
+        let mut file = write(&triton_program!(push 1 halt));
+        let argument_of_push = MAGIC.len() + 2 * size_of::<u32>() + 7 * size_of::<u64>();
+        file[argument_of_push] = 2;
+        let_assert!(Err(ProgramFileError::DigestMismatch { .. }) = read(&file));
+    }
+
+    #[test]
+    fn reading_fails_for_foreign_file() {
+        let_assert!(Err(ProgramFileError::InvalidMagic) = read(b"\x7fELF and so on"));
+    }
+
+    #[test]
+    fn reading_fails_for_unsupported_version() {
+        let mut file = write(&triton_program!(halt));
+        file[MAGIC.len()] = 0xff;
+        let_assert!(Err(ProgramFileError::UnsupportedVersion(_)) = read(&file));
+    }
+
+    #[test]
+    fn reading_fails_for_non_canonical_word() {
+        let mut file = write(&triton_program!(halt));
+        let first_word = MAGIC.len() + 2 * size_of::<u32>();
+        file[first_word..first_word + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let_assert!(Err(ProgramFileError::NonCanonicalWord(u64::MAX)) = read(&file));
+    }
+
+    #[test]
+    fn reading_fails_if_data_sections_do_not_match_data_prologue() {
+        let mut file = write(&triton_program!(data d: bfe @ 7 = [42] halt));
+        let last_word = file.len() - size_of::<u64>();
+        file[last_word] = 43;
+        let_assert!(Err(ProgramFileError::MalformedDebugInformation(_)) = read(&file));
+    }
+
+    #[test]
+    fn data_sections_of_every_type_survive_writing_and_reading() {
+        let program = triton_program!(
+            data a: bfe @ 0 = [42]
+            data b: xfe @ 1 = [(1, 2, 3)]
+            data c: digest @ 4 = [(1, 2, 3, 4, 5)]
+            data d: u32 @ 9 = [7]
+            data e: bool @ 10 = [1]
+            halt
+        );
+        let_assert!(Ok(read_program) = read(&write(&program)));
+        assert!(program.data_sections() == read_program.data_sections());
+    }
+
+    #[test]
+    fn reading_fails_for_unknown_data_type() {
+        let mut file = write(&triton_program!(data d: bfe @ 7 = [42] halt));
+        let data_type = file.len() - 3 * size_of::<u64>() - 1;
+        assert!(BFIELD_ELEMENT == file[data_type]);
+        file[data_type] = 0xff;
+        let_assert!(Err(ProgramFileError::MalformedDebugInformation(_)) = read(&file));
+    }
+
+    #[test]
+    fn reading_fails_for_truncated_file() {
+        let file = write(&triton_program!(push 1 call foo halt foo: return));
+        for length in 0..file.len() {
+            assert!(read(&file[..length]).is_err());
+        }
+    }
+}
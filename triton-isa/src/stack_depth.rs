@@ -0,0 +1,628 @@
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use num_traits::Zero;
+use strum::EnumCount;
+use thiserror::Error;
+use twenty_first::prelude::*;
+
+use crate::instruction::Instruction;
+use crate::op_stack::OpStackElement;
+use crate::program::Program;
+
+/// A static analysis of the op stack's depth throughout a [`Program`].
+///
+/// Every subroutine, _i.e._, the code starting at address 0 as well as the code starting
+/// at any [`call`](Instruction::Call) target, is analyzed separately. Based on the
+/// [size influence](Instruction::op_stack_size_influence) of the individual instructions,
+/// the analysis infers each subroutine's net effect on the op stack's depth. Calls are
+/// resolved using the inferred effect of the called subroutine.
+///
+/// All paths through a subroutine are followed. Where the analysis can tell statically
+/// whether [`skiz`](Instruction::Skiz) skips the next instruction, for example, because the
+/// tested element was [`push`](Instruction::Push)ed, the impossible path is ignored. This
+/// accommodates the common idiom of branching into one of two subroutines. The analysis
+/// then ensures that all paths reaching the same instruction leave the op stack equally
+/// deep, that all [`return`](Instruction::Return)s of a subroutine do so as well, and that every [`recurse`](Instruction::Recurse) and
+/// [`recurse_or_return`](Instruction::RecurseOrReturn) leaves the op stack exactly as deep
+/// as it was when the subroutine was entered. Violations are reported as
+/// [`StackDepthError`]s.
+///
+/// Since the op stack's depth is known when the program starts, the analysis also detects
+/// instructions that [underflow](crate::op_stack::OpStackError::TooShallow) the op stack
+/// whenever they are reached through the analyzed path.
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use triton_isa::stack_depth::StackDepthAnalysis;
+/// let program = triton_program!(
+///     push 1 push 2 push 3 call sum_three halt
+///     sum_three:
+///         add add return
+/// );
+/// let analysis = StackDepthAnalysis::new(&program);
+/// assert_eq!(Some(-2), analysis.subroutine(9).unwrap().net_effect);
+/// assert!(analysis.errors().is_empty());
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct StackDepthAnalysis {
+    subroutines: BTreeMap<u64, SubroutineStackEffect>,
+    errors: Vec<StackDepthError>,
+}
+
+/// The effect a subroutine has on the depth of the op stack. All depths are relative to the
+/// depth of the op stack when entering the subroutine.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct SubroutineStackEffect {
+    /// The address of the subroutine's first instruction.
+    pub address: u64,
+
+    /// The change of the op stack's depth from entering the subroutine to returning from
+    /// it. `None` if the subroutine never returns, for example, because it always halts.
+    pub net_effect: Option<i32>,
+
+    /// The lowest depth of the op stack while executing the subroutine, including any
+    /// subroutines it calls. Never positive.
+    pub lowest_depth: i32,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Error)]
+pub enum StackDepthError {
+    #[error(
+        "subroutine at address {subroutine} returns with op stack depths \
+        {depth} and {other_depth}"
+    )]
+    InconsistentReturn {
+        subroutine: u64,
+        depth: i32,
+        other_depth: i32,
+    },
+
+    #[error(
+        "paths merging at address {address} have op stack depths \
+        {depth} and {other_depth}"
+    )]
+    InconsistentMerge {
+        address: u64,
+        depth: i32,
+        other_depth: i32,
+    },
+
+    #[error("recursion at address {address} changes the op stack depth by {depth}")]
+    UnbalancedRecursion { address: u64, depth: i32 },
+
+    #[error("stack effect of recursive call at address {address} cannot be inferred")]
+    RecursiveCall { address: u64 },
+
+    #[error("instruction at address {address} makes the op stack too shallow")]
+    TooShallow { address: u64 },
+}
+
+/// The state of one [`StackDepthAnalysis`] while it is being performed.
+#[derive(Debug)]
+struct Analyzer<'a> {
+    program: &'a Program,
+    summaries: HashMap<u64, Summary>,
+    subroutines_in_progress: HashSet<u64>,
+    errors: Vec<StackDepthError>,
+}
+
+/// Everything the analysis of a calling subroutine needs to know about a called
+/// subroutine.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Summary {
+    effect: SubroutineStackEffect,
+
+    /// The statically known elements on top of the op stack when the subroutine returns.
+    known_on_return: KnownElements,
+}
+
+/// The statically known elements on top of the op stack, starting with `st0`. Elements
+/// beyond the end are not known.
+type KnownElements = Vec<Option<BFieldElement>>;
+
+/// One point on one path through a subroutine.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct PathState {
+    address: u64,
+    depth: i32,
+    known_elements: KnownElements,
+}
+
+impl StackDepthAnalysis {
+    pub fn new(program: &Program) -> Self {
+        let mut analyzer = Analyzer {
+            program,
+            summaries: HashMap::new(),
+            subroutines_in_progress: HashSet::new(),
+            errors: vec![],
+        };
+
+        let call_targets = program.instructions.iter().filter_map(|instruction| {
+            let Instruction::Call(target) = instruction else {
+                return None;
+            };
+            Some(target.value())
+        });
+        for subroutine in [0].into_iter().chain(call_targets) {
+            analyzer.summarize(subroutine);
+        }
+
+        let subroutines = analyzer
+            .summaries
+            .into_iter()
+            .map(|(address, summary)| (address, summary.effect))
+            .collect();
+
+        Self {
+            subroutines,
+            errors: analyzer.errors,
+        }
+    }
+
+    /// The stack effect of the subroutine starting at the given address, if any.
+    pub fn subroutine(&self, address: u64) -> Option<&SubroutineStackEffect> {
+        self.subroutines.get(&address)
+    }
+
+    /// The stack effects of all subroutines, ordered by their address.
+    pub fn subroutines(&self) -> impl Iterator<Item = &SubroutineStackEffect> {
+        self.subroutines.values()
+    }
+
+    /// All violations found by the analysis. Empty if the program is consistent.
+    pub fn errors(&self) -> &[StackDepthError] {
+        &self.errors
+    }
+
+    /// Fails with the first violation found by the analysis, if any.
+    pub fn ensure_consistency(&self) -> Result<(), StackDepthError> {
+        match self.errors.first() {
+            Some(&error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Analyzer<'_> {
+    /// Returns `None` if the subroutine's summary cannot be known yet because the
+    /// subroutine is currently being analyzed, _i.e._, if it is called recursively.
+    fn summarize(&mut self, subroutine: u64) -> Option<Summary> {
+        if let Some(summary) = self.summaries.get(&subroutine) {
+            return Some(summary.clone());
+        }
+        if !self.subroutines_in_progress.insert(subroutine) {
+            return None;
+        }
+
+        // A subroutine calling itself can only be summarized by a fixed point: first
+        // assume that the recursive call never returns, then check whether the
+        // assumption holds.
+        let (mut summary, mut errors, calls_itself) = self.analyze(subroutine, None);
+        if calls_itself && summary.effect.net_effect.is_some() {
+            let (fixed_point, fixed_point_errors, _) = self.analyze(subroutine, Some(&summary));
+            if fixed_point != summary {
+                let address = subroutine;
+                errors.push(StackDepthError::RecursiveCall { address });
+            }
+            (summary, errors) = (fixed_point, fixed_point_errors);
+        }
+
+        self.errors.append(&mut errors);
+        self.subroutines_in_progress.remove(&subroutine);
+        self.summaries.insert(subroutine, summary.clone());
+        Some(summary)
+    }
+
+    fn analyze(
+        &mut self,
+        subroutine: u64,
+        assumed_summary: Option<&Summary>,
+    ) -> (Summary, Vec<StackDepthError>, bool) {
+        // Only at the start of the program is the absolute depth of the op stack known.
+        let may_underflow = subroutine == 0;
+
+        let mut effect = SubroutineStackEffect {
+            address: subroutine,
+            ..Default::default()
+        };
+        let mut returns = vec![];
+        let mut errors = vec![];
+        let mut calls_itself = false;
+
+        // Paths reaching the same instruction are merged, keeping only the elements known on
+        // all of them. Paths reaching a `skiz` with opposite, statically known outcomes are
+        // kept apart, since they diverge again right away.
+        let mut merged_states = HashMap::new();
+        let mut work_list = vec![PathState {
+            address: subroutine,
+            depth: 0,
+            known_elements: vec![],
+        }];
+        while let Some(state) = work_list.pop() {
+            let PathState {
+                address,
+                depth,
+                known_elements,
+            } = state;
+
+            // Running past the end of the program is an error unrelated to the op stack.
+            let Some(&instruction) = self.program.instructions.get(address as usize) else {
+                continue;
+            };
+
+            let known_condition = match instruction {
+                Instruction::Skiz => known_elements.first().copied().flatten(),
+                _ => None,
+            };
+            let merge_key = (address, known_condition.map(|c| c.is_zero()));
+            let known_elements = match merged_states.entry(merge_key) {
+                Entry::Vacant(entry) => {
+                    entry.insert((depth, known_elements.clone()));
+                    known_elements
+                }
+                Entry::Occupied(mut entry) => {
+                    let (merged_depth, merged_elements) = entry.get_mut();
+                    if *merged_depth != depth {
+                        errors.push(StackDepthError::InconsistentMerge {
+                            address,
+                            depth: *merged_depth,
+                            other_depth: depth,
+                        });
+                        continue;
+                    }
+                    let common = common_known_elements(merged_elements.clone(), known_elements);
+                    if common == *merged_elements {
+                        continue;
+                    }
+                    *merged_elements = common.clone();
+                    common
+                }
+            };
+
+            let successor = address + instruction.size() as u64;
+            let mut lowest_depth = depth;
+            match instruction {
+                Instruction::Halt => (),
+                Instruction::Call(target) => {
+                    let target = target.value();
+                    let callee = match assumed_summary {
+                        Some(summary) if target == subroutine => Some(summary.clone()),
+                        _ => self.summarize(target),
+                    };
+                    match callee {
+                        Some(callee) => {
+                            lowest_depth = depth + callee.effect.lowest_depth;
+                            if let Some(net_effect) = callee.effect.net_effect {
+                                work_list.push(PathState {
+                                    address: successor,
+                                    depth: depth + net_effect,
+                                    known_elements: callee.known_on_return,
+                                });
+                            }
+                        }
+                        None if target == subroutine => calls_itself = true,
+                        None => errors.push(StackDepthError::RecursiveCall { address }),
+                    }
+                }
+                Instruction::Return => returns.push((depth, known_elements)),
+                Instruction::Recurse | Instruction::RecurseOrReturn => {
+                    if depth != 0 {
+                        errors.push(StackDepthError::UnbalancedRecursion { address, depth });
+                    }
+                    if instruction == Instruction::RecurseOrReturn {
+                        returns.push((depth, known_elements));
+                    }
+                }
+                Instruction::Skiz => {
+                    let depth = depth + instruction.op_stack_size_influence();
+                    lowest_depth = depth;
+
+                    let mut known_elements = known_elements;
+                    let condition = if known_elements.is_empty() {
+                        None
+                    } else {
+                        known_elements.remove(0)
+                    };
+                    let (may_execute_next, may_skip_next) = match condition {
+                        None => (true, true),
+                        Some(condition) => (!condition.is_zero(), condition.is_zero()),
+                    };
+
+                    if may_execute_next {
+                        work_list.push(PathState {
+                            address: successor,
+                            depth,
+                            known_elements: known_elements.clone(),
+                        });
+                    }
+                    let skipped = self.program.instructions.get(successor as usize);
+                    if let (true, Some(skipped)) = (may_skip_next, skipped) {
+                        work_list.push(PathState {
+                            address: successor + skipped.size() as u64,
+                            depth,
+                            known_elements,
+                        });
+                    }
+                }
+                _ => {
+                    lowest_depth = depth + instruction.op_stack_size_influence();
+                    work_list.push(PathState {
+                        address: successor,
+                        depth: lowest_depth,
+                        known_elements: track_known_elements(known_elements, instruction),
+                    });
+                }
+            }
+
+            if may_underflow && depth >= 0 && lowest_depth < 0 {
+                errors.push(StackDepthError::TooShallow { address });
+            }
+            effect.lowest_depth = effect.lowest_depth.min(lowest_depth);
+        }
+
+        let mut known_on_return = None;
+        for (depth, known_elements) in returns {
+            match effect.net_effect {
+                Some(net_effect) if net_effect != depth => {
+                    errors.push(StackDepthError::InconsistentReturn {
+                        subroutine,
+                        depth: net_effect,
+                        other_depth: depth,
+                    });
+                }
+                Some(_) => (),
+                None => effect.net_effect = Some(depth),
+            };
+            known_on_return = match known_on_return {
+                None => Some(known_elements),
+                Some(known) => Some(common_known_elements(known, known_elements)),
+            };
+        }
+        let known_on_return = known_on_return.unwrap_or_default();
+
+        // Multiple paths can lead to the same problem.
+        let errors = errors.into_iter().unique().collect();
+        let summary = Summary {
+            effect,
+            known_on_return,
+        };
+
+        (summary, errors, calls_itself)
+    }
+}
+
+/// The statically known elements on top of the op stack after executing the given
+/// instruction. Only instructions that move elements around are tracked; any other
+/// instruction makes all elements unknown.
+fn track_known_elements(mut known: KnownElements, instruction: Instruction) -> KnownElements {
+    let mut ensure_len = |len: usize| {
+        if known.len() < len {
+            known.resize(len, None);
+        }
+    };
+
+    match instruction {
+        Instruction::Push(element) => known.insert(0, Some(element)),
+        Instruction::Pop(n) => {
+            ensure_len(n.into());
+            known.drain(..usize::from(n));
+        }
+        Instruction::Dup(st) => {
+            ensure_len(usize::from(st) + 1);
+            known.insert(0, known[usize::from(st)]);
+        }
+        Instruction::Swap(st) => {
+            ensure_len(usize::from(st) + 1);
+            known.swap(0, st.into());
+        }
+        Instruction::Pick(st) => {
+            ensure_len(usize::from(st) + 1);
+            let element = known.remove(st.into());
+            known.insert(0, element);
+        }
+        Instruction::Place(st) => {
+            ensure_len(usize::from(st) + 1);
+            let element = known.remove(0);
+            known.insert(st.into(), element);
+        }
+        _ => known.clear(),
+    }
+
+    known.truncate(OpStackElement::COUNT);
+    known
+}
+
+/// The elements that are known in both given lists and that agree.
+fn common_known_elements(known: KnownElements, other: KnownElements) -> KnownElements {
+    known
+        .into_iter()
+        .zip(other)
+        .map(|(element, other)| element.filter(|&element| Some(element) == other))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use crate::triton_program;
+
+    use super::*;
+
+    #[test]
+    fn infer_net_stack_effect_of_subroutines() {
+        let program = triton_program!(
+            call push_two call pop_one halt
+            push_two: push 0 push 0 return
+            pop_one: pop 1 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!(analysis.errors().is_empty());
+
+        let push_two = analysis.subroutine(5).unwrap();
+        assert!(Some(2) == push_two.net_effect);
+        assert!(0 == push_two.lowest_depth);
+
+        let pop_one = analysis.subroutine(10).unwrap();
+        assert!(Some(-1) == pop_one.net_effect);
+        assert!(-1 == pop_one.lowest_depth);
+
+        let main = analysis.subroutine(0).unwrap();
+        assert!(main.net_effect.is_none());
+    }
+
+    #[test]
+    fn lowest_depth_includes_called_subroutines() {
+        let program = triton_program!(
+            call outer halt
+            outer: push 1 call inner return
+            inner: pop 3 push 0 push 0 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        let outer = analysis.subroutine(3).unwrap();
+        assert!(Some(0) == outer.net_effect);
+        assert!(-2 == outer.lowest_depth);
+    }
+
+    #[test]
+    fn balanced_loop_is_consistent() {
+        let program = triton_program!(
+            push 10 call loop pop 1 halt
+            loop:
+                dup 0 push 0 eq skiz return
+                push -1 add recurse
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!(let Ok(()) = analysis.ensure_consistency());
+        assert!(Some(0) == analysis.subroutine(7).unwrap().net_effect);
+    }
+
+    #[test]
+    fn unbalanced_loop_is_inconsistent() {
+        let program = triton_program!(
+            call loop halt
+            loop: push 0 recurse
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        let_assert!(Err(err) = analysis.ensure_consistency());
+        assert!(
+            StackDepthError::UnbalancedRecursion {
+                address: 5,
+                depth: 1
+            } == err
+        );
+    }
+
+    #[test]
+    fn returns_with_different_depths_are_inconsistent() {
+        let program = triton_program!(
+            push 0 call f halt
+            f: skiz return push 0 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        let_assert!([error] = analysis.errors());
+        let_assert!(StackDepthError::InconsistentReturn { subroutine: 5, .. } = error);
+    }
+
+    #[test]
+    fn paths_merging_with_different_depths_are_inconsistent() {
+        let program = triton_program!(read_io 1 skiz push 1 halt);
+        let analysis = StackDepthAnalysis::new(&program);
+        let_assert!([error] = analysis.errors());
+        let_assert!(StackDepthError::InconsistentMerge { address: 5, .. } = error);
+
+        let program = triton_program!(
+            push 0 call f halt
+            f: skiz push 0 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        let_assert!([error] = analysis.errors());
+        let_assert!(StackDepthError::InconsistentMerge { address: 8, .. } = error);
+    }
+
+    #[test]
+    fn branching_into_one_of_two_subroutines_is_consistent() {
+        // The `then` branch has a different stack effect than the `else` branch, but the
+        // paths merge again once the `else` branch is skipped.
+        let program = triton_program!(
+            push 1 push 2 call branch halt
+            branch:
+                push 1 dup 1 skiz call then skiz call else return
+            then: pop 3 push 7 push 0 return
+            else: pop 2 push 8 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!(let Ok(()) = analysis.ensure_consistency());
+        assert!(Some(-1) == analysis.subroutine(7).unwrap().net_effect);
+    }
+
+    #[test]
+    fn self_recursive_call_that_never_returns_is_consistent() {
+        let program = triton_program!(
+            push 3 call loop
+            loop:
+                dup 0 push 0 eq skiz halt
+                push -1 add call loop
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!(let Ok(()) = analysis.ensure_consistency());
+        assert!(analysis.subroutine(4).unwrap().net_effect.is_none());
+    }
+
+    #[test]
+    fn mutually_recursive_calls_are_reported() {
+        let program = triton_program!(
+            call f halt
+            f: call g return
+            g: call f return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        let_assert!([error] = analysis.errors());
+        assert!(StackDepthError::RecursiveCall { address: 6 } == *error);
+    }
+
+    #[test]
+    fn guaranteed_underflow_is_detected() {
+        let program = triton_program!(push 1 pop 1 halt);
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!(analysis.errors().is_empty());
+
+        let program = triton_program!(push 1 pop 2 pop 1 halt);
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!([StackDepthError::TooShallow { address: 2 }] == analysis.errors());
+    }
+
+    #[test]
+    fn guaranteed_underflow_in_called_subroutine_is_attributed_to_call() {
+        let program = triton_program!(
+            call drain halt
+            drain: pop 5 pop 5 pop 5 pop 5 return
+        );
+        let analysis = StackDepthAnalysis::new(&program);
+        assert!([StackDepthError::TooShallow { address: 0 }] == analysis.errors());
+    }
+
+    #[test]
+    fn known_elements_are_tracked_through_stack_manipulation() {
+        let known = track_known_elements(vec![], Instruction::Push(bfe!(1)));
+        let known = track_known_elements(known, Instruction::Push(bfe!(2)));
+        assert!(vec![Some(bfe!(2)), Some(bfe!(1))] == known);
+
+        let known = track_known_elements(known, Instruction::Dup(OpStackElement::ST2));
+        assert!(vec![None, Some(bfe!(2)), Some(bfe!(1)), None] == known);
+
+        let known = track_known_elements(known, Instruction::Swap(OpStackElement::ST2));
+        assert!(vec![Some(bfe!(1)), Some(bfe!(2)), None, None] == known);
+
+        let known = track_known_elements(known, Instruction::Add);
+        assert!(known.is_empty());
+    }
+}
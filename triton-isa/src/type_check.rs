@@ -0,0 +1,565 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use strum::Display;
+
+use crate::control_flow::BasicBlock;
+use crate::control_flow::ControlFlowGraph;
+use crate::control_flow::EdgeKind;
+use crate::instruction::Instruction;
+use crate::instruction::TypeHint;
+use crate::op_stack::OpStackElement;
+use crate::program::Program;
+
+/// A static check of a [`Program`]'s [type hints](TypeHint).
+///
+/// Type hints do not enforce anything on their own. This check tracks the types they
+/// declare through the op stack, following the effects of the individual instructions,
+/// and produces a [`TypeWarning`] whenever an element is consumed by an instruction that
+/// does not expect an element of that type. For example, a `Digest` should not be
+/// [`split`](Instruction::Split), and an element that was hinted to be a `u32` but has
+/// since been [`mul`](Instruction::Mul)tiplied should not be used in
+/// [`div_mod`](Instruction::DivMod).
+///
+/// The recognized type names are `u32`, `bool`, `BFieldElement` (or `BFE`),
+/// `XFieldElement` (or `XFE`), and `Digest`. Elements without a (recognized) type hint
+/// are never warned about. Where control flow merges, only types that agree on all
+/// incoming paths are kept. The types of elements do not survive a
+/// [`call`](Instruction::Call).
+///
+/// # Example
+///
+/// ```
+/// # use triton_isa::triton_program;
+/// # use triton_isa::type_check::TypeCheck;
+/// let program = triton_program!(
+///     divine 2
+///     hint numerator: u32 = stack[0]
+///     hint denominator: u32 = stack[1]
+///     push 2 mul div_mod
+///     halt
+/// );
+/// let type_check = TypeCheck::new(&program);
+/// let [warning] = type_check.warnings() else {
+///     panic!("expected exactly one warning");
+/// };
+/// assert_eq!(5, warning.address);
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct TypeCheck {
+    warnings: Vec<TypeWarning>,
+}
+
+/// The type of a single element on the op stack, as far as a [`TypeCheck`] is concerned.
+#[derive(Debug, Display, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ElementType {
+    #[strum(serialize = "bool")]
+    Bool,
+
+    #[strum(serialize = "u32")]
+    U32,
+
+    #[strum(serialize = "field element")]
+    Field,
+
+    #[strum(serialize = "part of an extension field element")]
+    XFieldElement,
+
+    #[strum(serialize = "part of a digest")]
+    Digest,
+}
+
+/// The reason an element is known to be of some [`ElementType`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TypeOrigin {
+    /// The element was hinted to be of the type.
+    Hint { address: u64, variable_name: String },
+
+    /// The element was produced by the instruction.
+    Instruction {
+        address: u64,
+        instruction: Instruction,
+    },
+}
+
+/// An instruction consumes an element of an [`ElementType`] it does not expect.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TypeWarning {
+    /// The address of the consuming instruction.
+    pub address: u64,
+    pub instruction: Instruction,
+
+    /// The position of the offending element at the time the instruction is executed.
+    pub operand: OpStackElement,
+    pub expected: ElementType,
+    pub actual: ElementType,
+    pub origin: TypeOrigin,
+}
+
+/// An element of a known type.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct TypedElement {
+    element_type: ElementType,
+    origin: TypeOrigin,
+}
+
+/// The types of the elements on top of the op stack, starting with `st0`. The types of
+/// elements beyond the end are not known.
+type TypeState = Vec<Option<TypedElement>>;
+
+impl Display for TypeOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Hint {
+                address,
+                variable_name,
+            } => write!(f, "`{variable_name}` as hinted at address {address}"),
+            Self::Instruction {
+                address,
+                instruction,
+            } => write!(f, "result of `{instruction}` at address {address}"),
+        }
+    }
+}
+
+impl Display for TypeWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self {
+            address,
+            instruction,
+            operand,
+            expected,
+            actual,
+            origin,
+        } = self;
+        write!(
+            f,
+            "`{instruction}` at address {address} expects a {expected} in st{operand} \
+            but finds a {actual} ({origin})"
+        )
+    }
+}
+
+impl ElementType {
+    /// The element type corresponding to the type name of a [`TypeHint`], if any.
+    pub fn from_type_name(type_name: &str) -> Option<Self> {
+        match type_name {
+            "bool" => Some(Self::Bool),
+            "u32" => Some(Self::U32),
+            "BFieldElement" | "BFE" => Some(Self::Field),
+            "XFieldElement" | "XFE" => Some(Self::XFieldElement),
+            "Digest" => Some(Self::Digest),
+            _ => None,
+        }
+    }
+
+    /// Whether an instruction expecting an element of type `self` can consume an element of
+    /// type `other` without raising suspicion.
+    fn accepts(self, other: Self) -> bool {
+        match self {
+            Self::Bool => other == Self::Bool,
+            Self::U32 => matches!(other, Self::Bool | Self::U32),
+            Self::Field => matches!(other, Self::Bool | Self::U32 | Self::Field),
+            Self::XFieldElement | Self::Digest => self == other,
+        }
+    }
+}
+
+impl TypeCheck {
+    pub fn new(program: &Program) -> Self {
+        let cfg = ControlFlowGraph::new(program);
+        let entry_states = Self::entry_states(program, &cfg);
+
+        let mut warnings = vec![];
+        for (&start, state) in &entry_states {
+            let mut state = state.clone();
+            let block = cfg.block(start).unwrap();
+            Self::check_block(program, block, &mut state, &mut warnings);
+        }
+
+        Self { warnings }
+    }
+
+    /// All [`TypeWarning`]s, ordered by the address of the consuming instruction.
+    pub fn warnings(&self) -> &[TypeWarning] {
+        &self.warnings
+    }
+
+    /// The types on the op stack when entering each reachable basic block.
+    fn entry_states(program: &Program, cfg: &ControlFlowGraph) -> BTreeMap<u64, TypeState> {
+        let mut entry_states = BTreeMap::new();
+        if cfg.block(0).is_none() {
+            return entry_states;
+        }
+
+        entry_states.insert(0, vec![]);
+        let mut work_list = BTreeSet::from([0]);
+        while let Some(start) = work_list.pop_first() {
+            let mut state = entry_states[&start].clone();
+            let block = cfg.block(start).unwrap();
+            Self::check_block(program, block, &mut state, &mut vec![]);
+
+            for edge in &block.successors {
+                // Types are tracked within subroutines only.
+                let successor_state = match edge.kind {
+                    EdgeKind::Next | EdgeKind::Skip => state.clone(),
+                    _ => vec![],
+                };
+                let joined_state = match entry_states.get(&edge.target) {
+                    Some(known_state) => join(known_state, &successor_state),
+                    None => successor_state,
+                };
+                if entry_states.get(&edge.target) != Some(&joined_state) {
+                    entry_states.insert(edge.target, joined_state);
+                    work_list.insert(edge.target);
+                }
+            }
+        }
+
+        entry_states
+    }
+
+    fn check_block(
+        program: &Program,
+        block: &BasicBlock,
+        state: &mut TypeState,
+        warnings: &mut Vec<TypeWarning>,
+    ) {
+        let mut address = block.start;
+        for &instruction in &block.instructions {
+            for hint in program.type_hints_at(address) {
+                apply_hint(state, address, &hint);
+            }
+            let mut checker = InstructionChecker {
+                address,
+                instruction,
+                state,
+                warnings,
+            };
+            checker.check();
+            address += instruction.size() as u64;
+        }
+    }
+}
+
+/// Keep only those types that are the same in both states.
+fn join(state: &TypeState, other: &TypeState) -> TypeState {
+    let mut joined = state
+        .iter()
+        .zip(other)
+        .map(|(typed, other_typed)| {
+            if typed == other_typed {
+                typed.clone()
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    while joined.last().is_some_and(Option::is_none) {
+        joined.pop();
+    }
+    joined
+}
+
+fn apply_hint(state: &mut TypeState, address: u64, hint: &TypeHint) {
+    let end = hint.starting_index + hint.length;
+    if state.len() < end {
+        state.resize(end, None);
+    }
+
+    let element_type = hint
+        .type_name
+        .as_deref()
+        .and_then(ElementType::from_type_name);
+    let typed = element_type.map(|element_type| TypedElement {
+        element_type,
+        origin: TypeOrigin::Hint {
+            address,
+            variable_name: hint.variable_name.clone(),
+        },
+    });
+    for element in &mut state[hint.starting_index..end] {
+        element.clone_from(&typed);
+    }
+}
+
+/// Checks and applies the effect of one instruction on a [`TypeState`].
+struct InstructionChecker<'a> {
+    address: u64,
+    instruction: Instruction,
+    state: &'a mut TypeState,
+    warnings: &'a mut Vec<TypeWarning>,
+}
+
+impl InstructionChecker<'_> {
+    fn check(&mut self) {
+        use ElementType::Bool;
+        use ElementType::Field;
+        use ElementType::U32;
+
+        match self.instruction {
+            Instruction::Pop(n) => self.pop(n.into()),
+            Instruction::Push(_) => self.push_unknown(1),
+            Instruction::Divine(n) => self.push_unknown(n.into()),
+            Instruction::Pick(st) => {
+                self.reserve(st.index() as usize + 1);
+                let picked = self.state.remove(st.index() as usize);
+                self.state.insert(0, picked);
+            }
+            Instruction::Place(st) => {
+                self.reserve(st.index() as usize + 1);
+                let placed = self.state.remove(0);
+                self.state.insert(st.index() as usize, placed);
+            }
+            Instruction::Dup(st) => {
+                let duplicate = self.state.get(st.index() as usize).cloned().flatten();
+                self.state.insert(0, duplicate);
+            }
+            Instruction::Swap(st) => {
+                self.reserve(st.index() as usize + 1);
+                self.state.swap(0, st.index() as usize);
+            }
+            Instruction::Skiz | Instruction::Assert => self.pop(1),
+            Instruction::AssertVector => self.pop(5),
+            Instruction::ReadMem(n) => {
+                self.pop(1);
+                self.push_unknown(usize::from(n) + 1);
+            }
+            Instruction::WriteMem(n) => {
+                self.pop(usize::from(n) + 1);
+                self.push_unknown(1);
+            }
+            Instruction::ReadIo(n) => self.push_unknown(n.into()),
+            Instruction::WriteIo(n) => self.pop(n.into()),
+            Instruction::Hash => {
+                self.pop(10);
+                self.push_result(ElementType::Digest, 5);
+            }
+            Instruction::Add | Instruction::Mul => self.arithmetic(&[Field, Field]),
+            Instruction::AddI(_) | Instruction::Invert => self.arithmetic(&[Field]),
+            Instruction::Pow => self.arithmetic(&[Field, U32]),
+            Instruction::Eq => {
+                self.pop(2);
+                self.push_result(Bool, 1);
+            }
+            Instruction::Split => {
+                self.expect(&[Field]);
+                self.pop(1);
+                self.push_result(U32, 2);
+            }
+            Instruction::Lt => {
+                self.expect(&[U32, U32]);
+                self.pop(2);
+                self.push_result(Bool, 1);
+            }
+            Instruction::And | Instruction::Xor => {
+                self.expect(&[U32, U32]);
+                self.pop(2);
+                self.push_result(U32, 1);
+            }
+            Instruction::DivMod => {
+                self.expect(&[U32, U32]);
+                self.pop(2);
+                self.push_result(U32, 2);
+            }
+            Instruction::Log2Floor | Instruction::PopCount => {
+                self.expect(&[U32]);
+                self.pop(1);
+                self.push_result(U32, 1);
+            }
+            Instruction::Nop
+            | Instruction::Halt
+            | Instruction::Return
+            | Instruction::Recurse
+            | Instruction::RecurseOrReturn => (),
+            _ => self.state.clear(),
+        }
+    }
+
+    fn reserve(&mut self, len: usize) {
+        if self.state.len() < len {
+            self.state.resize(len, None);
+        }
+    }
+
+    fn pop(&mut self, n: usize) {
+        let n = n.min(self.state.len());
+        self.state.drain(..n);
+    }
+
+    fn push_unknown(&mut self, n: usize) {
+        self.state.splice(0..0, vec![None; n]);
+    }
+
+    fn push_result(&mut self, element_type: ElementType, n: usize) {
+        let typed = TypedElement {
+            element_type,
+            origin: TypeOrigin::Instruction {
+                address: self.address,
+                instruction: self.instruction,
+            },
+        };
+        self.state.splice(0..0, vec![Some(typed); n]);
+    }
+
+    /// Warn about any operand that is not of the expected type. The first expected type
+    /// is for `st0`, the second for `st1`, and so on.
+    fn expect(&mut self, expected_types: &[ElementType]) {
+        for (i, &expected) in expected_types.iter().enumerate() {
+            let Some(Some(typed)) = self.state.get(i) else {
+                continue;
+            };
+            if expected.accepts(typed.element_type) {
+                continue;
+            }
+            self.warnings.push(TypeWarning {
+                address: self.address,
+                instruction: self.instruction,
+                operand: OpStackElement::try_from(i).unwrap(),
+                expected,
+                actual: typed.element_type,
+                origin: typed.origin.clone(),
+            });
+        }
+    }
+
+    /// Field arithmetic produces field elements, but only elements of known type are
+    /// tracked: an operation on unknown elements produces an unknown element.
+    fn arithmetic(&mut self, expected_types: &[ElementType]) {
+        self.expect(expected_types);
+        let num_operands = expected_types.len();
+        let any_operand_is_typed = self.state.iter().take(num_operands).any(Option::is_some);
+        self.pop(num_operands);
+        if any_operand_is_typed {
+            self.push_result(ElementType::Field, 1);
+        } else {
+            self.push_unknown(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use crate::triton_program;
+
+    use super::*;
+
+    #[test]
+    fn correctly_typed_program_has_no_warnings() {
+        let program = triton_program!(
+            divine 2
+            hint a: u32 = stack[0]
+            hint b: u32 = stack[1]
+            dup 1 dup 1 lt pop 1
+            div_mod
+            split
+            pop 3
+            divine 5
+            hint digest: Digest = stack[0..5]
+            dup 4 dup 4 dup 4 dup 4 dup 4
+            assert_vector pop 5
+            halt
+        );
+        assert!(TypeCheck::new(&program).warnings().is_empty());
+    }
+
+    #[test]
+    fn splitting_digest_raises_warning() {
+        let program = triton_program!(
+            divine 5
+            hint digest: Digest = stack[0..5]
+            swap 3 split
+            halt
+        );
+        let type_check = TypeCheck::new(&program);
+        let_assert!([warning] = type_check.warnings());
+        assert!(4 == warning.address);
+        assert!(Instruction::Split == warning.instruction);
+        assert!(ElementType::Digest == warning.actual);
+        let_assert!(TypeOrigin::Hint { variable_name, .. } = &warning.origin);
+        assert!("digest" == variable_name);
+    }
+
+    #[test]
+    fn product_of_u32s_in_u32_instruction_raises_warning() {
+        let program = triton_program!(
+            divine 2
+            hint a: u32 = stack[0]
+            hint b: u32 = stack[1]
+            mul push 3 swap 1 div_mod
+            halt
+        );
+        let type_check = TypeCheck::new(&program);
+        let_assert!([warning] = type_check.warnings());
+        assert!(OpStackElement::ST0 == warning.operand);
+        assert!(ElementType::U32 == warning.expected);
+        assert!(ElementType::Field == warning.actual);
+        let_assert!(TypeOrigin::Instruction { address: 2, .. } = &warning.origin);
+    }
+
+    #[test]
+    fn hashing_produces_digest() {
+        let program = triton_program!(push 0 push 0 push 0 push 0 push 0 hash split halt);
+        let type_check = TypeCheck::new(&program);
+        let_assert!([warning] = type_check.warnings());
+        assert!(ElementType::Digest == warning.actual);
+        assert!(warning.to_string().contains("result of `hash`"));
+    }
+
+    #[test]
+    fn unknown_and_missing_type_names_are_ignored() {
+        let program = triton_program!(
+            divine 2
+            hint a: Magic = stack[0]
+            hint b = stack[1]
+            split split
+            halt
+        );
+        assert!(TypeCheck::new(&program).warnings().is_empty());
+    }
+
+    #[test]
+    fn types_survive_skiz_if_they_agree() {
+        let program = triton_program!(
+            divine 2
+            hint digest_part: Digest = stack[1]
+            skiz nop
+            split
+            halt
+        );
+        let type_check = TypeCheck::new(&program);
+        let_assert!([warning] = type_check.warnings());
+        assert!(4 == warning.address);
+    }
+
+    #[test]
+    fn types_do_not_survive_calls() {
+        let program = triton_program!(
+            divine 1
+            hint digest_part: Digest = stack[0]
+            call foo split halt
+            foo: return
+        );
+        assert!(TypeCheck::new(&program).warnings().is_empty());
+    }
+
+    #[test]
+    fn stack_manipulation_moves_types() {
+        let program = triton_program!(
+            divine 3
+            hint x: XFieldElement = stack[2]
+            pick 2 place 1 swap 1 dup 0 log_2_floor
+            halt
+        );
+        let type_check = TypeCheck::new(&program);
+        let_assert!([warning] = type_check.warnings());
+        assert!(OpStackElement::ST0 == warning.operand);
+        assert!(ElementType::XFieldElement == warning.actual);
+    }
+}
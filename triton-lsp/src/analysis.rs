@@ -0,0 +1,430 @@
+use std::ops::Range;
+
+use isa::diagnostic::diagnose;
+use isa::diagnostic::Diagnostic;
+use isa::instruction::AnInstruction;
+use isa::instruction::ALL_INSTRUCTIONS;
+use isa::instruction::ALL_INSTRUCTION_NAMES;
+use isa::parser;
+use isa::parser::InstructionToken;
+use itertools::Itertools;
+
+/// Keywords of Triton assembly that are not instructions.
+const KEYWORDS: [&str; 17] = [
+    "break",
+    "hint",
+    "error_id",
+    "error_message",
+    "const",
+    "macro",
+    "endmacro",
+    "import",
+    "include",
+    "data",
+    "if",
+    "else",
+    "endif",
+    "while",
+    "endwhile",
+    "for",
+    "endfor",
+];
+
+/// Everything the language server knows about one document of Triton assembly.
+///
+/// All positions are byte offsets into the document's source code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Analysis<'a> {
+    code: &'a str,
+    tokens: Vec<InstructionToken<'a>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Information about the code at some position. See [`Analysis::hover`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Hover {
+    /// The code the information is about.
+    pub span: Range<usize>,
+
+    /// The information, formatted as Markdown.
+    pub markdown: String,
+}
+
+/// A suggestion for the code at some position. See [`Analysis::completions`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+
+    /// Additional information, like an instruction's opcode.
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum CompletionKind {
+    Instruction,
+    Keyword,
+    Label,
+}
+
+/// The depth of the op stack after some instruction, relative to the depth at the
+/// start of the surrounding subroutine. See [`Analysis::inlay_hints`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct InlayHint {
+    /// The position right after the instruction.
+    pub offset: usize,
+    pub relative_depth: i32,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new(code: &'a str) -> Self {
+        Self {
+            code,
+            tokens: parser::tokenize_with_recovery(code),
+            diagnostics: diagnose(code),
+        }
+    }
+
+    /// All problems in the code, as found by the [parser](diagnose).
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The span of the definition of the label at the given position, if any.
+    pub fn definition(&self, offset: usize) -> Option<Range<usize>> {
+        let (label, _) = self.label_at(offset)?;
+        self.tokens.iter().find_map(|token| match token {
+            InstructionToken::Label(defined_label, token_str) if *defined_label == label => {
+                let start = self.offset_of(token_str);
+                Some(start..start + label.len())
+            }
+            _ => None,
+        })
+    }
+
+    /// The opcode and the stack effect of the instruction at the given position, if any.
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let (word, span) = self.word_at(offset)?;
+        let instruction = self.tokens.iter().find_map(|token| match token {
+            InstructionToken::Instruction(instruction, token_str)
+                if self.offset_of(token_str) == span.start =>
+            {
+                Some(instruction)
+            }
+            _ => None,
+        })?;
+        if instruction.name() != word {
+            return None;
+        }
+
+        let opcode = instruction.opcode();
+        let size = instruction.size();
+        let influence = instruction.op_stack_size_influence();
+        let words = if size == 1 { "word" } else { "words" };
+        let markdown = format!(
+            "```tasm\n{instruction}\n```\n\
+            opcode: `{opcode}`, size: {size} {words}, op stack size change: `{influence:+}`"
+        );
+        Some(Hover { span, markdown })
+    }
+
+    /// Suggestions for the word ending at the given position. Following `call`, labels
+    /// are suggested. Elsewhere, instructions and keywords are suggested.
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let line_start = self.code[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let preceding_code = &self.code[line_start..offset];
+        let preceding_words = preceding_code.split_whitespace().collect_vec();
+        let is_at_word_boundary = preceding_code.ends_with(char::is_whitespace);
+        let (prefix, previous_word) = match (is_at_word_boundary, &preceding_words[..]) {
+            (true, [.., previous]) => ("", Some(*previous)),
+            (true, []) => ("", None),
+            (false, [.., previous, prefix]) => (*prefix, Some(*previous)),
+            (false, [prefix]) => (*prefix, None),
+            (false, []) => ("", None),
+        };
+
+        if previous_word == Some("call") {
+            return self
+                .labels()
+                .into_iter()
+                .filter(|label| label.starts_with(prefix))
+                .map(|label| Completion {
+                    label: label.to_string(),
+                    kind: CompletionKind::Label,
+                    detail: None,
+                })
+                .collect();
+        }
+
+        let instructions = ALL_INSTRUCTIONS.into_iter().map(|instruction| Completion {
+            label: instruction.name().to_string(),
+            kind: CompletionKind::Instruction,
+            detail: Some(format!("opcode {}", instruction.opcode())),
+        });
+        let keywords = KEYWORDS.into_iter().map(|keyword| Completion {
+            label: keyword.to_string(),
+            kind: CompletionKind::Keyword,
+            detail: None,
+        });
+        instructions
+            .chain(keywords)
+            .filter(|completion| completion.label.starts_with(prefix))
+            .collect()
+    }
+
+    /// The depth of the op stack after each instruction, relative to the depth at the
+    /// start of the surrounding subroutine, computed using
+    /// [`op_stack_size_influence`](AnInstruction::op_stack_size_influence).
+    ///
+    /// Since the stack effect of a subroutine is not known without analyzing it, no hints
+    /// are given between a `call` and the next label. The bodies of control-flow
+    /// constructs like `if … endif` are subroutines of their own. Instructions that are
+    /// the result of macro expansion or of lowering control-flow constructs contribute to
+    /// the depth but have no hints of their own.
+    pub fn inlay_hints(&self) -> Vec<InlayHint> {
+        let mut hints = vec![];
+        let mut relative_depth = Some(0);
+        let macro_definitions = self.macro_definitions();
+        for token in &self.tokens {
+            let offset = self.offset_of(token.token_str());
+            let is_from_macro_expansion = macro_definitions
+                .iter()
+                .any(|definition| definition.contains(&offset));
+
+            match token {
+                InstructionToken::Label(..) => relative_depth = Some(0),
+                InstructionToken::Instruction(AnInstruction::Call(_), _) => relative_depth = None,
+                InstructionToken::Instruction(instruction, token_str) => {
+                    let Some(depth) = relative_depth.as_mut() else {
+                        continue;
+                    };
+                    *depth += instruction.op_stack_size_influence();
+                    let is_generated = !token_str.starts_with(instruction.name());
+                    if !is_from_macro_expansion && !is_generated {
+                        let end = self.end_of_instruction(token_str, instruction.size());
+                        let relative_depth = *depth;
+                        hints.push(InlayHint {
+                            offset: end,
+                            relative_depth,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        hints
+    }
+
+    /// The spans of all macro definitions, from `macro` to `endmacro`.
+    fn macro_definitions(&self) -> Vec<Range<usize>> {
+        let mut definitions = vec![];
+        let mut definition_start = None;
+        for line in self.code.lines() {
+            let code = line.split("//").next().unwrap_or_default();
+            for word in code.split_whitespace() {
+                let offset = self.offset_of(word);
+                match word {
+                    "macro" => definition_start = Some(offset),
+                    "endmacro" => {
+                        let Some(start) = definition_start.take() else {
+                            continue;
+                        };
+                        definitions.push(start..offset);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        definitions
+    }
+
+    fn labels(&self) -> Vec<&str> {
+        let labels = self.tokens.iter().filter_map(|token| match token {
+            InstructionToken::Label(label, _) => Some(label.as_str()),
+            _ => None,
+        });
+        labels.unique().sorted().collect()
+    }
+
+    /// The label at the given position, either where it is defined or where it is
+    /// called, and its span.
+    fn label_at(&self, offset: usize) -> Option<(String, Range<usize>)> {
+        let (word, span) = self.word_at(offset)?;
+        let label = word.trim_end_matches(':');
+        let is_instruction = ALL_INSTRUCTION_NAMES.contains(&label);
+        let is_keyword = KEYWORDS.contains(&label);
+        if label.is_empty() || is_instruction || is_keyword {
+            return None;
+        }
+        Some((label.to_string(), span.start..span.start + label.len()))
+    }
+
+    /// The word containing, or ending at, the given position, and its span.
+    fn word_at(&self, offset: usize) -> Option<(&'a str, Range<usize>)> {
+        let is_word_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '!');
+        let offset = offset.min(self.code.len());
+        let start = self.code[..offset]
+            .rfind(|c| !is_word_char(c))
+            .map_or(0, |i| i + 1);
+        let end = self.code[offset..]
+            .find(|c| !is_word_char(c))
+            .map_or(self.code.len(), |i| offset + i);
+        let word = self.code[start..end].trim_end_matches(':');
+        if word.is_empty() {
+            return None;
+        }
+        Some((word, start..start + word.len()))
+    }
+
+    /// The offset of the given token string in the code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token string is not a part of the code.
+    fn offset_of(&self, token_str: &str) -> usize {
+        let offset = token_str.as_ptr() as usize - self.code.as_ptr() as usize;
+        assert!(offset <= self.code.len());
+        offset
+    }
+
+    /// The offset right after the instruction at the start of the given token string.
+    fn end_of_instruction(&self, token_str: &str, size: usize) -> usize {
+        let word_end = |s: &str| s.find(char::is_whitespace).unwrap_or(s.len());
+        let mut length = word_end(token_str);
+        if size > 1 {
+            let argument = token_str[length..].trim_start();
+            length = token_str.len() - argument.len() + word_end(argument);
+        }
+        self.offset_of(token_str) + length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use super::*;
+
+    const CODE: &str = "\
+push 1 call foo
+halt
+foo:
+    pop 2 dup 0
+    return";
+
+    fn offset(needle: &str) -> usize {
+        offset_in(CODE, needle)
+    }
+
+    fn offset_in(code: &str, needle: &str) -> usize {
+        code.find(needle).unwrap()
+    }
+
+    #[test]
+    fn definition_of_called_label_is_found() {
+        let analysis = Analysis::new(CODE);
+        let definition_start = offset("foo:");
+        let expected = definition_start..definition_start + 3;
+        assert!(Some(expected.clone()) == analysis.definition(offset("foo") + 1));
+        assert!(Some(expected) == analysis.definition(definition_start));
+    }
+
+    #[test]
+    fn instructions_have_no_definition() {
+        let analysis = Analysis::new(CODE);
+        assert!(None == analysis.definition(offset("halt")));
+    }
+
+    #[test]
+    fn hover_shows_stack_effect_and_opcode() {
+        let analysis = Analysis::new(CODE);
+        let_assert!(Some(hover) = analysis.hover(offset("pop") + 1));
+        let expected_span = offset("pop")..offset("pop") + 3;
+        assert!(expected_span == hover.span);
+        assert!(hover.markdown.contains("pop 2"));
+        assert!(hover.markdown.contains("op stack size change: `-2`"));
+
+        let opcode = AnInstruction::<String>::Pop(isa::op_stack::NumberOfWords::N2).opcode();
+        assert!(hover.markdown.contains(&format!("opcode: `{opcode}`")));
+    }
+
+    #[test]
+    fn there_is_no_hover_for_labels_or_arguments() {
+        let analysis = Analysis::new(CODE);
+        assert!(None == analysis.hover(offset("foo:")));
+        assert!(None == analysis.hover(offset("2 dup")));
+    }
+
+    #[test]
+    fn instructions_are_completed() {
+        let code = "push 1 recur";
+        let analysis = Analysis::new(code);
+        let completions = analysis.completions(code.len());
+        let labels = completions.iter().map(|c| c.label.as_str()).collect_vec();
+        assert!(vec!["recurse", "recurse_or_return"] == labels);
+        let only_instructions = completions
+            .iter()
+            .all(|c| c.kind == CompletionKind::Instruction);
+        assert!(only_instructions);
+    }
+
+    #[test]
+    fn labels_are_completed_after_call() {
+        let code = "call f\nhalt\nfoo: return\nbar: return\nfizz: return";
+        let analysis = Analysis::new(code);
+        let completions = analysis.completions(offset_in(code, "call f") + 6);
+        let labels = completions.iter().map(|c| c.label.as_str()).collect_vec();
+        assert!(vec!["fizz", "foo"] == labels);
+    }
+
+    #[test]
+    fn everything_is_completed_at_start_of_empty_line() {
+        let analysis = Analysis::new("");
+        let num_completions = ALL_INSTRUCTION_NAMES.len() + KEYWORDS.len();
+        assert!(num_completions == analysis.completions(0).len());
+    }
+
+    #[test]
+    fn inlay_hints_show_relative_stack_depth() {
+        let analysis = Analysis::new(CODE);
+        let hints = analysis.inlay_hints();
+        let hints = hints
+            .iter()
+            .map(|hint| (&CODE[..hint.offset], hint.relative_depth))
+            .map(|(code, depth)| (code.split_whitespace().last().unwrap(), depth))
+            .collect_vec();
+        let expected = vec![("1", 1), ("2", -2), ("0", -1), ("return", -1)];
+        assert!(expected == hints);
+    }
+
+    #[test]
+    fn instructions_from_macro_expansion_have_no_inlay_hints() {
+        let code = "macro two push 1 push 1 endmacro\ntwo! add halt";
+        let hints = Analysis::new(code).inlay_hints();
+        let_assert!([add, halt] = hints.as_slice());
+        assert!(offset_in(code, " halt") == add.offset);
+        assert!((1, 1) == (add.relative_depth, halt.relative_depth));
+    }
+
+    #[test]
+    fn instructions_from_lowering_control_flow_have_no_inlay_hints() {
+        let code = "foo: push 1 if push 2 endif return";
+        let hints = Analysis::new(code).inlay_hints();
+        let_assert!([push_1, push_2] = hints.as_slice());
+        assert!(offset_in(code, " if") == push_1.offset);
+        assert!(offset_in(code, " endif") == push_2.offset);
+        assert!(1 == push_2.relative_depth);
+    }
+
+    #[test]
+    fn analysis_of_erroneous_code_still_works() {
+        let code = "push 1 frobnicate call foo halt foo: pop 1 return";
+        let analysis = Analysis::new(code);
+        assert!(1 == analysis.diagnostics().len());
+        assert!(analysis.definition(offset_in(code, "foo")).is_some());
+        assert!(analysis.hover(offset_in(code, "pop")).is_some());
+    }
+}
@@ -0,0 +1,7 @@
+//! A language server for Triton assembly.
+//!
+//! The [analysis] of a document is independent of the language server protocol, which is
+//! implemented by the [server].
+
+pub mod analysis;
+pub mod server;
@@ -0,0 +1,5 @@
+use std::io;
+
+fn main() -> io::Result<()> {
+    triton_lsp::server::run(io::stdin().lock(), io::stdout().lock())
+}
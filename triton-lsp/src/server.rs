@@ -0,0 +1,491 @@
+//! A [language server](https://microsoft.github.io/language-server-protocol/) for Triton
+//! assembly, communicating via JSON-RPC.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::ops::Range;
+
+use isa::diagnostic::Diagnostic;
+use isa::diagnostic::Severity;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::analysis::Analysis;
+use crate::analysis::CompletionKind;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_PARAMS: i32 = -32602;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The state of the language server: all open documents, keyed by their URI.
+///
+/// Only full document synchronization is supported, _i.e._, every change notification
+/// is expected to contain the entire document.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Server {
+    documents: HashMap<String, String>,
+    has_exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the client has asked the server to exit.
+    pub fn has_exited(&self) -> bool {
+        self.has_exited
+    }
+
+    /// Handle one message from the client, returning all messages for the client.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.handle_notification(method, params);
+        };
+
+        let response = match self.handle_request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => {
+                let error = json!({ "code": code, "message": message });
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
+            }
+        };
+        vec![response]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let Some(text) = params["textDocument"]["text"].as_str() else {
+                    return vec![];
+                };
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![self.diagnostics_notification(uri)]
+            }
+            "textDocument/didChange" => {
+                let Some(change) = params["contentChanges"].as_array().and_then(|c| c.last())
+                else {
+                    return vec![];
+                };
+                let Some(text) = change["text"].as_str() else {
+                    return vec![];
+                };
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![self.diagnostics_notification(uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![self.diagnostics_notification(uri)]
+            }
+            "exit" => {
+                self.has_exited = true;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn handle_request(&self, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+        if method == "initialize" {
+            return Ok(Self::capabilities());
+        }
+        if method == "shutdown" {
+            return Ok(Value::Null);
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(code) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("unknown document: {uri}")));
+        };
+        let analysis = Analysis::new(code);
+        let cursor = offset(code, &params["position"]);
+        let result = match method {
+            "textDocument/definition" => match analysis.definition(cursor) {
+                Some(span) => json!({ "uri": uri, "range": range(code, span) }),
+                None => Value::Null,
+            },
+            "textDocument/hover" => match analysis.hover(cursor) {
+                Some(hover) => json!({
+                    "contents": { "kind": "markdown", "value": hover.markdown },
+                    "range": range(code, hover.span),
+                }),
+                None => Value::Null,
+            },
+            "textDocument/completion" => {
+                let completions = analysis.completions(cursor).into_iter();
+                let items = completions.map(|completion| {
+                    let kind = match completion.kind {
+                        CompletionKind::Instruction => 3,
+                        CompletionKind::Keyword => 14,
+                        CompletionKind::Label => 18,
+                    };
+                    let label = completion.label;
+                    json!({ "label": label, "kind": kind, "detail": completion.detail })
+                });
+                Value::Array(items.collect())
+            }
+            "textDocument/inlayHint" => {
+                let start = offset(code, &params["range"]["start"]);
+                let end = offset(code, &params["range"]["end"]);
+                let hints = analysis.inlay_hints().into_iter();
+                let hints = hints.filter(|hint| (start..=end).contains(&hint.offset));
+                let hints = hints.map(|hint| {
+                    json!({
+                        "position": position(code, hint.offset),
+                        "label": format!("{:+}", hint.relative_depth),
+                        "paddingLeft": true,
+                    })
+                });
+                Value::Array(hints.collect())
+            }
+            "textDocument/codeAction" => {
+                let start = offset(code, &params["range"]["start"]);
+                let end = offset(code, &params["range"]["end"]);
+                let diagnostics = analysis.diagnostics().iter();
+                let diagnostics = diagnostics.filter(|diagnostic| {
+                    diagnostic.span.start <= end && start <= diagnostic.span.end
+                });
+                let actions = diagnostics.filter_map(|diagnostic| {
+                    let fix = diagnostic.fix.as_ref()?;
+                    let edit = json!({
+                        "range": range(code, diagnostic.span.clone()),
+                        "newText": fix.replacement,
+                    });
+                    Some(json!({
+                        "title": fix.description,
+                        "kind": "quickfix",
+                        "diagnostics": [lsp_diagnostic(code, diagnostic)],
+                        "edit": { "changes": { uri: [edit] } },
+                    }))
+                });
+                Value::Array(actions.collect())
+            }
+            _ => return Err((METHOD_NOT_FOUND, format!("unknown method: {method}"))),
+        };
+
+        Ok(result)
+    }
+
+    fn capabilities() -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "completionProvider": {},
+                "inlayHintProvider": true,
+                "codeActionProvider": true,
+            },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+
+    /// The diagnostics of the document with the given URI. Closed documents have none.
+    fn diagnostics_notification(&self, uri: &str) -> Value {
+        let diagnostics = match self.documents.get(uri) {
+            Some(code) => Analysis::new(code)
+                .diagnostics()
+                .iter()
+                .map(|diagnostic| lsp_diagnostic(code, diagnostic))
+                .collect(),
+            None => vec![],
+        };
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+}
+
+/// Serve one client, reading from `input` and writing to `output`, until the client
+/// asks the server to exit or closes the connection.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+    while !server.has_exited() {
+        let Some(content) = read_message(&mut input)? else {
+            break;
+        };
+        let responses = match serde_json::from_slice(&content) {
+            Ok(message) => server.handle(&message),
+            Err(error) => {
+                let error = json!({ "code": PARSE_ERROR, "message": error.to_string() });
+                vec![json!({ "jsonrpc": "2.0", "id": null, "error": error })]
+            }
+        };
+        for response in responses {
+            write_message(&mut output, &response)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the content of the next message, or `None` if the input has ended.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        let message = "missing header “Content-Length”";
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    };
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+    Ok(Some(content))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()
+}
+
+fn lsp_diagnostic(code: &str, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+
+    json!({
+        "range": range(code, diagnostic.span.clone()),
+        "severity": severity,
+        "code": diagnostic.kind.to_string(),
+        "source": env!("CARGO_PKG_NAME"),
+        "message": diagnostic.message,
+    })
+}
+
+fn range(code: &str, span: Range<usize>) -> Value {
+    json!({ "start": position(code, span.start), "end": position(code, span.end) })
+}
+
+/// The LSP position of the given byte offset. Like the protocol demands, the column is
+/// counted in UTF-16 code units.
+fn position(code: &str, offset: usize) -> Value {
+    let preceding_code = &code[..offset.min(code.len())];
+    let line = preceding_code.matches('\n').count();
+    let line_start = preceding_code.rfind('\n').map_or(0, |i| i + 1);
+    let character = preceding_code[line_start..].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// The byte offset of the given LSP position. Positions beyond the end of a line are
+/// clamped to the line's end.
+fn offset(code: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default();
+    let character = position["character"].as_u64().unwrap_or_default();
+    let Ok(line) = usize::try_from(line) else {
+        return code.len();
+    };
+
+    let line_start = match line {
+        0 => 0,
+        _ => match code.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1,
+            None => return code.len(),
+        },
+    };
+    let line_end = code[line_start..]
+        .find('\n')
+        .map_or(code.len(), |i| line_start + i);
+
+    let mut num_code_units = 0;
+    for (i, c) in code[line_start..line_end].char_indices() {
+        if num_code_units >= character {
+            return line_start + i;
+        }
+        num_code_units += c.len_utf16() as u64;
+    }
+    line_end
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use super::*;
+
+    const URI: &str = "file:///program.tasm";
+
+    fn open(code: &str) -> (Server, Vec<Value>) {
+        let mut server = Server::new();
+        let text_document = json!({ "uri": URI, "languageId": "tasm", "version": 0, "text": code });
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": text_document },
+        });
+        let messages = server.handle(&notification);
+        (server, messages)
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let responses = server.handle(&request);
+        let_assert!([response] = responses.as_slice());
+        assert!(1 == response["id"]);
+        response.clone()
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": position_value(line, character) })
+    }
+
+    fn position_value(line: u64, character: u64) -> Value {
+        json!({ "line": line, "character": character })
+    }
+
+    #[test]
+    fn initialization_announces_capabilities() {
+        let mut server = Server::new();
+        let response = request(&mut server, "initialize", json!({}));
+        assert!(true == response["result"]["capabilities"]["hoverProvider"]);
+        assert!(1 == response["result"]["capabilities"]["textDocumentSync"]);
+    }
+
+    #[test]
+    fn opening_a_document_publishes_its_diagnostics() {
+        let (_, messages) = open("push 1\npop 6\nhalt");
+        let_assert!([notification] = messages.as_slice());
+        assert!("textDocument/publishDiagnostics" == notification["method"]);
+
+        let diagnostics = notification["params"]["diagnostics"].as_array();
+        let_assert!(Some([diagnostic]) = diagnostics.map(Vec::as_slice));
+        assert!(1 == diagnostic["severity"]);
+        assert!("syntax" == diagnostic["code"]);
+        assert!(position_value(1, 4) == diagnostic["range"]["start"]);
+    }
+
+    #[test]
+    fn changing_a_document_updates_its_diagnostics() {
+        let (mut server, _) = open("pop 6");
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": URI, "version": 1 },
+                "contentChanges": [{ "text": "pop 5 halt" }],
+            },
+        });
+        let messages = server.handle(&notification);
+        let_assert!([notification] = messages.as_slice());
+        assert!(json!([]) == notification["params"]["diagnostics"]);
+    }
+
+    #[test]
+    fn definition_of_label_is_found() {
+        let (mut server, _) = open("call foo\nhalt\nfoo:\n    return");
+        let response = request(&mut server, "textDocument/definition", at(0, 6));
+        let expected_range = json!({ "start": position_value(2, 0), "end": position_value(2, 3) });
+        assert!(expected_range == response["result"]["range"]);
+        assert!(URI == response["result"]["uri"]);
+    }
+
+    #[test]
+    fn hover_is_markdown() {
+        let (mut server, _) = open("push 1 halt");
+        let response = request(&mut server, "textDocument/hover", at(0, 1));
+        assert!("markdown" == response["result"]["contents"]["kind"]);
+    }
+
+    #[test]
+    fn completions_have_instruction_kind() {
+        let (mut server, _) = open("hal");
+        let response = request(&mut server, "textDocument/completion", at(0, 3));
+        let_assert!(Some([completion]) = response["result"].as_array().map(Vec::as_slice));
+        assert!("halt" == completion["label"]);
+        assert!(3 == completion["kind"]);
+    }
+
+    #[test]
+    fn inlay_hints_are_restricted_to_requested_range() {
+        let (mut server, _) = open("push 1\npush 2\nadd\nhalt");
+        let range = json!({ "start": position_value(1, 0), "end": position_value(2, 3) });
+        let params = json!({ "textDocument": { "uri": URI }, "range": range });
+        let response = request(&mut server, "textDocument/inlayHint", params);
+        let_assert!(Some([push, add]) = response["result"].as_array().map(Vec::as_slice));
+        assert!("+2" == push["label"]);
+        assert!("+1" == add["label"]);
+        assert!(position_value(2, 3) == add["position"]);
+    }
+
+    #[test]
+    fn code_action_applies_fix() {
+        let (mut server, _) = open("push 1 hallt");
+        let range = json!({ "start": position_value(0, 8), "end": position_value(0, 8) });
+        let params = json!({ "textDocument": { "uri": URI }, "range": range });
+        let response = request(&mut server, "textDocument/codeAction", params);
+        let_assert!(Some([action]) = response["result"].as_array().map(Vec::as_slice));
+        let_assert!(Some([edit]) = action["edit"]["changes"][URI].as_array().map(Vec::as_slice));
+        assert!("halt" == edit["newText"]);
+    }
+
+    #[test]
+    fn unknown_method_is_an_error() {
+        let (mut server, _) = open("halt");
+        let response = request(&mut server, "textDocument/frobnicate", at(0, 0));
+        assert!(METHOD_NOT_FOUND == response["error"]["code"]);
+    }
+
+    #[test]
+    fn positions_are_counted_in_utf16_code_units() {
+        let code = "// 🦀\npush 1";
+        let offset_of_push = code.find("push").unwrap();
+        assert!(position_value(1, 0) == position(code, offset_of_push));
+        assert!(code.find('🦀').unwrap() == offset(code, &position_value(0, 3)));
+        assert!(code.find('\n').unwrap() == offset(code, &position_value(0, 5)));
+        assert!(offset_of_push + 2 == offset(code, &position_value(1, 2)));
+    }
+
+    #[test]
+    fn run_speaks_json_rpc() {
+        let requests = [
+            json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        ];
+        let mut input = vec![];
+        for request in requests {
+            write_message(&mut input, &request).unwrap();
+        }
+
+        let mut output = vec![];
+        run(input.as_slice(), &mut output).unwrap();
+
+        let mut output = output.as_slice();
+        let_assert!(Some(initialization) = read_message(&mut output).unwrap());
+        let_assert!(Some(shutdown) = read_message(&mut output).unwrap());
+        let_assert!(None = read_message(&mut output).unwrap());
+
+        let initialization: Value = serde_json::from_slice(&initialization).unwrap();
+        let shutdown: Value = serde_json::from_slice(&shutdown).unwrap();
+        assert!(0 == initialization["id"]);
+        assert!(json!({ "jsonrpc": "2.0", "id": 1, "result": null }) == shutdown);
+    }
+}
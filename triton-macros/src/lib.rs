@@ -0,0 +1,436 @@
+//! Procedural variants of the macros `triton_program!` and `triton_asm!`. Unlike their
+//! declarative counterparts, which parse the embedded [Triton assembly][tasm] when the
+//! surrounding code runs, these macros parse and check it at compile time. Problems are
+//! reported as compilation errors that point at the offending token.
+//!
+//! The macros are re-exported by crate `triton-vm` in module `macros` and refer to it in
+//! the code they generate.
+//!
+//! [tasm]: https://triton-vm.org/spec/instructions.html
+
+use std::ops::Range;
+
+use isa::diagnostic::diagnose;
+use isa::diagnostic::Diagnostic;
+use isa::diagnostic::DiagnosticKind;
+use isa::diagnostic::Severity;
+use isa::instruction::AnInstruction;
+use isa::instruction::ALL_INSTRUCTIONS;
+use proc_macro2::Delimiter;
+use proc_macro2::Span;
+use proc_macro2::TokenStream;
+use proc_macro2::TokenTree;
+use quote::quote;
+use quote::quote_spanned;
+
+/// Compile [Triton assembly][tasm] into a [`Program`][program], checking it at compile
+/// time. All labels must be defined exactly once.
+///
+/// Rust expressions can be interpolated as arguments of instructions like so:
+/// `push {expression}`. The expression's type depends on the instruction:
+///
+/// - `push` and `addi` take anything that converts [`Into`] a
+///   [`BFieldElement`][bfe],
+/// - `pop`, `divine`, `read_mem`, `write_mem`, `read_io`, and `write_io` take a
+///   [`NumberOfWords`][words], and
+/// - `dup`, `swap`, `pick`, and `place` take an [`OpStackElement`][element].
+///
+/// Labels cannot be interpolated, since they could not be checked at compile time.
+///
+/// # Examples
+///
+/// ```
+/// # use triton_vm::prelude::*;
+/// # use triton_vm::isa::op_stack::NumberOfWords;
+/// let num_words = NumberOfWords::N2;
+/// let program = triton_vm::macros::triton_program!(
+///     push {42} push {-1} pop {num_words}
+///     call done
+///     done: halt
+/// );
+/// assert_eq!(triton_program!(push 42 push -1 pop 2 call done done: halt), program);
+/// ```
+///
+/// Misspelled instructions, illegal arguments, and missing labels, among other things,
+/// make compilation fail:
+///
+/// ```compile_fail
+/// let program = triton_vm::macros::triton_program!(push 1 hlat);
+/// ```
+///
+/// ```compile_fail
+/// let program = triton_vm::macros::triton_program!(call missing_label halt);
+/// ```
+///
+/// [tasm]: https://triton-vm.org/spec/instructions.html
+/// [program]: https://docs.rs/triton-isa/latest/triton_isa/program/struct.Program.html
+/// [bfe]: https://docs.rs/twenty-first/latest/twenty_first/math/b_field_element/struct.BFieldElement.html
+/// [words]: https://docs.rs/triton-isa/latest/triton_isa/op_stack/enum.NumberOfWords.html
+/// [element]: https://docs.rs/triton-isa/latest/triton_isa/op_stack/enum.OpStackElement.html
+#[proc_macro]
+pub fn triton_program(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(input.into(), Target::Program).into()
+}
+
+/// Compile [Triton assembly][tasm] into a list of
+/// [`LabelledInstruction`][instruction]s, checking it at compile time.
+///
+/// Like in [`triton_program!`], Rust expressions can be interpolated as instruction
+/// arguments. Calls to labels that are not defined in the code are allowed, since they
+/// might be defined elsewhere.
+///
+/// # Example
+///
+/// ```
+/// # use triton_vm::prelude::*;
+/// # use triton_vm::isa::op_stack::OpStackElement;
+/// let instructions = triton_vm::macros::triton_asm!(
+///     dup {OpStackElement::ST3} call defined_elsewhere
+/// );
+/// assert_eq!(triton_asm!(dup 3 call defined_elsewhere), instructions);
+/// ```
+///
+/// [tasm]: https://triton-vm.org/spec/instructions.html
+/// [instruction]: https://docs.rs/triton-isa/latest/triton_isa/instruction/enum.LabelledInstruction.html
+#[proc_macro]
+pub fn triton_asm(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(input.into(), Target::Instructions).into()
+}
+
+/// What a macro compiles the Triton assembly into.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Target {
+    Program,
+    Instructions,
+}
+
+/// The type of an instruction argument that can be interpolated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ArgumentType {
+    BFieldElement,
+    NumberOfWords,
+    OpStackElement,
+}
+
+impl ArgumentType {
+    /// The type of the argument of the instruction with the given name, if it can be
+    /// interpolated.
+    fn of_instruction(name: &str) -> Option<Self> {
+        let instruction = ALL_INSTRUCTIONS.iter().find(|i| i.name() == name)?;
+        match instruction {
+            AnInstruction::Push(_) | AnInstruction::AddI(_) => Some(Self::BFieldElement),
+            AnInstruction::Pop(_)
+            | AnInstruction::Divine(_)
+            | AnInstruction::ReadMem(_)
+            | AnInstruction::WriteMem(_)
+            | AnInstruction::ReadIo(_)
+            | AnInstruction::WriteIo(_) => Some(Self::NumberOfWords),
+            AnInstruction::Dup(_)
+            | AnInstruction::Swap(_)
+            | AnInstruction::Pick(_)
+            | AnInstruction::Place(_) => Some(Self::OpStackElement),
+            _ => None,
+        }
+    }
+
+    /// A legal argument standing in for the interpolated one during compile-time checks.
+    fn placeholder(self) -> &'static str {
+        match self {
+            Self::NumberOfWords => "1",
+            Self::BFieldElement | Self::OpStackElement => "0",
+        }
+    }
+
+    /// Code that evaluates the expression as an argument of this type and turns it into
+    /// something that [displays](std::fmt::Display) like Triton assembly.
+    fn interpolate(self, expression: TokenStream, span: Span) -> TokenStream {
+        match self {
+            Self::BFieldElement => quote_spanned! {span=> {
+                let argument: ::triton_vm::prelude::BFieldElement =
+                    ::core::convert::Into::into(#expression);
+                argument.value()
+            }},
+            Self::NumberOfWords => quote_spanned! {span=> {
+                let argument: ::triton_vm::isa::op_stack::NumberOfWords = #expression;
+                argument
+            }},
+            Self::OpStackElement => quote_spanned! {span=> {
+                let argument: ::triton_vm::isa::op_stack::OpStackElement = #expression;
+                argument
+            }},
+        }
+    }
+}
+
+/// Triton assembly, assembled from Rust tokens.
+#[derive(Debug, Default)]
+struct Source {
+    /// The assembly, with a placeholder in place of every interpolated argument.
+    code: String,
+
+    /// A format string that, given the interpolated arguments, produces the assembly.
+    format_string: String,
+
+    /// The interpolated arguments.
+    arguments: Vec<TokenStream>,
+
+    /// The span of the Rust token that each part of the code originates from.
+    spans: Vec<(Range<usize>, Span)>,
+}
+
+impl Source {
+    fn from_tokens(tokens: TokenStream) -> syn::Result<Self> {
+        let mut source = Self::default();
+        source.append_tokens(tokens)?;
+
+        // some statements, like assertion contexts, must be terminated by whitespace
+        source.code.push(' ');
+        source.format_string.push(' ');
+        Ok(source)
+    }
+
+    fn append_tokens(&mut self, tokens: TokenStream) -> syn::Result<()> {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) => self.append(&ident.to_string(), ident.span()),
+                TokenTree::Literal(literal) => self.append(&literal.to_string(), literal.span()),
+                TokenTree::Punct(punct) => self.append(&punct.to_string(), punct.span()),
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                        Delimiter::Brace => {
+                            self.append_argument(group.stream(), group.span())?;
+                            continue;
+                        }
+                    };
+                    self.append(open, group.span_open());
+                    self.append_tokens(group.stream())?;
+                    self.append(close, group.span_close());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the given code, separating it from the preceding code the way Triton
+    /// assembly is usually written. For example, labels are directly followed by `:`,
+    /// and negative numbers are written without space after `-`.
+    fn append(&mut self, code: &str, span: Span) {
+        if code.is_empty() {
+            return;
+        }
+        if self.needs_space_before(code) {
+            self.code.push(' ');
+            self.format_string.push(' ');
+        }
+        let start = self.code.len();
+        self.code.push_str(code);
+        self.spans.push((start..self.code.len(), span));
+        let escaped = code.replace('{', "{{").replace('}', "}}");
+        self.format_string.push_str(&escaped);
+    }
+
+    fn needs_space_before(&self, code: &str) -> bool {
+        let Some(previous) = self.code.chars().last() else {
+            return false;
+        };
+        if matches!(previous, '[' | '(' | '-' | '+' | '.')
+            || code.starts_with([':', ',', '.', '!', ')', ']'])
+        {
+            return false;
+        }
+        let follows_word = previous.is_alphanumeric() || matches!(previous, '_' | '!');
+        !(code.starts_with(['[', '(']) && follows_word)
+    }
+
+    fn append_argument(&mut self, expression: TokenStream, span: Span) -> syn::Result<()> {
+        let instruction = self.code.split_whitespace().last().unwrap_or_default();
+        let Some(argument_type) = ArgumentType::of_instruction(instruction) else {
+            let message = match instruction {
+                "call" => "labels cannot be interpolated because they are checked at compile time",
+                _ => "only arguments of instructions like `push`, `pop`, or `dup` can be interpolated",
+            };
+            return Err(syn::Error::new(span, message));
+        };
+
+        self.code.push(' ');
+        self.format_string.push_str(" {}");
+        let start = self.code.len();
+        self.code.push_str(argument_type.placeholder());
+        self.spans.push((start..self.code.len(), span));
+        self.arguments
+            .push(argument_type.interpolate(expression, span));
+        Ok(())
+    }
+
+    /// The span of the Rust token that the given part of the code originates from.
+    fn span_of(&self, range: &Range<usize>) -> Span {
+        self.spans
+            .iter()
+            .find(|(code_range, _)| code_range.end > range.start)
+            .or(self.spans.last())
+            .map_or_else(Span::call_site, |&(_, span)| span)
+    }
+
+    /// Turn the given diagnostic into a compilation error, if it is one.
+    fn error(&self, diagnostic: &Diagnostic, target: Target) -> Option<syn::Error> {
+        if diagnostic.severity != Severity::Error {
+            return None;
+        }
+        // labels not defined in a snippet might be defined elsewhere
+        if target == Target::Instructions && diagnostic.kind == DiagnosticKind::MissingLabel {
+            return None;
+        }
+
+        let message = match &diagnostic.fix {
+            Some(fix) => format!("{} ({})", diagnostic.message, fix.description),
+            None => diagnostic.message.clone(),
+        };
+        Some(syn::Error::new(self.span_of(&diagnostic.span), message))
+    }
+}
+
+fn expand(input: TokenStream, target: Target) -> TokenStream {
+    match try_expand(input, target) {
+        Ok(expansion) => expansion,
+        Err(error) => error.to_compile_error(),
+    }
+}
+
+fn try_expand(input: TokenStream, target: Target) -> syn::Result<TokenStream> {
+    let source = Source::from_tokens(input)?;
+    let error = diagnose(&source.code)
+        .iter()
+        .filter_map(|diagnostic| source.error(diagnostic, target))
+        .reduce(|mut error, next_error| {
+            error.combine(next_error);
+            error
+        });
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    let Source {
+        format_string,
+        arguments,
+        ..
+    } = source;
+    let instructions = quote! {{
+        let source_code = ::std::format!(#format_string, #(#arguments),*);
+        let (_, instructions) = ::triton_vm::isa::parser::tokenize(&source_code).unwrap();
+        ::triton_vm::isa::parser::to_labelled_instructions(&instructions)
+    }};
+    let expansion = match target {
+        Target::Instructions => instructions,
+        Target::Program => quote! {
+            ::triton_vm::isa::program::Program::new(&#instructions)
+        },
+    };
+    Ok(expansion)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use super::*;
+
+    fn source(tokens: TokenStream) -> String {
+        let_assert!(Ok(source) = Source::from_tokens(tokens));
+        source.code
+    }
+
+    fn errors(tokens: TokenStream, target: Target) -> Vec<String> {
+        let_assert!(Err(error) = try_expand(tokens, target));
+        error.into_iter().map(|error| error.to_string()).collect()
+    }
+
+    #[test]
+    fn tokens_are_assembled_into_idiomatic_code() {
+        let code = source(quote! {
+            const N = 3
+            data primes: u32 @ 10 = [2, 3] data point: xfe @ 20 = [(1, -1, 0)]
+            macro push_twice(x) push x push x endmacro
+            foo: push_twice!(N) push -1 addi -5
+            hint bar: Digest = stack[0..5] hint baz = stack[5]
+            halt assert error_message "oops"
+        });
+        let expected = "const N = 3 \
+            data primes: u32 @ 10 = [2, 3] data point: xfe @ 20 = [(1, -1, 0)] \
+            macro push_twice(x) push x push x endmacro \
+            foo: push_twice!(N) push -1 addi -5 \
+            hint bar: Digest = stack[0..5] hint baz = stack[5] \
+            halt assert error_message \"oops\" ";
+        assert!(expected == code);
+    }
+
+    #[test]
+    fn relative_call_targets_keep_their_sign() {
+        let code = source(quote!(call +3 return call -2 halt));
+        assert!("call +3 return call -2 halt " == code);
+        assert!(try_expand(quote!(call +2 halt), Target::Program).is_ok());
+    }
+
+    #[test]
+    fn interpolated_arguments_are_replaced_by_placeholders() {
+        let_assert!(Ok(source) = Source::from_tokens(quote!(push {x} pop {n} dup {s} halt)));
+        assert!("push 0 pop 1 dup 0 halt " == source.code);
+        assert!("push {} pop {} dup {} halt " == source.format_string);
+        assert!(3 == source.arguments.len());
+    }
+
+    #[test]
+    fn braces_in_code_are_escaped_in_format_string() {
+        let_assert!(Ok(source) = Source::from_tokens(quote!(assert error_message "{}")));
+        assert!("assert error_message \"{{}}\" " == source.format_string);
+    }
+
+    #[test]
+    fn valid_code_expands() {
+        let tokens = quote!(push 1 call foo halt foo: return);
+        assert!(try_expand(tokens.clone(), Target::Program).is_ok());
+        assert!(try_expand(tokens, Target::Instructions).is_ok());
+    }
+
+    #[test]
+    fn typo_is_reported_with_fix() {
+        let errors = errors(quote!(push 1 halr), Target::Program);
+        assert!(["unknown instruction `halr` (did you mean `halt`?)"] == errors[..]);
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        let errors = errors(quote!(pop 7 dup 16 halt), Target::Instructions);
+        assert!(2 == errors.len());
+        assert!(errors[0].contains("illegal argument for `pop`"));
+        assert!(errors[1].contains("illegal argument for `dup`"));
+    }
+
+    #[test]
+    fn missing_labels_are_errors_in_programs_only() {
+        let tokens = quote!(call foo halt);
+        assert!(["missing label `foo`"] == errors(tokens.clone(), Target::Program)[..]);
+        assert!(try_expand(tokens, Target::Instructions).is_ok());
+    }
+
+    #[test]
+    fn duplicate_labels_are_errors_in_snippets() {
+        let errors = errors(quote!(foo: nop foo: halt), Target::Instructions);
+        assert!(["duplicate label `foo`"; 2] == errors[..]);
+    }
+
+    #[test]
+    fn labels_cannot_be_interpolated() {
+        let errors = errors(quote!(call {label} halt), Target::Program);
+        assert!(errors[0].contains("labels cannot be interpolated"));
+    }
+
+    #[test]
+    fn arguments_of_instructions_without_arguments_cannot_be_interpolated() {
+        let errors = errors(quote!(halt { x }), Target::Program);
+        assert!(errors[0].contains("only arguments of instructions"));
+    }
+}
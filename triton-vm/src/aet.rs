@@ -167,7 +167,7 @@ impl AlgebraicExecutionTrace {
         height.try_into().unwrap()
     }
 
-    fn padded_program_length(program: &Program) -> usize {
+    pub(crate) fn padded_program_length(program: &Program) -> usize {
         // Padding is at least one 1.
         // Also note that the Program Table's side of the instruction lookup argument requires at
         // least one padding row to account for the processor's “next instruction or argument.”
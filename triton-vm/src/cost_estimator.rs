@@ -0,0 +1,446 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use air::table::hash::PERMUTATION_TRACE_LENGTH;
+use isa::instruction::Instruction;
+use isa::program::Program;
+use twenty_first::math::x_field_element::EXTENSION_DEGREE;
+use twenty_first::prelude::tip5::NUM_SPLIT_AND_LOOKUP;
+use twenty_first::prelude::*;
+
+use crate::aet::AlgebraicExecutionTrace;
+use crate::error::CostEstimationError;
+use crate::execution_trace_profiler::VMTableHeights;
+
+/// The largest number of rows a single entry can contribute to the U32 Table. See also
+/// [`U32TableEntry`](crate::table::u32::U32TableEntry).
+const MAX_U32_TABLE_CONTRIBUTION: u32 = 2 + u32::MAX.ilog2();
+
+/// The maximal height of the Cascade Table, which has one row per 16-bit limb.
+const MAX_CASCADE_TABLE_HEIGHT: usize = 1 << 16;
+
+/// A static, worst-case estimate of the [table heights](VMTableHeights) and the
+/// [padded height](AlgebraicExecutionTrace::padded_height) resulting from running a
+/// [`Program`], without running it.
+///
+/// Every subroutine, _i.e._, the code starting at address 0 as well as the code starting
+/// at any [`call`](Instruction::Call) target, is estimated separately. For each
+/// subroutine, the estimator follows all paths from its entry to any
+/// [`return`](Instruction::Return), [`recurse`](Instruction::Recurse),
+/// [`recurse_or_return`](Instruction::RecurseOrReturn), or [`halt`](Instruction::Halt),
+/// and takes the maximum of each table's height over all those paths. Calls contribute
+/// the estimate of the called subroutine.
+///
+/// Subroutines that can `recurse` are loops. Their number of iterations cannot be
+/// inferred statically and must be [provided](Self::with_loop_bound). A subroutine that
+/// calls itself, directly or indirectly, cannot be estimated.
+///
+/// The estimate is an upper bound: for every execution that respects the loop bounds,
+/// each table is at most as high as estimated. Since the heights of the U32 Table and the
+/// Cascade Table depend on the concrete values being processed, their worst case is
+/// assumed. Table heights exceeding [`u32::MAX`] saturate.
+///
+/// # Example
+///
+/// ```
+/// # use triton_vm::cost_estimator::CostEstimator;
+/// # use triton_vm::prelude::*;
+/// let program = triton_program!(
+///     push 10 call countdown halt
+///     countdown:
+///         dup 0 push 0 eq skiz return
+///         addi -1 recurse
+/// );
+/// let estimate = CostEstimator::new(&program)
+///     .with_loop_bound(5, 11)
+///     .estimate()
+///     .unwrap();
+///
+/// let (_, profile) = VM::profile(program, [].into(), [].into()).unwrap();
+/// assert!(profile.total.processor <= estimate.table_heights.processor);
+/// assert!(estimate.padded_height.is_power_of_two());
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CostEstimator<'p> {
+    program: &'p Program,
+
+    /// The maximal number of iterations of loops, keyed by the address of the respective
+    /// subroutine.
+    loop_bounds: HashMap<u64, u32>,
+}
+
+/// The result of a [`CostEstimator`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct CostEstimate {
+    /// Upper bounds for the heights of the tables the [`VMTableHeights`] cover.
+    pub table_heights: VMTableHeights,
+
+    /// An upper bound for the [padded height](AlgebraicExecutionTrace::padded_height),
+    /// taking into account all tables.
+    pub padded_height: usize,
+}
+
+/// The state of one [`CostEstimator`] while the estimate is being computed.
+#[derive(Debug)]
+struct Estimation<'e> {
+    estimator: &'e CostEstimator<'e>,
+    subroutines: HashMap<u64, VMTableHeights>,
+    subroutines_in_progress: HashSet<u64>,
+}
+
+impl<'p> CostEstimator<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            loop_bounds: HashMap::new(),
+        }
+    }
+
+    /// Set the maximal number of times the loop starting at the given address is
+    /// executed. For a loop in the form of a subroutine, that's the number of
+    /// [`recurse`](Instruction::Recurse)s plus 1.
+    #[must_use]
+    pub fn with_loop_bound(mut self, subroutine: u64, max_iterations: u32) -> Self {
+        self.loop_bounds.insert(subroutine, max_iterations);
+        self
+    }
+
+    pub fn estimate(&self) -> Result<CostEstimate, CostEstimationError> {
+        let mut estimation = Estimation {
+            estimator: self,
+            subroutines: HashMap::new(),
+            subroutines_in_progress: HashSet::new(),
+        };
+        let execution = estimation.subroutine(0)?;
+
+        let padded_program_length = AlgebraicExecutionTrace::padded_program_length(self.program);
+        let num_program_hash_rows = padded_program_length / Tip5::RATE * PERMUTATION_TRACE_LENGTH;
+        let program_hashing = VMTableHeights {
+            hash: u32::try_from(num_program_hash_rows).unwrap_or(u32::MAX),
+            ..Default::default()
+        };
+        let table_heights = saturating_add(program_hashing, execution);
+
+        // every row of the Hash Table, except the last of each permutation, looks up
+        // at most this many limbs in the Cascade Table
+        let limbs_per_row = NUM_SPLIT_AND_LOOKUP * BFieldElement::BYTES / size_of::<u16>();
+        let cascade_table_height = (table_heights.hash as usize)
+            .saturating_mul(limbs_per_row)
+            .min(MAX_CASCADE_TABLE_HEIGHT);
+
+        let height = [
+            table_heights.processor as usize,
+            table_heights.op_stack as usize,
+            table_heights.ram as usize,
+            table_heights.hash as usize,
+            table_heights.u32 as usize,
+            padded_program_length,
+            cascade_table_height,
+            AlgebraicExecutionTrace::LOOKUP_TABLE_HEIGHT,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default();
+
+        Ok(CostEstimate {
+            table_heights,
+            padded_height: height.next_power_of_two(),
+        })
+    }
+}
+
+impl Estimation<'_> {
+    /// The estimate for executing the subroutine starting at the given address, including
+    /// all its iterations.
+    fn subroutine(&mut self, entry: u64) -> Result<VMTableHeights, CostEstimationError> {
+        if let Some(&heights) = self.subroutines.get(&entry) {
+            return Ok(heights);
+        }
+        if !self.subroutines_in_progress.insert(entry) {
+            return Err(CostEstimationError::RecursiveCall { subroutine: entry });
+        }
+
+        let program = self.estimator.program;
+        let instruction_at = |address: u64| program.instructions.get(address as usize).copied();
+
+        // Within one iteration, control only ever flows to higher addresses. Estimating
+        // the addresses from highest to lowest guarantees that all successors of an
+        // address have been estimated when that address is.
+        let reachable = Self::reachable_addresses(program, entry);
+        for address in &reachable {
+            if let Some(Instruction::Call(callee)) = instruction_at(*address) {
+                self.subroutine(callee.value())?;
+            }
+        }
+
+        // for every address: the estimate until the end of the iteration, and whether
+        // the iteration can end with recursion
+        let mut estimates = HashMap::<u64, (VMTableHeights, bool)>::new();
+        for &address in reachable.iter().rev() {
+            let instruction = instruction_at(address).unwrap();
+            let mut heights = instruction_cost(instruction);
+            let mut recurses = matches!(
+                instruction,
+                Instruction::Recurse | Instruction::RecurseOrReturn
+            );
+            if let Instruction::Call(callee) = instruction {
+                heights = saturating_add(heights, self.subroutines[&callee.value()]);
+            }
+
+            let successors = Self::successors(program, address);
+            let successor_estimates = successors.iter().filter_map(|s| estimates.get(s));
+            let mut worst_successor = VMTableHeights::default();
+            for &(successor_heights, successor_recurses) in successor_estimates {
+                worst_successor = component_wise_max(worst_successor, successor_heights);
+                recurses |= successor_recurses;
+            }
+            heights = saturating_add(heights, worst_successor);
+            estimates.insert(address, (heights, recurses));
+        }
+
+        let (iteration, is_loop) = estimates.get(&entry).copied().unwrap_or_default();
+        let heights = if is_loop {
+            let Some(&bound) = self.estimator.loop_bounds.get(&entry) else {
+                return Err(CostEstimationError::MissingLoopBound { subroutine: entry });
+            };
+            saturating_mul(iteration, bound)
+        } else {
+            iteration
+        };
+
+        self.subroutines_in_progress.remove(&entry);
+        self.subroutines.insert(entry, heights);
+        Ok(heights)
+    }
+
+    /// All addresses that can be reached from the given entry within one iteration of a
+    /// subroutine, _i.e._, without following `call`s, `return`s, or `recurse`s.
+    fn reachable_addresses(program: &Program, entry: u64) -> BTreeSet<u64> {
+        let mut reachable = BTreeSet::new();
+        let mut to_visit = vec![entry];
+        while let Some(address) = to_visit.pop() {
+            if address as usize >= program.len_bwords() || !reachable.insert(address) {
+                continue;
+            }
+            to_visit.extend(Self::successors(program, address));
+        }
+
+        reachable
+    }
+
+    fn successors(program: &Program, address: u64) -> Vec<u64> {
+        let instruction = program.instructions[address as usize];
+        let next = address + instruction.size() as u64;
+        match instruction {
+            Instruction::Skiz => {
+                let Some(skipped) = program.instructions.get(next as usize) else {
+                    return vec![next];
+                };
+                vec![next, next + skipped.size() as u64]
+            }
+            Instruction::Return
+            | Instruction::Recurse
+            | Instruction::RecurseOrReturn
+            | Instruction::Halt => vec![],
+            _ => vec![next],
+        }
+    }
+}
+
+/// The worst-case contribution of a single instruction to the table heights.
+fn instruction_cost(instruction: Instruction) -> VMTableHeights {
+    let permutation = PERMUTATION_TRACE_LENGTH as u32;
+    let ram = match instruction {
+        Instruction::ReadMem(n) | Instruction::WriteMem(n) => n.num_words(),
+        Instruction::SpongeAbsorbMem => Tip5::RATE,
+        Instruction::MerkleStepMem => Digest::LEN,
+        Instruction::XxDotStep => 2 * EXTENSION_DEGREE,
+        Instruction::XbDotStep => 1 + EXTENSION_DEGREE,
+        _ => 0,
+    };
+    let hash = match instruction {
+        Instruction::SpongeInit => 1,
+        Instruction::Hash
+        | Instruction::SpongeAbsorb
+        | Instruction::SpongeAbsorbMem
+        | Instruction::SpongeSqueeze
+        | Instruction::MerkleStep
+        | Instruction::MerkleStepMem => permutation,
+        _ => 0,
+    };
+    let num_u32_table_entries = match instruction {
+        Instruction::Split
+        | Instruction::Lt
+        | Instruction::And
+        | Instruction::Xor
+        | Instruction::Log2Floor
+        | Instruction::Pow
+        | Instruction::PopCount
+        | Instruction::MerkleStep
+        | Instruction::MerkleStepMem => 1,
+        Instruction::DivMod => 2,
+        _ => 0,
+    };
+
+    VMTableHeights {
+        processor: 1,
+        op_stack: instruction.op_stack_size_influence().unsigned_abs(),
+        ram: ram as u32,
+        hash,
+        u32: num_u32_table_entries * MAX_U32_TABLE_CONTRIBUTION,
+    }
+}
+
+fn saturating_add(lhs: VMTableHeights, rhs: VMTableHeights) -> VMTableHeights {
+    VMTableHeights {
+        processor: lhs.processor.saturating_add(rhs.processor),
+        op_stack: lhs.op_stack.saturating_add(rhs.op_stack),
+        ram: lhs.ram.saturating_add(rhs.ram),
+        hash: lhs.hash.saturating_add(rhs.hash),
+        u32: lhs.u32.saturating_add(rhs.u32),
+    }
+}
+
+fn saturating_mul(heights: VMTableHeights, factor: u32) -> VMTableHeights {
+    VMTableHeights {
+        processor: heights.processor.saturating_mul(factor),
+        op_stack: heights.op_stack.saturating_mul(factor),
+        ram: heights.ram.saturating_mul(factor),
+        hash: heights.hash.saturating_mul(factor),
+        u32: heights.u32.saturating_mul(factor),
+    }
+}
+
+fn component_wise_max(lhs: VMTableHeights, rhs: VMTableHeights) -> VMTableHeights {
+    VMTableHeights {
+        processor: lhs.processor.max(rhs.processor),
+        op_stack: lhs.op_stack.max(rhs.op_stack),
+        ram: lhs.ram.max(rhs.ram),
+        hash: lhs.hash.max(rhs.hash),
+        u32: lhs.u32.max(rhs.u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+
+    use crate::example_programs::FIBONACCI_SEQUENCE;
+    use crate::prelude::*;
+
+    use super::*;
+
+    fn assert_estimate_bounds_execution(
+        estimator: CostEstimator,
+        public_input: PublicInput,
+        non_determinism: NonDeterminism,
+    ) {
+        let_assert!(Ok(estimate) = estimator.estimate());
+        let program = estimator.program.clone();
+        let (_, profile) = VM::profile(
+            program.clone(),
+            public_input.clone(),
+            non_determinism.clone(),
+        )
+        .unwrap();
+        let (aet, _) = VM::trace_execution(program, public_input, non_determinism).unwrap();
+
+        let actual = profile.total;
+        let estimated = estimate.table_heights;
+        assert!(actual.processor <= estimated.processor);
+        assert!(actual.op_stack <= estimated.op_stack);
+        assert!(actual.ram <= estimated.ram);
+        assert!(actual.hash <= estimated.hash);
+        assert!(actual.u32 <= estimated.u32);
+        assert!(aet.padded_height() <= estimate.padded_height);
+    }
+
+    #[test]
+    fn estimate_for_straight_line_program_is_exact() {
+        let program = triton_program!(
+            push 1 push 2 add push 3 dup 0 write_mem 2 pop 1
+            sponge_init read_mem 1 pop 1 push 0 sponge_absorb_mem pop 1
+            push 1 push 2 push 3 push 4 push 5 hash halt
+        );
+        let_assert!(Ok(estimate) = CostEstimator::new(&program).estimate());
+        let (_, profile) = VM::profile(program.clone(), [].into(), [].into()).unwrap();
+        let (aet, _) = VM::trace_execution(program, [].into(), [].into()).unwrap();
+
+        let estimated = estimate.table_heights;
+        let actual = profile.total;
+        assert!(actual.processor == estimated.processor);
+        assert!(actual.op_stack == estimated.op_stack);
+        assert!(actual.ram == estimated.ram);
+        assert!(aet.height_of_table(TableId::Hash) == estimated.hash as usize);
+        assert!(aet.padded_height() <= estimate.padded_height);
+    }
+
+    #[test]
+    fn estimate_takes_worst_branch() {
+        let program = triton_program!(
+            push 0 skiz call expensive
+            push 1 skiz call cheap
+            halt
+            expensive: hash hash return
+            cheap: return
+        );
+        let_assert!(Ok(estimate) = CostEstimator::new(&program).estimate());
+        assert!(11 == estimate.table_heights.processor);
+        assert_estimate_bounds_execution(CostEstimator::new(&program), [].into(), [].into());
+    }
+
+    #[test]
+    fn estimate_bounds_execution_of_loops() {
+        let program = triton_program!(
+            read_io 1 call countdown halt
+            countdown:
+                dup 0 push 0 eq skiz return
+                push 3 push 5 lt pop 1
+                addi -1 recurse
+        );
+        let estimator = CostEstimator::new(&program).with_loop_bound(5, 11);
+        let public_input = PublicInput::new(bfe_vec![10]);
+        assert_estimate_bounds_execution(estimator, public_input, [].into());
+    }
+
+    #[test]
+    fn estimate_of_loop_scales_with_bound() {
+        let program = triton_program!(call body halt body: push 0 pop 1 recurse_or_return);
+        let estimate = |bound| {
+            let estimator = CostEstimator::new(&program).with_loop_bound(3, bound);
+            estimator.estimate().unwrap().table_heights
+        };
+        assert!(2 + 30 == estimate(10).processor);
+        assert!(2 + 300 == estimate(100).processor);
+        assert!(u32::MAX == estimate(u32::MAX).processor);
+    }
+
+    #[test]
+    fn estimate_bounds_execution_of_example_program() {
+        let program = FIBONACCI_SEQUENCE.clone();
+        let estimator = CostEstimator::new(&program);
+        let_assert!(Err(err) = estimator.estimate());
+        assert!(CostEstimationError::MissingLoopBound { subroutine: 16 } == err);
+
+        // `fib_loop` runs once per requested number
+        let estimator = estimator.with_loop_bound(16, 20);
+        let public_input = PublicInput::new(bfe_vec![20]);
+        assert_estimate_bounds_execution(estimator, public_input, [].into());
+    }
+
+    #[test]
+    fn loops_without_bound_cannot_be_estimated() {
+        let program = triton_program!(call body halt body: recurse);
+        let_assert!(Err(err) = CostEstimator::new(&program).estimate());
+        assert!(CostEstimationError::MissingLoopBound { subroutine: 3 } == err);
+    }
+
+    #[test]
+    fn recursive_calls_cannot_be_estimated() {
+        let program = triton_program!(call foo halt foo: call bar return bar: call foo return);
+        let_assert!(Err(err) = CostEstimator::new(&program).estimate());
+        let_assert!(CostEstimationError::RecursiveCall { .. } = err);
+    }
+}
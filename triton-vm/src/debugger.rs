@@ -0,0 +1,459 @@
+//! Drive [Triton VM](VMState) step by step, like a conventional debugger.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+
+use isa::instruction::Instruction;
+use twenty_first::prelude::*;
+
+use crate::error::InstructionError;
+use crate::vm::VMState;
+
+/// A predicate on the [state](VMState) of Triton VM, for example, on its op stack or
+/// its random-access memory. Used for
+/// [conditional breakpoints](Debugger::set_conditional_breakpoint).
+pub type Condition = Box<dyn Fn(&VMState) -> bool>;
+
+/// Executes a [`VMState`] under the control of the usual debugger commands: continue
+/// to the next breakpoint, step into, over, or out of a subroutine, and run to a given
+/// cycle count or address. Each command returns the [reason](StopReason) execution
+/// stopped.
+///
+/// Execution stops at the breakpoints set on the debugger, at the
+/// [breakpoints of the program](isa::program::Program::is_breakpoint), and whenever the
+/// value at a [watched](Self::watch) RAM address changes. A breakpoint stops execution
+/// right before the instruction at its address is executed. Except for
+/// [`run_to_cycle_count`](Self::run_to_cycle_count), every command executes at least one
+/// instruction, so continuing from a breakpoint does not stop at that same breakpoint
+/// again.
+///
+/// # Example
+///
+/// ```
+/// # use triton_vm::debugger::Debugger;
+/// # use triton_vm::debugger::StopReason;
+/// # use triton_vm::prelude::*;
+/// let program = triton_program!(
+///     push 3 call square write_io 1 halt
+///     square: dup 0 mul return
+/// );
+/// let state = VMState::new(program, [].into(), [].into());
+/// let mut debugger = Debugger::new(state);
+/// debugger.set_breakpoint(7);
+///
+/// assert_eq!(StopReason::Breakpoint(7), debugger.continue_to_breakpoint());
+/// assert_eq!(StopReason::StepCompleted, debugger.step_out());
+/// assert_eq!(bfe!(9), debugger.state().op_stack[0]);
+/// assert_eq!(StopReason::Halted, debugger.continue_to_breakpoint());
+/// ```
+pub struct Debugger {
+    state: VMState,
+    breakpoints: BTreeMap<u64, Option<Condition>>,
+    watchpoints: Vec<BFieldElement>,
+}
+
+/// The reason a [`Debugger`] stopped executing the program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StopReason {
+    /// The requested [step](Debugger::step_into) was completed.
+    StepCompleted,
+
+    /// A breakpoint at the given address was hit. The breakpoint was either
+    /// [set on the debugger](Debugger::set_breakpoint) and its condition, if any, holds,
+    /// or it is part of the [program](isa::program::Program::is_breakpoint).
+    Breakpoint(u64),
+
+    /// The value at a [watched](Debugger::watch) RAM address changed.
+    Watchpoint {
+        address: BFieldElement,
+        old_value: BFieldElement,
+        new_value: BFieldElement,
+    },
+
+    /// The [requested cycle count](Debugger::run_to_cycle_count) was reached.
+    CycleCountReached(u32),
+
+    /// The [requested address](Debugger::run_to_address) was reached.
+    AddressReached(u64),
+
+    /// The program executed instruction `halt`.
+    Halted,
+
+    /// Triton VM crashed. The state of the debugger is the state at the time of the
+    /// crash.
+    Crashed(InstructionError),
+}
+
+impl Debug for Debugger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let breakpoints = self.breakpoints.keys().collect::<Vec<_>>();
+        f.debug_struct("Debugger")
+            .field("state", &self.state)
+            .field("breakpoints", &breakpoints)
+            .field("watchpoints", &self.watchpoints)
+            .finish()
+    }
+}
+
+impl From<VMState> for Debugger {
+    fn from(state: VMState) -> Self {
+        Self::new(state)
+    }
+}
+
+impl Debugger {
+    pub fn new(state: VMState) -> Self {
+        Self {
+            state,
+            breakpoints: BTreeMap::new(),
+            watchpoints: vec![],
+        }
+    }
+
+    /// The current state of Triton VM.
+    pub fn state(&self) -> &VMState {
+        &self.state
+    }
+
+    /// Mutable access to the current state of Triton VM, for example, to patch the op
+    /// stack or RAM before continuing.
+    pub fn state_mut(&mut self) -> &mut VMState {
+        &mut self.state
+    }
+
+    pub fn into_state(self) -> VMState {
+        self.state
+    }
+
+    /// Stop execution before the instruction at the given address is executed.
+    /// Replaces any breakpoint previously set at that address.
+    pub fn set_breakpoint(&mut self, address: u64) {
+        self.breakpoints.insert(address, None);
+    }
+
+    /// Like [`set_breakpoint`](Self::set_breakpoint), but only stop if the given
+    /// condition holds when the instruction at the given address is about to be executed.
+    ///
+    /// ```
+    /// # use triton_vm::debugger::Debugger;
+    /// # use triton_vm::debugger::StopReason;
+    /// # use triton_vm::prelude::*;
+    /// let program = triton_program!(
+    ///     push 5 call countdown halt
+    ///     countdown: addi -1 dup 0 skiz recurse return
+    /// );
+    /// let mut debugger = Debugger::new(VMState::new(program, [].into(), [].into()));
+    /// debugger.set_conditional_breakpoint(5, |state| state.op_stack[0] == bfe!(2));
+    ///
+    /// assert_eq!(StopReason::Breakpoint(5), debugger.continue_to_breakpoint());
+    /// assert_eq!(bfe!(2), debugger.state().op_stack[0]);
+    /// ```
+    pub fn set_conditional_breakpoint(
+        &mut self,
+        address: u64,
+        condition: impl Fn(&VMState) -> bool + 'static,
+    ) {
+        self.breakpoints.insert(address, Some(Box::new(condition)));
+    }
+
+    /// Remove the breakpoint at the given address, if any. Returns whether there was a
+    /// breakpoint. Breakpoints that are part of the program cannot be removed.
+    pub fn remove_breakpoint(&mut self, address: u64) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    /// The addresses of all breakpoints set on the debugger, in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u64> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// Stop execution whenever an instruction changes the value at the given RAM address.
+    pub fn watch(&mut self, address: BFieldElement) {
+        if !self.watchpoints.contains(&address) {
+            self.watchpoints.push(address);
+        }
+    }
+
+    /// Stop watching the given RAM address. Returns whether the address was watched.
+    pub fn unwatch(&mut self, address: BFieldElement) -> bool {
+        let len_before = self.watchpoints.len();
+        self.watchpoints.retain(|&watched| watched != address);
+        self.watchpoints.len() != len_before
+    }
+
+    /// The watched RAM addresses, in the order they were first watched in.
+    pub fn watchpoints(&self) -> impl Iterator<Item = BFieldElement> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// Run until a breakpoint or watchpoint is hit, or until the program terminates.
+    pub fn continue_to_breakpoint(&mut self) -> StopReason {
+        self.run_until(|_| None)
+    }
+
+    /// Execute exactly one instruction. If it is a `call`, stop at the first
+    /// instruction of the called subroutine.
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(|_| Some(StopReason::StepCompleted))
+    }
+
+    /// Execute one instruction. If it is a `call`, run until the called subroutine
+    /// returns, unless a breakpoint or watchpoint is hit first.
+    pub fn step_over(&mut self) -> StopReason {
+        let is_call = matches!(self.state.current_instruction(), Ok(Instruction::Call(_)));
+        if !is_call {
+            return self.step_into();
+        }
+
+        let jump_stack_len = self.state.jump_stack.len();
+        self.run_until(|state| {
+            (state.jump_stack.len() <= jump_stack_len).then_some(StopReason::StepCompleted)
+        })
+    }
+
+    /// Run until the currently executing subroutine returns to its caller, unless a
+    /// breakpoint or watchpoint is hit first. If no subroutine is executing, this is the
+    /// same as [continuing](Self::continue_to_breakpoint).
+    pub fn step_out(&mut self) -> StopReason {
+        let Some(jump_stack_len) = self.state.jump_stack.len().checked_sub(1) else {
+            return self.continue_to_breakpoint();
+        };
+        self.run_until(|state| {
+            (state.jump_stack.len() <= jump_stack_len).then_some(StopReason::StepCompleted)
+        })
+    }
+
+    /// Run until the given number of cycles has been executed, unless a breakpoint or
+    /// watchpoint is hit first. If that cycle count has already been reached, nothing
+    /// is executed.
+    pub fn run_to_cycle_count(&mut self, cycle_count: u32) -> StopReason {
+        if self.state.cycle_count >= cycle_count {
+            return StopReason::CycleCountReached(self.state.cycle_count);
+        }
+        self.run_until(|state| {
+            (state.cycle_count >= cycle_count).then_some(StopReason::CycleCountReached(cycle_count))
+        })
+    }
+
+    /// Run until the instruction at the given address is about to be executed, unless a
+    /// breakpoint or watchpoint is hit first.
+    pub fn run_to_address(&mut self, address: u64) -> StopReason {
+        self.run_until(|state| {
+            let ip = u64::try_from(state.instruction_pointer).ok()?;
+            (ip == address).then_some(StopReason::AddressReached(address))
+        })
+    }
+
+    /// Step until the program terminates, a watchpoint is hit, the given function
+    /// returns a reason to stop, or a breakpoint is hit – in that order.
+    fn run_until(
+        &mut self,
+        mut should_stop: impl FnMut(&VMState) -> Option<StopReason>,
+    ) -> StopReason {
+        loop {
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+            if let Some(reason) = should_stop(&self.state) {
+                return reason;
+            }
+            if let Some(reason) = self.hit_breakpoint() {
+                return reason;
+            }
+        }
+    }
+
+    /// Execute one instruction. Returns a reason to stop if the program terminated or a
+    /// watched RAM address changed.
+    fn step(&mut self) -> Option<StopReason> {
+        if self.state.halting {
+            return Some(StopReason::Halted);
+        }
+
+        let watched_values = self.watched_values().collect::<Vec<_>>();
+        if let Err(err) = self.state.step() {
+            return Some(StopReason::Crashed(err));
+        }
+        if self.state.halting {
+            return Some(StopReason::Halted);
+        }
+
+        self.watched_values()
+            .zip(watched_values)
+            .find(|((_, new_value), (_, old_value))| new_value != old_value)
+            .map(
+                |((address, new_value), (_, old_value))| StopReason::Watchpoint {
+                    address,
+                    old_value,
+                    new_value,
+                },
+            )
+    }
+
+    fn watched_values(&self) -> impl Iterator<Item = (BFieldElement, BFieldElement)> + '_ {
+        self.watchpoints.iter().map(|&address| {
+            let value = self.state.ram.get(&address).copied();
+            (address, value.unwrap_or_else(|| bfe!(0)))
+        })
+    }
+
+    fn hit_breakpoint(&self) -> Option<StopReason> {
+        let address = u64::try_from(self.state.instruction_pointer).ok()?;
+        let is_hit = match self.breakpoints.get(&address) {
+            Some(Some(condition)) => condition(&self.state),
+            Some(None) => true,
+            None => false,
+        };
+        let is_hit = is_hit || self.state.program.is_breakpoint(address);
+
+        is_hit.then_some(StopReason::Breakpoint(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use isa::triton_program;
+
+    use super::*;
+
+    /// Calls a subroutine, which in turn calls another subroutine, and writes the
+    /// result to RAM address 42.
+    fn debugger_for_nested_calls() -> Debugger {
+        let program = triton_program!(
+            push 3 call quad    // 0, 2
+            push 42 write_mem 1 // 4, 6
+            pop 1 halt          // 8, 10
+            quad:               // 11
+                call double     // 11
+                call double     // 13
+                return          // 15
+            double:             // 16
+                dup 0 add       // 16, 18
+                return          // 19
+        );
+        Debugger::new(VMState::new(program, [].into(), [].into()))
+    }
+
+    #[test]
+    fn step_into_enters_called_subroutine() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.run_to_address(2);
+        assert!(StopReason::StepCompleted == debugger.step_into());
+        assert!(11 == debugger.state().instruction_pointer);
+        assert!(1 == debugger.state().jump_stack.len());
+    }
+
+    #[test]
+    fn step_over_executes_called_subroutine_entirely() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.run_to_address(2);
+        assert!(StopReason::StepCompleted == debugger.step_over());
+        assert!(4 == debugger.state().instruction_pointer);
+        assert!(bfe!(12) == debugger.state().op_stack[0]);
+    }
+
+    #[test]
+    fn step_over_stops_at_breakpoint_in_called_subroutine() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.set_breakpoint(16);
+        debugger.run_to_address(2);
+        assert!(StopReason::Breakpoint(16) == debugger.step_over());
+        assert!(2 == debugger.state().jump_stack.len());
+    }
+
+    #[test]
+    fn step_out_returns_to_matching_caller() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.run_to_address(16);
+        assert!(StopReason::StepCompleted == debugger.step_out());
+        assert!(13 == debugger.state().instruction_pointer);
+        assert!(StopReason::StepCompleted == debugger.step_out());
+        assert!(4 == debugger.state().instruction_pointer);
+    }
+
+    #[test]
+    fn step_out_of_main_runs_to_completion() {
+        let mut debugger = debugger_for_nested_calls();
+        assert!(StopReason::Halted == debugger.step_out());
+        assert!(StopReason::Halted == debugger.step_into());
+    }
+
+    #[test]
+    fn breakpoints_of_program_are_honored() {
+        let program = triton_program!(push 1 break pop 1 halt);
+        let mut debugger = Debugger::new(VMState::new(program, [].into(), [].into()));
+        assert!(StopReason::Breakpoint(2) == debugger.continue_to_breakpoint());
+        assert!(StopReason::Halted == debugger.continue_to_breakpoint());
+    }
+
+    #[test]
+    fn removed_breakpoint_is_not_hit() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.set_breakpoint(16);
+        assert!(debugger.remove_breakpoint(16));
+        assert!(!debugger.remove_breakpoint(16));
+        assert!(debugger.breakpoints().next().is_none());
+        assert!(StopReason::Halted == debugger.continue_to_breakpoint());
+    }
+
+    #[test]
+    fn conditional_breakpoint_can_depend_on_ram() {
+        let mut debugger = debugger_for_nested_calls();
+        let condition = |state: &VMState| state.ram.contains_key(&bfe!(42));
+        debugger.set_conditional_breakpoint(16, condition);
+        debugger.set_conditional_breakpoint(10, condition);
+        assert!(StopReason::Breakpoint(10) == debugger.continue_to_breakpoint());
+    }
+
+    #[test]
+    fn watchpoint_reports_old_and_new_value() {
+        let mut debugger = debugger_for_nested_calls();
+        debugger.watch(bfe!(42));
+        debugger.watch(bfe!(43));
+        assert!(debugger.unwatch(bfe!(43)));
+        assert!(vec![bfe!(42)] == debugger.watchpoints().collect::<Vec<_>>());
+
+        let_assert!(
+            StopReason::Watchpoint {
+                address,
+                old_value,
+                new_value
+            } = debugger.continue_to_breakpoint()
+        );
+        assert!(bfe!(42) == address);
+        assert!(bfe!(0) == old_value);
+        assert!(bfe!(12) == new_value);
+        assert!(8 == debugger.state().instruction_pointer);
+    }
+
+    #[test]
+    fn run_to_cycle_count_stops_after_that_many_cycles() {
+        let mut debugger = debugger_for_nested_calls();
+        assert!(StopReason::CycleCountReached(4) == debugger.run_to_cycle_count(4));
+        assert!(4 == debugger.state().cycle_count);
+        assert!(StopReason::CycleCountReached(4) == debugger.run_to_cycle_count(2));
+        assert!(4 == debugger.state().cycle_count);
+    }
+
+    #[test]
+    fn run_to_address_reaches_address_again() {
+        let mut debugger = debugger_for_nested_calls();
+        assert!(StopReason::AddressReached(16) == debugger.run_to_address(16));
+        assert!(StopReason::AddressReached(16) == debugger.run_to_address(16));
+        assert!(StopReason::Halted == debugger.run_to_address(16));
+    }
+
+    #[test]
+    fn crash_is_reported_with_reason() {
+        let program = triton_program!(push 0 assert halt);
+        let mut debugger = Debugger::new(VMState::new(program, [].into(), [].into()));
+        let_assert!(
+            StopReason::Crashed(InstructionError::AssertionFailed(_)) =
+                debugger.continue_to_breakpoint()
+        );
+    }
+}
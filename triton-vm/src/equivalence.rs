@@ -0,0 +1,984 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use isa::instruction::AnInstruction;
+use isa::instruction::LabelledInstruction;
+use isa::op_stack::NumberOfWords;
+use isa::op_stack::OpStackElement;
+use isa::program::Program;
+use itertools::Itertools;
+use num_traits::ConstOne;
+use num_traits::ConstZero;
+use num_traits::Zero;
+use rand::prelude::StdRng;
+use rand::random;
+use rand::Rng;
+use rand_core::SeedableRng;
+use twenty_first::math::x_field_element::EXTENSION_DEGREE;
+use twenty_first::prelude::*;
+
+use crate::error::EquivalenceError;
+use crate::vm::NonDeterminism;
+use crate::vm::PublicInput;
+use crate::vm::VM;
+
+/// The largest number of terms any intermediate polynomial may have. Beyond that, no
+/// attempt is made to prove equivalence symbolically.
+const MAX_NUM_TERMS: usize = 1 << 12;
+
+/// Checks whether two snippets of [Triton assembly][tasm] compute the same thing, for
+/// example, a hand-optimized snippet and the original.
+///
+/// Both snippets are executed symbolically. All elements of the initial op stack, all
+/// public input read by [`read_io`](AnInstruction::ReadIo), and all secret input read by
+/// [`divine`](AnInstruction::Divine) are treated as unknown field elements. Arithmetic is
+/// tracked exactly as multivariate polynomials in those unknowns; all other instructions,
+/// like [`invert`](AnInstruction::Invert), [`lt`](AnInstruction::Lt), or
+/// [`hash`](AnInstruction::Hash), are tracked as functions that are not further
+/// interpreted. Additionally, the conditions under which a snippet crashes, for example,
+/// failing [`assert`](AnInstruction::Assert)s or non-u32 arguments to u32 instructions,
+/// are recorded.
+///
+/// The snippets are [equivalent](Equivalence::Equivalent) if, for both, the final op
+/// stack, the public output, the amount of consumed public and secret input, and the
+/// crash conditions are identical. Otherwise, both
+/// snippets are run on random inputs, looking for a
+/// [counterexample](Equivalence::Counterexample). Failing to find one within the
+/// [configured number of trials](Self::with_num_trials), the result is
+/// [unknown](Equivalence::Unknown).
+///
+/// Only straight-line code is supported, _i.e._, snippets without control flow and
+/// without access to memory or the sponge state. Loops can be checked after unrolling
+/// them up to some bound.
+///
+/// # Example
+///
+/// ```
+/// # use triton_vm::equivalence::Equivalence;
+/// # use triton_vm::equivalence::EquivalenceChecker;
+/// # use triton_vm::prelude::*;
+/// let original = triton_asm!(dup 0 push 2 mul add);
+/// let optimized = triton_asm!(push 3 mul);
+/// let checker = EquivalenceChecker::default();
+/// assert_eq!(Equivalence::Equivalent, checker.check(&original, &optimized).unwrap());
+///
+/// let wrong = triton_asm!(push 2 mul);
+/// let result = checker.check(&original, &wrong).unwrap();
+/// assert!(matches!(result, Equivalence::Counterexample(_)));
+/// ```
+///
+/// [tasm]: https://triton-vm.org/spec/instructions.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EquivalenceChecker {
+    num_trials: usize,
+    randomness_seed: <StdRng as SeedableRng>::Seed,
+}
+
+/// The result of an [`EquivalenceChecker`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Equivalence {
+    /// The snippets behave identically on all inputs.
+    Equivalent,
+
+    /// The snippets behave differently on the contained inputs.
+    Counterexample(Box<Counterexample>),
+
+    /// Neither equivalence nor a counterexample could be established.
+    Unknown,
+}
+
+/// Inputs for which two snippets behave differently.
+///
+/// Each snippet is embedded into a [program](Self::programs) that first reads the
+/// snippets' initial op stack from public input, then executes the snippet, and finally
+/// writes the resulting op stack to public output, followed by any public and secret
+/// input the snippet did not consume. The public input starts with the initial op
+/// stack, deepest element first. Any input read by the snippets follows.
+/// Running the two programs on the public input and non-determinism results in
+/// different outputs, or in only one of them crashing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counterexample {
+    pub public_input: PublicInput,
+    pub non_determinism: NonDeterminism,
+    pub programs: [Program; 2],
+}
+
+/// An unknown field element.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+enum Variable {
+    /// The element at the given depth of the initial op stack.
+    Stack(usize),
+
+    /// The public input with the given index.
+    PublicInput(usize),
+
+    /// The secret input with the given index.
+    SecretInput(usize),
+
+    /// The uninterpreted result of applying a function.
+    Application(Box<Application>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct Application {
+    function: Function,
+    arguments: Vec<Polynomial>,
+}
+
+/// Functions that are not interpreted symbolically. Where a function has multiple
+/// results, the index selects one of them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+enum Function {
+    Invert,
+    IsZero,
+    SplitHi,
+    SplitLo,
+    Lt,
+    And,
+    Xor,
+    Log2Floor,
+    Pow,
+    Quotient,
+    Remainder,
+    PopCount,
+    XInvert(usize),
+    Hash(usize),
+}
+
+/// A product of [variables](Variable), each with a positive exponent, sorted by variable.
+type Monomial = Vec<(Variable, u32)>;
+
+/// A multivariate polynomial in canonical form, _i.e._, without terms with coefficient 0.
+/// Coefficients are stored as their canonical representation to allow ordering.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct Polynomial {
+    terms: BTreeMap<Monomial, u64>,
+}
+
+/// A condition that must hold, or a snippet crashes.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+enum Condition {
+    Zero(Polynomial),
+    NonZero(Polynomial),
+    ExtensionFieldNonZero([Polynomial; EXTENSION_DEGREE]),
+    U32(Polynomial),
+}
+
+/// Reasons why symbolic execution is not possible.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum SymbolicExecutionError {
+    Unsupported(AnInstruction<String>),
+    TooComplex,
+}
+
+type SymbolicResult<T> = Result<T, SymbolicExecutionError>;
+
+/// Everything that distinguishes two snippets: the final op stack, the public output, the
+/// number of consumed public and secret inputs, and the crash conditions.
+type ObservableBehavior<'s> = (
+    &'s [Polynomial],
+    &'s [Polynomial],
+    (usize, usize),
+    &'s BTreeSet<Condition>,
+);
+
+/// The symbolic state of a snippet's execution.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+struct SymbolicState {
+    /// The part of the op stack that was accessed or modified, bottom to top.
+    op_stack: Vec<Polynomial>,
+    num_initial_op_stack_elements: usize,
+    num_public_inputs: usize,
+    num_secret_inputs: usize,
+    public_output: Vec<Polynomial>,
+    conditions: BTreeSet<Condition>,
+}
+
+impl Default for EquivalenceChecker {
+    fn default() -> Self {
+        Self {
+            num_trials: 1000,
+            randomness_seed: random(),
+        }
+    }
+}
+
+impl EquivalenceChecker {
+    /// Set the number of random inputs to try when looking for a counterexample.
+    #[must_use]
+    pub fn with_num_trials(mut self, num_trials: usize) -> Self {
+        self.num_trials = num_trials;
+        self
+    }
+
+    /// Set the seed for generating random inputs, making the search for counterexamples
+    /// deterministic.
+    #[must_use]
+    pub fn with_randomness_seed(mut self, seed: <StdRng as SeedableRng>::Seed) -> Self {
+        self.randomness_seed = seed;
+        self
+    }
+
+    pub fn check(
+        &self,
+        snippet: &[LabelledInstruction],
+        other: &[LabelledInstruction],
+    ) -> Result<Equivalence, EquivalenceError> {
+        let (mut state, mut other_state) = match (execute(snippet), execute(other)) {
+            (Err(SymbolicExecutionError::Unsupported(instruction)), _)
+            | (_, Err(SymbolicExecutionError::Unsupported(instruction))) => {
+                return Err(EquivalenceError::UnsupportedInstruction(instruction));
+            }
+            (Ok(state), Ok(other_state)) => (state, other_state),
+            _ => {
+                // symbolic execution is too expensive; fall back to testing
+                let mut states = [snippet, other].map(Self::concrete_dimensions);
+                let [state, other_state] = &mut states;
+                return Ok(self.search_counterexample(snippet, other, state, other_state));
+            }
+        };
+
+        let num_initial_elements = state
+            .num_initial_op_stack_elements
+            .max(other_state.num_initial_op_stack_elements);
+        state.access_initial_op_stack_elements(num_initial_elements);
+        other_state.access_initial_op_stack_elements(num_initial_elements);
+        if state.observable_behavior() == other_state.observable_behavior() {
+            return Ok(Equivalence::Equivalent);
+        }
+
+        Ok(self.search_counterexample(snippet, other, &state, &other_state))
+    }
+
+    /// Without symbolic execution, the dimensions of the inputs and outputs can be
+    /// derived from the instructions alone.
+    fn concrete_dimensions(snippet: &[LabelledInstruction]) -> SymbolicState {
+        let mut state = SymbolicState::default();
+        let mut depth = 0_i32;
+        for instruction in instructions(snippet) {
+            let num_accessed = instruction_access_depth(instruction);
+            let num_missing = (num_accessed as i32 - depth).max(0);
+            state.num_initial_op_stack_elements += num_missing as usize;
+            depth += num_missing + instruction.op_stack_size_influence();
+            match instruction {
+                AnInstruction::ReadIo(n) => state.num_public_inputs += n.num_words(),
+                AnInstruction::Divine(n) => state.num_secret_inputs += n.num_words(),
+                _ => (),
+            }
+        }
+        state.op_stack = vec![Polynomial::default(); depth.max(0) as usize];
+        state
+    }
+
+    fn search_counterexample(
+        &self,
+        snippet: &[LabelledInstruction],
+        other: &[LabelledInstruction],
+        state: &SymbolicState,
+        other_state: &SymbolicState,
+    ) -> Equivalence {
+        let num_initial_elements = state
+            .num_initial_op_stack_elements
+            .max(other_state.num_initial_op_stack_elements);
+        // elements below those accessed by either snippet are identical
+        let num_final_elements = |s: &SymbolicState| {
+            s.op_stack.len() + num_initial_elements - s.num_initial_op_stack_elements
+        };
+        let num_final_elements = num_final_elements(state).max(num_final_elements(other_state));
+        let num_public_inputs = state.num_public_inputs.max(other_state.num_public_inputs);
+        let num_secret_inputs = state.num_secret_inputs.max(other_state.num_secret_inputs);
+        let programs = [(snippet, state), (other, other_state)].map(|(snippet, state)| {
+            let num_unread_inputs = (
+                num_public_inputs - state.num_public_inputs,
+                num_secret_inputs - state.num_secret_inputs,
+            );
+            harness(
+                snippet,
+                num_initial_elements,
+                num_final_elements,
+                num_unread_inputs,
+            )
+        });
+
+        let mut rng = StdRng::from_seed(self.randomness_seed);
+        for _ in 0..self.num_trials {
+            let num_public_tokens = num_initial_elements + num_public_inputs;
+            let public_input = (0..num_public_tokens).map(|_| sample(&mut rng)).collect();
+            let secret_input = (0..num_secret_inputs)
+                .map(|_| sample(&mut rng))
+                .collect_vec();
+            let public_input = PublicInput::new(public_input);
+            let non_determinism = NonDeterminism::new(secret_input);
+
+            let [output, other_output] = programs.clone().map(|program| {
+                VM::run(program, public_input.clone(), non_determinism.clone()).ok()
+            });
+            if output != other_output {
+                let counterexample = Counterexample {
+                    public_input,
+                    non_determinism,
+                    programs,
+                };
+                return Equivalence::Counterexample(Box::new(counterexample));
+            }
+        }
+
+        Equivalence::Unknown
+    }
+}
+
+/// Embed the snippet into a program that reads the given number of initial op stack
+/// elements from public input, and writes the given number of op stack elements to
+/// public output after the snippet's execution. Finally, the given number of unread
+/// public and secret inputs are copied to public output, such that consuming different
+/// amounts of input is observable.
+fn harness(
+    snippet: &[LabelledInstruction],
+    num_initial_elements: usize,
+    num_final_elements: usize,
+    (num_unread_public_inputs, num_unread_secret_inputs): (usize, usize),
+) -> Program {
+    let chunks = |num_elements: usize| {
+        let max_chunk_size = NumberOfWords::N5.num_words();
+        (0..num_elements)
+            .step_by(max_chunk_size)
+            .map(move |start| (num_elements - start).min(max_chunk_size))
+            .map(|n| NumberOfWords::try_from(n).unwrap())
+    };
+
+    let read_initial_elements = chunks(num_initial_elements).map(AnInstruction::ReadIo);
+    let write_final_elements = chunks(num_final_elements).map(AnInstruction::WriteIo);
+    let copy_unread_public_inputs = chunks(num_unread_public_inputs)
+        .flat_map(|n| [AnInstruction::ReadIo(n), AnInstruction::WriteIo(n)]);
+    let copy_unread_secret_inputs = chunks(num_unread_secret_inputs)
+        .flat_map(|n| [AnInstruction::Divine(n), AnInstruction::WriteIo(n)]);
+    let code = read_initial_elements
+        .map(LabelledInstruction::Instruction)
+        .chain(snippet.iter().cloned())
+        .chain(write_final_elements.map(LabelledInstruction::Instruction))
+        .chain(copy_unread_public_inputs.map(LabelledInstruction::Instruction))
+        .chain(copy_unread_secret_inputs.map(LabelledInstruction::Instruction))
+        .chain([LabelledInstruction::Instruction(AnInstruction::Halt)])
+        .collect_vec();
+
+    Program::new(&code)
+}
+
+/// Sample a field element, with a bias towards values that are likely to trigger
+/// special cases.
+fn sample(rng: &mut StdRng) -> BFieldElement {
+    match rng.gen_range(0..6) {
+        0 => bfe!(rng.gen_range(0..4)),
+        1 => -bfe!(rng.gen_range(1..4)),
+        2 => bfe!(rng.gen_range(0..1_u64 << 8)),
+        3 => bfe!(rng.gen::<u32>()),
+        4 => bfe!(u32::MAX - rng.gen_range(0..4)),
+        _ => rng.gen(),
+    }
+}
+
+fn instructions(
+    snippet: &[LabelledInstruction],
+) -> impl Iterator<Item = &AnInstruction<String>> + '_ {
+    snippet.iter().filter_map(|instruction| match instruction {
+        LabelledInstruction::Instruction(instruction) => Some(instruction),
+        _ => None,
+    })
+}
+
+/// The number of op stack elements an instruction accesses, _i.e._, the minimal depth
+/// of the op stack needed for executing the instruction without touching elements below.
+fn instruction_access_depth(instruction: &AnInstruction<String>) -> usize {
+    let op_stack_element = |st: &OpStackElement| usize::from(st) + 1;
+    match instruction {
+        AnInstruction::Pop(n) | AnInstruction::WriteIo(n) => n.num_words(),
+        AnInstruction::Pick(st) | AnInstruction::Dup(st) | AnInstruction::Swap(st) => {
+            op_stack_element(st)
+        }
+        AnInstruction::Place(st) => op_stack_element(st),
+        AnInstruction::Assert
+        | AnInstruction::AddI(_)
+        | AnInstruction::Invert
+        | AnInstruction::Split
+        | AnInstruction::Log2Floor
+        | AnInstruction::PopCount => 1,
+        AnInstruction::Add
+        | AnInstruction::Mul
+        | AnInstruction::Eq
+        | AnInstruction::Lt
+        | AnInstruction::And
+        | AnInstruction::Xor
+        | AnInstruction::Pow
+        | AnInstruction::DivMod => 2,
+        AnInstruction::XInvert => EXTENSION_DEGREE,
+        AnInstruction::XbMul => EXTENSION_DEGREE + 1,
+        AnInstruction::XxAdd | AnInstruction::XxMul => 2 * EXTENSION_DEGREE,
+        AnInstruction::AssertVector => 2 * Digest::LEN,
+        AnInstruction::Hash => tip5::RATE,
+        _ => 0,
+    }
+}
+
+/// Execute the snippet symbolically.
+fn execute(snippet: &[LabelledInstruction]) -> SymbolicResult<SymbolicState> {
+    let mut state = SymbolicState::default();
+    for instruction in instructions(snippet) {
+        state.step(instruction)?;
+    }
+
+    Ok(state)
+}
+
+impl SymbolicState {
+    fn step(&mut self, instruction: &AnInstruction<String>) -> SymbolicResult<()> {
+        match instruction {
+            AnInstruction::Pop(n) => {
+                for _ in 0..n.num_words() {
+                    self.pop();
+                }
+            }
+            AnInstruction::Push(element) => self.push(Polynomial::constant(*element)),
+            AnInstruction::Divine(n) => {
+                for _ in 0..n.num_words() {
+                    let variable = Variable::SecretInput(self.num_secret_inputs);
+                    self.num_secret_inputs += 1;
+                    self.push(Polynomial::variable(variable));
+                }
+            }
+            AnInstruction::Pick(st) => {
+                let index = self.index(*st);
+                let element = self.op_stack.remove(index);
+                self.push(element);
+            }
+            AnInstruction::Place(st) => {
+                let element = self.pop();
+                self.access(usize::from(st));
+                let index = self.op_stack.len() - usize::from(st);
+                self.op_stack.insert(index, element);
+            }
+            AnInstruction::Dup(st) => {
+                let index = self.index(*st);
+                let element = self.op_stack[index].clone();
+                self.push(element);
+            }
+            AnInstruction::Swap(st) => {
+                let index = self.index(*st);
+                let top = self.op_stack.len() - 1;
+                self.op_stack.swap(index, top);
+            }
+            AnInstruction::Nop => (),
+            AnInstruction::Assert => {
+                let element = self.pop();
+                let difference = element.add(&Polynomial::constant(-BFieldElement::ONE));
+                self.require(Condition::Zero(difference));
+            }
+            AnInstruction::AssertVector => {
+                self.access(2 * Digest::LEN);
+                for i in 0..Digest::LEN {
+                    let expected = &self.op_stack[self.index_of(i)];
+                    let actual = &self.op_stack[self.index_of(i + Digest::LEN)];
+                    let difference = expected.add(&actual.neg());
+                    self.require(Condition::Zero(difference));
+                }
+                for _ in 0..Digest::LEN {
+                    self.pop();
+                }
+            }
+            AnInstruction::Add => {
+                let (lhs, rhs) = (self.pop(), self.pop());
+                self.push(lhs.add(&rhs));
+            }
+            AnInstruction::AddI(summand) => {
+                let element = self.pop();
+                self.push(element.add(&Polynomial::constant(*summand)));
+            }
+            AnInstruction::Mul => {
+                let (lhs, rhs) = (self.pop(), self.pop());
+                self.push(lhs.mul(&rhs)?);
+            }
+            AnInstruction::Invert => {
+                let element = self.pop();
+                self.require(Condition::NonZero(element.clone()));
+                self.push(Function::Invert.apply(vec![element]));
+            }
+            AnInstruction::Eq => {
+                let (lhs, rhs) = (self.pop(), self.pop());
+                let difference = lhs.add(&rhs.neg()).monic();
+                self.push(Function::IsZero.apply(vec![difference]));
+            }
+            AnInstruction::Split => {
+                let element = self.pop();
+                self.push(Function::SplitHi.apply(vec![element.clone()]));
+                self.push(Function::SplitLo.apply(vec![element]));
+            }
+            AnInstruction::Lt => {
+                let (lhs, rhs) = (self.pop(), self.pop());
+                self.require(Condition::U32(lhs.clone()));
+                self.require(Condition::U32(rhs.clone()));
+                self.push(Function::Lt.apply(vec![lhs, rhs]));
+            }
+            AnInstruction::And | AnInstruction::Xor => {
+                let (lhs, rhs) = (self.pop(), self.pop());
+                self.require(Condition::U32(lhs.clone()));
+                self.require(Condition::U32(rhs.clone()));
+                let function = match instruction {
+                    AnInstruction::And => Function::And,
+                    _ => Function::Xor,
+                };
+
+                // both functions are commutative
+                let arguments = [lhs, rhs].into_iter().sorted().collect();
+                self.push(function.apply(arguments));
+            }
+            AnInstruction::Log2Floor => {
+                let element = self.pop();
+                self.require(Condition::U32(element.clone()));
+                self.require(Condition::NonZero(element.clone()));
+                self.push(Function::Log2Floor.apply(vec![element]));
+            }
+            AnInstruction::Pow => {
+                let (base, exponent) = (self.pop(), self.pop());
+                self.require(Condition::U32(exponent.clone()));
+                self.push(Function::Pow.apply(vec![base, exponent]));
+            }
+            AnInstruction::DivMod => {
+                let (numerator, denominator) = (self.pop(), self.pop());
+                self.require(Condition::U32(numerator.clone()));
+                self.require(Condition::U32(denominator.clone()));
+                self.require(Condition::NonZero(denominator.clone()));
+                let arguments = vec![numerator, denominator];
+                self.push(Function::Quotient.apply(arguments.clone()));
+                self.push(Function::Remainder.apply(arguments));
+            }
+            AnInstruction::PopCount => {
+                let element = self.pop();
+                self.require(Condition::U32(element.clone()));
+                self.push(Function::PopCount.apply(vec![element]));
+            }
+            AnInstruction::XxAdd => {
+                let (lhs, rhs) = (self.pop_extension(), self.pop_extension());
+                let sum = [0, 1, 2].map(|i| lhs[i].add(&rhs[i]));
+                self.push_extension(sum);
+            }
+            AnInstruction::XxMul => {
+                let (lhs, rhs) = (self.pop_extension(), self.pop_extension());
+                self.push_extension(extension_field_product(&lhs, &rhs)?);
+            }
+            AnInstruction::XInvert => {
+                let element = self.pop_extension();
+                self.require(Condition::ExtensionFieldNonZero(element.clone()));
+                let inverse = [0, 1, 2].map(|i| Function::XInvert(i).apply(element.to_vec()));
+                self.push_extension(inverse);
+            }
+            AnInstruction::XbMul => {
+                let (lhs, rhs) = (self.pop(), self.pop_extension());
+                let [c0, c1, c2] = &rhs;
+                self.push_extension([lhs.mul(c0)?, lhs.mul(c1)?, lhs.mul(c2)?]);
+            }
+            AnInstruction::ReadIo(n) => {
+                for _ in 0..n.num_words() {
+                    let variable = Variable::PublicInput(self.num_public_inputs);
+                    self.num_public_inputs += 1;
+                    self.push(Polynomial::variable(variable));
+                }
+            }
+            AnInstruction::WriteIo(n) => {
+                for _ in 0..n.num_words() {
+                    let element = self.pop();
+                    self.public_output.push(element);
+                }
+            }
+            AnInstruction::Hash => {
+                let arguments = (0..tip5::RATE).map(|_| self.pop()).collect_vec();
+                for i in (0..Digest::LEN).rev() {
+                    self.push(Function::Hash(i).apply(arguments.clone()));
+                }
+            }
+            _ => return Err(SymbolicExecutionError::Unsupported(instruction.clone())),
+        }
+
+        Ok(())
+    }
+
+    /// Make sure the given number of op stack elements are known, introducing variables
+    /// for elements of the initial op stack as needed.
+    fn access(&mut self, num_elements: usize) {
+        while self.op_stack.len() < num_elements {
+            let variable = Variable::Stack(self.num_initial_op_stack_elements);
+            self.num_initial_op_stack_elements += 1;
+            self.op_stack.insert(0, Polynomial::variable(variable));
+        }
+    }
+
+    /// Make sure the given number of elements of the initial op stack are known.
+    fn access_initial_op_stack_elements(&mut self, num_elements: usize) {
+        let num_missing = num_elements.saturating_sub(self.num_initial_op_stack_elements);
+        self.access(self.op_stack.len() + num_missing);
+    }
+
+    fn index(&mut self, st: OpStackElement) -> usize {
+        let st = usize::from(st);
+        self.access(st + 1);
+        self.index_of(st)
+    }
+
+    fn index_of(&self, st: usize) -> usize {
+        self.op_stack.len() - 1 - st
+    }
+
+    fn push(&mut self, element: Polynomial) {
+        self.op_stack.push(element);
+    }
+
+    fn pop(&mut self) -> Polynomial {
+        self.access(1);
+        self.op_stack.pop().unwrap()
+    }
+
+    fn push_extension(&mut self, element: [Polynomial; EXTENSION_DEGREE]) {
+        for coefficient in element.into_iter().rev() {
+            self.push(coefficient);
+        }
+    }
+
+    fn pop_extension(&mut self) -> [Polynomial; EXTENSION_DEGREE] {
+        [(); EXTENSION_DEGREE].map(|()| self.pop())
+    }
+
+    fn require(&mut self, condition: Condition) {
+        if let Some(condition) = condition.normalize() {
+            self.conditions.insert(condition);
+        }
+    }
+
+    fn observable_behavior(&self) -> ObservableBehavior<'_> {
+        let consumed_input = (self.num_public_inputs, self.num_secret_inputs);
+        (
+            &self.op_stack,
+            &self.public_output,
+            consumed_input,
+            &self.conditions,
+        )
+    }
+}
+
+/// The product of two elements of the extension field, given by their coefficients. The
+/// extension field's modulus is `x^3 - x + 1`.
+fn extension_field_product(
+    lhs: &[Polynomial; EXTENSION_DEGREE],
+    rhs: &[Polynomial; EXTENSION_DEGREE],
+) -> SymbolicResult<[Polynomial; EXTENSION_DEGREE]> {
+    let mut product = vec![Polynomial::default(); 2 * EXTENSION_DEGREE - 1];
+    for (i, j) in (0..EXTENSION_DEGREE).cartesian_product(0..EXTENSION_DEGREE) {
+        product[i + j] = product[i + j].add(&lhs[i].mul(&rhs[j])?);
+    }
+
+    // reduce using x^3 = x - 1 and x^4 = x^2 - x
+    let [c0, c1, c2, c3, c4] = &product[..] else {
+        unreachable!()
+    };
+    Ok([c0.add(&c3.neg()), c1.add(c3).add(&c4.neg()), c2.add(c4)])
+}
+
+impl Function {
+    /// Apply the function to the arguments. Evaluates the function if all arguments are
+    /// known constants for which the function is defined.
+    fn apply(self, arguments: Vec<Polynomial>) -> Polynomial {
+        let constants = arguments
+            .iter()
+            .map(Polynomial::as_constant)
+            .collect::<Option<Vec<_>>>();
+        if let Some(result) = constants.and_then(|c| self.evaluate(&c)) {
+            return Polynomial::constant(result);
+        }
+
+        let application = Application {
+            function: self,
+            arguments,
+        };
+        Polynomial::variable(Variable::Application(Box::new(application)))
+    }
+
+    fn evaluate(self, arguments: &[BFieldElement]) -> Option<BFieldElement> {
+        let as_u32 = |i: usize| u32::try_from(arguments[i]).ok();
+        let non_zero = |i: usize| (!arguments[i].is_zero()).then_some(arguments[i]);
+        let result = match self {
+            Self::Invert => non_zero(0)?.inverse(),
+            Self::IsZero => bfe!(u64::from(arguments[0].is_zero())),
+            Self::SplitHi => bfe!(arguments[0].value() >> 32),
+            Self::SplitLo => bfe!(arguments[0].value() & 0xffff_ffff),
+            Self::Lt => bfe!(u64::from(as_u32(0)? < as_u32(1)?)),
+            Self::And => bfe!(as_u32(0)? & as_u32(1)?),
+            Self::Xor => bfe!(as_u32(0)? ^ as_u32(1)?),
+            Self::Log2Floor => bfe!(as_u32(0)?.checked_ilog2()?),
+            Self::Pow => arguments[0].mod_pow(as_u32(1)?.into()),
+            Self::Quotient => bfe!(as_u32(0)?.checked_div(as_u32(1)?)?),
+            Self::Remainder => bfe!(as_u32(0)?.checked_rem(as_u32(1)?)?),
+            Self::PopCount => bfe!(as_u32(0)?.count_ones()),
+            Self::XInvert(i) => {
+                let element = xfe!([arguments[0], arguments[1], arguments[2]]);
+                (!element.is_zero()).then(|| element.inverse().coefficients[i])?
+            }
+            Self::Hash(i) => Tip5::hash_10(&arguments.try_into().ok()?)[i],
+        };
+
+        Some(result)
+    }
+}
+
+impl Condition {
+    /// A canonical representation of the condition, or `None` if it trivially holds.
+    fn normalize(self) -> Option<Self> {
+        let always_holds = match &self {
+            Self::Zero(p) => p.as_constant().is_some_and(|c| c.is_zero()),
+            Self::NonZero(p) => p.as_constant().is_some_and(|c| !c.is_zero()),
+            Self::ExtensionFieldNonZero(coefficients) => coefficients
+                .iter()
+                .any(|p| p.as_constant().is_some_and(|c| !c.is_zero())),
+            Self::U32(p) => p.as_constant().is_some_and(|c| u32::try_from(c).is_ok()),
+        };
+        if always_holds {
+            return None;
+        }
+
+        let normalized = match self {
+            Self::Zero(p) => Self::Zero(p.monic()),
+            Self::NonZero(p) => Self::NonZero(p.monic()),
+            condition => condition,
+        };
+        Some(normalized)
+    }
+}
+
+impl Polynomial {
+    fn constant(constant: BFieldElement) -> Self {
+        let mut terms = BTreeMap::new();
+        if !constant.is_zero() {
+            terms.insert(vec![], constant.value());
+        }
+        Self { terms }
+    }
+
+    fn variable(variable: Variable) -> Self {
+        let terms = [(vec![(variable, 1)], 1)].into();
+        Self { terms }
+    }
+
+    fn as_constant(&self) -> Option<BFieldElement> {
+        match self.terms.iter().at_most_one().ok()? {
+            None => Some(BFieldElement::ZERO),
+            Some((monomial, &coefficient)) => monomial.is_empty().then_some(bfe!(coefficient)),
+        }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let mut terms = self.terms.clone();
+        for (monomial, &coefficient) in &other.terms {
+            let sum = bfe!(*terms.get(monomial).unwrap_or(&0)) + bfe!(coefficient);
+            if sum.is_zero() {
+                terms.remove(monomial);
+            } else {
+                terms.insert(monomial.clone(), sum.value());
+            }
+        }
+        Self { terms }
+    }
+
+    fn neg(&self) -> Self {
+        self.scale(-BFieldElement::ONE)
+    }
+
+    fn scale(&self, factor: BFieldElement) -> Self {
+        if factor.is_zero() {
+            return Self::default();
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|(monomial, &coefficient)| {
+                let product = bfe!(coefficient) * factor;
+                (monomial.clone(), product.value())
+            })
+            .collect();
+        Self { terms }
+    }
+
+    fn mul(&self, other: &Self) -> SymbolicResult<Self> {
+        if self.terms.len() * other.terms.len() > MAX_NUM_TERMS {
+            return Err(SymbolicExecutionError::TooComplex);
+        }
+
+        let mut product = Self::default();
+        for (monomial, &coefficient) in &self.terms {
+            for (other_monomial, &other_coefficient) in &other.terms {
+                let coefficient = bfe!(coefficient) * bfe!(other_coefficient);
+                let monomial = Self::monomial_product(monomial, other_monomial);
+                let term = Self {
+                    terms: [(monomial, coefficient.value())].into(),
+                };
+                product = product.add(&term);
+            }
+        }
+
+        Ok(product)
+    }
+
+    fn monomial_product(lhs: &Monomial, rhs: &Monomial) -> Monomial {
+        lhs.iter()
+            .chain(rhs)
+            .cloned()
+            .sorted_by(|(v, _), (w, _)| v.cmp(w))
+            .coalesce(|(v, e), (w, f)| {
+                if v == w {
+                    Ok((v, e + f))
+                } else {
+                    Err(((v, e), (w, f)))
+                }
+            })
+            .collect()
+    }
+
+    /// The polynomial scaled such that the coefficient of the first term is 1. Scaling
+    /// does not change whether the polynomial evaluates to zero.
+    fn monic(self) -> Self {
+        let Some(&leading_coefficient) = self.terms.values().next() else {
+            return self;
+        };
+        self.scale(bfe!(leading_coefficient).inverse())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use assert2::let_assert;
+    use test_strategy::proptest;
+
+    use crate::prelude::*;
+
+    use super::*;
+
+    fn checker() -> EquivalenceChecker {
+        EquivalenceChecker::default().with_randomness_seed([0; 32])
+    }
+
+    fn assert_equivalent(snippet: &[LabelledInstruction], other: &[LabelledInstruction]) {
+        let_assert!(Ok(Equivalence::Equivalent) = checker().check(snippet, other));
+        let_assert!(Ok(Equivalence::Equivalent) = checker().check(other, snippet));
+    }
+
+    fn assert_counterexample(snippet: &[LabelledInstruction], other: &[LabelledInstruction]) {
+        let_assert!(
+            Ok(Equivalence::Counterexample(counterexample)) = checker().check(snippet, other)
+        );
+        let Counterexample {
+            public_input,
+            non_determinism,
+            programs: [program, other_program],
+        } = *counterexample;
+        let output = VM::run(program, public_input.clone(), non_determinism.clone()).ok();
+        let other_output = VM::run(other_program, public_input, non_determinism).ok();
+        assert!(output != other_output);
+    }
+
+    #[test]
+    fn snippet_is_equivalent_to_itself() {
+        let snippet = triton_asm!(divine 2 read_io 1 mul add invert dup 3 hash split lt);
+        assert_equivalent(&snippet, &snippet);
+    }
+
+    #[test]
+    fn arithmetic_is_normalized() {
+        assert_equivalent(&triton_asm!(swap 1 add), &triton_asm!(add));
+        assert_equivalent(&triton_asm!(dup 0 add), &triton_asm!(push 2 mul));
+        assert_equivalent(&triton_asm!(push 3 add push -3 add), &triton_asm!());
+        assert_equivalent(&triton_asm!(pick 2 pick 2 pick 2), &triton_asm!());
+        assert_equivalent(
+            &triton_asm!(dup 1 dup 1 add mul),
+            &triton_asm!(dup 0 pick 2 dup 0 place 3 add mul),
+        );
+        assert_equivalent(&triton_asm!(push 1 addi 4), &triton_asm!(push 5));
+    }
+
+    #[test]
+    fn extension_field_arithmetic_is_normalized() {
+        let square = triton_asm!(dup 2 dup 2 dup 2 xx_mul);
+        let square_via_multiplication_by_constant = triton_asm!(
+            dup 2 dup 2 dup 2 push 0 push 0 push 1 xx_mul xx_mul
+        );
+        assert_equivalent(&square, &square_via_multiplication_by_constant);
+
+        let scaled = triton_asm!(push 3 xb_mul);
+        let added = triton_asm!(dup 2 dup 2 dup 2 dup 2 dup 2 dup 2 xx_add xx_add);
+        assert_equivalent(&scaled, &added);
+    }
+
+    #[test]
+    fn functions_of_constants_are_evaluated() {
+        assert_equivalent(&triton_asm!(push 3 push 5 lt), &triton_asm!(push 0));
+        assert_equivalent(
+            &triton_asm!(push 7 push 22 div_mod),
+            &triton_asm!(push 3 push 1),
+        );
+        assert_equivalent(&triton_asm!(push 4 invert push 4 mul), &triton_asm!(push 1));
+        assert_equivalent(&triton_asm!(push 1 assert), &triton_asm!());
+    }
+
+    #[test]
+    fn equality_is_symmetric() {
+        assert_equivalent(&triton_asm!(eq), &triton_asm!(swap 1 eq));
+        assert_equivalent(&triton_asm!(push 0 eq), &triton_asm!(push -1 mul push 0 eq));
+    }
+
+    #[test]
+    fn different_results_give_counterexample() {
+        assert_counterexample(&triton_asm!(add), &triton_asm!(mul));
+        assert_counterexample(
+            &triton_asm!(read_io 1 divine 1 add),
+            &triton_asm!(read_io 1 divine 1 mul),
+        );
+        assert_counterexample(&triton_asm!(dup 0), &triton_asm!(push 0));
+        assert_counterexample(
+            &triton_asm!(push 1 write_io 1),
+            &triton_asm!(push 2 write_io 1),
+        );
+    }
+
+    #[test]
+    fn consuming_different_amounts_of_input_gives_counterexample() {
+        assert_counterexample(&triton_asm!(read_io 1 pop 1), &triton_asm!(push 0 pop 1));
+        assert_counterexample(&triton_asm!(divine 2 pop 2), &triton_asm!(divine 1 pop 1));
+        assert_counterexample(&triton_asm!(read_io 1), &triton_asm!(divine 1));
+    }
+
+    #[test]
+    fn different_crash_conditions_give_counterexample() {
+        assert_counterexample(&triton_asm!(invert pop 1), &triton_asm!(pop 1));
+        assert_counterexample(&triton_asm!(dup 0 lt), &triton_asm!(pop 1 push 0));
+        assert_counterexample(&triton_asm!(assert), &triton_asm!(pop 1));
+    }
+
+    #[test]
+    fn undecided_equivalence_is_unknown() {
+        // both compute the bitwise “not”, but only within the u32 range, and
+        // the functions are not interpreted
+        let snippet = triton_asm!(push 4294967295 xor);
+        let other = triton_asm!(dup 0 push 0 lt pop 1 push -1 mul addi 4294967295);
+        let_assert!(
+            Ok(Equivalence::Unknown) = checker().with_num_trials(10).check(&snippet, &other)
+        );
+    }
+
+    #[test]
+    fn control_flow_is_unsupported() {
+        let_assert!(Err(err) = checker().check(&triton_asm!(skiz nop), &triton_asm!(pop 1)));
+        let_assert!(EquivalenceError::UnsupportedInstruction(AnInstruction::Skiz) = err);
+    }
+
+    #[proptest(cases = 20)]
+    fn unrolled_loop_is_equivalent_to_closed_form(#[strategy(1_u32..40)] num_iterations: u32) {
+        let unrolled = vec![triton_asm!(dup 0 add); num_iterations as usize].concat();
+        let closed_form = triton_asm!(push {1_u64 << num_iterations} mul);
+        assert_equivalent(&unrolled, &closed_form);
+    }
+}
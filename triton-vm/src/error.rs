@@ -9,6 +9,8 @@ use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
 
+use isa::instruction::AnInstruction;
+use isa::program::SourceLocation;
 use thiserror::Error;
 use twenty_first::error::MerkleTreeError;
 use twenty_first::prelude::*;
@@ -33,11 +35,23 @@ impl VMError {
         let vm_state = Box::new(vm_state);
         Self { source, vm_state }
     }
+
+    /// The location in the source code of the instruction that caused the crash, if
+    /// known. See also [`Program::source_location_at`](isa::program::Program::source_location_at).
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        let instruction_pointer = self.vm_state.instruction_pointer as u64;
+        self.vm_state
+            .program
+            .source_location_at(instruction_pointer)
+    }
 }
 
 impl Display for VMError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "VM error: {}", self.source)?;
+        if let Some(location) = self.source_location() {
+            writeln!(f, "Source location: {location}")?;
+        }
         writeln!(f, "VM state:")?;
         writeln!(f, "{}", self.vm_state)
     }
@@ -209,6 +223,23 @@ pub enum VerificationError {
     FriValidationError(#[from] FriValidationError),
 }
 
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Error)]
+pub enum CostEstimationError {
+    #[error("no bound for the loop at address {subroutine}")]
+    MissingLoopBound { subroutine: u64 },
+
+    #[error("subroutine at address {subroutine} calls itself")]
+    RecursiveCall { subroutine: u64 },
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum EquivalenceError {
+    #[error("instruction `{0}` is not supported in equivalence checks")]
+    UnsupportedInstruction(AnInstruction<String>),
+}
+
 #[cfg(test)]
 mod tests {
     use assert2::assert;
@@ -270,6 +301,37 @@ mod tests {
         assert!(Some(42) == err.id);
     }
 
+    #[test]
+    fn assert_false_with_assertion_message() {
+        let program = triton_program! {
+            push 3 push 2 push 0
+            assert error_message "expected {st2} <= {st1}"
+            halt
+        };
+        let_assert!(Err(vm_error) = VM::run(program, [].into(), [].into()));
+        let_assert!(InstructionError::AssertionFailed(err) = &vm_error.source);
+        assert!(bfe!(1) == err.expected);
+        assert!(bfe!(0) == err.actual);
+        assert!(err.id.is_none());
+        assert!(Some("expected 3 <= 2") == err.message.as_deref());
+        assert!(vm_error
+            .to_string()
+            .contains("expected 3 <= 2: expected 1, got 0"));
+    }
+
+    #[test]
+    fn assert_vector_failure_renders_assertion_message() {
+        let program = triton_program! {
+            push 4 push 3 push 2 push  1 push 0
+            push 4 push 3 push 2 push 10 push 0
+            assert_vector error_message "element {st1} differs from {st6}"
+            halt
+        };
+        let_assert!(Err(err) = VM::run(program, [].into(), [].into()));
+        let_assert!(InstructionError::VectorAssertionFailed(1, err) = err.source);
+        assert!(Some("element 10 differs from 1") == err.message.as_deref());
+    }
+
     #[test]
     fn print_unequal_vec_assert_error() {
         let program = triton_program! {
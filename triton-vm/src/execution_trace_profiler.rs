@@ -8,6 +8,7 @@ use std::ops::Sub;
 
 use air::table::hash::PERMUTATION_TRACE_LENGTH;
 use arbitrary::Arbitrary;
+use isa::program::SourceLocation;
 use twenty_first::prelude::*;
 
 use crate::table::u32::U32TableEntry;
@@ -23,9 +24,14 @@ pub(crate) struct ExecutionTraceProfiler {
 
 /// A single line in a [profile report](ExecutionTraceProfile) for profiling
 /// [Triton](crate) programs.
+#[non_exhaustive]
 #[derive(Debug, Default, Clone, Eq, PartialEq, Hash, Arbitrary)]
 pub struct ProfileLine {
     pub label: String,
+
+    /// The location of the called subroutine in the source code, if known. See also
+    /// [`Program::source_location_at`](isa::program::Program::source_location_at).
+    pub source_location: Option<SourceLocation>,
     pub call_depth: usize,
 
     /// Table heights at the start of this span, _i.e._, right before the corresponding
@@ -67,12 +73,17 @@ impl ExecutionTraceProfiler {
         }
     }
 
-    pub fn enter_span(&mut self, label: impl Into<String>) {
+    pub fn enter_span(
+        &mut self,
+        label: impl Into<String>,
+        source_location: Option<SourceLocation>,
+    ) {
         let call_stack_len = self.call_stack.len();
         let line_number = self.profile.len();
 
         let profile_line = ProfileLine {
             label: label.into(),
+            source_location,
             call_depth: call_stack_len,
             table_heights_start: self.table_heights,
             table_heights_stop: VMTableHeights::default(),
@@ -177,7 +188,11 @@ impl Display for ProfileLine {
         let indentation = "  ".repeat(self.call_depth);
         let label = &self.label;
         let cycle_count = self.table_height_contributions().processor;
-        write!(f, "{indentation}{label}: {cycle_count}")
+        write!(f, "{indentation}{label}")?;
+        if let Some(location) = &self.source_location {
+            write!(f, " ({location})")?;
+        }
+        write!(f, ": {cycle_count}")
     }
 }
 
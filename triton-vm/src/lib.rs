@@ -158,8 +158,13 @@
 
 pub use air;
 pub use isa;
+pub use macros;
 pub use twenty_first;
 
+// Allows the code generated by the procedural macros to refer to this crate by name,
+// including from within this crate.
+extern crate self as triton_vm;
+
 use isa::program::Program;
 
 use crate::error::ProvingError;
@@ -170,6 +175,9 @@ pub mod arithmetic_domain;
 pub mod challenges;
 pub mod config;
 pub mod constraints;
+pub mod cost_estimator;
+pub mod debugger;
+pub mod equivalence;
 pub mod error;
 pub mod example_programs;
 pub mod execution_trace_profiler;
@@ -275,6 +283,8 @@ mod tests {
     use assert2::let_assert;
     use isa::instruction::LabelledInstruction;
     use isa::instruction::TypeHint;
+    use isa::op_stack::NumberOfWords;
+    use isa::op_stack::OpStackElement;
     use proptest::prelude::*;
     use proptest_arbitrary_interop::arb;
     use test_strategy::proptest;
@@ -329,6 +339,8 @@ mod tests {
         implements_auto_traits::<error::FriValidationError>();
         implements_auto_traits::<error::ProvingError>();
         implements_auto_traits::<error::VerificationError>();
+        implements_auto_traits::<error::CostEstimationError>();
+        implements_auto_traits::<error::EquivalenceError>();
 
         // table things
         implements_auto_traits::<challenges::Challenges>();
@@ -345,6 +357,11 @@ mod tests {
         implements_auto_traits::<aet::AlgebraicExecutionTrace>();
         implements_auto_traits::<aet::TableHeight>();
         implements_auto_traits::<arithmetic_domain::ArithmeticDomain>();
+        implements_auto_traits::<cost_estimator::CostEstimate>();
+        implements_auto_traits::<cost_estimator::CostEstimator>();
+        implements_auto_traits::<equivalence::Counterexample>();
+        implements_auto_traits::<equivalence::Equivalence>();
+        implements_auto_traits::<equivalence::EquivalenceChecker>();
         implements_auto_traits::<execution_trace_profiler::ExecutionTraceProfile>();
         implements_auto_traits::<execution_trace_profiler::ProfileLine>();
         implements_auto_traits::<execution_trace_profiler::VMTableHeights>();
@@ -415,6 +432,63 @@ mod tests {
         assert!(verdict);
     }
 
+    #[test]
+    fn checked_and_unchecked_macros_agree() {
+        let checked = macros::triton_program!(
+            const N = 3
+            data primes: u32 @ 10 = [2, 3, 5]
+            macro push_twice(x) push x push x endmacro
+            push_twice!(N) push -1 addi -5
+            hint sum = stack[0]
+            for N push primes read_mem 1 pop 2 endfor
+            call done
+            done: assert error_message "unreachable" halt
+        );
+        let unchecked = triton_program!(
+            const N = 3
+            data primes: u32 @ 10 = [2, 3, 5]
+            macro push_twice(x) push x push x endmacro
+            push_twice!(N) push -1 addi -5
+            hint sum = stack[0]
+            for N push primes read_mem 1 pop 2 endfor
+            call done
+            done: assert error_message "unreachable" halt
+        );
+        assert!(unchecked == checked);
+    }
+
+    #[test]
+    fn checked_macros_interpolate_typed_arguments() {
+        let element = bfe!(-7);
+        let num_words = NumberOfWords::N4;
+        let stack_element = OpStackElement::ST12;
+        let checked = macros::triton_asm!(
+            push {element} addi {1_u64} read_mem {num_words} swap {stack_element}
+        );
+        let unchecked = triton_asm!(push -7 addi 1 read_mem 4 swap 12);
+        assert!(unchecked == checked);
+    }
+
+    #[test]
+    fn lib_use_data_of_program() {
+        let program = triton_program!(
+            data factors: u32 @ 42 = [17, 13]
+            push factors read_mem 1
+            addi 2 read_mem 1 pop 1
+            mul
+            write_io 1 halt
+        );
+
+        let non_determinism = NonDeterminism::default().with_ram([(bfe!(42), bfe!(0))]);
+        let (stark, claim, proof) =
+            prove_program(program.clone(), PublicInput::default(), non_determinism).unwrap();
+        assert!(13 * 17 == claim.output[0].value());
+        assert!(program.hash() == claim.program_digest);
+
+        let verdict = verify(stark, &claim, &proof);
+        assert!(verdict);
+    }
+
     #[test]
     fn lib_prove_verify() {
         let program = triton_program!(push 1 assert halt);
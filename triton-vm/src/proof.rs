@@ -213,11 +213,11 @@ mod tests {
 
         insta::assert_snapshot!(
             Tip5::hash(&proof),
-            @"11952904396812311265,\
-              16054901534874249652,\
-              09773073309626813769,\
-              15649970038336331462,\
-              07145239594398485773",
+            @"06013918411579961380,\
+              13114861776797421473,\
+              11036751204028647818,\
+              07553849056231532275,\
+              10506765401137499391",
         );
     }
 }
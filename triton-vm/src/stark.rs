@@ -1645,6 +1645,7 @@ pub(crate) mod tests {
     use crate::vm::tests::test_program_for_merkle_step_mem_left_sibling;
     use crate::vm::tests::test_program_for_merkle_step_mem_right_sibling;
     use crate::vm::tests::test_program_for_merkle_step_right_sibling;
+    use crate::vm::tests::test_program_for_permutation_check;
     use crate::vm::tests::test_program_for_pop_count;
     use crate::vm::tests::test_program_for_pow;
     use crate::vm::tests::test_program_for_push_pop_dup_swap_nop;
@@ -1809,11 +1810,11 @@ pub(crate) mod tests {
 
         insta::assert_snapshot!(
             Tip5::hash(&proof),
-            @"01208218823199023966,\
-              07248217050651886224,\
-              07139898735589794621,\
-              11774487641367625949,\
-              06650915987150064355",
+            @"02506060641889615250,\
+              01293430555737506104,\
+              00311781668527821880,\
+              13491961054482292067,\
+              10000375746146693690",
         );
     }
 
@@ -2385,6 +2386,11 @@ pub(crate) mod tests {
         triton_constraints_evaluate_to_zero(test_program_for_read_io_write_io())
     }
 
+    #[test]
+    fn constraints_evaluate_to_zero_on_program_for_permutation_check() {
+        triton_constraints_evaluate_to_zero(test_program_for_permutation_check())
+    }
+
     #[test]
     fn constraints_evaluate_to_zero_on_property_based_test_program_for_assert_vector() {
         triton_constraints_evaluate_to_zero(property_based_test_program_for_assert_vector())
@@ -2711,6 +2717,14 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn prove_and_verify_program_for_permutation_check() {
+        prove_and_verify(
+            test_program_for_permutation_check(),
+            DEFAULT_LOG2_FRI_EXPANSION_FACTOR_FOR_TESTS,
+        );
+    }
+
     #[test]
     fn prove_and_verify_fibonacci_100() {
         let program_and_input =
@@ -3105,6 +3119,14 @@ pub(crate) mod tests {
             write_mem 5             // _ address
             pop 1                   // _
 
+            // permutation check
+            push 1 push 2 push 3    // _ 1 2 3
+            push 4 push 5           // _ [stuff; 5]
+            dup 4 dup 4 dup 4       // _ [stuff; 5] 1 2 3
+            dup 4 dup 4             // _ [stuff; 5] [stuff; 5]
+            push_perm pop_perm      // _
+            assert_perm             // _
+
             // control flow
             push 0 skiz nop         // _
             push 1 skiz nop         // _
@@ -2366,9 +2366,9 @@ mod tests {
         // evaluate polynomial in pseudorandom indeterminate
         let value = polynomial.evaluate(rng.gen::<XFieldElement>());
         let expected = xfe!([
-            3564660585377840245_u64,
-            8403714483000428991_u64,
-            2799326871924992342_u64,
+            17486714262337271469_u64,
+            9943837730924826145_u64,
+            8716985301352576484_u64,
         ]);
         assert_eq!(
             expected, value,
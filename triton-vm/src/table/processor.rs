@@ -122,6 +122,7 @@ impl TraceTable for ProcessorTable {
             auxiliary_column_sponge_eval_argument,
             auxiliary_column_for_u32_lookup_argument,
             auxiliary_column_for_clock_jump_difference_lookup_argument,
+            auxiliary_column_for_permutation_check,
         ];
         all_column_generators
             .into_par_iter()
@@ -588,6 +589,44 @@ fn auxiliary_column_for_clock_jump_difference_lookup_argument(
     Array2::from_shape_vec((main_table.nrows(), 1), auxiliary_column).unwrap()
 }
 
+/// The run-time permutation check manipulated by instructions `push_perm` and `pop_perm`.
+fn auxiliary_column_for_permutation_check(
+    main_table: ArrayView2<BFieldElement>,
+    challenges: &Challenges,
+) -> Array2<XFieldElement> {
+    let st0_through_st4 = [
+        MainColumn::ST0,
+        MainColumn::ST1,
+        MainColumn::ST2,
+        MainColumn::ST3,
+        MainColumn::ST4,
+    ];
+    let weights = &challenges[ChallengeId::StackWeight0..ChallengeId::StackWeight5];
+    let factor = |row: ArrayView1<BFieldElement>| {
+        let compressed_row = st0_through_st4
+            .map(|st| row[st.main_index()])
+            .into_iter()
+            .zip_eq(weights.iter())
+            .map(|(st, &weight)| weight * st)
+            .sum::<XFieldElement>();
+        challenges[ChallengeId::PermutationCheckIndeterminate] - compressed_row
+    };
+
+    let mut running_product = PermArg::default_initial();
+    let mut auxiliary_column = Vec::with_capacity(main_table.nrows());
+    auxiliary_column.push(running_product);
+    for previous_row in main_table.rows().into_iter().take(main_table.nrows() - 1) {
+        let previous_ci = previous_row[MainColumn::CI.main_index()];
+        if previous_ci == Instruction::PushPerm.opcode_b() {
+            running_product *= factor(previous_row);
+        } else if previous_ci == Instruction::PopPerm.opcode_b() {
+            running_product *= factor(previous_row).inverse();
+        }
+        auxiliary_column.push(running_product);
+    }
+    Array2::from_shape_vec((main_table.nrows(), 1), auxiliary_column).unwrap()
+}
+
 fn factor_for_op_stack_table_running_product(
     previous_row: ArrayView1<BFieldElement>,
     current_row: ArrayView1<BFieldElement>,
@@ -15,6 +15,7 @@ use isa::error::AssertionError;
 use isa::error::InstructionError;
 use isa::error::OpStackError;
 use isa::instruction::Instruction;
+use isa::instruction::TypeHint;
 use isa::op_stack::NumberOfWords;
 use isa::op_stack::OpStack;
 use isa::op_stack::OpStackElement;
@@ -44,6 +45,26 @@ use crate::table::u32::U32TableEntry;
 type VMResult<T> = Result<T, VMError>;
 type InstructionResult<T> = Result<T, InstructionError>;
 
+/// Dispatches an instruction to the method of [`VMState`] implementing it. See
+/// [`for_each_instruction`](isa::for_each_instruction).
+macro_rules! execute_instruction {
+    ([$vm:expr, $instruction:expr] $(
+        $variant:ident $(($arg:ident: $ty:ident))? {
+            name: $name:literal,
+            stack: $stack:expr,
+            u32: $u32:literal,
+            execute: $execute:ident,
+            constraints: $constraints:ident $(,)?
+        }
+    )*) => {
+        match $instruction {
+            $(Instruction::$variant $(($arg))? => {
+                $vm.$execute($($arg)?).into_instruction_result()
+            })*
+        }
+    };
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Arbitrary)]
 pub struct VM;
 
@@ -96,8 +117,22 @@ pub struct VMState {
     /// [squeeze]: Instruction::SpongeSqueeze
     pub sponge: Option<Tip5>,
 
+    /// The elements pushed using instruction [`PushPerm`][push] that have not yet been
+    /// popped using instruction [`PopPerm`][pop]. The order of the elements is
+    /// irrelevant; instruction [`AssertPerm`][assert] checks that no element remains.
+    ///
+    /// [push]: Instruction::PushPerm
+    /// [pop]: Instruction::PopPerm
+    /// [assert]: Instruction::AssertPerm
+    pub permutation_check: Vec<Digest>,
+
     /// Indicates whether the terminating instruction `halt` has been executed.
     pub halting: bool,
+
+    /// Whether to verify, before executing an instruction, that all op stack elements
+    /// [hinted](isa::instruction::TypeHint) to be of type `u32` are actually u32s.
+    /// Slows down execution; disabled by default.
+    pub check_u32_type_hints: bool,
 }
 
 /// A call from the main processor to one of the coprocessors, including the trace for that
@@ -120,6 +155,24 @@ pub enum CoProcessorCall {
     Ram(RamTableCall),
 }
 
+/// Unifies the results of the methods implementing instructions, some of which cannot
+/// fail.
+trait IntoInstructionResult {
+    fn into_instruction_result(self) -> InstructionResult<Vec<CoProcessorCall>>;
+}
+
+impl IntoInstructionResult for Vec<CoProcessorCall> {
+    fn into_instruction_result(self) -> InstructionResult<Vec<CoProcessorCall>> {
+        Ok(self)
+    }
+}
+
+impl IntoInstructionResult for InstructionResult<Vec<CoProcessorCall>> {
+    fn into_instruction_result(self) -> InstructionResult<Vec<CoProcessorCall>> {
+        self
+    }
+}
+
 impl VM {
     /// Run Triton VM on the [`Program`] with the given public input and non-determinism.
     /// If an error is encountered, the returned [`VMError`] contains the [`VMState`] at the point
@@ -212,7 +265,8 @@ impl VM {
         while !state.halting {
             if let Ok(Instruction::Call(address)) = state.current_instruction() {
                 let label = program.label_for_address(address.value());
-                profiler.enter_span(label);
+                let source_location = program.source_location_at(address.value());
+                profiler.enter_span(label, source_location);
             }
 
             match state.step() {
@@ -232,6 +286,9 @@ impl VM {
 
 impl VMState {
     /// Create initial `VMState` for a given `program`.
+    ///
+    /// The program's own [data](Program::initial_ram) is not yet in RAM. It is written
+    /// by the program's [data prologue](Program::len_data_prologue).
     pub fn new(
         program: Program,
         public_input: PublicInput,
@@ -252,7 +309,9 @@ impl VMState {
             cycle_count: 0,
             instruction_pointer: 0,
             sponge: None,
+            permutation_check: vec![],
             halting: false,
+            check_u32_type_hints: false,
         }
     }
 
@@ -364,56 +423,13 @@ impl VMState {
         if self.op_stack.would_be_too_shallow(op_stack_delta) {
             return Err(InstructionError::OpStackError(OpStackError::TooShallow));
         }
+        if self.check_u32_type_hints {
+            self.ensure_u32_type_hints_hold()?;
+        }
 
         self.start_recording_op_stack_calls();
-        let mut co_processor_calls = match current_instruction {
-            Instruction::Pop(n) => self.pop(n)?,
-            Instruction::Push(field_element) => self.push(field_element),
-            Instruction::Divine(n) => self.divine(n)?,
-            Instruction::Pick(stack_element) => self.pick(stack_element),
-            Instruction::Place(stack_element) => self.place(stack_element)?,
-            Instruction::Dup(stack_element) => self.dup(stack_element),
-            Instruction::Swap(stack_element) => self.swap(stack_element),
-            Instruction::Halt => self.halt(),
-            Instruction::Nop => self.nop(),
-            Instruction::Skiz => self.skiz()?,
-            Instruction::Call(address) => self.call(address),
-            Instruction::Return => self.return_from_call()?,
-            Instruction::Recurse => self.recurse()?,
-            Instruction::RecurseOrReturn => self.recurse_or_return()?,
-            Instruction::Assert => self.assert()?,
-            Instruction::ReadMem(n) => self.read_mem(n)?,
-            Instruction::WriteMem(n) => self.write_mem(n)?,
-            Instruction::Hash => self.hash()?,
-            Instruction::SpongeInit => self.sponge_init(),
-            Instruction::SpongeAbsorb => self.sponge_absorb()?,
-            Instruction::SpongeAbsorbMem => self.sponge_absorb_mem()?,
-            Instruction::SpongeSqueeze => self.sponge_squeeze()?,
-            Instruction::AssertVector => self.assert_vector()?,
-            Instruction::Add => self.add()?,
-            Instruction::AddI(field_element) => self.addi(field_element),
-            Instruction::Mul => self.mul()?,
-            Instruction::Invert => self.invert()?,
-            Instruction::Eq => self.eq()?,
-            Instruction::Split => self.split()?,
-            Instruction::Lt => self.lt()?,
-            Instruction::And => self.and()?,
-            Instruction::Xor => self.xor()?,
-            Instruction::Log2Floor => self.log_2_floor()?,
-            Instruction::Pow => self.pow()?,
-            Instruction::DivMod => self.div_mod()?,
-            Instruction::PopCount => self.pop_count()?,
-            Instruction::XxAdd => self.xx_add()?,
-            Instruction::XxMul => self.xx_mul()?,
-            Instruction::XInvert => self.x_invert()?,
-            Instruction::XbMul => self.xb_mul()?,
-            Instruction::WriteIo(n) => self.write_io(n)?,
-            Instruction::ReadIo(n) => self.read_io(n)?,
-            Instruction::MerkleStep => self.merkle_step_non_determinism()?,
-            Instruction::MerkleStepMem => self.merkle_step_mem()?,
-            Instruction::XxDotStep => self.xx_dot_step()?,
-            Instruction::XbDotStep => self.xb_dot_step()?,
-        };
+        let mut co_processor_calls =
+            isa::for_each_instruction!(execute_instruction(self, current_instruction))?;
         let op_stack_calls = self.stop_recording_op_stack_calls();
         co_processor_calls.extend(op_stack_calls);
 
@@ -581,10 +597,11 @@ impl VMState {
         Ok(vec![])
     }
 
-    fn halt(&mut self) -> Vec<CoProcessorCall> {
+    fn halt(&mut self) -> InstructionResult<Vec<CoProcessorCall>> {
+        self.assert_permutation_check_is_empty()?;
         self.halting = true;
         self.instruction_pointer += 1;
-        vec![]
+        Ok(vec![])
     }
 
     fn read_mem(&mut self, n: NumberOfWords) -> InstructionResult<Vec<CoProcessorCall>> {
@@ -1097,6 +1114,36 @@ impl VMState {
         Ok(ram_calls)
     }
 
+    fn push_perm(&mut self) -> InstructionResult<Vec<CoProcessorCall>> {
+        let element = Digest::new(self.op_stack.pop_multiple()?);
+        self.permutation_check.push(element);
+        self.instruction_pointer += 1;
+        Ok(vec![])
+    }
+
+    fn pop_perm(&mut self) -> InstructionResult<Vec<CoProcessorCall>> {
+        let element = Digest::new(self.op_stack.pop_multiple()?);
+        let Some(index) = self.permutation_check.iter().position(|&e| e == element) else {
+            return Err(InstructionError::PermutationCheckElementNotFound(element));
+        };
+        self.permutation_check.swap_remove(index);
+        self.instruction_pointer += 1;
+        Ok(vec![])
+    }
+
+    fn assert_perm(&mut self) -> InstructionResult<Vec<CoProcessorCall>> {
+        self.assert_permutation_check_is_empty()?;
+        self.instruction_pointer += 1;
+        Ok(vec![])
+    }
+
+    fn assert_permutation_check_is_empty(&self) -> InstructionResult<()> {
+        match self.permutation_check.len() {
+            0 => Ok(()),
+            n => Err(InstructionError::PermutationCheckFailed(n)),
+        }
+    }
+
     pub fn to_processor_row(&self) -> Array1<BFieldElement> {
         use isa::instruction::InstructionBit;
         use ProcessorMainColumn as Col;
@@ -1188,6 +1235,20 @@ impl VMState {
         maybe_destination.unwrap_or_else(BFieldElement::zero)
     }
 
+    fn ensure_u32_type_hints_hold(&self) -> InstructionResult<()> {
+        let type_hints = self.program.type_hints_at(self.instruction_pointer as u64);
+        let is_u32_hint = |hint: &TypeHint| hint.type_name.as_deref() == Some("u32");
+        for hint in type_hints.into_iter().filter(is_u32_hint) {
+            let stack = self.op_stack.stack.iter().rev();
+            let mut hinted_elements = stack.skip(hint.starting_index).take(hint.length);
+            if let Some(&element) = hinted_elements.find(|&&e| u32::try_from(e).is_err()) {
+                return Err(InstructionError::ViolatedU32TypeHint(hint, element));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn current_instruction(&self) -> InstructionResult<Instruction> {
         let instructions = &self.program.instructions;
         let maybe_current_instruction = instructions.get(self.instruction_pointer).copied();
@@ -1247,7 +1308,7 @@ impl VMState {
 
         let error = AssertionError::new(expected, actual);
         if let Some(context) = self.program.assertion_context_at(current_address) {
-            error.with_context(context)
+            error.with_context(context.interpolate(&self.op_stack))
         } else {
             error
         }
@@ -1448,6 +1509,9 @@ impl PublicInput {
 /// All sources of non-determinism for a program. This includes elements that
 /// can be read using instruction `divine`, digests that can be read using
 /// instruction `merkle_step`, and an initial state of random-access memory.
+///
+/// Where the initial state of random-access memory conflicts with the program's own
+/// [data](Program::initial_ram), the program's data prologue overwrites it.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, Arbitrary)]
 pub struct NonDeterminism {
     pub individual_tokens: Vec<BFieldElement>,
@@ -1509,14 +1573,18 @@ pub(crate) mod tests {
     use air::table::TableId;
     use assert2::assert;
     use assert2::let_assert;
+    use isa::control_flow::ControlFlowGraph;
+    use isa::disassembler::Disassembly;
     use isa::instruction::AnInstruction;
     use isa::instruction::LabelledInstruction;
     use isa::instruction::ALL_INSTRUCTIONS;
     use isa::op_stack::NUM_OP_STACK_REGISTERS;
     use isa::program::Program;
+    use isa::stack_depth::StackDepthAnalysis;
     use isa::triton_asm;
     use isa::triton_instr;
     use isa::triton_program;
+    use isa::type_check::TypeCheck;
     use itertools::izip;
     use proptest::collection::vec;
     use proptest::prelude::*;
@@ -1574,9 +1642,19 @@ pub(crate) mod tests {
             let stack_size = NUM_OP_STACK_REGISTERS;
             (program, stack_size)
         } else {
+            // the permutation check must be empty when halting
+            let push_ones = triton_asm![push 1; 5];
+            let (prelude, epilogue) = match instruction {
+                Instruction::PushPerm => (vec![], triton_asm!({&push_ones} pop_perm)),
+                Instruction::PopPerm => (triton_asm!({&push_ones} push_perm), vec![]),
+                _ => (vec![], vec![]),
+            };
+
             let num_push_instructions = 10;
             let push_instructions = triton_asm![push 1; num_push_instructions];
-            let program = triton_program!(sponge_init {&push_instructions} {instruction} nop halt);
+            let program = triton_program!(
+                sponge_init {&prelude} {&push_instructions} {instruction} {&epilogue} nop halt
+            );
 
             let stack_size_when_reaching_test_instruction =
                 NUM_OP_STACK_REGISTERS + num_push_instructions;
@@ -1669,6 +1747,281 @@ pub(crate) mod tests {
         assert!(bfe!(14) == stdout[0]);
     }
 
+    fn all_example_programs() -> [&'static Program; 7] {
+        use crate::example_programs as examples;
+
+        [
+            &examples::FIBONACCI_SEQUENCE,
+            &examples::GREATEST_COMMON_DIVISOR,
+            &examples::PROGRAM_WITH_MANY_U32_INSTRUCTIONS,
+            &examples::VERIFY_SUDOKU,
+            &examples::CALCULATE_NEW_MMR_PEAKS_FROM_APPEND_WITH_SAFE_LISTS,
+            &examples::MERKLE_TREE_AUTHENTICATION_PATH_VERIFY,
+            &examples::MERKLE_TREE_UPDATE,
+        ]
+    }
+
+    #[test]
+    fn example_programs_have_consistent_op_stack_depths() {
+        for program in all_example_programs() {
+            let analysis = StackDepthAnalysis::new(program);
+            assert!(let Ok(()) = analysis.ensure_consistency());
+        }
+    }
+
+    #[test]
+    fn control_flow_graphs_of_example_programs_cover_all_instructions() {
+        for program in all_example_programs() {
+            let cfg = ControlFlowGraph::new(program);
+            let covered_instructions = cfg.blocks().flat_map(|block| &block.instructions);
+            let num_covered_words = covered_instructions.map(|i| i.size()).sum::<usize>();
+            assert!(program.len_bwords() == num_covered_words);
+
+            // every called subroutine should have a label
+            for subroutine in cfg.subroutines().filter(|s| s.entry != 0) {
+                assert!(!subroutine.label.starts_with("address_"));
+            }
+        }
+    }
+
+    /// Straight-line code of which large parts can be rewritten by the peephole optimizer.
+    /// The op stack never gets shallower than its initial depth.
+    fn peephole_candidate_code(
+        snippets: Vec<(usize, OpStackElement, BFieldElement)>,
+    ) -> Vec<LabelledInstruction> {
+        let instruction = |instruction| vec![LabelledInstruction::Instruction(instruction)];
+
+        // overwrite the program digest, which changes during optimization
+        let mut code = vec![];
+        for i in 11..OpStackElement::COUNT {
+            code.extend(triton_asm!(pick {i} push 0 swap 1 pop 1 place {i}));
+        }
+
+        let mut num_extra_words = 0;
+        for (snippet, st, c) in snippets {
+            let (snippet, stack_size_influence) = match snippet {
+                0 => (instruction(AnInstruction::Push(c)), 1),
+                1 => (instruction(AnInstruction::Dup(st)), 1),
+                2 => (triton_asm!(push 0 add), 0),
+                3 => (triton_asm!(push 1 mul), 0),
+                4 => (triton_asm!(swap {st} swap {st}), 0),
+                5 => (triton_asm!(pick {st} place {st}), 0),
+                6 => (triton_asm!(place {st} pick {st}), 0),
+                7 => (triton_asm!(dup {st} pop 1), 0),
+                8 => (triton_asm!(push {c} push {c} add), 1),
+                9 => (triton_asm!(push {c} push {c} mul), 1),
+                10 => (instruction(AnInstruction::Swap(st)), 0),
+                11 => (instruction(AnInstruction::Pick(st)), 0),
+                12 => (instruction(AnInstruction::Place(st)), 0),
+                13 => (triton_asm!(pick {st} pick {st} pick {st}), 0),
+                14 if num_extra_words >= 1 => (triton_asm!(add), -1),
+                15 if num_extra_words >= 1 => (triton_asm!(mul), -1),
+                _ if num_extra_words >= 2 => (triton_asm!(pop 1 pop 1), -2),
+                _ => (triton_asm!(push {c} pop 1), 0),
+            };
+            code.extend(snippet);
+            num_extra_words += stack_size_influence;
+        }
+
+        // write the entire accessible op stack to public output
+        code.extend(triton_asm![dup 15; 16]);
+        code.extend(triton_asm!(write_io 5 write_io 5 write_io 5 write_io 1 halt));
+        code
+    }
+
+    #[proptest]
+    fn peephole_optimization_preserves_semantics(
+        #[strategy(vec((0_usize..18, arb(), arb()), 0..100))] snippets: Vec<(
+            usize,
+            OpStackElement,
+            BFieldElement,
+        )>,
+    ) {
+        let code = peephole_candidate_code(snippets);
+        let optimization = isa::peephole::optimize(&code);
+        let program = Program::new(&code);
+        let optimized_program = Program::new(&optimization.instructions);
+
+        let output = VM::run(program.clone(), [].into(), [].into())?;
+        let optimized_output = VM::run(optimized_program.clone(), [].into(), [].into())?;
+        prop_assert_eq!(output, optimized_output);
+
+        let mut vm_state = VMState::new(program, [].into(), [].into());
+        let mut optimized_vm_state = VMState::new(optimized_program, [].into(), [].into());
+        vm_state.run()?;
+        optimized_vm_state.run()?;
+        prop_assert_eq!(vm_state.op_stack.stack, optimized_vm_state.op_stack.stack);
+
+        let saved_cycles = vm_state.cycle_count - optimized_vm_state.cycle_count;
+        prop_assert_eq!(optimization.saved_cycles, saved_cycles as usize);
+    }
+
+    #[test]
+    fn peephole_optimization_of_example_programs_preserves_output() {
+        let programs_and_inputs = [
+            test_program_for_push_pop_dup_swap_nop(),
+            test_program_for_call_recurse_return(),
+            test_program_for_add_mul_invert(),
+            test_program_for_pow(),
+            test_program_for_sponge_instructions(),
+            program_executing_every_instruction(),
+        ];
+        for program_and_input in programs_and_inputs {
+            let ProgramAndInput {
+                program,
+                public_input,
+                non_determinism,
+            } = program_and_input;
+            let optimization = isa::peephole::optimize(&program.labelled_instructions());
+            let optimized_program = Program::new(&optimization.instructions);
+
+            let output = VM::run(program, public_input.clone(), non_determinism.clone());
+            let optimized_output = VM::run(optimized_program, public_input, non_determinism);
+            assert!(output.unwrap() == optimized_output.unwrap());
+        }
+    }
+
+    #[test]
+    fn dead_code_elimination_of_example_programs_preserves_output() {
+        let programs_and_inputs = [
+            test_program_for_call_recurse_return(),
+            test_program_for_recurse_or_return(),
+            test_program_for_halt(),
+            test_program_for_pow(),
+            program_executing_every_instruction(),
+        ];
+        for program_and_input in programs_and_inputs {
+            let ProgramAndInput {
+                program,
+                public_input,
+                non_determinism,
+            } = program_and_input;
+            let mut code = program.labelled_instructions();
+            code.extend(triton_asm!(unused_library_function: push 1 push 2 add return));
+            let program = Program::new(&code);
+
+            let elimination = isa::dead_code::eliminate_dead_code(&program);
+            assert!(elimination.num_removed_words(&program) >= 6);
+
+            let output = VM::run(program, public_input.clone(), non_determinism.clone());
+            let eliminated_output = VM::run(elimination.program, public_input, non_determinism);
+            assert!(output.unwrap() == eliminated_output.unwrap());
+        }
+    }
+
+    #[test]
+    fn violated_u32_type_hint_is_only_detected_if_checking_is_enabled() {
+        let program = triton_program!(
+            push 42 push -1
+            hint small: u32 = stack[1]
+            hint large: u32 = stack[0]
+            pop 2 halt
+        );
+
+        let mut vm_state = VMState::new(program.clone(), [].into(), [].into());
+        assert!(let Ok(()) = vm_state.run());
+
+        let mut vm_state = VMState::new(program, [].into(), [].into());
+        vm_state.check_u32_type_hints = true;
+        let_assert!(Err(InstructionError::ViolatedU32TypeHint(hint, element)) = vm_state.run());
+        assert!("large" == hint.variable_name);
+        assert!(bfe!(-1) == element);
+    }
+
+    #[test]
+    fn satisfied_u32_type_hints_do_not_interfere_with_execution() {
+        let program = triton_program!(
+            push 42 push 1337 push 0
+            hint numbers: u32 = stack[0..3]
+            pop 3 halt
+        );
+        let mut vm_state = VMState::new(program, [].into(), [].into());
+        vm_state.check_u32_type_hints = true;
+        assert!(let Ok(()) = vm_state.run());
+    }
+
+    #[test]
+    fn example_programs_have_no_type_warnings() {
+        for program in all_example_programs() {
+            assert!(TypeCheck::new(program).warnings().is_empty());
+        }
+    }
+
+    #[test]
+    fn formatting_example_programs_preserves_them() {
+        for program in all_example_programs() {
+            let code = program.to_string();
+            let_assert!(Ok(formatted) = isa::formatter::format(&code));
+            let_assert!(Ok(reparsed) = Program::from_code(&formatted));
+            assert!(program.hash() == reparsed.hash());
+            let_assert!(Ok(formatted_again) = isa::formatter::format(&formatted));
+            assert!(formatted == formatted_again);
+        }
+    }
+
+    #[test]
+    fn disassembling_example_programs_preserves_their_hash() {
+        for program in all_example_programs() {
+            let bwords = program.to_bwords();
+            let_assert!(Ok(disassembly) = Disassembly::from_bwords(&bwords));
+            let code = disassembly.to_string();
+            let_assert!(Ok(reassembled) = Program::from_code(&code));
+            assert!(program.hash() == reassembled.hash());
+        }
+    }
+
+    #[proptest]
+    fn if_else_executes_branch_according_to_condition(#[strategy(arb())] condition: BFieldElement) {
+        let program = triton_program!(
+            read_io 1
+            if push 1 else push 2 endif
+            write_io 1
+            halt
+        );
+        let output = VM::run(program, PublicInput::new(vec![condition]), [].into())?;
+        let expected = if condition.is_zero() { 2 } else { 1 };
+        prop_assert_eq!(vec![bfe!(expected)], output);
+    }
+
+    #[proptest(cases = 20)]
+    fn while_loop_computes_triangular_number(#[strategy(0_u64..100)] n: u64) {
+        let program = triton_program!(
+            push 0 read_io 1 dup 0
+            while
+                dup 0 pick 2 add place 1
+                addi -1 dup 0
+            endwhile
+            pop 1 write_io 1
+            halt
+        );
+        let output = VM::run(program, PublicInput::new(bfe_vec![n]), [].into())?;
+        prop_assert_eq!(bfe_vec![n * (n + 1) / 2], output);
+    }
+
+    #[test]
+    fn for_loop_counts_down_number_of_remaining_iterations() {
+        let program = triton_program!(
+            for 4
+                dup 0 write_io 1
+            endfor
+            halt
+        );
+        let_assert!(Ok(output) = VM::run(program, [].into(), [].into()));
+        assert!(bfe_vec![4, 3, 2, 1] == output);
+    }
+
+    #[test]
+    fn conditional_return_does_not_fall_into_bodies_of_control_flow() {
+        let program = triton_program!(
+            push 0 if push 7 endif
+            push 0 skiz return
+            push 5 write_io 1
+            halt
+        );
+        let_assert!(Ok(output) = VM::run(program, [].into(), [].into()));
+        assert!(bfe_vec![5] == output);
+    }
+
     #[test]
     fn crash_triton_vm_and_print_vm_error() {
         let crashing_program = triton_program!(push 2 assert halt);
@@ -1676,6 +2029,27 @@ pub(crate) mod tests {
         println!("{err}");
     }
 
+    #[test]
+    fn vm_error_points_to_source_location_of_crashing_instruction() {
+        let code = "push 2\ncall check\nhalt\ncheck:\n  assert\n  return";
+        let_assert!(Ok(program) = Program::from_code(code));
+        let_assert!(Err(err) = VM::run(program, [].into(), [].into()));
+
+        let_assert!(Some(location) = err.source_location());
+        assert!((5, 3) == (location.line, location.column));
+        assert!(err.to_string().contains("Source location: 5:3"));
+    }
+
+    #[test]
+    fn profile_points_to_source_locations_of_called_subroutines() {
+        let code = "call foo halt\nfoo: return";
+        let_assert!(Ok(program) = Program::from_code(code));
+        let_assert!(Ok((_, profile)) = VM::profile(program, [].into(), [].into()));
+
+        let_assert!(Some(location) = &profile.profile[0].source_location);
+        assert!((2, 6) == (location.line, location.column));
+    }
+
     #[test]
     fn crash_tritom_vm_with_non_empty_jump_stack_and_print_vm_error() {
         let crashing_program = triton_program! {
@@ -2781,6 +3155,55 @@ pub(crate) mod tests {
         ProgramAndInput::new(program).with_input([1, 3, 14].map(|b| bfe!(b)))
     }
 
+    pub(crate) fn test_program_for_permutation_check() -> ProgramAndInput {
+        let program = triton_program! {
+            read_io 5 push_perm
+            read_io 5 push_perm
+            read_io 5 push_perm
+            read_io 5 pop_perm
+            read_io 5 pop_perm
+            read_io 5 pop_perm
+            assert_perm halt
+        };
+        let list = [
+            bfe_array![1, 2, 3, 4, 5],
+            bfe_array![6, 7, 8, 9, 10],
+            bfe_array![1, 2, 3, 4, 5],
+        ];
+        let shuffled_list = [list[1], list[2], list[0]];
+        let input = [list, shuffled_list].concat().concat();
+        ProgramAndInput::new(program).with_input(input)
+    }
+
+    #[test]
+    fn run_test_program_for_permutation_check() {
+        let program_and_input = test_program_for_permutation_check();
+        let_assert!(Ok(_) = program_and_input.run());
+    }
+
+    #[test]
+    fn popping_element_that_was_never_pushed_to_permutation_check_crashes_vm() {
+        let program = triton_program!(push 1 push 2 push 3 push 4 push 5 pop_perm halt);
+        let_assert!(Err(err) = VM::run(program, [].into(), [].into()));
+        let_assert!(InstructionError::PermutationCheckElementNotFound(element) = err.source);
+        assert!(bfe_array![5, 4, 3, 2, 1] == element.values());
+    }
+
+    #[test]
+    fn asserting_permutation_check_with_remaining_elements_crashes_vm() {
+        let program =
+            triton_program!(push 1 push 2 push 3 push 4 push 5 push_perm assert_perm halt);
+        let_assert!(Err(err) = VM::run(program, [].into(), [].into()));
+        let_assert!(InstructionError::PermutationCheckFailed(1) = err.source);
+    }
+
+    #[test]
+    fn halting_with_remaining_elements_in_permutation_check_crashes_vm() {
+        let program = triton_program!(push 1 push 2 push 3 push 4 push 5 push_perm halt);
+        let_assert!(Err(err) = VM::run(program, [].into(), [].into()));
+        let_assert!(InstructionError::PermutationCheckFailed(1) = err.source);
+    }
+
     pub(crate) fn test_program_claim_in_ram_corresponds_to_currently_running_program(
     ) -> ProgramAndInput {
         let program = triton_program! {
@@ -3157,6 +3580,32 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn data_prologue_overwrites_conflicting_ram_and_keeps_op_stack() {
+        let program = triton_program!(
+            data primes: u32 @ 3 = [2, 3, 5, 7, 11, 13]
+            push 3 read_mem 1 pop 1 write_io 1
+            halt
+        );
+        let non_determinism = NonDeterminism::default().with_ram([(bfe!(3), bfe!(42))]);
+        let mut state = VMState::new(program.clone(), [].into(), non_determinism.clone());
+        let initial_op_stack = state.op_stack.clone();
+        while state.instruction_pointer < program.len_data_prologue() {
+            let_assert!(Ok(_) = state.step());
+        }
+        assert!(initial_op_stack == state.op_stack);
+        assert!(program.initial_ram() == state.ram);
+
+        let program_and_input = ProgramAndInput::new(program).with_non_determinism(non_determinism);
+        let_assert!(Ok(public_output) = program_and_input.clone().run());
+        assert!(bfe_vec![2] == public_output);
+
+        prove_and_verify(
+            program_and_input,
+            DEFAULT_LOG2_FRI_EXPANSION_FACTOR_FOR_TESTS,
+        );
+    }
+
     #[proptest(cases = 10)]
     fn read_non_deterministically_initialized_ram_at_random_address(
         #[strategy(arb())] uninitialized_address: BFieldElement,